@@ -0,0 +1,220 @@
+"""
+Pillow-compatible ICC colour management
+
+Profiles and transforms are handled by a pure-Rust colour management
+engine, with sRGB, Display P3, Adobe RGB, ProPhoto RGB and BT.2020
+profiles built in.
+"""
+
+import os
+from enum import IntEnum
+from pathlib import Path
+from typing import BinaryIO, Optional, Union
+
+from ._core import CmsProfile, CmsTransform, PyCMSError
+from .image import Image
+
+__all__ = [
+    "Intent",
+    "PyCMSError",
+    "ImageCmsProfile",
+    "ImageCmsTransform",
+    "createProfile",
+    "getOpenProfile",
+    "getProfileDescription",
+    "buildTransform",
+    "applyTransform",
+    "profileToProfile",
+]
+
+
+class Intent(IntEnum):
+    """ICC rendering intents."""
+
+    PERCEPTUAL = 0
+    RELATIVE_COLORIMETRIC = 1
+    SATURATION = 2
+    ABSOLUTE_COLORIMETRIC = 3
+
+
+class ImageCmsProfile:
+    """An ICC profile read from a file, a buffer, or bytes."""
+
+    def __init__(
+        self, profile: Union[str, "os.PathLike[str]", bytes, BinaryIO, CmsProfile]
+    ):
+        """
+        Args:
+            profile: Path to an ICC file, file-like object, profile bytes
+                (e.g. ``im.info["icc_profile"]``) or a core profile
+        """
+        if isinstance(profile, CmsProfile):
+            self.profile = profile
+        elif isinstance(profile, (str, Path, os.PathLike)):
+            with open(profile, "rb") as f:
+                self.profile = CmsProfile.frombytes(f.read())
+        elif hasattr(profile, "read"):
+            self.profile = CmsProfile.frombytes(profile.read())
+        else:
+            self.profile = CmsProfile.frombytes(profile)
+
+    def tobytes(self) -> bytes:
+        """Get the profile as ICC bytes, e.g. for save(icc_profile=...)."""
+        return self.profile.tobytes()
+
+    def __repr__(self) -> str:
+        return repr(self.profile)
+
+
+_ProfileArg = Union[str, "os.PathLike[str]", bytes, BinaryIO, ImageCmsProfile]
+
+
+def _profile(profile: _ProfileArg) -> ImageCmsProfile:
+    if isinstance(profile, ImageCmsProfile):
+        return profile
+    return ImageCmsProfile(profile)
+
+
+class ImageCmsTransform:
+    """A prepared conversion between two profiles for fixed image modes."""
+
+    def __init__(
+        self,
+        input: ImageCmsProfile,
+        output: ImageCmsProfile,
+        input_mode: str,
+        output_mode: str,
+        intent: Intent = Intent.PERCEPTUAL,
+    ):
+        self.input_profile = input
+        self.output_profile = output
+        self.transform = CmsTransform(
+            input.profile, output.profile, input_mode, output_mode, int(intent)
+        )
+
+    @property
+    def input_mode(self) -> str:
+        return self.transform.input_mode
+
+    @property
+    def output_mode(self) -> str:
+        return self.transform.output_mode
+
+    def apply(self, im: Image) -> Image:
+        """
+        Apply the transform to an image.
+
+        Returns:
+            New image in the output mode, with the output profile in
+            ``info["icc_profile"]``
+        """
+        return Image(im._rust_image.apply_color_transform(self.transform))
+
+
+def createProfile(colorSpace: str) -> ImageCmsProfile:
+    """
+    Create a built-in profile.
+
+    Args:
+        colorSpace: "sRGB", "DISPLAY_P3" (or "P3"), "ADOBE_RGB",
+            "PROPHOTO_RGB" or "BT2020"
+    """
+    return ImageCmsProfile(CmsProfile.builtin(colorSpace))
+
+
+def getOpenProfile(profileFilename: Union[str, "os.PathLike[str]", BinaryIO]):
+    """Open an ICC profile from a file path or file-like object."""
+    return ImageCmsProfile(profileFilename)
+
+
+def getProfileDescription(profile: _ProfileArg) -> str:
+    """Get the description stored in a profile, or an empty string."""
+    return _profile(profile).profile.description or ""
+
+
+def buildTransform(
+    inputProfile: _ProfileArg,
+    outputProfile: _ProfileArg,
+    inMode: str,
+    outMode: str,
+    renderingIntent: Intent = Intent.PERCEPTUAL,
+) -> ImageCmsTransform:
+    """
+    Build a transform between two profiles.
+
+    Building a transform is much more expensive than applying it, so reuse
+    the result for images with the same profiles and modes.
+
+    Args:
+        inputProfile: Profile of the images to convert
+        outputProfile: Profile to convert to
+        inMode: Mode of the input images ("L", "LA", "RGB", "RGBA" or "CMYK")
+        outMode: Mode of the output images
+        renderingIntent: How out-of-gamut colours are mapped
+
+    Raises:
+        PyCMSError: If a profile is invalid or the modes do not match the
+            profiles' colour spaces
+    """
+    return ImageCmsTransform(
+        _profile(inputProfile),
+        _profile(outputProfile),
+        inMode,
+        outMode,
+        renderingIntent,
+    )
+
+
+def applyTransform(
+    im: Image, transform: ImageCmsTransform, inPlace: bool = False
+) -> Optional[Image]:
+    """
+    Apply a transform from buildTransform() to an image.
+
+    Args:
+        im: Image in the transform's input mode
+        transform: Transform to apply
+        inPlace: Modify im instead of returning a new image
+
+    Returns:
+        Transformed image, or None if inPlace is True
+    """
+    converted = transform.apply(im)
+    if inPlace:
+        im._rust_image = converted._rust_image
+        return None
+    return converted
+
+
+def profileToProfile(
+    im: Image,
+    inputProfile: _ProfileArg,
+    outputProfile: _ProfileArg,
+    renderingIntent: Intent = Intent.PERCEPTUAL,
+    outputMode: Optional[str] = None,
+    inPlace: bool = False,
+) -> Optional[Image]:
+    """
+    Convert an image from one profile to another.
+
+    Example:
+        >>> srgb = ImageCms.createProfile("sRGB")
+        >>> p3 = ImageCms.createProfile("DISPLAY_P3")
+        >>> out = ImageCms.profileToProfile(im, p3, srgb)
+
+    Args:
+        im: Image to convert
+        inputProfile: Profile of im, e.g. ``im.info["icc_profile"]``
+        outputProfile: Profile to convert to
+        renderingIntent: How out-of-gamut colours are mapped
+        outputMode: Mode of the result; defaults to the mode of im
+        inPlace: Modify im instead of returning a new image
+
+    Returns:
+        Converted image with the output profile in ``info["icc_profile"]``,
+        or None if inPlace is True
+    """
+    transform = buildTransform(
+        inputProfile, outputProfile, im.mode, outputMode or im.mode, renderingIntent
+    )
+    return applyTransform(im, transform, inPlace)
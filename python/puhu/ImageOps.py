@@ -0,0 +1,30 @@
+"""
+Pillow-compatible ImageOps functions
+"""
+
+from typing import Optional
+
+from .image import Image
+
+
+def exif_transpose(image: Image, *, in_place: bool = False) -> Optional[Image]:
+    """
+    Rotate or flip an image upright according to its EXIF Orientation tag.
+
+    The orientation tag (0x0112) is removed from the EXIF data of the
+    result, so saving it with exif=result.getexif() does not rotate it
+    again in other viewers.
+
+    Args:
+        image: Image to transpose
+        in_place: Modify image instead of returning a new image
+
+    Returns:
+        Transposed copy of the image, or None if in_place is True.
+        Images without an orientation are returned unchanged as a copy.
+    """
+    transposed = image._rust_image.exif_transpose()
+    if in_place:
+        image._rust_image = transposed
+        return None
+    return Image(transposed)
@@ -0,0 +1,68 @@
+"""
+Pillow-compatible iteration over the frames of an image
+"""
+
+from typing import Callable, List, Optional, Union
+
+from .image import Image
+
+
+class Iterator:
+    """
+    Iterate over the frames of an animation or pages of a multi-page file.
+
+    Each step seeks the image to the next frame and yields the image
+    itself, so copy() frames that must outlive the iteration:
+
+        frames = [frame.copy() for frame in ImageSequence.Iterator(im)]
+    """
+
+    def __init__(self, im: Image):
+        if not hasattr(im, "seek"):
+            raise AttributeError("im must have seek method")
+        self.im = im
+        self.position = 0
+
+    def __getitem__(self, ix: int) -> Image:
+        try:
+            self.im.seek(ix)
+            return self.im
+        except EOFError as e:
+            raise IndexError from e  # end of sequence
+
+    def __iter__(self) -> "Iterator":
+        return self
+
+    def __next__(self) -> Image:
+        try:
+            self.im.seek(self.position)
+            self.position += 1
+            return self.im
+        except EOFError as e:
+            raise StopIteration from e
+
+
+def all_frames(
+    im: Union[Image, List[Image]],
+    func: Optional[Callable[[Image], Image]] = None,
+) -> List[Image]:
+    """
+    Copy every frame of one or more images.
+
+    Args:
+        im: Image or list of images
+        func: Function applied to each frame
+
+    Returns:
+        List of frames; the images are left at their current frame
+    """
+    if not isinstance(im, list):
+        im = [im]
+
+    frames = []
+    for image in im:
+        current = image.tell()
+        for frame in Iterator(image):
+            frames.append(func(frame) if func else frame.copy())
+        image.seek(current)
+    return frames
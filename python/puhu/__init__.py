@@ -5,8 +5,19 @@ Provides the high-level API while addressing
 performance and memory-safety issues through a Rust backend.
 """
 
+from ._core import DecompressionBombError, DecompressionBombWarning, Exif
+from . import ImageCms, ImageOps, ImageSequence
 from .enums import Palette  # noqa: F401
-from .enums import Dither, ImageFormat, ImageMode, Resampling, Transpose
+from .enums import (
+    ColorMetric,
+    Dither,
+    ImageFormat,
+    ImageMode,
+    Quantize,
+    Resampling,
+    Transform,
+    Transpose,
+)
 from .image import Image
 from .operations import convert, crop, new, open, resize, rotate, save
 
@@ -18,9 +29,18 @@ __all__ = [
     "ImageMode",
     "ImageFormat",
     "Resampling",
+    "Transform",
     "Transpose",
     "Dither",
     "Palette",
+    "Quantize",
+    "ColorMetric",
+    "DecompressionBombError",
+    "DecompressionBombWarning",
+    "Exif",
+    "ImageCms",
+    "ImageOps",
+    "ImageSequence",
     "open",
     "new",
     "save",
@@ -9,11 +9,18 @@ class ImageMode:
     # Grayscale modes
     L = "L"  # 8-bit grayscale
     LA = "LA"  # 8-bit grayscale + alpha
+    I_16 = "I;16"  # 16-bit grayscale
+    LA_16 = "LA;16"  # 16-bit grayscale + alpha
     I = "I"  # 32-bit integer grayscale
+    F = "F"  # 32-bit float grayscale
 
     # Color modes
     RGB = "RGB"  # 8-bit RGB
     RGBA = "RGBA"  # 8-bit RGB + alpha
+    RGB_16 = "RGB;16"  # 16-bit RGB
+    RGBA_16 = "RGBA;16"  # 16-bit RGB + alpha
+    RGB_F = "RGB;F"  # 32-bit float RGB
+    RGBA_F = "RGBA;F"  # 32-bit float RGB + alpha
     CMYK = "CMYK"  # 8-bit CMYK
     YCbCr = "YCbCr"  # 8-bit YCbCr
     HSV = "HSV"  # 8-bit HSV
@@ -100,11 +107,71 @@ class Transpose:
         return mapping.get(value, cls.FLIP_LEFT_RIGHT)
 
 
+class Transform:
+    """Geometric transform method constants for transform()."""
+
+    AFFINE = "AFFINE"
+    EXTENT = "EXTENT"
+    PERSPECTIVE = "PERSPECTIVE"
+    QUAD = "QUAD"
+    MESH = "MESH"
+
+    # Pillow compatibility - numeric constants
+    AFFINE_INT = 0
+    EXTENT_INT = 1
+    PERSPECTIVE_INT = 2
+    QUAD_INT = 3
+    MESH_INT = 4
+
+    @classmethod
+    def from_int(cls, value: int) -> str:
+        """Convert integer transform constant to string."""
+        mapping = {
+            cls.AFFINE_INT: cls.AFFINE,
+            cls.EXTENT_INT: cls.EXTENT,
+            cls.PERSPECTIVE_INT: cls.PERSPECTIVE,
+            cls.QUAD_INT: cls.QUAD,
+            cls.MESH_INT: cls.MESH,
+        }
+        # Other values are passed on to be rejected
+        return mapping.get(value, str(value))
+
+
 class Dither:
     """Dithering method constants for convert() and quantize()."""
 
     NONE = "NONE"
+
+    # Error diffusion
     FLOYDSTEINBERG = "FLOYDSTEINBERG"
+    ATKINSON = "ATKINSON"
+    JARVIS_JUDICE_NINKE = "JARVIS_JUDICE_NINKE"
+    STUCKI = "STUCKI"
+    SIERRA = "SIERRA"
+    SIERRA_TWO_ROW = "SIERRA_TWO_ROW"
+    SIERRA_LITE = "SIERRA_LITE"
+
+    # Ordered dithering with a Bayer matrix; ORDERED is the 8x8 matrix
+    ORDERED = "ORDERED"
+    BAYER2 = "BAYER2"
+    BAYER4 = "BAYER4"
+    BAYER8 = "BAYER8"
+
+    # Pillow compatibility - numeric constants
+    NONE_INT = 0
+    ORDERED_INT = 1
+    FLOYDSTEINBERG_INT = 3
+
+    @classmethod
+    def from_int(cls, value: int) -> str:
+        """Convert integer dither constant to string."""
+        mapping = {
+            cls.NONE_INT: cls.NONE,
+            cls.ORDERED_INT: cls.ORDERED,
+            cls.FLOYDSTEINBERG_INT: cls.FLOYDSTEINBERG,
+        }
+        # Other Pillow methods are passed on to be rejected
+        return mapping.get(value, str(value))
 
 
 class Palette:
@@ -112,3 +179,33 @@ class Palette:
 
     WEB = "WEB"
     ADAPTIVE = "ADAPTIVE"
+
+
+class Quantize:
+    """Quantization method constants for quantize()."""
+
+    MEDIANCUT = "MEDIANCUT"
+    FASTOCTREE = "FASTOCTREE"
+    NEUQUANT = "NEUQUANT"
+
+    # Pillow compatibility - numeric constants
+    MEDIANCUT_INT = 0
+    FASTOCTREE_INT = 2
+
+    @classmethod
+    def from_int(cls, value: int) -> str:
+        """Convert integer quantize constant to string."""
+        mapping = {
+            cls.MEDIANCUT_INT: cls.MEDIANCUT,
+            cls.FASTOCTREE_INT: cls.FASTOCTREE,
+        }
+        # Other Pillow methods are passed on to be rejected
+        return mapping.get(value, str(value))
+
+
+class ColorMetric:
+    """Color distance constants for matching colors to a palette."""
+
+    RGB = "RGB"
+    CIEDE2000 = "CIEDE2000"
+    OKLAB = "OKLAB"
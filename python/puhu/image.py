@@ -2,11 +2,22 @@
 Python Image class that wraps the Rust Puhu implementation
 """
 
+import os
 from pathlib import Path
-from typing import Any, Optional, Tuple, Union
+from typing import Any, BinaryIO, List, Optional, Tuple, Union
 
+from ._core import MAX_IMAGE_PIXELS as DEFAULT_MAX_IMAGE_PIXELS
+from ._core import Exif
 from ._core import Image as RustImage
-from .enums import Palette, Resampling, Transpose
+from .enums import (
+    ColorMetric,
+    Dither,
+    Palette,
+    Quantize,
+    Resampling,
+    Transform,
+    Transpose,
+)
 
 
 class Image:
@@ -17,6 +28,12 @@ class Image:
     performance and memory safety for all image operations.
     """
 
+    #: Soft limit on the number of pixels in opened images, like Pillow's
+    #: ``Image.MAX_IMAGE_PIXELS``. Images larger than this emit a
+    #: DecompressionBombWarning and images larger than twice this raise
+    #: DecompressionBombError. Set to None to disable the check.
+    MAX_IMAGE_PIXELS: Optional[int] = DEFAULT_MAX_IMAGE_PIXELS
+
     def __init__(self, rust_image=None):
         """Initialize an Image instance."""
         if RustImage is None:
@@ -32,25 +49,63 @@ class Image:
     @classmethod
     def open(
         cls,
-        fp: Union[str, Path, bytes],
+        fp: Union[str, "os.PathLike[str]", bytes, bytearray, memoryview, BinaryIO],
         mode: Optional[str] = None,
-        formats: Optional[list] = None,
+        formats: Optional[Union[list, tuple]] = None,
+        *,
+        max_width: Optional[int] = None,
+        max_height: Optional[int] = None,
+        max_alloc: Optional[int] = None,
+        exif_transpose: bool = False,
     ) -> "Image":
         """
         Open an image file.
 
+        The image data is not decoded until it is needed. File-like objects
+        are only read far enough to identify the format, and the remainder
+        is read on first access, so they must stay open until then.
+
         Args:
-            fp: File path, file object, or bytes
-            mode: Optional mode hint TODO: implement
-            formats: Optional list of formats to try TODO: implement
+            fp: File path (str or os.PathLike), bytes-like object
+                (bytes, bytearray, memoryview), or binary file-like object
+                with a read() method
+            mode: Must be "r" (or None); only reading is supported
+            formats: List or tuple of format names the image may be decoded
+                as, e.g. ["JPEG", "PNG"]. None allows every supported format.
+            max_width: Reject images wider than this many pixels
+            max_height: Reject images taller than this many pixels
+            max_alloc: Maximum number of bytes the decoder may allocate
+            exif_transpose: Rotate the image upright according to its EXIF
+                orientation, as ImageOps.exif_transpose() does. Oriented
+                images are decoded immediately.
 
         Returns:
             Image instance
-        """
-        if isinstance(fp, Path):
-            fp = str(fp)
 
-        rust_image = RustImage.open(fp)
+        Raises:
+            ValueError: If mode is not "r"
+            TypeError: If formats is not a list or tuple
+            UnsupportedFormatError: If the image format is not in formats.
+                For file paths this is checked when the file is first read.
+            DecompressionBombError: If the image exceeds twice
+                Image.MAX_IMAGE_PIXELS or one of the per-call limits. The
+                check runs when the header is first read, not in open().
+        """
+        if mode is not None and mode != "r":
+            raise ValueError(f"bad mode {mode!r}")
+        if formats is not None and not isinstance(formats, (list, tuple)):
+            raise TypeError("formats must be a list or tuple")
+
+        rust_image = RustImage.open(
+            fp,
+            formats=formats,
+            max_image_pixels=cls.MAX_IMAGE_PIXELS,
+            max_width=max_width,
+            max_height=max_height,
+            max_alloc=max_alloc,
+        )
+        if exif_transpose:
+            rust_image = rust_image.exif_transpose()
         return cls(rust_image)
 
     @classmethod
@@ -64,12 +119,23 @@ class Image:
         Create a new image with the given mode and size.
 
         Args:
-            mode: Image mode (e.g., 'RGB', 'RGBA', 'L', 'LA')
+            mode: Image mode (e.g., 'RGB', 'RGBA', 'L', 'LA', 'P'), a 16-bit
+                mode ('I;16', 'LA;16', 'RGB;16', 'RGBA;16'), a float color
+                mode ('RGB;F', 'RGBA;F'), 'I' (32-bit integer) or 'F'
+                (32-bit float) for values rather than colors, or 'CMYK' or
+                'YCbCr'
             size: Image size as (width, height)
             color: Fill color. Can be:
-                - Single integer for grayscale modes
+                - Single integer for grayscale modes, or a palette index
+                  into a grayscale palette for mode 'P'
                 - Tuple of integers for RGB/RGBA modes
-                - String color name or hex code (e.g., 'red', '#ff0000')
+                - Numbers in the range of the mode for 16-bit, float, 'I'
+                  and 'F' modes, e.g. 65535 for white in 'I;16'
+                - Tuple of ink amounts for 'CMYK', or of Y, Cb and Cr for
+                  'YCbCr'
+                - String color name or hex code (e.g., 'red', '#ff0000'),
+                  scaled to the range of 16-bit and float modes and
+                  converted to 'CMYK' and 'YCbCr'
                 - Default is 0 (black/transparent)
 
         Returns:
@@ -79,20 +145,69 @@ class Image:
         return cls(rust_image)
 
     def save(
-        self, fp: Union[str, Path], format: Optional[str] = None, **options
+        self,
+        fp: Union[str, Path, BinaryIO, bytearray],
+        format: Optional[str] = None,
+        save_all: bool = False,
+        append_images: Optional[List["Image"]] = None,
+        **options,
     ) -> None:
         """
-        Save the image to a file.
+        Save the image to a file or buffer.
 
         Args:
-            fp: File path to save to
-            format: Image format (e.g., 'JPEG', 'PNG')
-            **options: Additional save options (TODO: implement)
+            fp: File path, bytearray, or file-like object with a write() method
+            format: Image format (e.g., 'JPEG', 'PNG'). Required when fp is
+                not a path, since there is no extension to infer it from.
+            save_all: Save every frame of the image, followed by the frames
+                of ``append_images``, as an animated GIF, PNG or WEBP or a
+                multi-page TIFF
+            append_images: Images to add as frames when save_all is True
+            **options: Format-specific encoder options:
+                - JPEG: quality (0-100), optimize, progressive,
+                  subsampling (0/1/2 or '4:4:4'/'4:2:2'/'4:2:0'), dpi,
+                  comment, exif, icc_profile
+                - PNG: optimize, compress_level (0-9), dpi, gamma,
+                  transparency (int for L, (r, g, b) for RGB),
+                  pnginfo (mapping of text chunks), exif, icc_profile,
+                  duration, loop, disposal (0-2)
+                - GIF: optimize, comment, duration, loop, disposal (0-3),
+                  background (palette index)
+                - TIFF: compression ('raw', 'tiff_lzw', 'tiff_deflate',
+                  'packbits', or a list with one per page), dpi, icc_profile
+                - ICO: sizes (list of (width, height) up to 256; by default
+                  every size from 16 to 256 that fits the image)
+                - WEBP: lossless (only lossless encoding is available), exif,
+                  icc_profile, optimize, duration, loop
+
+                Animations take ``duration`` in milliseconds and
+                ``disposal`` either once for every frame or as a list with
+                one value per frame; the durations of the source frames are
+                kept by default. ``loop`` is the number of times to play
+                the animation, 0 meaning forever. With ``optimize``, frames
+                after the first only store the area that changed. Frames
+                that differ in size or mode from the first are converted to
+                RGBA and placed at its top left.
+
+                ``exif`` takes the bytes from ``info["exif"]`` or an
+                ``Exif`` object from getexif().
+
+                The ICC profile in ``info["icc_profile"]`` is embedded by
+                default; pass ``icc_profile=b""`` to leave it out. Other
+                metadata is only written when passed explicitly. To keep
+                it when re-saving, pass the values from ``info``, e.g.
+                ``img.save(out, dpi=img.info["dpi"], pnginfo=img.text)``.
+
+        Raises:
+            UnsupportedFormatError: If an option is not supported by the format
+            PuhuProcessingError: If an option is unknown or has an invalid value
         """
         if isinstance(fp, Path):
             fp = str(fp)
 
-        self._rust_image.save(fp, format)
+        if append_images is not None:
+            append_images = [im._rust_image for im in append_images]
+        self._rust_image.save(fp, format, save_all, append_images, **options)
 
     def resize(
         self,
@@ -137,39 +252,98 @@ class Image:
     def rotate(
         self,
         angle: float,
+        resample: Union[int, str] = Resampling.NEAREST,
         expand: bool = False,
+        center: Optional[Tuple[float, float]] = None,
+        translate: Optional[Tuple[float, float]] = None,
         fillcolor: Optional[Any] = None,
     ) -> "Image":
         """
-        Rotate the image.
+        Rotate the image counter-clockwise.
+
+        Quarter turns without ``center`` or ``translate`` are exact. Other
+        angles sample the image, blending its edges into ``fillcolor``.
 
         Args:
-            angle: Rotation angle in degrees
-            expand: Whether to expand the image to fit the rotated content
-            fillcolor: Fill color for empty areas (TODO: implement)
+            angle: Rotation angle in degrees, counter-clockwise
+            resample: NEAREST, BILINEAR or BICUBIC; "P" images always use
+                NEAREST
+            expand: Whether to enlarge the output to hold the whole rotated
+                image, rather than keeping the size of the original
+            center: Center of rotation as (x, y), defaulting to the center
+                of the image
+            translate: Offset (x, y) applied after rotating
+            fillcolor: Color of the area outside the rotated image,
+                defaulting to transparent black
 
         Returns:
             New rotated Image instance
         """
-        # Only support 90-degree rotations TODO: implement arbitrary angles
-        angle = angle % 360
+        if isinstance(resample, int):
+            resample = Resampling.from_int(resample)
 
-        if angle in [90, 180, 270]:
-            rust_image = self._rust_image.rotate(float(angle))
-        else:
-            raise NotImplementedError(
-                f"Arbitrary angle rotation ({angle}°) not yet implemented. "
-                "Only 90°, 180°, and 270° rotations are supported."
-            )
+        rust_image = self._rust_image.rotate(
+            float(angle), resample, expand, center, translate, fillcolor
+        )
+        return Image(rust_image)
+
+    def transform(
+        self,
+        size: Tuple[int, int],
+        method: Union[int, str, Any],
+        data: Optional[Any] = None,
+        resample: Union[int, str] = Resampling.NEAREST,
+        fillcolor: Optional[Any] = None,
+    ) -> "Image":
+        """
+        Transform the image to the given size.
+
+        Every output pixel is sampled from the position its center maps to
+        in this image; pixels mapping outside it blend into ``fillcolor``.
+
+        Args:
+            size: Output size as (width, height)
+            method: AFFINE, EXTENT, PERSPECTIVE, QUAD or MESH, or an object
+                whose ``getdata()`` returns the method and data
+            data: For AFFINE, (a, b, c, d, e, f) mapping (x, y) to
+                (a*x + b*y + c, d*x + e*y + f); for EXTENT, the source
+                rectangle (x0, y0, x1, y1); for PERSPECTIVE, the 8
+                coefficients of a projective map; for QUAD, the upper left,
+                lower left, lower right and upper right source corners; for
+                MESH, a list of (box, quad) pairs mapping output boxes to
+                source quadrilaterals
+            resample: NEAREST, BILINEAR or BICUBIC; "P" images always use
+                NEAREST
+            fillcolor: Color of the area outside the source, defaulting to
+                transparent black
+
+        Returns:
+            New transformed Image instance
+        """
+        if hasattr(method, "getdata"):
+            method, data = method.getdata()
+        if isinstance(method, int):
+            method = Transform.from_int(method)
+        if isinstance(resample, int):
+            resample = Resampling.from_int(resample)
+        if data is None:
+            raise ValueError("missing method data")
 
+        rust_image = self._rust_image.transform(
+            tuple(size), method, data, resample, fillcolor
+        )
         return Image(rust_image)
 
     def transpose(self, method: Union[int, str]) -> "Image":
         """
         Transpose the image.
 
+        Every method copies each pixel once, straight to its new position.
+
         Args:
-            method: Transpose method
+            method: FLIP_LEFT_RIGHT, FLIP_TOP_BOTTOM, ROTATE_90, ROTATE_180,
+                ROTATE_270, TRANSPOSE or TRANSVERSE. The rotations turn
+                counter-clockwise.
 
         Returns:
             New transposed Image instance
@@ -177,79 +351,222 @@ class Image:
         if isinstance(method, int):
             method = Transpose.from_int(method)
 
-        if method == Transpose.FLIP_LEFT_RIGHT:
-            method_str = "FLIP_LEFT_RIGHT"
-        elif method == Transpose.FLIP_TOP_BOTTOM:
-            method_str = "FLIP_TOP_BOTTOM"
-        elif method == Transpose.ROTATE_90:
-            method_str = "ROTATE_90"
-        elif method == Transpose.ROTATE_180:
-            method_str = "ROTATE_180"
-        elif method == Transpose.ROTATE_270:
-            method_str = "ROTATE_270"
-        else:
-            raise NotImplementedError(f"Transpose method {method} not yet implemented")
-
-        rust_image = self._rust_image.transpose(method_str)
-
+        rust_image = self._rust_image.transpose(method)
         return Image(rust_image)
 
     def copy(self) -> "Image":
-        """Create a copy of the image."""
+        """Create a copy of the image (the current frame of an animation)."""
         rust_image = self._rust_image.copy()
         return Image(rust_image)
 
     def thumbnail(
         self,
-        size: Tuple[int, int],
+        size: Tuple[float, float],
         resample: Union[int, str] = Resampling.BICUBIC,
+        reducing_gap: Optional[float] = 2.0,
     ) -> None:
         """
-        Create a thumbnail version of the image in-place.
+        Shrink the image in-place to fit within the given size.
+
+        The aspect ratio is kept, and neither side is rounded down to 0.
+        Images that already fit are left as they are.
 
         Args:
             size: Maximum size as (width, height)
             resample: Resampling filter
+            reducing_gap: Speed up shrinking by first reducing the image by
+                whole factors, to no less than ``reducing_gap`` times the
+                final size; JPEG files that are not yet decoded are then
+                decoded at 1/2, 1/4 or 1/8 scale. None resizes the full
+                image with ``resample`` only.
         """
-        # Calculate thumbnail size preserving aspect ratio
-        current_width, current_height = self.size
-        max_width, max_height = size
+        if isinstance(resample, int):
+            resample = Resampling.from_int(resample)
+
+        self._rust_image.thumbnail(tuple(size), resample, reducing_gap)
 
-        width_ratio = max_width / current_width
-        height_ratio = max_height / current_height
-        scale = min(width_ratio, height_ratio)
+    def draft(
+        self, mode: Optional[str], size: Optional[Tuple[int, int]] = None
+    ) -> Optional[Tuple[str, Tuple[float, float, float, float]]]:
+        """
+        Configure a JPEG file that is not yet decoded to load faster.
 
-        new_width = int(current_width * scale)
-        new_height = int(current_height * scale)
+        The file is decoded at the largest scale of 1/2, 1/4 or 1/8 that
+        keeps it at least ``size``, and color files are converted to "L"
+        when ``mode`` is "L". Other modes are ignored. Progressive and CMYK
+        files are decoded at full size and then reduced.
 
-        # Resize in-place by replacing the rust image
-        self._rust_image = self._rust_image.resize((new_width, new_height), resample)
+        Args:
+            mode: Requested mode, or None
+            size: Requested size as (width, height), or None for full size
+
+        Returns:
+            The mode and the box of the file the image is decoded from, in
+            decoded pixels, or None if the image is not a JPEG file that is
+            still to be decoded
+        """
+        result = self._rust_image.draft(mode, None if size is None else tuple(size))
+        if result is None:
+            return None
+        mode, box = result
+        return mode, tuple(box)
+
+    def reduce(
+        self,
+        factor: Union[int, Tuple[int, int]],
+        box: Optional[Tuple[int, int, int, int]] = None,
+    ) -> "Image":
+        """
+        Shrink the image by whole factors, averaging each block of pixels.
+
+        Much faster than ``resize()`` for large reductions. Blocks at the
+        right and bottom edges may be smaller than the factor.
+
+        Args:
+            factor: Reduction factor, or (x_factor, y_factor)
+            box: Region to reduce as (left, top, right, bottom), the whole
+                image by default
+
+        Returns:
+            New Image of size ceil(width / x_factor) by
+            ceil(height / y_factor)
+        """
+        if isinstance(factor, int):
+            factor = (factor, factor)
+        rust_image = self._rust_image.reduce(
+            tuple(factor), None if box is None else tuple(box)
+        )
+        return Image(rust_image)
+
+    def seek(self, frame: int) -> None:
+        """
+        Move to another frame of an animation or page of a multi-page file.
+
+        Frames are decoded on demand, each composited onto the frames
+        before it. Stepping forward continues from the current frame;
+        seeking backward decodes again from the first frame.
+
+        Args:
+            frame: Frame index, starting at 0
+
+        Raises:
+            EOFError: If the frame is outside the sequence
+        """
+        if frame < 0:
+            raise EOFError("attempt to seek outside sequence")
+        self._rust_image.seek(frame)
+
+    def tell(self) -> int:
+        """Get the index of the current frame."""
+        return self._rust_image.tell()
+
+    def getexif(self) -> Exif:
+        """
+        Get the EXIF data as a mutable mapping of tag ids to values.
+
+        The Exif (0x8769), GPS (0x8825) and Interop (0xA005) IFDs are read
+        with get_ifd(), and deleting their tag removes the whole IFD. Pass
+        the result to save() to write modified EXIF data:
+
+            exif = img.getexif()
+            del exif[0x8825]  # Drop GPS location, keep copyright (0x8298)
+            img.save("out.jpg", exif=exif)
+
+        Returns:
+            Exif mapping, empty if the image has no EXIF data
+        """
+        return self._rust_image.getexif()
 
     def to_bytes(self) -> bytes:
-        """Get the raw pixel data as bytes."""
+        """
+        Get the raw pixel data as bytes; palette indices for mode "P".
+
+        16-bit and float samples are in native byte order, with "I" images
+        as 32-bit signed integers and "F" images as 32-bit floats. "CMYK"
+        images have 4 bytes per pixel of ink amounts, 0 meaning no ink.
+        """
         return self._rust_image.to_bytes()
 
+    def getpalette(self, rawmode: str = "RGB") -> Optional[List[int]]:
+        """
+        Get the palette of a "P" image.
+
+        Args:
+            rawmode: "RGB" for 3 values per entry, or "RGBA" for 4 values
+                with alpha 0 at the transparent index
+
+        Returns:
+            Flat list of palette values, or None if the image has no palette
+        """
+        return self._rust_image.getpalette(rawmode)
+
+    def putpalette(self, data: Union[bytes, List[int]], rawmode: str = "RGB") -> None:
+        """
+        Attach a palette to a "P" or "L" image.
+
+        An "L" image becomes a "P" image whose pixel values are indices into
+        the palette.
+
+        Args:
+            data: Palette values, at most 256 entries
+            rawmode: "RGB" for 3 values per entry, or "RGBA" for 4 values;
+                the first entry with alpha 0 becomes the transparent index
+        """
+        self._rust_image.putpalette(data, rawmode)
+
     def convert(
         self,
         mode: str,
         matrix: Optional[Tuple[float, ...]] = None,
-        dither: Optional[str] = None,
+        dither: Union[int, str, None] = None,
         palette: str = Palette.WEB,
         colors: int = 256,
+        serpentine: bool = False,
+        metric: str = ColorMetric.RGB,
     ) -> "Image":
         """
         Convert the image to a different mode.
 
         Args:
-            mode: Target mode (e.g., 'L', 'RGB', 'RGBA', 'LA', '1', 'P')
+            mode: Target mode (e.g., 'L', 'RGB', 'RGBA', 'LA', '1', 'P',
+                   'I;16', 'RGB;16', 'RGBA;16', 'I', 'F', 'CMYK', 'YCbCr')
             matrix: Optional conversion matrix (4-tuple or 12-tuple of floats).
                    If given, this should be a 4- or 12-tuple containing floating point values.
             dither: Dithering method, used when converting from mode "RGB" to "P"
-                   or from "RGB" or "L" to "1". Available methods are "NONE" or
-                   "FLOYDSTEINBERG" (default). Note that this is not used when matrix is supplied.
+                   or from "RGB" or "L" to "1". One of the Dither constants:
+                   "NONE", error diffusion with "FLOYDSTEINBERG" (default),
+                   "ATKINSON", "JARVIS_JUDICE_NINKE", "STUCKI", "SIERRA",
+                   "SIERRA_TWO_ROW" or "SIERRA_LITE", or ordered dithering with
+                   "BAYER2", "BAYER4" or "BAYER8" ("ORDERED").
+                   Note that this is not used when matrix is supplied.
             palette: Palette to use when converting from mode "RGB" to "P".
                     Available palettes are "WEB" (default) or "ADAPTIVE".
             colors: Number of colors to use for the "ADAPTIVE" palette. Defaults to 256.
+            serpentine: Diffuse the error of every other row right to left,
+                    which avoids diagonal artifacts of error diffusion.
+            metric: Color distance used to pick the nearest palette entry when
+                    converting to "P": "RGB" (default), "CIEDE2000" or "OKLAB".
+                    The perceptual metrics match skin tones and gradients
+                    more closely at some cost in speed.
+
+        Converting to "P" returns an image of palette indices; see
+        getpalette(). Transparent pixels of an RGBA image share one
+        palette entry, stored as ``info["transparency"]``. Converting a
+        "P" image to "RGB" or "RGBA" looks the indices up again, so an
+        "ADAPTIVE" palette of an image with few colors round trips
+        exactly.
+
+        Converting between 8-bit, 16-bit and float color modes scales the
+        values, so "RGB" 255 becomes "RGB;16" 65535. Converting to "I" or
+        "F" keeps the gray level in the range of the source mode, and
+        converting "I" or "F" to another mode clamps the values to its
+        range, as Pillow does.
+
+        "CMYK" is converted to and from RGB with the plain formulas Pillow
+        uses, without black ink in the result, and "YCbCr" with the JPEG
+        formulas. A "CMYK" image with an embedded CMYK ICC profile, such as
+        a print-ready JPEG or TIFF, is instead converted to sRGB through the
+        profile, and the result carries the sRGB profile.
 
         Returns:
             Image instance in the target mode
@@ -260,12 +577,69 @@ class Image:
             >>> rgba = img.convert('RGBA')  # Add alpha channel
             >>> bw = img.convert('1')  # Convert to black and white with dithering
             >>> bw_no_dither = img.convert('1', dither='NONE')  # No dithering
+            >>> bw_bayer = img.convert('1', dither='BAYER4')  # 4x4 ordered dithering
             >>> palette_img = img.convert('P', palette='ADAPTIVE', colors=128)  # 128-color palette
         """
         matrix_list = list(matrix) if matrix is not None else None
+        if isinstance(dither, int):
+            dither = Dither.from_int(dither)
 
         rust_image = self._rust_image.convert(
-            mode, matrix=matrix_list, dither=dither, palette=palette, colors=colors
+            mode,
+            matrix=matrix_list,
+            dither=dither,
+            palette=palette,
+            colors=colors,
+            serpentine=serpentine,
+            metric=metric,
+        )
+        return Image(rust_image)
+
+    def quantize(
+        self,
+        colors: int = 256,
+        method: Union[int, str, None] = None,
+        kmeans: int = 0,
+        palette: Optional["Image"] = None,
+        dither: Union[int, str] = Dither.FLOYDSTEINBERG,
+        serpentine: bool = False,
+        metric: str = ColorMetric.RGB,
+    ) -> "Image":
+        """
+        Convert the image to a "P" image with at most the given number of colors.
+
+        Images with alpha are quantized in RGBA, so the palette keeps the
+        alpha of each color, and fully transparent pixels share one entry.
+        Images with no more colors than requested keep their exact colors.
+
+        Args:
+            colors: Number of colors, from 1 to 256
+            method: Quantize.MEDIANCUT (default for images without alpha),
+                Quantize.FASTOCTREE (default for images with alpha) or
+                Quantize.NEUQUANT
+            kmeans: Number of k-means iterations to refine the palette with;
+                0 to skip refinement
+            palette: "P" image whose palette to use instead of building one
+            dither: Dithering method, one of the Dither constants as for
+                convert(). Defaults to Dither.FLOYDSTEINBERG.
+            serpentine: Diffuse the error of every other row right to left
+            metric: Color distance used to map pixels to the palette, one of
+                the ColorMetric constants as for convert()
+
+        Returns:
+            New "P" image
+
+        Examples:
+            >>> small = img.quantize(16, method=Quantize.FASTOCTREE, kmeans=3)
+            >>> matched = img.quantize(palette=small)  # Reuse small's palette
+        """
+        if isinstance(method, int):
+            method = Quantize.from_int(method)
+        if isinstance(dither, int):
+            dither = Dither.from_int(dither)
+        rust_palette = palette._rust_image if palette is not None else None
+        rust_image = self._rust_image.quantize(
+            colors, method, kmeans, rust_palette, dither, serpentine, metric
         )
         return Image(rust_image)
 
@@ -357,9 +731,52 @@ class Image:
 
     @property
     def info(self) -> dict:
-        """Image metadata dictionary."""
-        # TODO: Implement metadata extraction in Rust
-        return {}
+        """
+        Image metadata dictionary.
+
+        Read from the file without decoding pixels. Depending on the format
+        it may contain:
+
+        - PNG: text chunks (by keyword), 'gamma', 'dpi', 'transparency'
+        - JPEG: 'jfif', 'jfif_version', 'jfif_unit', 'jfif_density', 'dpi',
+          'comment'
+        - GIF: 'duration', 'loop', 'background', 'comment'
+        - APNG, animated WebP: 'duration', 'loop'
+        - JPEG, PNG, WebP, TIFF: 'exif' (raw bytes; see getexif()),
+          'icc_profile'
+
+        Images created in memory start with an empty dictionary, and
+        operations such as resize() and crop() keep the source metadata.
+        convert() drops an ICC profile that does not match the new mode.
+        For animations, 'duration' is that of the current frame.
+        """
+        return self._rust_image.info
+
+    @property
+    def n_frames(self) -> int:
+        """Number of frames (GIF, APNG, WebP) or pages (TIFF)."""
+        return self._rust_image.n_frames
+
+    @property
+    def is_animated(self) -> bool:
+        """Whether the image has more than one frame."""
+        return self._rust_image.is_animated
+
+    @property
+    def disposal(self) -> Optional[int]:
+        """
+        How the current frame is cleared before the next one is drawn.
+
+        The value is format-specific: the GIF disposal method (0-3), the
+        APNG dispose_op (0-2) or the WebP dispose flag (0-1). None for
+        other formats.
+        """
+        return self._rust_image.disposal
+
+    @property
+    def text(self) -> dict:
+        """PNG text chunks (tEXt, zTXt and iTXt) as a dictionary."""
+        return self._rust_image.text
 
     def __repr__(self) -> str:
         """String representation of the image."""
@@ -3,30 +3,51 @@ Functional API for image operations
 provides Pillow-compatible module-level functions
 """
 
+import os
 from pathlib import Path
-from typing import Optional, Tuple, Union
+from typing import BinaryIO, Optional, Tuple, Union
 
 from .enums import Resampling
 from .image import Image
 
 
 def open(
-    fp: Union[str, Path, bytes],
+    fp: Union[str, "os.PathLike[str]", bytes, bytearray, memoryview, BinaryIO],
     mode: Optional[str] = None,
-    formats: Optional[list] = None,
+    formats: Optional[Union[list, tuple]] = None,
+    *,
+    max_width: Optional[int] = None,
+    max_height: Optional[int] = None,
+    max_alloc: Optional[int] = None,
+    exif_transpose: bool = False,
 ) -> Image:
     """
     Open an image file.
 
     Args:
-        fp: File path, file object, or bytes
-        mode: Optional mode hint TODO: implement
-        formats: Optional list of formats TODO: implement
+        fp: File path (str or os.PathLike), bytes-like object, or binary
+            file-like object with a read() method
+        mode: Must be "r" (or None); only reading is supported
+        formats: List or tuple of format names the image may be decoded as.
+            None allows every supported format.
+        max_width: Reject images wider than this many pixels
+        max_height: Reject images taller than this many pixels
+        max_alloc: Maximum number of bytes the decoder may allocate
+        exif_transpose: Rotate the image upright according to its EXIF
+            orientation
 
     Returns:
         Image instance
     """
-    return Image.open(fp, mode, formats)
+    return Image.open(
+        fp,
+        mode,
+        formats,
+        max_width=max_width,
+        max_height=max_height,
+        max_alloc=max_alloc,
+        exif_transpose=exif_transpose,
+    )
 
 
 def new(
@@ -53,16 +74,19 @@ def new(
 
 
 def save(
-    image: Image, fp: Union[str, Path], format: Optional[str] = None, **options
+    image: Image,
+    fp: Union[str, Path, BinaryIO, bytearray],
+    format: Optional[str] = None,
+    **options,
 ) -> None:
     """
-    Save an image to a file.
+    Save an image to a file or buffer.
 
     Args:
         image: Image instance to save
-        fp: File path to save to
+        fp: File path, bytearray, or file-like object with a write() method
         format: Image format (e.g., 'JPEG', 'PNG')
-        **options: Additional save options (not yet implemented)
+        **options: Format-specific encoder options, see Image.save()
     """
     image.save(fp, format, **options)
 
@@ -112,7 +136,7 @@ def rotate(image: Image, angle: float, expand: bool = False) -> Image:
     Returns:
         New rotated Image instance
     """
-    return image.rotate(angle, expand)
+    return image.rotate(angle, expand=expand)
 
 
 def convert(image: Image, mode: str) -> Image:
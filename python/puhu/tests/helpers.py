@@ -0,0 +1,27 @@
+"""
+Helpers shared by the tests
+"""
+
+import io
+import struct
+import zlib
+
+
+def saved(img, format, **options):
+    """Bytes of an image saved in a format."""
+    buffer = io.BytesIO()
+    img.save(buffer, format=format, **options)
+    return buffer.getvalue()
+
+
+def pixels(img):
+    """Tuples of the channels of every pixel, in row-major order."""
+    data = img.to_bytes()
+    channels = len(data) // (img.width * img.height)
+    return [tuple(data[i : i + channels]) for i in range(0, len(data), channels)]
+
+
+def png_chunk(kind, data):
+    """PNG chunk of a type and data, with its length and CRC."""
+    body = kind + data
+    return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body))
@@ -0,0 +1,245 @@
+"""
+Tests for saving animations with save_all and append_images
+"""
+
+import io
+
+import pytest
+
+from puhu import Image, ImageSequence
+from puhu._core import PuhuProcessingError, UnsupportedFormatError
+
+from .helpers import saved
+
+COLORS = [(255, 0, 0), (0, 255, 0), (0, 0, 255)]
+
+
+def _save_all(img, format, **options):
+    return saved(img, format, save_all=True, **options)
+
+
+def _frames(mode="RGB", size=(4, 4)):
+    """Solid frames of each color in COLORS."""
+    alpha = (255,) if mode == "RGBA" else ()
+    return [Image.new(mode, size, color + alpha) for color in COLORS]
+
+
+def _decoded(data):
+    """Bytes of every decoded frame."""
+    img = Image.open(data)
+    return [frame.to_bytes() for frame in ImageSequence.Iterator(img)]
+
+
+def _marked(color, pixel):
+    """4x4 RGB frame of one color with the given pixel set to white."""
+    img = Image.new("RGB", (4, 4), color)
+    white = Image.new("RGB", (1, 1), "white")
+    img.paste(white, pixel)
+    return img
+
+
+class TestSaveAll:
+    """Test animations round trip through every animated format."""
+
+    @pytest.mark.parametrize("format", ["GIF", "PNG", "WEBP"])
+    def test_round_trip(self, format):
+        """Test frames, durations and the loop count are stored."""
+        first, *rest = _frames()
+        data = _save_all(
+            first, format, append_images=rest, duration=[100, 200, 300], loop=2
+        )
+
+        img = Image.open(data)
+        assert img.n_frames == 3
+        assert img.is_animated
+        assert img.info["loop"] == 2
+        for index, color in enumerate(COLORS):
+            img.seek(index)
+            assert img.info["duration"] == (index + 1) * 100
+            assert img.convert("RGB").to_bytes() == bytes(color) * 16
+
+    @pytest.mark.parametrize("format", ["GIF", "PNG", "WEBP"])
+    def test_single_duration(self, format):
+        """Test a single duration applies to every frame."""
+        first, *rest = _frames()
+        img = Image.open(_save_all(first, format, append_images=rest, duration=80))
+        for index in range(3):
+            img.seek(index)
+            assert img.info["duration"] == 80
+
+    def test_without_save_all(self):
+        """Test append_images is ignored unless save_all is True."""
+        first, *rest = _frames()
+        buffer = io.BytesIO()
+        first.save(buffer, format="GIF", append_images=rest)
+        assert Image.open(buffer.getvalue()).n_frames == 1
+
+    def test_single_frame(self):
+        """Test save_all with one frame saves a still image."""
+        data = _save_all(Image.new("RGB", (4, 4), "red"), "PNG")
+        assert Image.open(data).n_frames == 1
+
+    def test_resave_animation(self):
+        """Test saving an opened animation keeps its frames and timing."""
+        first, *rest = _frames()
+        gif = _save_all(first, "GIF", append_images=rest, duration=[40, 50, 60])
+        img = Image.open(gif)
+        img.seek(2)
+
+        png = _save_all(img, "PNG")
+        assert _decoded(png) == _decoded(gif)
+        durations = [
+            frame.info["duration"] for frame in ImageSequence.Iterator(Image.open(png))
+        ]
+        assert durations == [40, 50, 60]
+        assert img.tell() == 2
+
+    def test_different_sizes(self):
+        """Test frames of another size or mode are placed on an RGBA canvas."""
+        first = Image.new("RGB", (4, 2), "red")
+        small = Image.new("L", (2, 1), 255)
+        img = Image.open(_save_all(first, "PNG", append_images=[small]))
+        assert img.size == (4, 2)
+        assert img.mode == "RGBA"
+        img.seek(1)
+        pixels = img.to_bytes()
+        assert pixels[:8] == bytes([255] * 8)
+        assert pixels[8:16] == bytes(8)
+
+    def test_gif_transparency(self):
+        """Test transparent pixels are stored with a transparent index."""
+        first = Image.new("RGBA", (2, 1), (0, 0, 0, 0))
+        second = Image.new("RGBA", (2, 1), (0, 255, 0, 255))
+        img = Image.open(_save_all(first, "GIF", append_images=[second]))
+        assert img.to_bytes()[3] == 0
+        img.seek(1)
+        assert img.to_bytes() == bytes([0, 255, 0, 255] * 2)
+
+    def test_gif_many_colors(self):
+        """Test frames with more than 256 colors get their own palette."""
+        gradient = Image.new("RGB", (24, 24))
+        for y in range(24):
+            for x in range(24):
+                gradient.paste((x * 10, y * 10, 128), (x, y, x + 1, y + 1))
+        img = Image.open(
+            _save_all(gradient, "GIF", append_images=[Image.new("RGB", (24, 24))])
+        )
+        assert img.n_frames == 2
+        decoded = img.convert("RGB").to_bytes()
+        errors = [abs(a - b) for a, b in zip(decoded, gradient.to_bytes())]
+        assert sum(errors) / len(errors) < 10
+
+
+class TestDisposal:
+    """Test disposal methods are written for each frame."""
+
+    @pytest.mark.parametrize(
+        "format,disposal", [("GIF", [2, 1, 3]), ("PNG", [1, 0, 2])]
+    )
+    def test_per_frame(self, format, disposal):
+        """Test a disposal value per frame is read back."""
+        first, *rest = _frames()
+        img = Image.open(
+            _save_all(first, format, append_images=rest, disposal=disposal)
+        )
+        for index, value in enumerate(disposal):
+            img.seek(index)
+            assert img.disposal == value
+
+    def test_gif_uncovered_pixels(self):
+        """Test transparent pixels do not show the previous frame by default."""
+        first = Image.new("RGBA", (4, 1), (255, 0, 0, 255))
+        second = Image.new("RGBA", (4, 1), (0, 0, 255, 255))
+        second.paste((0, 0, 0, 0), (0, 0, 2, 1))
+        for optimize in [False, True]:
+            data = _save_all(first, "GIF", append_images=[second], optimize=optimize)
+            img = Image.open(data)
+            assert img.disposal == 2
+            img.seek(1)
+            assert img.to_bytes()[3::4] == bytes([0, 0, 255, 255])
+            assert img.to_bytes()[8:11] == bytes([0, 0, 255])
+
+    def test_gif_default_kept(self):
+        """Test frames covering the previous one keep disposal 0."""
+        first, *rest = _frames("RGBA")
+        img = Image.open(_save_all(first, "GIF", append_images=rest))
+        assert img.disposal == 0
+
+    def test_invalid(self):
+        """Test disposal values outside the format's range are rejected."""
+        first, *rest = _frames()
+        with pytest.raises(PuhuProcessingError):
+            _save_all(first, "PNG", append_images=rest, disposal=3)
+
+    def test_unsupported(self):
+        """Test disposal is rejected for formats without it."""
+        first, *rest = _frames()
+        with pytest.raises(UnsupportedFormatError):
+            _save_all(first, "WEBP", append_images=rest, disposal=1)
+
+
+class TestOptimize:
+    """Test frame-difference optimization."""
+
+    @pytest.mark.parametrize("format", ["GIF", "PNG", "WEBP"])
+    def test_frames_unchanged(self, format):
+        """Test cropped frames decode to the same pixels."""
+        frames = [
+            _marked("red", (0, 0)),
+            _marked("red", (2, 3)),
+            _marked("red", (2, 3)),
+            Image.new("RGB", (4, 4), "blue"),
+        ]
+        plain = _save_all(frames[0], format, append_images=frames[1:])
+        optimized = _save_all(
+            frames[0], format, append_images=frames[1:], optimize=True
+        )
+        assert _decoded(optimized) == _decoded(plain)
+        img = Image.open(optimized)
+        assert img.n_frames == 4
+        img.seek(3)
+        assert img.convert("RGB").to_bytes() == bytes([0, 0, 255]) * 16
+
+    def test_gif_smaller(self):
+        """Test frames with a small change are stored cropped."""
+        frames = [_marked("red", (x, x)) for x in range(4)]
+        frames = [frame.resize((64, 64), "nearest") for frame in frames]
+        plain = _save_all(frames[0], "GIF", append_images=frames[1:])
+        optimized = _save_all(
+            frames[0], "GIF", append_images=frames[1:], optimize=True
+        )
+        assert len(optimized) < len(plain)
+
+    def test_disposed_frames_kept_whole(self):
+        """Test frames after a disposed frame are not cropped."""
+        frames = [_marked("red", (0, 0)), _marked("red", (1, 1))]
+        data = _save_all(
+            frames[0], "GIF", append_images=frames[1:], disposal=2, optimize=True
+        )
+        assert _decoded(data)[1] == frames[1].convert("RGBA").to_bytes()
+
+
+class TestErrors:
+    """Test invalid animation options."""
+
+    def test_duration_count(self):
+        """Test a duration list must have one value per frame."""
+        first, *rest = _frames()
+        with pytest.raises(PuhuProcessingError):
+            _save_all(first, "GIF", append_images=rest, duration=[100, 200])
+
+    def test_negative_duration(self):
+        """Test durations must not be negative."""
+        first, *rest = _frames()
+        with pytest.raises(PuhuProcessingError):
+            _save_all(first, "GIF", append_images=rest, duration=-1)
+
+    def test_unsupported_format(self):
+        """Test formats without animation reject several frames."""
+        first, *rest = _frames()
+        with pytest.raises(UnsupportedFormatError):
+            _save_all(first, "JPEG", append_images=rest)
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
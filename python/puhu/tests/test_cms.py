@@ -0,0 +1,113 @@
+"""
+Tests for ICC colour management with ImageCms
+"""
+
+import io
+
+import pytest
+
+from puhu import Image, ImageCms
+from puhu._core import PuhuProcessingError
+
+
+def _pixel(img):
+    return tuple(img.to_bytes()[: len(img.mode)])
+
+
+class TestProfiles:
+    """Test creating and reading profiles."""
+
+    @pytest.mark.parametrize(
+        "name", ["sRGB", "DISPLAY_P3", "ADOBE_RGB", "PROPHOTO_RGB", "BT2020"]
+    )
+    def test_builtin(self, name):
+        """Test built-in profiles can be created and serialized."""
+        profile = ImageCms.createProfile(name)
+        assert profile.profile.color_space == "RGB"
+        data = profile.tobytes()
+        assert data[36:40] == b"acsp"
+        assert ImageCms.ImageCmsProfile(data).profile.color_space == "RGB"
+
+    def test_open_from_file(self):
+        """Test profiles can be read from file-like objects."""
+        data = ImageCms.createProfile("sRGB").tobytes()
+        profile = ImageCms.getOpenProfile(io.BytesIO(data))
+        assert profile.tobytes() == data
+        assert ImageCms.getProfileDescription(profile)
+
+    def test_unknown_builtin(self):
+        """Test unknown profile names raise PyCMSError."""
+        with pytest.raises(ImageCms.PyCMSError):
+            ImageCms.createProfile("LAB")
+
+    def test_invalid_profile(self):
+        """Test invalid profile data raises PyCMSError."""
+        with pytest.raises(ImageCms.PyCMSError):
+            ImageCms.ImageCmsProfile(b"not an icc profile")
+
+
+class TestTransforms:
+    """Test converting pixels between profiles."""
+
+    def test_srgb_to_p3(self):
+        """Test sRGB red maps to its Display P3 coordinates."""
+        srgb = ImageCms.createProfile("sRGB")
+        p3 = ImageCms.createProfile("DISPLAY_P3")
+        img = Image.new("RGB", (4, 4), (255, 0, 0))
+
+        converted = ImageCms.profileToProfile(
+            img, srgb, p3, ImageCms.Intent.RELATIVE_COLORIMETRIC
+        )
+        r, g, b = _pixel(converted)
+        assert abs(r - 234) <= 2 and abs(g - 51) <= 2 and abs(b - 35) <= 2
+
+    def test_identity(self):
+        """Test converting to the same profile keeps pixel values."""
+        srgb = ImageCms.createProfile("sRGB")
+        img = Image.new("RGB", (4, 4), (12, 128, 240))
+        converted = ImageCms.profileToProfile(img, srgb, srgb)
+        assert all(abs(a - b) <= 1 for a, b in zip(_pixel(converted), (12, 128, 240)))
+
+    def test_output_profile_embedded(self):
+        """Test the result carries the output profile and save embeds it."""
+        p3 = ImageCms.createProfile("DISPLAY_P3")
+        img = Image.new("RGB", (4, 4), "red")
+        converted = ImageCms.profileToProfile(img, ImageCms.createProfile("sRGB"), p3)
+        assert converted.info["icc_profile"] == p3.tobytes()
+
+        buffer = io.BytesIO()
+        converted.save(buffer, format="PNG")
+        assert Image.open(buffer.getvalue()).info["icc_profile"] == p3.tobytes()
+
+    def test_alpha_and_output_mode(self):
+        """Test alpha is kept and outputMode changes the layout."""
+        srgb = ImageCms.createProfile("sRGB")
+        p3 = ImageCms.createProfile("DISPLAY_P3")
+        img = Image.new("RGBA", (2, 2), (255, 0, 0, 77))
+
+        assert _pixel(ImageCms.profileToProfile(img, srgb, p3))[3] == 77
+        assert ImageCms.profileToProfile(img, srgb, p3, outputMode="RGB").mode == "RGB"
+
+    def test_reuse_transform(self):
+        """Test a built transform applies to several images, in place."""
+        transform = ImageCms.buildTransform(
+            ImageCms.createProfile("DISPLAY_P3"),
+            ImageCms.createProfile("sRGB"),
+            "RGB",
+            "RGB",
+        )
+        images = [Image.new("RGB", (3, 3), color) for color in ("red", "blue")]
+        for img in images:
+            assert ImageCms.applyTransform(img, transform, inPlace=True) is None
+            assert "icc_profile" in img.info
+
+    def test_mode_mismatch(self):
+        """Test images must be in the transform's input mode."""
+        srgb = ImageCms.createProfile("sRGB")
+        transform = ImageCms.buildTransform(srgb, srgb, "RGB", "RGB")
+        with pytest.raises(PuhuProcessingError, match="input mode"):
+            transform.apply(Image.new("L", (2, 2)))
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,214 @@
+"""
+Tests for "CMYK" and "YCbCr" image modes
+"""
+
+import struct
+
+import pytest
+
+from puhu import Image, ImageCms, ImageMode
+from puhu._core import PuhuProcessingError
+
+from .helpers import saved
+
+
+def _without_adobe_marker(data):
+    """JPEG data with its Adobe APP14 segment removed."""
+    # The marker and segment length precede the identifier
+    start = data.index(b"Adobe") - 4
+    assert data[start : start + 2] == b"\xff\xee"
+    (length,) = struct.unpack(">H", data[start + 2 : start + 4])
+    return data[:start] + data[start + 2 + length :]
+
+
+def _cmyk_profile():
+    """Minimal CMYK printer profile whose colors depend on the black ink only."""
+
+    def s15(value):
+        return struct.pack(">i", round(value * 65536))
+
+    identity = bytes(range(256))
+    # 2x2x2x2 grid of Lab colors, white without black ink and black with it
+    clut = b"".join(bytes([255 * (1 - (i & 1)), 128, 128]) for i in range(16))
+    matrix = b"".join(s15(v) for v in (1, 0, 0, 0, 1, 0, 0, 0, 1))
+    lut = b"mft1" + bytes(4) + bytes([4, 3, 2, 0]) + matrix
+    lut += identity * 4 + clut + identity * 3
+    d50 = s15(0.9642) + s15(1.0) + s15(0.8249)
+    tags = [(b"A2B0", lut), (b"wtpt", b"XYZ " + bytes(4) + d50)]
+
+    offset = 128 + 4 + 12 * len(tags)
+    table, data = struct.pack(">I", len(tags)), b""
+    for signature, body in tags:
+        body += bytes(-len(body) % 4)
+        table += signature + struct.pack(">II", offset + len(data), len(body))
+        data += body
+    header = struct.pack(">I", offset + len(data)) + bytes(4) + bytes([2, 0x10, 0, 0])
+    header += b"prtrCMYKLab " + bytes(12) + b"acsp" + bytes(28) + d50 + bytes(48)
+    return header + table + data
+
+
+class TestModes:
+    """Test creating "CMYK" and "YCbCr" images."""
+
+    @pytest.mark.parametrize("mode", ["CMYK", "YCbCr"])
+    def test_new(self, mode):
+        """Test each mode is created and reported as itself."""
+        img = Image.new(mode, (4, 3))
+        assert img.mode == mode
+        assert f"mode={mode} " in repr(img)
+
+    def test_cmyk_default_is_white(self):
+        """Test new CMYK images have no ink."""
+        assert Image.new("CMYK", (2, 1)).to_bytes() == bytes(8)
+
+    def test_color_names(self):
+        """Test color names are converted to the mode."""
+        assert Image.new("CMYK", (1, 1), "red").to_bytes() == bytes([0, 255, 255, 0])
+        assert Image.new("YCbCr", (1, 1), "white").to_bytes() == bytes([255, 128, 128])
+
+    def test_ink_tuple(self):
+        """Test tuples give the ink amounts, with no black ink by default."""
+        assert Image.new("CMYK", (1, 1), (1, 2, 3, 4)).to_bytes() == bytes([1, 2, 3, 4])
+        assert Image.new("CMYK", (1, 1), (1, 2, 3)).to_bytes() == bytes([1, 2, 3, 0])
+
+    def test_constants(self):
+        """Test the ImageMode constants."""
+        assert Image.new(ImageMode.CMYK, (1, 1)).mode == "CMYK"
+        assert Image.new(ImageMode.YCbCr, (1, 1)).mode == "YCbCr"
+
+
+class TestConvert:
+    """Test converting to and from "CMYK" and "YCbCr"."""
+
+    def test_rgb_to_cmyk(self):
+        """Test RGB colors become inks without black."""
+        img = Image.new("RGB", (1, 1), (255, 128, 0)).convert("CMYK")
+        assert img.mode == "CMYK"
+        assert img.to_bytes() == bytes([0, 127, 255, 0])
+
+    def test_cmyk_to_rgb(self):
+        """Test black ink darkens every channel."""
+        img = Image.new("CMYK", (1, 1), (0, 255, 0, 128))
+        assert img.convert("RGB").to_bytes() == bytes([127, 0, 127])
+
+    def test_ycbcr_round_trip(self):
+        """Test RGB colors survive a round trip through YCbCr."""
+        img = Image.new("RGB", (1, 1), (200, 30, 90))
+        ycbcr = img.convert("YCbCr")
+        assert ycbcr.to_bytes() == bytes([88, 129, 208])
+        restored = ycbcr.convert("RGB").to_bytes()
+        assert all(abs(a - b) <= 1 for a, b in zip(restored, img.to_bytes()))
+
+    def test_between_cmyk_and_ycbcr(self):
+        """Test converting between the modes goes through RGB."""
+        img = Image.new("CMYK", (1, 1), (0, 0, 0, 255)).convert("YCbCr")
+        assert img.to_bytes() == bytes([0, 128, 128])
+
+    def test_cmyk_to_gray(self):
+        """Test CMYK images convert to grayscale from their colors."""
+        img = Image.new("CMYK", (1, 1), (0, 0, 0, 255))
+        assert img.convert("L").to_bytes() == bytes([0])
+
+    def test_paste(self):
+        """Test pasted images are converted to inks, with black not used as alpha."""
+        img = Image.new("CMYK", (2, 1), (0, 0, 0, 255))
+        img.paste(Image.new("RGB", (1, 1), (255, 0, 0)), (1, 0))
+        assert img.to_bytes() == bytes([0, 0, 0, 255, 0, 255, 255, 0])
+
+
+class TestJpeg:
+    """Test reading and writing CMYK JPEG files."""
+
+    def test_round_trip(self):
+        """Test CMYK JPEGs are read back as their inks."""
+        img = Image.new("CMYK", (8, 8), (10, 20, 200, 40))
+        data = saved(img, "JPEG", quality=100)
+        assert b"Adobe" in data
+        result = Image.open(data)
+        assert result.mode == "CMYK"
+        assert result.to_bytes()[:4] == bytes([10, 20, 200, 40])
+
+    def test_without_adobe_marker(self):
+        """Test samples are only inverted when the file has an Adobe marker."""
+        data = saved(Image.new("CMYK", (8, 8), (10, 20, 200, 40)), "JPEG", quality=100)
+        result = Image.open(_without_adobe_marker(data))
+        assert result.mode == "CMYK"
+        assert result.to_bytes()[:4] == bytes([245, 235, 55, 215])
+
+    def test_mode_without_decoding(self):
+        """Test the mode is known before the pixels are decoded."""
+        data = saved(Image.new("CMYK", (8, 8)), "JPEG")
+        assert repr(Image.open(data)) == "<Image size=8x8 mode=CMYK format=JPEG>"
+
+    def test_ycbcr_saved_as_rgb(self):
+        """Test YCbCr images are written with their RGB colors."""
+        img = Image.new("RGB", (8, 8), (0, 0, 255)).convert("YCbCr")
+        result = Image.open(saved(img, "PNG"))
+        assert result.mode == "RGB"
+        assert result.to_bytes() == img.convert("RGB").to_bytes()
+
+
+class TestTiff:
+    """Test reading and writing CMYK TIFF files."""
+
+    def test_round_trip(self):
+        """Test CMYK TIFFs keep their inks exactly."""
+        img = Image.new("CMYK", (3, 2), (1, 2, 3, 4))
+        result = Image.open(saved(img, "TIFF"))
+        assert result.mode == "CMYK"
+        assert result.to_bytes() == img.to_bytes()
+
+    def test_save_all(self):
+        """Test every CMYK page saved with save_all keeps its inks."""
+        img = Image.new("CMYK", (3, 2), (1, 2, 3, 4))
+        pages = [Image.new("CMYK", (2, 2), (250, 0, 9, 0)), Image.new("RGB", (1, 1))]
+        result = Image.open(saved(img, "TIFF", save_all=True, append_images=pages))
+        assert result.n_frames == 3
+        assert result.mode == "CMYK"
+        assert result.to_bytes() == img.to_bytes()
+        result.seek(1)
+        assert result.mode == "CMYK"
+        assert result.to_bytes() == pages[0].to_bytes()
+        result.seek(2)
+        assert result.mode == "RGB"
+        result.seek(0)
+        assert result.mode == "CMYK"
+        assert result.to_bytes() == img.to_bytes()
+
+    @pytest.mark.parametrize("format", ["PNG", "BMP", "GIF"])
+    def test_unsupported(self, format):
+        """Test formats without CMYK reject CMYK images."""
+        with pytest.raises(PuhuProcessingError):
+            saved(Image.new("CMYK", (2, 2)), format)
+
+
+class TestProfiles:
+    """Test ICC-aware conversion of CMYK images."""
+
+    def test_convert_through_profile(self):
+        """Test an embedded CMYK profile is used to convert to RGB."""
+        profile = _cmyk_profile()
+        img = Image.new("CMYK", (1, 1), (255, 0, 0, 0))
+        result = Image.open(saved(img, "TIFF", icc_profile=profile)).convert("RGB")
+        # The profile ignores cyan, where the plain conversion gives (0, 255, 255)
+        assert result.to_bytes() == bytes([255, 255, 255])
+        assert result.info["icc_profile"][16:20] == b"RGB "
+
+    def test_convert_without_profile(self):
+        """Test images without a profile use the plain conversion."""
+        img = Image.open(saved(Image.new("CMYK", (1, 1), (255, 0, 0, 0)), "TIFF"))
+        assert img.convert("RGB").to_bytes() == bytes([0, 255, 255])
+
+    def test_profile_to_profile(self):
+        """Test ImageCms transforms accept CMYK images."""
+        profile = ImageCms.ImageCmsProfile(_cmyk_profile())
+        img = Image.new("CMYK", (1, 1), (0, 0, 0, 255))
+        result = ImageCms.profileToProfile(
+            img, profile, ImageCms.createProfile("sRGB"), outputMode="RGB"
+        )
+        assert result.mode == "RGB"
+        assert max(result.to_bytes()) <= 3
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,231 @@
+"""
+Tests for matching colors to palette entries with RGB, CIEDE2000 and OKLab
+distances
+"""
+
+import math
+import random
+
+import pytest
+
+from puhu import ColorMetric, Image
+from puhu._core import PuhuProcessingError
+
+METRICS = [ColorMetric.RGB, ColorMetric.CIEDE2000, ColorMetric.OKLAB]
+
+
+def _random_image(width, height, seed):
+    """RGB image of random pixels, and the pixels as a list of tuples."""
+    rng = random.Random(seed)
+    pixels = [
+        (rng.randrange(256), rng.randrange(256), rng.randrange(256))
+        for _ in range(width * height)
+    ]
+    img = Image.new("RGB", (width, height))
+    for i, color in enumerate(pixels):
+        x, y = i % width, i // width
+        img.paste(color, (x, y, x + 1, y + 1))
+    return img, pixels
+
+
+def _random_palette(entries, seed):
+    rng = random.Random(seed)
+    palette = [rng.randrange(256) for _ in range(entries * 3)]
+    img = Image.new("P", (1, 1))
+    img.putpalette(palette)
+    return img, [tuple(palette[i : i + 3]) for i in range(0, len(palette), 3)]
+
+
+def _oklab(color):
+    def linear(v):
+        v /= 255
+        return v / 12.92 if v <= 0.04045 else ((v + 0.055) / 1.055) ** 2.4
+
+    r, g, b = (linear(v) for v in color)
+    l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b) ** (1 / 3)
+    m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b) ** (1 / 3)
+    s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b) ** (1 / 3)
+    return (
+        0.2104542553 * l + 0.793617785 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.428592205 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.808675766 * s,
+    )
+
+
+def _lab(color):
+    def linear(v):
+        v /= 255
+        return v / 12.92 if v <= 0.04045 else ((v + 0.055) / 1.055) ** 2.4
+
+    def f(t):
+        return t ** (1 / 3) if t > 216 / 24389 else (24389 / 27 * t + 16) / 116
+
+    r, g, b = (linear(v) for v in color)
+    x = f((0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047)
+    y = f(0.2126729 * r + 0.7151522 * g + 0.0721750 * b)
+    z = f((0.0193339 * r + 0.119192 * g + 0.9503041 * b) / 1.08883)
+    return 116 * y - 16, 500 * (x - y), 200 * (y - z)
+
+
+def _ciede2000(lab1, lab2):
+    """CIEDE2000 difference, following Sharma, Wu and Dalal (2005)."""
+    (l1, a1, b1), (l2, a2, b2) = lab1, lab2
+    c7 = ((math.hypot(a1, b1) + math.hypot(a2, b2)) / 2) ** 7
+    g = 0.5 * (1 - math.sqrt(c7 / (c7 + 25**7)))
+    a1, a2 = a1 * (1 + g), a2 * (1 + g)
+    c1, c2 = math.hypot(a1, b1), math.hypot(a2, b2)
+    h1 = math.degrees(math.atan2(b1, a1)) % 360 if a1 or b1 else 0
+    h2 = math.degrees(math.atan2(b2, a2)) % 360 if a2 or b2 else 0
+
+    dh = 0 if c1 * c2 == 0 else (h2 - h1 + 180) % 360 - 180
+    dh = 2 * math.sqrt(c1 * c2) * math.sin(math.radians(dh / 2))
+    if c1 * c2 == 0:
+        h = h1 + h2
+    elif abs(h1 - h2) <= 180:
+        h = (h1 + h2) / 2
+    else:
+        h = (h1 + h2 + 360) / 2 if h1 + h2 < 360 else (h1 + h2 - 360) / 2
+    t = (
+        1
+        - 0.17 * math.cos(math.radians(h - 30))
+        + 0.24 * math.cos(math.radians(2 * h))
+        + 0.32 * math.cos(math.radians(3 * h + 6))
+        - 0.20 * math.cos(math.radians(4 * h - 63))
+    )
+    l, c = (l1 + l2) / 2, (c1 + c2) / 2
+    r_c = 2 * math.sqrt(c**7 / (c**7 + 25**7))
+    r_t = -math.sin(math.radians(60 * math.exp(-(((h - 275) / 25) ** 2)))) * r_c
+    dl = (l2 - l1) / (1 + 0.015 * (l - 50) ** 2 / math.sqrt(20 + (l - 50) ** 2))
+    dc = (c2 - c1) / (1 + 0.045 * c)
+    dh /= 1 + 0.015 * c * t
+    return math.sqrt(dl**2 + dc**2 + dh**2 + r_t * dc * dh)
+
+
+def _brute_force(pixels, entries, space):
+    """Index of the nearest entry of each pixel by a linear scan."""
+    points = [space(entry) for entry in entries]
+
+    def nearest(color):
+        point = space(color)
+        distances = [sum((a - b) ** 2 for a, b in zip(point, p)) for p in points]
+        return distances.index(min(distances))
+
+    return [nearest(color) for color in pixels]
+
+
+class TestSearch:
+    """Test the palette index finds the nearest entries."""
+
+    @pytest.mark.parametrize("entries", [1, 2, 16, 216, 256])
+    def test_rgb_matches_linear_scan(self, entries):
+        """Test RGB matching agrees with an exhaustive search."""
+        img, pixels = _random_image(32, 32, entries)
+        palette, colors = _random_palette(entries, entries + 1)
+        result = img.quantize(palette=palette, dither="NONE")
+        assert list(result.to_bytes()) == _brute_force(pixels, colors, tuple)
+
+    def test_oklab_matches_linear_scan(self):
+        """Test OKLab matching agrees with an exhaustive search."""
+        img, pixels = _random_image(32, 32, 7)
+        palette, colors = _random_palette(64, 8)
+        result = img.quantize(palette=palette, dither="NONE", metric="OKLAB")
+        assert list(result.to_bytes()) == _brute_force(pixels, colors, _oklab)
+
+    def test_ciede2000_near_linear_scan(self):
+        """Test CIEDE2000 matching stays close to an exhaustive search.
+
+        Only the entries nearest in CIELAB are ranked by CIEDE2000, so a
+        pixel may get an entry slightly further than the nearest one.
+        """
+        img, pixels = _random_image(32, 32, 9)
+        palette, colors = _random_palette(64, 10)
+        result = img.quantize(palette=palette, dither="NONE", metric="CIEDE2000")
+        entries = [_lab(color) for color in colors]
+        excess = []
+        for pixel, index in zip(pixels, result.to_bytes()):
+            distances = [_ciede2000(_lab(pixel), entry) for entry in entries]
+            excess.append(distances[index] - min(distances))
+        assert sum(e < 1e-4 for e in excess) / len(excess) > 0.95
+        assert sum(excess) / len(excess) < 0.1
+
+    def test_ties_prefer_first_entry(self):
+        """Test equally near entries resolve to the lowest index."""
+        palette = Image.new("P", (1, 1))
+        palette.putpalette([0, 0, 0, 100, 100, 100, 100, 100, 100, 200, 200, 200])
+        img = Image.new("RGB", (1, 1), (100, 100, 100))
+        assert img.quantize(palette=palette, dither="NONE").to_bytes() == b"\x01"
+
+    def test_web_palette(self):
+        """Test web colors map to their own entries with every metric."""
+        img = Image.new("RGB", (6, 1))
+        for x in range(6):
+            img.paste((x * 51, 255 - x * 51, 102), (x, 0, x + 1, 1))
+        for metric in METRICS:
+            result = img.convert("P", palette="WEB", dither="NONE", metric=metric)
+            assert result.convert("RGB").to_bytes() == img.to_bytes()
+
+
+class TestMetrics:
+    """Test choosing the color distance."""
+
+    def test_skin_tone(self):
+        """Test perceptual distances prefer a skin tone over a closer gray."""
+        palette = Image.new("P", (1, 1))
+        palette.putpalette([128, 128, 128, 200, 120, 100])
+        img = Image.new("RGB", (1, 1), (160, 128, 120))
+        rgb = img.quantize(palette=palette, dither="NONE")
+        lab = img.quantize(palette=palette, dither="NONE", metric="CIEDE2000")
+        assert rgb.to_bytes() == b"\x00"
+        assert lab.to_bytes() == b"\x01"
+
+    def test_default_is_rgb(self):
+        """Test RGB distance is used unless another is given."""
+        img, _ = _random_image(16, 16, 3)
+        assert img.convert("P").to_bytes() == (
+            img.convert("P", metric=ColorMetric.RGB).to_bytes()
+        )
+
+    def test_case_insensitive(self):
+        """Test metric names are case-insensitive, with LAB for CIEDE2000."""
+        img, _ = _random_image(16, 16, 4)
+        lab = img.convert("P", metric="CIEDE2000").to_bytes()
+        assert img.convert("P", metric="ciede2000").to_bytes() == lab
+        assert img.convert("P", metric="lab").to_bytes() == lab
+
+    @pytest.mark.parametrize("metric", METRICS)
+    def test_dither(self, metric):
+        """Test every metric keeps the average color when dithering."""
+        img = Image.new("RGB", (16, 16), (128, 128, 128))
+        for dither in ["FLOYDSTEINBERG", "SIERRA", "BAYER4"]:
+            result = img.convert("P", dither=dither, metric=metric).convert("L")
+            mean = sum(result.to_bytes()) / 256
+            assert abs(mean - 128) < 16
+
+    @pytest.mark.parametrize("metric", METRICS)
+    def test_transparency(self, metric):
+        """Test transparent pixels keep the transparent entry."""
+        img = Image.new("RGBA", (4, 1), (200, 120, 100, 255))
+        img.paste((200, 120, 100, 0), (0, 0, 2, 1))
+        result = img.convert("P", palette="ADAPTIVE", metric=metric)
+        transparency = result.info["transparency"]
+        data = result.to_bytes()
+        assert data[:2] == bytes([transparency] * 2)
+        assert transparency not in data[2:]
+
+
+class TestErrors:
+    """Test invalid metrics."""
+
+    def test_convert(self):
+        """Test convert() rejects unknown metrics."""
+        with pytest.raises(PuhuProcessingError):
+            Image.new("RGB", (4, 4)).convert("P", metric="HSV")
+
+    def test_quantize(self):
+        """Test quantize() rejects unknown metrics."""
+        with pytest.raises(PuhuProcessingError):
+            Image.new("RGB", (4, 4)).quantize(4, metric="CIE76")
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,161 @@
+"""
+Tests for error-diffusion and ordered dithering in convert() and quantize()
+"""
+
+import pytest
+
+from puhu import Dither, Image
+from puhu._core import PuhuProcessingError
+
+KERNELS = [
+    Dither.FLOYDSTEINBERG,
+    Dither.ATKINSON,
+    Dither.JARVIS_JUDICE_NINKE,
+    Dither.STUCKI,
+    Dither.SIERRA,
+    Dither.SIERRA_TWO_ROW,
+    Dither.SIERRA_LITE,
+]
+ORDERED = [Dither.BAYER2, Dither.BAYER4, Dither.BAYER8, Dither.ORDERED]
+
+
+def _mean(img):
+    data = img.to_bytes()
+    return sum(data) / len(data)
+
+
+def _gradient(width=64, height=16):
+    """L image getting lighter from left to right."""
+    img = Image.new("L", (width, height))
+    for x in range(width):
+        img.paste(x * 255 // (width - 1), (x, 0, x + 1, height))
+    return img
+
+
+class TestBilevel:
+    """Test dithering to mode "1"."""
+
+    @pytest.mark.parametrize("dither", KERNELS + ORDERED)
+    def test_mean_preserved(self, dither):
+        """Test a flat gray keeps its average brightness."""
+        img = Image.new("L", (32, 32), 64)
+        result = img.convert("1", dither=dither)
+        assert set(result.to_bytes()) == {0, 255}
+        if dither != Dither.ATKINSON:
+            assert abs(_mean(result) - 64) < 8
+
+    def test_atkinson_contrast(self):
+        """Test Atkinson drops part of the error, keeping shadows darker."""
+        img = Image.new("L", (32, 32), 64)
+        atkinson = img.convert("1", dither=Dither.ATKINSON)
+        assert 0 < _mean(atkinson) < _mean(img.convert("1"))
+
+    def test_bayer2_pattern(self):
+        """Test mid gray becomes a checkerboard with the 2x2 matrix."""
+        result = Image.new("L", (2, 2), 128).convert("1", dither="BAYER2")
+        assert result.to_bytes() == bytes([0, 255, 255, 0])
+
+    def test_bayer4_levels(self):
+        """Test a quarter gray lights a quarter of each 4x4 tile."""
+        result = Image.new("L", (4, 4), 64).convert("1", dither="BAYER4")
+        assert result.to_bytes().count(255) == 4
+
+    def test_ordered_is_tiled(self):
+        """Test ordered dithering repeats with the matrix size."""
+        result = Image.new("L", (16, 16), 100).convert("1", dither="BAYER8")
+        data = result.to_bytes()
+        rows = [data[y * 16 : y * 16 + 16] for y in range(16)]
+        assert all(row[:8] == row[8:] for row in rows)
+        assert rows[:8] == rows[8:]
+
+    def test_kernels_differ(self):
+        """Test each kernel produces its own pattern."""
+        img = _gradient()
+        patterns = {img.convert("1", dither=d).to_bytes() for d in KERNELS}
+        assert len(patterns) == len(KERNELS)
+
+    def test_no_dither(self):
+        """Test NONE thresholds at the middle gray."""
+        result = _gradient().convert("1", dither=Dither.NONE)
+        row = result.to_bytes()[:64]
+        assert row == bytes([0] * 32 + [255] * 32)
+
+    def test_pillow_constants(self):
+        """Test Pillow's integer dither constants."""
+        img = _gradient()
+        assert img.convert("1", dither=0).to_bytes() == (
+            img.convert("1", dither="NONE").to_bytes()
+        )
+        assert img.convert("1", dither=3).to_bytes() == img.convert("1").to_bytes()
+
+    def test_narrow_image(self):
+        """Test images one pixel wide or high."""
+        for size in [(1, 8), (8, 1), (1, 1)]:
+            img = Image.new("L", size, 200)
+            assert img.convert("1", dither="STUCKI").size == size
+
+
+class TestSerpentine:
+    """Test serpentine scanning."""
+
+    @pytest.mark.parametrize("dither", KERNELS)
+    def test_serpentine(self, dither):
+        """Test alternate rows are scanned in the other direction."""
+        img = Image.new("L", (32, 32), 77)
+        plain = img.convert("1", dither=dither)
+        serpentine = img.convert("1", dither=dither, serpentine=True)
+        assert serpentine.to_bytes() != plain.to_bytes()
+        assert abs(_mean(serpentine) - 77) < 20
+
+    def test_ordered_ignores_serpentine(self):
+        """Test serpentine scanning does not change ordered dithering."""
+        img = _gradient()
+        assert img.convert("1", dither="BAYER4", serpentine=True).to_bytes() == (
+            img.convert("1", dither="BAYER4").to_bytes()
+        )
+
+
+class TestPalette:
+    """Test dithering to palettes."""
+
+    @pytest.mark.parametrize("dither", KERNELS + ORDERED)
+    def test_web_palette(self, dither):
+        """Test a gray between web colors mixes its neighbours."""
+        img = Image.new("RGB", (16, 16), (128, 128, 128))
+        result = img.convert("P", palette="WEB", dither=dither).convert("L")
+        assert set(result.to_bytes()) == {102, 153}
+        assert abs(_mean(result) - 128) < 8
+
+    @pytest.mark.parametrize("dither", [Dither.ATKINSON, Dither.BAYER8])
+    def test_quantize(self, dither):
+        """Test quantize() accepts every dither method."""
+        source = Image.new("P", (1, 1))
+        source.putpalette([0, 0, 0, 255, 255, 255])
+        img = Image.new("RGB", (16, 16), (64, 64, 64))
+        result = img.quantize(palette=source, dither=dither, serpentine=True)
+        assert set(result.to_bytes()) == {0, 1}
+
+    def test_transparent_pixels(self):
+        """Test transparent pixels stay transparent when dithering."""
+        img = Image.new("RGBA", (8, 8), (128, 128, 128, 255))
+        img.paste((255, 255, 255, 0), (0, 0, 4, 8))
+        result = img.convert("P", palette="WEB", dither="STUCKI")
+        transparency = result.info["transparency"]
+        data = result.to_bytes()
+        for y in range(8):
+            assert set(data[y * 8 : y * 8 + 4]) == {transparency}
+            assert transparency not in data[y * 8 + 4 : y * 8 + 8]
+
+
+class TestErrors:
+    """Test invalid dither methods."""
+
+    @pytest.mark.parametrize("dither", ["BAYER3", "RASTERIZE", 2])
+    def test_unknown(self, dither):
+        """Test unknown methods are rejected."""
+        with pytest.raises(PuhuProcessingError):
+            Image.new("L", (4, 4)).convert("1", dither=dither)
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
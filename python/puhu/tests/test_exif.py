@@ -0,0 +1,190 @@
+"""
+Tests for reading and writing EXIF data
+"""
+
+import struct
+
+import pytest
+
+from puhu import Exif, Image
+from puhu._core import (
+    InvalidImageError,
+    PuhuProcessingError,
+    UnsupportedFormatError,
+)
+
+from .helpers import saved
+
+ORIENTATION = 0x0112
+COPYRIGHT = 0x8298
+EXIF_IFD = 0x8769
+GPS_IFD = 0x8825
+DATE_TIME_ORIGINAL = 0x9003
+GPS_LATITUDE_REF = 0x0001
+GPS_LATITUDE = 0x0002
+
+
+def _big_endian_exif():
+    """Build a big-endian EXIF block with orientation, copyright and GPS."""
+    copyright = b"(c) puhu\x00"
+    copyright_offset = 8 + 2 + 3 * 12 + 4
+    gps_offset = copyright_offset + len(copyright) + 1
+
+    ifd0 = struct.pack(">H", 3)
+    ifd0 += struct.pack(">HHI", ORIENTATION, 3, 1) + struct.pack(">HH", 6, 0)
+    ifd0 += struct.pack(">HHII", COPYRIGHT, 2, len(copyright), copyright_offset)
+    ifd0 += struct.pack(">HHII", GPS_IFD, 4, 1, gps_offset)
+    ifd0 += struct.pack(">I", 0)
+
+    gps = struct.pack(">H", 1)
+    gps += struct.pack(">HHI", GPS_LATITUDE_REF, 2, 2) + b"N\x00\x00\x00"
+    gps += struct.pack(">I", 0)
+
+    return b"MM\x00*" + struct.pack(">I", 8) + ifd0 + copyright + b"\x00" + gps
+
+
+def _photo_exif():
+    exif = Exif()
+    exif[ORIENTATION] = 1
+    exif[COPYRIGHT] = "(c) puhu"
+    exif.get_ifd(EXIF_IFD)[DATE_TIME_ORIGINAL] = "2024:05:01 12:00:00"
+    gps = exif.get_ifd(GPS_IFD)
+    gps[GPS_LATITUDE_REF] = "N"
+    gps[GPS_LATITUDE] = (41.0, 0.5, 0.25)
+    return exif
+
+
+class TestExifMapping:
+    """Test the Exif mapping."""
+
+    def test_parse_big_endian(self):
+        """Test Motorola byte order and IFD pointers."""
+        exif = Exif(_big_endian_exif())
+        assert exif[ORIENTATION] == 6
+        assert exif[COPYRIGHT] == "(c) puhu"
+        assert exif.get_ifd(GPS_IFD)[GPS_LATITUDE_REF] == "N"
+        assert sorted(exif) == [ORIENTATION, COPYRIGHT, GPS_IFD]
+
+    def test_value_types(self):
+        """Test values are converted to and from Python types."""
+        exif = _photo_exif()
+        exif[0x011A] = 72.0
+        exif[0x9286] = b"ASCII\x00\x00\x00hello"
+        exif = Exif(exif.tobytes())
+        assert exif[0x011A] == 72.0
+        assert exif[0x9286] == b"ASCII\x00\x00\x00hello"
+        assert exif.get_ifd(GPS_IFD)[GPS_LATITUDE] == (41.0, 0.5, 0.25)
+
+    def test_values_and_items(self):
+        """Test values() and items() follow the tag order."""
+        exif = _photo_exif()
+        assert list(exif.items())[:2] == [(ORIENTATION, 1), (COPYRIGHT, "(c) puhu")]
+        assert list(exif.values())[:2] == [1, "(c) puhu"]
+        gps = exif.get_ifd(GPS_IFD)
+        assert list(gps.items()) == [
+            (GPS_LATITUDE_REF, "N"),
+            (GPS_LATITUDE, (41.0, 0.5, 0.25)),
+        ]
+        assert len(list(exif.values())) == len(exif)
+
+    def test_missing_tag(self):
+        """Test missing tags raise KeyError."""
+        exif = Exif()
+        with pytest.raises(KeyError):
+            exif[ORIENTATION]
+        assert exif.get(ORIENTATION) is None
+        assert len(exif) == 0
+
+    def test_delete_gps(self):
+        """Test deleting the GPS pointer removes the GPS IFD."""
+        exif = _photo_exif()
+        assert GPS_IFD in exif
+        del exif[GPS_IFD]
+        assert GPS_IFD not in exif
+        assert len(exif.get_ifd(GPS_IFD)) == 0
+        assert exif[COPYRIGHT] == "(c) puhu"
+
+    def test_pointer_tags_are_read_only(self):
+        """Test IFD pointers cannot be assigned directly."""
+        with pytest.raises(PuhuProcessingError, match="get_ifd"):
+            Exif()[GPS_IFD] = 1234
+
+    def test_invalid_data(self):
+        """Test data without a TIFF header is rejected."""
+        with pytest.raises(InvalidImageError):
+            Exif(b"not exif")
+
+
+class TestExifRoundTrip:
+    """Test EXIF data is read from and written to image files."""
+
+    @pytest.mark.parametrize("format", ["JPEG", "PNG", "WEBP", "TIFF"])
+    def test_round_trip(self, format):
+        """Test getexif() returns the EXIF data written by save."""
+        data = saved(Image.new("RGB", (8, 8)), format, exif=_photo_exif())
+        exif = Image.open(data).getexif()
+        assert exif[COPYRIGHT] == "(c) puhu"
+        assert exif.get_ifd(EXIF_IFD)[DATE_TIME_ORIGINAL] == "2024:05:01 12:00:00"
+        assert exif.get_ifd(GPS_IFD)[GPS_LATITUDE_REF] == "N"
+
+    def test_strip_gps(self):
+        """Test removing GPS data while keeping the copyright."""
+        data = saved(Image.new("RGB", (8, 8)), "JPEG", exif=_photo_exif())
+        exif = Image.open(data).getexif()
+        del exif[GPS_IFD]
+        stripped = Image.open(saved(Image.new("RGB", (8, 8)), "JPEG", exif=exif))
+
+        exif = stripped.getexif()
+        assert GPS_IFD not in exif
+        assert exif[COPYRIGHT] == "(c) puhu"
+
+    def test_info_bytes(self):
+        """Test info['exif'] holds the raw block and can be saved again."""
+        data = saved(Image.new("RGB", (8, 8)), "PNG", exif=_photo_exif())
+        raw = Image.open(data).info["exif"]
+        assert raw.startswith(b"Exif\x00\x00")
+
+        data = saved(Image.new("RGB", (8, 8)), "JPEG", exif=raw)
+        assert Image.open(data).info["exif"] == raw
+
+    def test_tiff(self):
+        """Test TIFF tags are read as EXIF without the layout tags."""
+        data = saved(Image.new("RGB", (8, 8)), "TIFF")
+        exif = Image.open(data).getexif()
+        assert 0x0100 not in exif  # ImageWidth
+
+    def test_tiff_field_types(self):
+        """Test UNDEFINED data and non-ASCII text are written to TIFF pages."""
+        exif = _photo_exif()
+        exif[0x010E] = "café"  # ImageDescription
+        exif[0x0100] = 4  # ImageWidth, replaced by the page layout
+        exif.get_ifd(EXIF_IFD)[0x9286] = b"ASCII\x00\x00\x00hello"
+        img = Image.new("RGB", (8, 8))
+        data = saved(img, "TIFF", exif=exif, save_all=True, append_images=[img])
+        for page in range(2):
+            loaded = Image.open(data)
+            loaded.seek(page)
+            exif = loaded.getexif()
+            assert exif[0x010E] == "café"
+            assert exif.get_ifd(EXIF_IFD)[0x9286] == b"ASCII\x00\x00\x00hello"
+        assert Image.open(data).size == (8, 8)
+
+    def test_no_exif(self):
+        """Test images without EXIF data return an empty mapping."""
+        img = Image.open(saved(Image.new("RGB", (8, 8)), "PNG"))
+        assert len(img.getexif()) == 0
+        assert "exif" not in img.info
+
+    def test_unsupported_format(self):
+        """Test formats that cannot store EXIF reject the option."""
+        with pytest.raises(UnsupportedFormatError):
+            saved(Image.new("RGB", (8, 8)), "GIF", exif=Exif())
+
+    def test_invalid_value(self):
+        """Test non-EXIF bytes are rejected."""
+        with pytest.raises(PuhuProcessingError, match="exif"):
+            saved(Image.new("RGB", (8, 8)), "JPEG", exif=b"garbage")
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,100 @@
+"""
+Tests for ImageOps.exif_transpose and open(exif_transpose=True)
+"""
+
+import io
+
+import pytest
+
+from puhu import Exif, Image, ImageOps
+
+ORIENTATION = 0x0112
+COPYRIGHT = 0x8298
+
+# 3x2 grayscale image with a distinct value per pixel, row by row
+WIDTH, HEIGHT = 3, 2
+PIXELS = [[10, 20, 30], [40, 50, 60]]
+
+# Pixel at (x, y) of the upright image for each orientation, read from the
+# stored image of size (WIDTH, HEIGHT)
+UPRIGHT = {
+    1: lambda x, y: PIXELS[y][x],
+    2: lambda x, y: PIXELS[y][WIDTH - 1 - x],
+    3: lambda x, y: PIXELS[HEIGHT - 1 - y][WIDTH - 1 - x],
+    4: lambda x, y: PIXELS[HEIGHT - 1 - y][x],
+    5: lambda x, y: PIXELS[x][y],
+    6: lambda x, y: PIXELS[HEIGHT - 1 - x][y],
+    7: lambda x, y: PIXELS[HEIGHT - 1 - x][WIDTH - 1 - y],
+    8: lambda x, y: PIXELS[x][WIDTH - 1 - y],
+}
+
+
+def _oriented_png(orientation):
+    """Encode the test pixels as PNG with the given EXIF orientation."""
+    img = Image.new("L", (WIDTH, HEIGHT))
+    for y, row in enumerate(PIXELS):
+        for x, value in enumerate(row):
+            img.paste(value, (x, y, x + 1, y + 1))
+
+    exif = Exif()
+    exif[ORIENTATION] = orientation
+    exif[COPYRIGHT] = "(c) puhu"
+    buffer = io.BytesIO()
+    img.save(buffer, format="PNG", exif=exif)
+    return buffer.getvalue()
+
+
+def _expected(orientation):
+    size = (HEIGHT, WIDTH) if orientation >= 5 else (WIDTH, HEIGHT)
+    pixels = bytes(
+        UPRIGHT[orientation](x, y) for y in range(size[1]) for x in range(size[0])
+    )
+    return size, pixels
+
+
+class TestExifTranspose:
+    """Test ImageOps.exif_transpose."""
+
+    @pytest.mark.parametrize("orientation", [1, 2, 3, 4, 5, 6, 7, 8])
+    def test_orientations(self, orientation):
+        """Test every orientation is turned upright."""
+        img = Image.open(_oriented_png(orientation))
+        transposed = ImageOps.exif_transpose(img)
+
+        size, pixels = _expected(orientation)
+        assert transposed.size == size
+        assert transposed.to_bytes() == pixels
+
+    def test_orientation_removed(self):
+        """Test the orientation tag is removed and other tags are kept."""
+        img = Image.open(_oriented_png(6))
+        exif = ImageOps.exif_transpose(img).getexif()
+        assert ORIENTATION not in exif
+        assert exif[COPYRIGHT] == "(c) puhu"
+        assert img.getexif()[ORIENTATION] == 6
+
+    def test_in_place(self):
+        """Test in_place modifies the image and returns None."""
+        img = Image.open(_oriented_png(8))
+        assert ImageOps.exif_transpose(img, in_place=True) is None
+        assert img.size == (HEIGHT, WIDTH)
+        assert ORIENTATION not in img.getexif()
+
+    def test_no_exif(self):
+        """Test images without EXIF data are returned unchanged."""
+        img = Image.new("RGB", (4, 2), "red")
+        transposed = ImageOps.exif_transpose(img)
+        assert transposed is not img
+        assert transposed == img
+
+    def test_open(self):
+        """Test open() can apply the orientation."""
+        data = _oriented_png(6)
+        img = Image.open(data, exif_transpose=True)
+        assert img.size == (HEIGHT, WIDTH)
+        assert img.to_bytes() == _expected(6)[1]
+        assert Image.open(data).size == (WIDTH, HEIGHT)
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,349 @@
+"""
+Tests for multi-frame images: n_frames, seek/tell and ImageSequence
+"""
+
+import io
+import struct
+import zlib
+
+import pytest
+
+from puhu import Image, ImageSequence
+
+from .helpers import png_chunk
+
+
+def _gray(index):
+    """RGBA pixel of a GIF palette index; the test palette is grayscale."""
+    return bytes([2 * index] * 3 + [255])
+
+
+def _sub_blocks(data):
+    return b"".join(
+        bytes([len(data[i : i + 255])]) + data[i : i + 255]
+        for i in range(0, len(data), 255)
+    )
+
+
+def _gif(size, frames, loop=0):
+    """
+    Build a GIF with a 128-entry grayscale palette.
+
+    Each frame is a dict with the box (left, top, width, height), the
+    palette indices of its pixels, and optional duration (ms), disposal
+    and transparency index. Pixels are stored as 8-bit LZW literals,
+    with a clear code often enough that the code size never grows.
+    """
+    data = b"GIF89a" + struct.pack("<HHBBB", *size, 0xF6, 0, 0)
+    data += b"".join(bytes([2 * i] * 3) for i in range(128))
+    data += b"!\xff\x0bNETSCAPE2.0\x03\x01" + struct.pack("<H", loop) + b"\x00"
+    for frame in frames:
+        transparency = frame.get("transparency")
+        packed = frame.get("disposal", 0) << 2 | (transparency is not None)
+        delay = frame.get("duration", 0) // 10
+        data += b"!\xf9\x04" + struct.pack("<BHB", packed, delay, transparency or 0)
+        data += b"\x00"
+        data += b"," + struct.pack("<HHHHB", *frame["box"], 0)
+
+        pixels = bytes(frame["pixels"])
+        codes = b"".join(
+            b"\x80" + pixels[i : i + 100] for i in range(0, len(pixels), 100)
+        )
+        data += b"\x07" + _sub_blocks(codes + b"\x81") + b"\x00"
+    return data + b";"
+
+
+def _apng(size, frames):
+    """
+    Build an RGBA APNG whose default image is the first frame.
+
+    Each frame is a tuple of (box, rows, duration, dispose_op, blend_op)
+    with rows of RGBA bytes covering the box.
+    """
+    data = b"\x89PNG\r\n\x1a\n"
+    data += png_chunk(b"IHDR", struct.pack(">IIBBBBB", *size, 8, 6, 0, 0, 0))
+    data += png_chunk(b"acTL", struct.pack(">II", len(frames), 0))
+    sequence = 0
+    for i, (box, rows, duration, dispose_op, blend_op) in enumerate(frames):
+        x, y, width, height = box
+        control = struct.pack(
+            ">IIIIIHHBB",
+            sequence,
+            width,
+            height,
+            x,
+            y,
+            duration,
+            1000,
+            dispose_op,
+            blend_op,
+        )
+        data += png_chunk(b"fcTL", control)
+        sequence += 1
+        compressed = zlib.compress(b"".join(b"\x00" + bytes(row) for row in rows))
+        if i == 0:
+            data += png_chunk(b"IDAT", compressed)
+        else:
+            data += png_chunk(b"fdAT", struct.pack(">I", sequence) + compressed)
+            sequence += 1
+    return data + png_chunk(b"IEND", b"")
+
+
+def _tiff(pages):
+    """Build an uncompressed grayscale TIFF with one page per (size, pixels)."""
+    data = b"II*\x00" + struct.pack("<I", 8)
+    for index, ((width, height), pixels) in enumerate(pages):
+        entries = [
+            (256, 3, width),  # ImageWidth
+            (257, 3, height),  # ImageLength
+            (258, 3, 8),  # BitsPerSample
+            (259, 3, 1),  # Compression: none
+            (262, 3, 1),  # PhotometricInterpretation: black is zero
+            (273, 4, 0),  # StripOffsets, filled in below
+            (277, 3, 1),  # SamplesPerPixel
+            (278, 3, height),  # RowsPerStrip
+            (279, 4, len(pixels)),  # StripByteCounts
+        ]
+        strip = len(data) + 2 + len(entries) * 12 + 4
+        following = strip + len(pixels) if index + 1 < len(pages) else 0
+        data += struct.pack("<H", len(entries))
+        for tag, kind, value in entries:
+            value = strip if tag == 273 else value
+            fmt = "<HHII" if kind == 4 else "<HHIHH"
+            data += struct.pack(fmt, tag, kind, 1, value, *([] if kind == 4 else [0]))
+        data += struct.pack("<I", following) + bytes(pixels)
+    return data
+
+
+def _riff_chunk(kind, data):
+    return kind + struct.pack("<I", len(data)) + data + b"\x00" * (len(data) % 2)
+
+
+def _webp(size, frames, loop=0):
+    """
+    Build an animated WebP from full-canvas RGBA frames of (color, duration).
+
+    Each frame is encoded as lossless WebP by puhu and wrapped in an ANMF
+    chunk.
+    """
+    width, height = size
+    data = _riff_chunk(
+        b"VP8X",
+        bytes([0x12, 0, 0, 0])
+        + (width - 1).to_bytes(3, "little")
+        + (height - 1).to_bytes(3, "little"),
+    )
+    data += _riff_chunk(b"ANIM", bytes(4) + struct.pack("<H", loop))
+    for color, duration in frames:
+        buffer = io.BytesIO()
+        Image.new("RGBA", size, color).save(buffer, format="WEBP")
+        encoded = buffer.getvalue()
+        assert encoded[12:16] == b"VP8L"
+        header = bytes(6)
+        header += (width - 1).to_bytes(3, "little")
+        header += (height - 1).to_bytes(3, "little")
+        header += duration.to_bytes(3, "little") + b"\x02"  # Do not blend
+        data += _riff_chunk(b"ANMF", header + encoded[12:])
+    return b"RIFF" + struct.pack("<I", 4 + len(data)) + b"WEBP" + data
+
+
+# 4x2 animation: a full first frame, a 2x1 patch, and a single pixel drawn
+# after the patch is disposed to the background
+GIF_FRAMES = [
+    {"box": (0, 0, 4, 2), "pixels": range(1, 9), "duration": 100},
+    {"box": (1, 0, 2, 1), "pixels": [20, 21], "duration": 200, "disposal": 2},
+    {"box": (0, 1, 1, 1), "pixels": [30], "duration": 300},
+]
+
+
+def _gif_frame(index):
+    """Expected RGBA bytes of each frame of GIF_FRAMES."""
+    pixels = [_gray(i) for i in range(1, 9)]
+    if index >= 1:
+        pixels[1], pixels[2] = _gray(20), _gray(21)
+    if index >= 2:
+        pixels[1] = pixels[2] = bytes(4)
+        pixels[4] = _gray(30)
+    return b"".join(pixels)
+
+
+class TestGif:
+    """Test seeking through GIF animations."""
+
+    def test_frame_count(self):
+        """Test n_frames and is_animated."""
+        img = Image.open(_gif((4, 2), GIF_FRAMES))
+        assert img.n_frames == 3
+        assert img.is_animated
+        assert img.tell() == 0
+
+    def test_seek(self):
+        """Test frames are composited with their disposal methods."""
+        img = Image.open(_gif((4, 2), GIF_FRAMES))
+        for index in range(3):
+            img.seek(index)
+            assert img.tell() == index
+            assert img.size == (4, 2)
+            assert img.mode == "RGBA"
+            assert img.to_bytes() == _gif_frame(index)
+
+    def test_seek_backward(self):
+        """Test seeking back decodes the earlier frame again."""
+        img = Image.open(_gif((4, 2), GIF_FRAMES))
+        img.seek(2)
+        img.seek(1)
+        assert img.to_bytes() == _gif_frame(1)
+        img.seek(0)
+        assert img.to_bytes() == _gif_frame(0)
+
+    def test_frame_info(self):
+        """Test duration and disposal follow the current frame."""
+        img = Image.open(_gif((4, 2), GIF_FRAMES))
+        assert img.info["duration"] == 100
+        assert img.info["loop"] == 0
+        img.seek(1)
+        assert img.info["duration"] == 200
+        assert img.disposal == 2
+        img.seek(2)
+        assert img.info["duration"] == 300
+        assert img.disposal == 0
+
+    def test_transparency(self):
+        """Test transparent pixels leave the previous frame visible."""
+        frames = [
+            {"box": (0, 0, 2, 1), "pixels": [1, 2]},
+            {"box": (0, 0, 2, 1), "pixels": [5, 3], "transparency": 5},
+        ]
+        img = Image.open(_gif((2, 1), frames))
+        img.seek(1)
+        assert img.to_bytes() == _gray(1) + _gray(3)
+
+    def test_seek_outside_sequence(self):
+        """Test seeking past the last frame raises EOFError."""
+        img = Image.open(_gif((4, 2), GIF_FRAMES))
+        with pytest.raises(EOFError):
+            img.seek(3)
+        with pytest.raises(EOFError):
+            img.seek(-1)
+        assert img.tell() == 0
+
+
+class TestOtherFormats:
+    """Test APNG, animated WebP and multi-page TIFF."""
+
+    def test_apng(self):
+        """Test APNG frames are blended over the canvas."""
+        red, green = [255, 0, 0, 255], [0, 255, 0, 255]
+        frames = [
+            ((0, 0, 2, 1), [red + green], 40, 0, 0),
+            ((1, 0, 1, 1), [[0, 0, 255, 128]], 60, 1, 1),
+        ]
+        img = Image.open(_apng((2, 1), frames))
+        assert img.n_frames == 2
+        assert img.info["duration"] == 40
+        assert img.to_bytes() == bytes(red + green)
+
+        img.seek(1)
+        assert img.info["duration"] == 60
+        assert img.disposal == 1
+        assert img.to_bytes() == bytes(red + [0, 127, 128, 255])
+
+    def test_webp(self):
+        """Test animated WebP frames and timing."""
+        data = _webp((2, 2), [("red", 50), ("blue", 70)], loop=3)
+        img = Image.open(data)
+        assert img.n_frames == 2
+        assert img.info["loop"] == 3
+        assert img.info["duration"] == 50
+
+        img.seek(1)
+        assert img.info["duration"] == 70
+        assert img.to_bytes() == bytes([0, 0, 255, 255] * 4)
+
+    def test_tiff_pages(self):
+        """Test each TIFF page is decoded with its own size."""
+        data = _tiff([((2, 2), [1, 2, 3, 4]), ((3, 1), [7, 8, 9])])
+        img = Image.open(data)
+        assert img.n_frames == 2
+        assert img.size == (2, 2)
+
+        img.seek(1)
+        assert img.size == (3, 1)
+        assert img.to_bytes() == bytes([7, 8, 9])
+        img.seek(0)
+        assert img.to_bytes() == bytes([1, 2, 3, 4])
+
+    def test_tiff_page_modes(self):
+        """Test pages of different color types are decoded in any order."""
+        pages = [
+            Image.new("L", (2, 2), 10),
+            Image.new("RGB", (3, 2), (1, 2, 3)),
+            Image.new("RGBA", (2, 3), (4, 5, 6, 7)),
+            Image.new("I;16", (1, 1), 40000),
+        ]
+        buf = io.BytesIO()
+        pages[0].save(buf, format="TIFF", save_all=True, append_images=pages[1:])
+        img = Image.open(buf.getvalue())
+        for index in [2, 3, 1, 0, 3]:
+            img.seek(index)
+            assert (img.mode, img.size) == (pages[index].mode, pages[index].size)
+            assert img.to_bytes() == pages[index].to_bytes()
+
+    def test_path(self, tmp_path):
+        """Test frames are read from files opened by path."""
+        path = tmp_path / "anim.gif"
+        path.write_bytes(_gif((4, 2), GIF_FRAMES))
+        img = Image.open(path)
+        img.seek(2)
+        assert img.to_bytes() == _gif_frame(2)
+
+
+class TestSingleFrame:
+    """Test single-frame and derived images."""
+
+    def test_single_frame(self):
+        """Test still images have one frame."""
+        img = Image.new("RGB", (4, 4))
+        assert img.n_frames == 1
+        assert not img.is_animated
+        img.seek(0)
+        with pytest.raises(EOFError):
+            img.seek(1)
+
+    def test_derived(self):
+        """Test operations and copy() return the current frame only."""
+        img = Image.open(_gif((4, 2), GIF_FRAMES))
+        img.seek(1)
+        assert img.resize((2, 1)).n_frames == 1
+        copy = img.copy()
+        assert copy.n_frames == 1
+        assert copy.to_bytes() == _gif_frame(1)
+
+
+class TestImageSequence:
+    """Test ImageSequence.Iterator and all_frames."""
+
+    def test_iterator(self):
+        """Test iterating visits every frame in order."""
+        img = Image.open(_gif((4, 2), GIF_FRAMES))
+        frames = [frame.to_bytes() for frame in ImageSequence.Iterator(img)]
+        assert frames == [_gif_frame(i) for i in range(3)]
+
+    def test_getitem(self):
+        """Test indexing seeks and raises IndexError past the end."""
+        iterator = ImageSequence.Iterator(Image.open(_gif((4, 2), GIF_FRAMES)))
+        assert iterator[2].tell() == 2
+        with pytest.raises(IndexError):
+            iterator[3]
+
+    def test_all_frames(self):
+        """Test all_frames copies every frame and restores the position."""
+        img = Image.open(_gif((4, 2), GIF_FRAMES))
+        img.seek(1)
+        frames = ImageSequence.all_frames(img, lambda im: im.resize((2, 1)))
+        assert [frame.size for frame in frames] == [(2, 1)] * 3
+        assert img.tell() == 1
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,84 @@
+import tempfile
+from pathlib import Path
+
+import pytest
+
+from puhu import Image
+
+from .helpers import saved
+
+
+def _truncated(data):
+    # Keep the header but drop most of the pixel data
+    return data[: len(data) // 2]
+
+
+class TestHeaderProbing:
+    """Test that metadata getters read the header without decoding pixels."""
+
+    def test_png_metadata_without_decode(self):
+        """Test size and mode of a truncated PNG."""
+        data = _truncated(saved(Image.new("RGB", (300, 200), 200), "PNG"))
+        img = Image.open(data)
+
+        assert img.size == (300, 200)
+        assert img.width == 300
+        assert img.height == 200
+        assert img.mode == "RGB"
+        assert img.format == "PNG"
+        assert "size=300x200" in repr(img)
+
+        # Decoding the pixels still fails
+        with pytest.raises(Exception):
+            img.to_bytes()
+
+    def test_jpeg_metadata_without_decode(self):
+        """Test size and mode of a truncated grayscale JPEG."""
+        data = saved(Image.new("L", (64, 48), 200), "JPEG")
+        # Cut the entropy-coded data right after the start-of-scan header
+        sos = data.index(b"\xff\xda")
+        data = data[: sos + 2 + int.from_bytes(data[sos + 2 : sos + 4], "big")]
+        img = Image.open(data)
+        assert img.size == (64, 48)
+        assert img.mode == "L"
+
+    def test_path_metadata_without_decode(self):
+        """Test header probing for file paths."""
+        data = _truncated(saved(Image.new("RGBA", (300, 200), 200), "PNG"))
+        with tempfile.TemporaryDirectory() as temp_dir:
+            file_path = Path(temp_dir) / "truncated.png"
+            file_path.write_bytes(data)
+
+            img = Image.open(file_path)
+            assert img.size == (300, 200)
+            assert img.mode == "RGBA"
+            with pytest.raises(Exception):
+                img.to_bytes()
+
+    def test_format_from_header(self):
+        """Test that the format is sniffed from the file content."""
+        with tempfile.TemporaryDirectory() as temp_dir:
+            unknown = Path(temp_dir) / "image.bin"
+            unknown.write_bytes(saved(Image.new("RGB", (40, 30), 200), "BMP"))
+            img = Image.open(unknown)
+            assert img.size == (40, 30)
+            assert img.format == "BMP"
+
+            misnamed = Path(temp_dir) / "image.png"
+            misnamed.write_bytes(saved(Image.new("RGB", (40, 30), 200), "JPEG"))
+            img = Image.open(misnamed)
+            assert img.size == (40, 30)
+            assert img.format == "JPEG"
+
+    def test_header_matches_decoded_image(self):
+        """Test that probed values match the decoded image."""
+        for fmt in ("PNG", "JPEG", "BMP", "TIFF", "WEBP", "GIF"):
+            img = Image.open(saved(Image.new("RGB", (40, 30), 200), fmt))
+            size, mode = img.size, img.mode
+            img.to_bytes()
+            assert img.size == size
+            assert img.mode == mode
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
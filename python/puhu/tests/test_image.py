@@ -70,9 +70,8 @@ class TestImage:
         rotated_270 = img.rotate(270)
         assert rotated_270 is not img
 
-        # Test that arbitrary angles raise NotImplementedError
-        with pytest.raises(NotImplementedError):
-            img.rotate(45)
+        rotated_45 = img.rotate(45)
+        assert rotated_45 is not img
 
     def test_transpose_operations(self):
         """Test transpose functionality."""
@@ -317,14 +316,14 @@ class TestImage:
         """Test converting to palette mode with WEB palette."""
         img = puhu_new("RGB", (50, 50), (128, 200, 64))
         palette_img = img.convert("P", palette="WEB")
-        assert palette_img.mode == "RGB"
+        assert palette_img.mode == "P"
         assert palette_img.size == (50, 50)
 
     def test_convert_to_palette_adaptive(self):
         """Test converting to palette mode with ADAPTIVE palette."""
         img = puhu_new("RGB", (50, 50), (255, 128, 64))
         palette_img = img.convert("P", palette="ADAPTIVE", colors=64)
-        assert palette_img.mode == "RGB"
+        assert palette_img.mode == "P"
         assert palette_img.size == (50, 50)
 
     def test_convert_to_palette_with_dither(self):
@@ -333,14 +332,14 @@ class TestImage:
         dithered = img.convert(
             "P", palette="ADAPTIVE", colors=32, dither="FLOYDSTEINBERG"
         )
-        assert dithered.mode == "RGB"
+        assert dithered.mode == "P"
         assert dithered.size == (50, 50)
 
     def test_convert_to_palette_without_dither(self):
         """Test palette conversion without dithering."""
         img = puhu_new("RGB", (50, 50), (200, 150, 100))
         no_dither = img.convert("P", palette="ADAPTIVE", colors=32, dither="NONE")
-        assert no_dither.mode == "RGB"
+        assert no_dither.mode == "P"
         assert no_dither.size == (50, 50)
 
 
@@ -355,12 +354,12 @@ class TestErrorHandling:
         with pytest.raises(Exception):  # Should raise PuhuProcessingError
             img.crop((200, 200, 300, 300))
 
-    def test_invalid_rotation_angle(self):
-        """Test rotation with invalid angles."""
+    def test_invalid_rotation_resample(self):
+        """Test rotation with filters other than NEAREST, BILINEAR and BICUBIC."""
         img = puhu_new("RGB", (100, 100))
 
-        with pytest.raises(NotImplementedError):
-            img.rotate(45)
+        with pytest.raises(Exception):  # Should raise PuhuProcessingError
+            img.rotate(45, resample="LANCZOS")
 
     def test_zero_size_resize(self):
         """Test resizing to zero size."""
@@ -373,10 +372,8 @@ class TestErrorHandling:
     def test_open_nonexistent_file(self):
         """Test opening a file that doesn't exist."""
 
-        img = puhu_open("nonexistent_file.png")
-
         with pytest.raises(Exception):
-            _ = img.size
+            puhu_open("nonexistent_file.png")
 
 
 if __name__ == "__main__":
@@ -0,0 +1,234 @@
+"""
+Tests for Image.info metadata and writing it back on save
+"""
+
+import struct
+import tempfile
+import zlib
+from pathlib import Path
+
+import pytest
+
+from puhu import Image
+from puhu._core import PuhuProcessingError, UnsupportedFormatError
+
+from .helpers import png_chunk, saved
+
+
+def _insert_after_ihdr(png, *chunks):
+    """Insert raw chunks after the 8-byte signature and 25-byte IHDR chunk."""
+    return png[:33] + b"".join(chunks) + png[33:]
+
+
+class TestPngInfo:
+    """Test PNG metadata."""
+
+    def test_round_trip(self):
+        """Test dpi, gamma and text chunks written by save."""
+        data = saved(
+            Image.new("RGB", (8, 8), "red"),
+            "PNG",
+            dpi=(300, 300),
+            gamma=0.45455,
+            pnginfo={"Title": "Red square", "Author": "puhu"},
+        )
+        info = Image.open(data).info
+        assert info["dpi"] == pytest.approx((300, 300), abs=0.01)
+        assert info["gamma"] == pytest.approx(0.45455)
+        assert info["Title"] == "Red square"
+        assert info["Author"] == "puhu"
+
+    def test_unicode_text(self):
+        """Test non-Latin-1 text is written and read as iTXt."""
+        data = saved(Image.new("L", (4, 4)), "PNG", pnginfo={"Comment": "Fotoğraf"})
+        img = Image.open(data)
+        assert b"iTXt" in data
+        assert img.text == {"Comment": "Fotoğraf"}
+
+    def test_compressed_text(self):
+        """Test zTXt chunks are decompressed."""
+        png = saved(Image.new("L", (4, 4)), "PNG")
+        ztxt = png_chunk(b"zTXt", b"Description\x00\x00" + zlib.compress(b"x" * 500))
+        info = Image.open(_insert_after_ihdr(png, ztxt)).info
+        assert info["Description"] == "x" * 500
+
+    def test_transparency_gray(self):
+        """Test a tRNS chunk in a grayscale PNG."""
+        png = saved(Image.new("L", (4, 4)), "PNG")
+        trns = png_chunk(b"tRNS", struct.pack(">H", 7))
+        assert Image.open(_insert_after_ihdr(png, trns)).info["transparency"] == 7
+
+    def test_transparency_rgb(self):
+        """Test writing a tRNS color for an RGB image."""
+        data = saved(Image.new("RGB", (4, 4)), "PNG", transparency=(255, 0, 0))
+        assert Image.open(data).info["transparency"] == (255, 0, 0)
+
+    def test_transparency_invalid_mode(self):
+        """Test transparency is rejected for images with alpha."""
+        with pytest.raises(PuhuProcessingError, match="transparency"):
+            saved(Image.new("RGBA", (4, 4)), "PNG", transparency=0)
+
+    def test_corrupt_chunk_length(self):
+        """Test a chunk claiming more data than the file holds is not read."""
+        png = saved(Image.new("L", (2, 2)), "PNG", dpi=(72, 72))
+        # A text chunk after the image data claiming almost 4 GiB
+        data = png[:-12] + struct.pack(">I", 0xFFFFFFF0) + b"tEXtTitle\0x"
+        img = Image.open(data)
+        assert img.size == (2, 2)
+        assert img.info["dpi"] == pytest.approx((72, 72), abs=0.01)
+        assert "Title" not in img.info
+
+    def test_no_metadata(self):
+        """Test a plain PNG has an empty info dictionary."""
+        assert Image.open(saved(Image.new("RGB", (4, 4)), "PNG")).info == {}
+
+
+class TestJpegInfo:
+    """Test JPEG metadata."""
+
+    def test_jfif_and_comment(self):
+        """Test JFIF density and comments written by save."""
+        data = saved(
+            Image.new("RGB", (8, 8)), "JPEG", dpi=(300, 150), comment="Hello"
+        )
+        info = Image.open(data).info
+        assert info["dpi"] == (300, 150)
+        assert info["jfif_unit"] == 1
+        assert info["jfif_density"] == (300, 150)
+        assert info["jfif_version"] == (1, 2)
+        assert info["comment"] == b"Hello"
+
+    def test_comment_bytes(self):
+        """Test comments given as bytes."""
+        data = saved(Image.new("L", (8, 8)), "JPEG", comment=b"\x00\xffraw")
+        img = Image.open(data)
+        assert img.info["comment"] == b"\x00\xffraw"
+        assert img.size == (8, 8)
+
+
+class TestGifInfo:
+    """Test GIF metadata."""
+
+    def test_round_trip(self):
+        """Test duration, loop, background and comment written by save."""
+        data = saved(
+            Image.new("RGB", (8, 8), "blue"),
+            "GIF",
+            duration=120,
+            loop=0,
+            background=3,
+            comment=b"made with puhu",
+        )
+        img = Image.open(data)
+        assert img.info["duration"] == 120
+        assert img.info["loop"] == 0
+        assert img.info["background"] == 3
+        assert img.info["comment"] == b"made with puhu"
+        assert img.to_bytes()[:3] == b"\x00\x00\xff"
+
+    def test_unsupported_option(self):
+        """Test GIF options are rejected for other formats."""
+        with pytest.raises(UnsupportedFormatError):
+            saved(Image.new("RGB", (4, 4)), "PNG", background=0)
+
+
+def _icc_profile(color_space=b"RGB ", size=512):
+    """Build a stand-in ICC profile; only the header colour space is checked."""
+    header = struct.pack(">I", size) + b"puhu" + bytes(4) + b"mntr" + color_space
+    body = bytes(range(256)) * (size // 256 + 1)
+    return header + body[: size - len(header)]
+
+
+class TestIccProfile:
+    """Test ICC profiles are read, carried and embedded."""
+
+    @pytest.mark.parametrize("format", ["JPEG", "PNG", "WEBP", "TIFF"])
+    def test_round_trip(self, format):
+        """Test an ICC profile passed to save is read back."""
+        profile = _icc_profile()
+        data = saved(Image.new("RGB", (8, 8)), format, icc_profile=profile)
+        assert Image.open(data).info["icc_profile"] == profile
+
+    def test_large_jpeg_profile(self):
+        """Test profiles split across several APP2 segments are joined."""
+        profile = _icc_profile(size=150_000)
+        data = saved(Image.new("RGB", (8, 8)), "JPEG", icc_profile=profile)
+        assert data.count(b"ICC_PROFILE\x00") == 3
+        assert Image.open(data).info["icc_profile"] == profile
+
+    def test_kept_through_operations(self):
+        """Test resize, crop and convert keep the profile and save embeds it."""
+        profile = _icc_profile()
+        img = Image.open(saved(Image.new("RGB", (8, 8)), "PNG", icc_profile=profile))
+        derived = img.resize((4, 4)).crop((0, 0, 2, 2)).convert("RGBA")
+
+        resaved = Image.open(saved(derived, "PNG"))
+        assert resaved.info["icc_profile"] == profile
+
+    def test_dropped_on_colour_space_change(self):
+        """Test converting to grayscale drops an RGB profile."""
+        profile = _icc_profile()
+        img = Image.open(saved(Image.new("RGB", (8, 8)), "PNG", icc_profile=profile))
+        assert "icc_profile" not in img.convert("L").info
+
+    def test_corrupt_webp_chunk_length(self):
+        """Test a WebP chunk claiming more data than the file holds is not read."""
+        profile = _icc_profile()
+        webp = saved(Image.new("RGB", (8, 8)), "WEBP", icc_profile=profile)
+        # An EXIF chunk after the image data claiming almost 4 GiB
+        data = webp + b"EXIF" + struct.pack("<I", 0xFFFFFFF0) + b"Exif\0\0"
+        img = Image.open(data)
+        assert img.size == (8, 8)
+        assert img.info["icc_profile"] == profile
+        assert "exif" not in img.info
+
+    def test_opt_out(self):
+        """Test an empty profile leaves the source profile out."""
+        profile = _icc_profile()
+        img = Image.open(saved(Image.new("RGB", (8, 8)), "JPEG", icc_profile=profile))
+        data = saved(img, "JPEG", icc_profile=b"")
+        assert "icc_profile" not in Image.open(data).info
+
+
+class TestInfoLifecycle:
+    """Test when metadata is read and how it is carried."""
+
+    def test_new_image(self):
+        """Test images created in memory have no metadata."""
+        assert Image.new("RGB", (4, 4)).info == {}
+
+    def test_info_survives_decoding(self):
+        """Test metadata read from a file is kept after loading."""
+        data = saved(Image.new("RGB", (8, 8)), "PNG", pnginfo={"Title": "x"})
+        with tempfile.TemporaryDirectory() as temp_dir:
+            file_path = Path(temp_dir) / "image.png"
+            file_path.write_bytes(data)
+
+            img = Image.open(file_path)
+            img.to_bytes()
+            assert img.info["Title"] == "x"
+
+    def test_info_read_with_header(self):
+        """Test metadata is read together with the header and cached."""
+        data = saved(Image.new("RGB", (8, 8)), "PNG", pnginfo={"Title": "x"})
+        with tempfile.TemporaryDirectory() as temp_dir:
+            file_path = Path(temp_dir) / "image.png"
+            file_path.write_bytes(data)
+
+            img = Image.open(file_path)
+            assert img.size == (8, 8)
+            # Metadata is not read again from the file
+            file_path.write_bytes(saved(Image.new("RGB", (8, 8)), "PNG"))
+            assert img.info["Title"] == "x"
+
+    def test_operations_keep_info(self):
+        """Test derived images keep the source metadata."""
+        data = saved(Image.new("RGB", (8, 8)), "PNG", dpi=(72, 72))
+        img = Image.open(data)
+        assert "dpi" in img.resize((4, 4)).info
+        assert "dpi" in img.crop((0, 0, 4, 4)).info
+        assert "dpi" in img.copy().info
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,104 @@
+"""
+Tests for decompression-bomb protection and decode limits
+"""
+
+import warnings
+
+import pytest
+
+from puhu import DecompressionBombError, DecompressionBombWarning, Image
+from puhu._core import PuhuProcessingError
+
+from .helpers import saved
+
+
+class TestDecompressionBomb:
+    """Test the MAX_IMAGE_PIXELS soft and hard limits."""
+
+    def setup_method(self, method):
+        self._max_pixels = Image.MAX_IMAGE_PIXELS
+
+    def teardown_method(self, method):
+        Image.MAX_IMAGE_PIXELS = self._max_pixels
+
+    def test_default_limit(self):
+        """Test the default limit matches Pillow."""
+        assert Image.MAX_IMAGE_PIXELS == 1024 * 1024 * 1024 // 4 // 3
+
+    def test_error_is_processing_error(self):
+        """Test DecompressionBombError subclasses PuhuProcessingError."""
+        assert issubclass(DecompressionBombError, PuhuProcessingError)
+        assert issubclass(DecompressionBombWarning, RuntimeWarning)
+
+    def test_below_limit(self):
+        """Test images within the limit open without a warning."""
+        Image.MAX_IMAGE_PIXELS = 100
+        img = Image.open(saved(Image.new("L", (10, 10)), "PNG"))
+        with warnings.catch_warnings():
+            warnings.simplefilter("error")
+            assert img.size == (10, 10)
+            img.to_bytes()
+
+    def test_soft_limit_warns(self):
+        """Test images above the limit emit a DecompressionBombWarning."""
+        Image.MAX_IMAGE_PIXELS = 100
+        img = Image.open(saved(Image.new("L", (15, 10)), "PNG"))
+        with pytest.warns(DecompressionBombWarning):
+            assert img.size == (15, 10)
+
+    def test_hard_limit_raises(self):
+        """Test images above twice the limit raise DecompressionBombError."""
+        Image.MAX_IMAGE_PIXELS = 100
+        img = Image.open(saved(Image.new("L", (30, 10)), "PNG"))
+        with pytest.raises(DecompressionBombError, match="300 pixels"):
+            img.to_bytes()
+
+    def test_limit_checked_before_decoding(self):
+        """Test the hard limit is checked from the header alone."""
+        Image.MAX_IMAGE_PIXELS = 100
+        data = saved(Image.new("L", (30, 10)), "PNG")
+        img = Image.open(data[: len(data) - 12])
+        with pytest.raises(DecompressionBombError):
+            img.size
+
+    def test_disable_limit(self):
+        """Test setting MAX_IMAGE_PIXELS to None disables the check."""
+        Image.MAX_IMAGE_PIXELS = None
+        with warnings.catch_warnings():
+            warnings.simplefilter("error")
+            img = Image.open(saved(Image.new("L", (30, 10)), "PNG"))
+            img.to_bytes()
+        assert img.size == (30, 10)
+
+
+class TestDecodeLimits:
+    """Test per-call limits passed to open()."""
+
+    def test_max_width(self):
+        """Test max_width rejects wider images."""
+        img = Image.open(saved(Image.new("L", (20, 10)), "PNG"), max_width=16)
+        with pytest.raises(DecompressionBombError):
+            img.to_bytes()
+
+    def test_max_height(self):
+        """Test max_height rejects taller images."""
+        img = Image.open(saved(Image.new("L", (10, 20)), "PNG"), max_height=16)
+        with pytest.raises(DecompressionBombError):
+            img.size
+
+    def test_max_alloc(self):
+        """Test max_alloc rejects images needing more memory."""
+        img = Image.open(saved(Image.new("L", (64, 64)), "PNG"), max_alloc=1024)
+        with pytest.raises(DecompressionBombError):
+            img.to_bytes()
+
+    def test_within_limits(self):
+        """Test images within all limits load normally."""
+        data = saved(Image.new("L", (20, 10)), "PNG")
+        img = Image.open(data, max_width=20, max_height=10, max_alloc=1 << 20)
+        img.to_bytes()
+        assert img.size == (20, 10)
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,279 @@
+"""
+Tests for 16-bit, float, "I" and "F" image modes
+"""
+
+import struct
+import sys
+
+import pytest
+
+from puhu import Image, ImageMode
+from puhu._core import PuhuProcessingError
+
+from .helpers import saved
+
+ORDER = "<" if sys.byteorder == "little" else ">"
+
+
+def _values(img, code):
+    """Samples of an image's raw data as numbers."""
+    data = img.to_bytes()
+    return list(struct.unpack(f"{ORDER}{len(data) // struct.calcsize(code)}{code}", data))
+
+
+def _tiff_page(data):
+    """Tags of the first TIFF page as {tag: value}, and the strip data."""
+    order = "<" if data[:2] == b"II" else ">"
+    (offset,) = struct.unpack(f"{order}I", data[4:8])
+    (count,) = struct.unpack(f"{order}H", data[offset : offset + 2])
+    tags = {}
+    for i in range(count):
+        entry = data[offset + 2 + i * 12 : offset + 14 + i * 12]
+        tag, kind, _, value = struct.unpack(f"{order}HHI4s", entry)
+        code = "H" if kind == 3 else "I"
+        tags[tag] = struct.unpack(f"{order}{code}", value[: struct.calcsize(code)])[0]
+    start = tags[273]
+    return tags, order, data[start : start + tags[279]]
+
+
+class TestModes:
+    """Test creating images in deep modes."""
+
+    @pytest.mark.parametrize(
+        "mode",
+        ["I;16", "LA;16", "RGB;16", "RGBA;16", "RGB;F", "RGBA;F", "I", "F"],
+    )
+    def test_new(self, mode):
+        """Test each mode is created and reported as itself."""
+        img = Image.new(mode, (4, 3))
+        assert img.mode == mode
+        assert img.size == (4, 3)
+        assert f"mode={mode} " in repr(img)
+
+    def test_16_bit_values(self):
+        """Test 16-bit fills keep values above 255."""
+        assert _values(Image.new("I;16", (2, 1), 40000), "H") == [40000, 40000]
+        img = Image.new("RGBA;16", (1, 1), (1, 300, 65535, 1000))
+        assert _values(img, "H") == [1, 300, 65535, 1000]
+
+    def test_color_names_scaled(self):
+        """Test color names are scaled to the range of the mode."""
+        assert _values(Image.new("RGB;16", (1, 1), "red"), "H") == [65535, 0, 0]
+        assert _values(Image.new("RGB;F", (1, 1), "#ff0000"), "f") == [1.0, 0.0, 0.0]
+
+    def test_int_and_float_values(self):
+        """Test "I" and "F" hold values outside the 8-bit range."""
+        assert _values(Image.new("I", (2, 1), -70000), "i") == [-70000, -70000]
+        assert _values(Image.new("F", (1, 1), -1.5), "f") == [-1.5]
+
+    def test_int_values_exact(self):
+        """Test "I" keeps every 32-bit value, beyond the precision of floats."""
+        img = Image.new("I", (2, 2), 16777217)
+        img.paste(-(2**31), (0, 0, 1, 1))
+        img.paste(2**31 - 1, (1, 1, 2, 2))
+        expected = [-(2**31), 16777217, 16777217, 2**31 - 1]
+        assert len(img.to_bytes()) == 16
+        assert _values(img, "i") == expected
+        assert _values(img.crop((0, 0, 2, 2)), "i") == expected
+        assert _values(img.transpose("FLIP_LEFT_RIGHT"), "i") == [
+            16777217,
+            -(2**31),
+            2**31 - 1,
+            16777217,
+        ]
+        assert _values(img.rotate(180), "i") == expected[::-1]
+
+    def test_resampled_values(self):
+        """Test resampling "I" and "F" images interpolates their values."""
+        img = Image.new("I", (4, 4), -70000)
+        assert set(_values(img.resize((2, 2), "BILINEAR"), "i")) == {-70000}
+        assert set(_values(img.reduce(2), "i")) == {-70000}
+        assert _values(img.rotate(10, "BILINEAR"), "i")[5] == -70000
+        img = Image.new("I", (4, 4), 16777217)
+        assert set(_values(img.resize((2, 2), "LANCZOS"), "i")) == {16777217}
+        img = Image.new("F", (4, 2), 0.0)
+        img.paste(1.5, (2, 0, 4, 2))
+        assert _values(img.reduce(2), "f") == [0.0, 1.5]
+
+    def test_constants(self):
+        """Test the ImageMode constants."""
+        assert ImageMode.I_16 == "I;16"
+        assert ImageMode.RGBA_16 == "RGBA;16"
+        assert ImageMode.F == "F"
+        assert Image.new(ImageMode.RGB_16, (1, 1)).mode == "RGB;16"
+
+
+class TestConvert:
+    """Test converting between 8-bit, 16-bit and value modes."""
+
+    def test_scale_to_16_bit(self):
+        """Test 8-bit colors are scaled to 16 bits and back."""
+        img = Image.new("RGB", (1, 1), (255, 128, 0))
+        deep = img.convert("RGB;16")
+        assert _values(deep, "H") == [65535, 128 * 257, 0]
+        assert deep.convert("RGB").to_bytes() == img.to_bytes()
+
+    def test_16_bit_to_i(self):
+        """Test "I;16" values are kept as they are in "I"."""
+        img = Image.new("I;16", (1, 1), 40000).convert("I")
+        assert img.mode == "I"
+        assert _values(img, "i") == [40000]
+
+    def test_i_clamped(self):
+        """Test "I" values are clamped to the range of the target mode."""
+        img = Image.new("I", (3, 1), 0)
+        img.paste(300, (1, 0, 2, 1))
+        img.paste(70000, (2, 0, 3, 1))
+        assert img.convert("L").to_bytes() == bytes([0, 255, 255])
+        assert _values(img.convert("I;16"), "H") == [0, 300, 65535]
+
+    def test_f_to_i_rounds(self):
+        """Test "F" values are rounded in "I"."""
+        img = Image.new("F", (1, 1), 2.75)
+        assert _values(img.convert("I"), "i") == [3]
+        assert _values(img.convert("I").convert("F"), "f") == [3.0]
+
+    def test_rgb_to_f(self):
+        """Test color images become their gray level."""
+        img = Image.new("RGB", (1, 1), (100, 100, 100))
+        assert _values(img.convert("F"), "f") == [100.0]
+
+    def test_palette_and_bilevel(self):
+        """Test "I" images convert to "1" and "P" from their clamped gray levels."""
+        img = Image.new("I", (2, 1), 1000)
+        assert img.convert("1").to_bytes() == bytes([255, 255])
+        assert img.convert("P").convert("L").to_bytes() == bytes([255, 255])
+
+
+class TestPaste:
+    """Test pasting into deep modes."""
+
+    def test_paste_converts_source(self):
+        """Test 8-bit sources are scaled to the destination mode."""
+        img = Image.new("RGBA;16", (2, 1), (0, 0, 0, 65535))
+        img.paste(Image.new("RGB", (1, 1), (255, 0, 0)), (1, 0))
+        assert _values(img, "H") == [0, 0, 0, 65535, 65535, 0, 0, 65535]
+
+    def test_paste_keeps_values(self):
+        """Test "I" and "F" pixels are copied without clamping."""
+        img = Image.new("I", (2, 1), 0)
+        img.paste(Image.new("I", (1, 1), -70000), (1, 0))
+        assert _values(img, "i") == [0, -70000]
+        img = Image.new("F", (2, 1), 0.0)
+        img.paste(2.5, (0, 0, 1, 1))
+        assert _values(img, "f") == [2.5, 0.0]
+
+    def test_fill_16_bit(self):
+        """Test color fills take values of the mode."""
+        img = Image.new("I;16", (2, 2), 0)
+        img.paste(50000, (0, 0, 1, 2))
+        assert _values(img, "H") == [50000, 0, 50000, 0]
+
+    def test_mask(self):
+        """Test masked pastes blend at full precision."""
+        img = Image.new("I;16", (2, 1), 0)
+        img.paste(Image.new("I;16", (2, 1), 1000), (0, 0), Image.new("L", (2, 1), 51))
+        assert _values(img, "H") == [200, 200]
+        img = Image.new("F", (1, 1), 0.0)
+        img.paste(Image.new("F", (1, 1), 10.0), (0, 0), Image.new("L", (1, 1), 51))
+        assert _values(img, "f") == [pytest.approx(2.0)]
+        img = Image.new("I", (2, 1), 0)
+        img.paste(Image.new("I", (2, 1), -1000), (0, 0), Image.new("L", (2, 1), 51))
+        assert _values(img, "i") == [-200, -200]
+
+
+class TestSave:
+    """Test saving deep modes."""
+
+    @pytest.mark.parametrize("mode", ["I;16", "LA;16", "RGB;16", "RGBA;16"])
+    def test_png_round_trip(self, mode):
+        """Test 16-bit PNGs keep their values."""
+        img = Image.new(mode, (2, 2), (1, 300, 40000, 65535))
+        result = Image.open(saved(img, "PNG"))
+        assert result.mode == mode
+        assert result.to_bytes() == img.to_bytes()
+
+    @pytest.mark.parametrize("mode", ["I;16", "RGB;16", "RGBA;16"])
+    def test_tiff_round_trip(self, mode):
+        """Test 16-bit TIFFs keep their values."""
+        img = Image.new(mode, (2, 2), (1, 300, 40000, 65535))
+        result = Image.open(saved(img, "TIFF"))
+        assert result.mode == mode
+        assert result.to_bytes() == img.to_bytes()
+
+    def test_i_to_png(self):
+        """Test "I" is saved to PNG as 16-bit gray."""
+        img = Image.new("I", (2, 1), 40000)
+        result = Image.open(saved(img, "PNG"))
+        assert result.mode == "I;16"
+        assert _values(result, "H") == [40000, 40000]
+
+    def test_i_tiff_samples(self):
+        """Test "I" is saved to TIFF as signed 32-bit samples."""
+        tags, order, strip = _tiff_page(saved(Image.new("I", (2, 1), -70000), "TIFF"))
+        assert tags[258] == 32
+        assert tags[339] == 2
+        assert struct.unpack(f"{order}2i", strip) == (-70000, -70000)
+
+    def test_f_tiff_samples(self):
+        """Test "F" is saved to TIFF as float samples."""
+        tags, order, strip = _tiff_page(saved(Image.new("F", (2, 1), 1.25), "TIFF"))
+        assert tags[258] == 32
+        assert tags[339] == 3
+        assert struct.unpack(f"{order}2f", strip) == (1.25, 1.25)
+
+    def test_i_tiff_round_trip(self):
+        """Test "I" TIFFs open as "I" with every value kept."""
+        img = Image.new("I", (3, 1), 16777217)
+        img.paste(-(2**31), (0, 0, 1, 1))
+        result = Image.open(saved(img, "TIFF"))
+        assert result.mode == "I"
+        assert result.size == (3, 1)
+        assert _values(result, "i") == [-(2**31), 16777217, 16777217]
+
+    def test_f_tiff_round_trip(self):
+        """Test "F" TIFFs open as "F" with every value kept."""
+        img = Image.new("F", (2, 2), -1.25)
+        img.paste(1e30, (1, 1, 2, 2))
+        result = Image.open(saved(img, "TIFF"))
+        assert result.mode == "F"
+        assert _values(result, "f") == _values(img, "f")
+        assert result.convert("L").to_bytes() == bytes([0, 0, 0, 255])
+
+    @pytest.mark.parametrize("mode, value", [("I", -70000), ("F", 3.75)])
+    def test_tiff_save_all(self, mode, value):
+        """Test every page saved with save_all keeps its values."""
+        img = Image.new(mode, (2, 1), value)
+        pages = [Image.new(mode, (2, 1), 2 * value)]
+        code = "i" if mode == "I" else "f"
+
+        result = Image.open(saved(img, "TIFF", save_all=True))
+        assert result.mode == mode
+        assert _values(result, code) == [value, value]
+
+        result = Image.open(saved(img, "TIFF", save_all=True, append_images=pages))
+        assert result.n_frames == 2
+        result.seek(1)
+        assert result.mode == mode
+        assert _values(result, code) == [2 * value, 2 * value]
+
+    def test_animation_save_all(self):
+        """Test formats without values save frames of values as "L"."""
+        pages = [Image.new("F", (2, 1), 300.0)]
+        img = Image.new("I", (2, 1), 7)
+        data = saved(img, "PNG", save_all=True, append_images=pages)
+        result = Image.open(data)
+        assert result.n_frames == 2
+        assert set(result.convert("L").to_bytes()) == {7}
+        result.seek(1)
+        assert set(result.convert("L").to_bytes()) == {255}
+
+    @pytest.mark.parametrize("format", ["PNG", "JPEG", "BMP"])
+    def test_f_unsupported(self, format):
+        """Test formats without float samples reject "F" images."""
+        with pytest.raises(PuhuProcessingError):
+            saved(Image.new("F", (2, 2)), format)
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,212 @@
+import io
+import tarfile
+import tempfile
+import zipfile
+from pathlib import Path
+
+import pytest
+
+from puhu import Image
+from puhu._core import UnsupportedFormatError
+
+from .helpers import saved
+
+
+class TestOpenSources:
+    """Test cases for opening images from different sources."""
+
+    def test_open_pathlib_path(self):
+        """Test opening from a pathlib.Path."""
+        with tempfile.TemporaryDirectory() as temp_dir:
+            file_path = Path(temp_dir) / "image.png"
+            file_path.write_bytes(saved(Image.new("RGB", (12, 8), "red"), "PNG"))
+
+            img = Image.open(file_path)
+            assert img.format == "PNG"
+            assert img.size == (12, 8)
+
+    def test_open_pathlike(self):
+        """Test opening from a custom os.PathLike object."""
+        with tempfile.TemporaryDirectory() as temp_dir:
+            file_path = Path(temp_dir) / "image.png"
+            file_path.write_bytes(saved(Image.new("RGB", (12, 8), "red"), "PNG"))
+
+            class PathLike:
+                def __fspath__(self):
+                    return str(file_path)
+
+            img = Image.open(PathLike())
+            assert img.size == (12, 8)
+
+    def test_open_bytes_like(self):
+        """Test opening from bytearray and memoryview."""
+        data = saved(Image.new("RGB", (12, 8), "red"), "PNG")
+        for source in (bytearray(data), memoryview(data)):
+            img = Image.open(source)
+            assert img.format == "PNG"
+            assert img.size == (12, 8)
+
+    def test_open_bytesio(self):
+        """Test opening from a BytesIO."""
+        img = Image.open(io.BytesIO(saved(Image.new("RGB", (12, 8), "red"), "PNG")))
+        assert img.format == "PNG"
+        assert img.size == (12, 8)
+        assert img.to_bytes()[:3] == b"\xff\x00\x00"
+
+    def test_open_file_object(self):
+        """Test opening from a file opened in binary mode."""
+        with tempfile.TemporaryDirectory() as temp_dir:
+            file_path = Path(temp_dir) / "image.bin"
+            file_path.write_bytes(saved(Image.new("RGB", (12, 8), "red"), "PNG"))
+
+            with open(file_path, "rb") as f:
+                img = Image.open(f)
+                assert img.size == (12, 8)
+
+    def test_open_zip_member(self):
+        """Test opening a non-seekable stream from a zip archive."""
+        buf = io.BytesIO()
+        with zipfile.ZipFile(buf, "w") as zf:
+            zf.writestr("image.png", saved(Image.new("RGB", (12, 8), "red"), "PNG"))
+
+        with zipfile.ZipFile(buf) as zf:
+            with zf.open("image.png") as member:
+                img = Image.open(member)
+                assert img.format == "PNG"
+                assert img.size == (12, 8)
+
+    def test_open_tar_member(self):
+        """Test opening a stream from a tar archive."""
+        data = saved(Image.new("RGB", (12, 8), "red"), "PNG")
+        buf = io.BytesIO()
+        with tarfile.open(fileobj=buf, mode="w") as tf:
+            info = tarfile.TarInfo("image.png")
+            info.size = len(data)
+            tf.addfile(info, io.BytesIO(data))
+        buf.seek(0)
+
+        with tarfile.open(fileobj=buf) as tf:
+            img = Image.open(tf.extractfile("image.png"))
+            assert img.size == (12, 8)
+
+    def test_stream_is_read_lazily(self):
+        """Test that only the header is read until the image is needed."""
+
+        class Reader(io.BytesIO):
+            def __init__(self, data):
+                super().__init__(data)
+                self.calls = []
+
+            def read(self, size=-1):
+                self.calls.append(size)
+                return super().read(size)
+
+        reader = Reader(saved(Image.new("RGB", (12, 8), "red"), "PNG"))
+        img = Image.open(reader)
+        assert img.format == "PNG"
+        assert len(reader.calls) == 1
+        assert reader.tell() < 32
+
+        assert img.size == (12, 8)
+        assert len(reader.calls) == 2
+
+    def test_copy_before_load(self):
+        """Test that copies of an unloaded stream image both decode."""
+        img = Image.open(io.BytesIO(saved(Image.new("RGB", (12, 8), "red"), "PNG")))
+        copied = img.copy()
+        assert img.size == (12, 8)
+        assert copied.size == (12, 8)
+
+    def test_open_text_stream(self):
+        """Test that text-mode streams are rejected."""
+        with pytest.raises(Exception, match="binary mode"):
+            Image.open(io.StringIO("not an image"))
+
+    def test_open_invalid_type(self):
+        """Test that unsupported source types are rejected."""
+        with pytest.raises(Exception, match="file-like object"):
+            Image.open(12345)
+
+    def test_reader_errors_propagate(self):
+        """Test that exceptions raised by read() reach the caller."""
+
+        class FailingReader:
+            def __init__(self):
+                self.first = True
+
+            def read(self, size=-1):
+                if self.first:
+                    self.first = False
+                    return saved(Image.new("RGB", (12, 8), "red"), "PNG")[:16]
+                raise OSError("connection reset")
+
+        img = Image.open(FailingReader())
+        with pytest.raises(OSError, match="connection reset"):
+            _ = img.size
+
+
+class TestOpenFormats:
+    """Test the formats allow-list and mode argument of open()."""
+
+    def test_allowed_format(self):
+        """Test opening an image whose format is allowed."""
+        data = saved(Image.new("RGB", (12, 8), "red"), "PNG")
+        img = Image.open(data, formats=["JPEG", "PNG"])
+        assert img.format == "PNG"
+        assert img.size == (12, 8)
+
+    def test_format_names_case_insensitive(self):
+        """Test that format names are matched case-insensitively."""
+        data = saved(Image.new("RGB", (12, 8), "red"), "PNG")
+        img = Image.open(data, formats=("png",))
+        assert img.format == "PNG"
+
+    def test_disallowed_bytes(self):
+        """Test that disallowed bytes are rejected in open()."""
+        data = saved(Image.new("RGB", (12, 8), "red"), "PNG")
+        with pytest.raises(UnsupportedFormatError, match="PNG"):
+            Image.open(data, formats=["JPEG", "WEBP"])
+
+    def test_disallowed_stream(self):
+        """Test that disallowed streams are rejected after sniffing."""
+        data = saved(Image.new("RGB", (12, 8), "red"), "PNG")
+        with pytest.raises(UnsupportedFormatError):
+            Image.open(io.BytesIO(data), formats=["JPEG"])
+
+    def test_disallowed_path(self):
+        """Test that files are checked by content, not extension."""
+        with tempfile.TemporaryDirectory() as temp_dir:
+            file_path = Path(temp_dir) / "image.jpg"
+            file_path.write_bytes(saved(Image.new("RGB", (12, 8), "red"), "PNG"))
+
+            with pytest.raises(UnsupportedFormatError):
+                Image.open(file_path, formats=["JPEG"])
+
+            # The extension is only used when the content is not recognized
+            assert Image.open(file_path, formats=["PNG"]).format == "PNG"
+
+    def test_unidentified_data(self):
+        """Test that unidentifiable data is rejected when formats is given."""
+        with pytest.raises(UnsupportedFormatError, match="identify"):
+            Image.open(b"not an image at all", formats=["PNG"])
+
+    def test_unknown_format_name(self):
+        """Test that unknown format names are rejected."""
+        with pytest.raises(UnsupportedFormatError, match="XYZ"):
+            Image.open(saved(Image.new("RGB", (12, 8), "red"), "PNG"), formats=["XYZ"])
+
+    def test_formats_type(self):
+        """Test that formats must be a list or tuple."""
+        with pytest.raises(TypeError):
+            Image.open(saved(Image.new("RGB", (12, 8), "red"), "PNG"), formats="PNG")
+
+    def test_mode(self):
+        """Test that only read mode is accepted."""
+        data = saved(Image.new("RGB", (12, 8), "red"), "PNG")
+        assert Image.open(data, "r").size == (12, 8)
+        with pytest.raises(ValueError, match="bad mode"):
+            Image.open(data, "w")
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
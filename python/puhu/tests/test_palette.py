@@ -0,0 +1,249 @@
+"""
+Tests for palette ("P") images: getpalette/putpalette, transparency and
+indexed saving
+"""
+
+import struct
+
+import pytest
+
+from puhu import Image
+from puhu._core import PuhuProcessingError
+
+from .helpers import saved
+
+COLORS = [(255, 0, 0), (0, 255, 0), (0, 0, 255), (255, 255, 0)]
+
+
+def _four_colors():
+    """2x2 RGB image with one pixel of each color in COLORS."""
+    img = Image.new("RGB", (2, 2))
+    for index, color in enumerate(COLORS):
+        x, y = index % 2, index // 2
+        img.paste(color, (x, y, x + 1, y + 1))
+    return img
+
+
+def _png_chunks(data):
+    """Map of chunk type to the data of its first occurrence."""
+    chunks = {}
+    offset = 8
+    while offset < len(data):
+        (length,) = struct.unpack(">I", data[offset : offset + 4])
+        kind = data[offset + 4 : offset + 8]
+        chunks.setdefault(kind, data[offset + 8 : offset + 8 + length])
+        offset += 12 + length
+    return chunks
+
+
+class TestPaletteMode:
+    """Test converting to and from "P" images."""
+
+    def test_indices(self):
+        """Test pixels are indices into the palette."""
+        img = _four_colors().convert("P", palette="ADAPTIVE")
+        assert img.mode == "P"
+        palette = img.getpalette()
+        assert len(palette) % 3 == 0
+        for index, color in zip(img.to_bytes(), COLORS):
+            assert tuple(palette[index * 3 : index * 3 + 3]) == color
+
+    def test_round_trip(self):
+        """Test an exact adaptive palette converts back losslessly."""
+        rgb = _four_colors()
+        img = rgb.convert("P", palette="ADAPTIVE")
+        assert img.convert("RGB").to_bytes() == rgb.to_bytes()
+        assert img.convert("RGBA").to_bytes() == rgb.convert("RGBA").to_bytes()
+
+    def test_web_palette(self):
+        """Test the web palette has 216 entries."""
+        img = _four_colors().convert("P", palette="WEB")
+        assert len(img.getpalette()) == 216 * 3
+        assert img.convert("RGB").to_bytes() == _four_colors().to_bytes()
+
+    def test_transparency(self):
+        """Test transparent pixels share the transparent index."""
+        rgba = Image.new("RGBA", (2, 1), (0, 0, 0, 0))
+        rgba.paste((255, 0, 0, 255), (0, 0, 1, 1))
+        img = rgba.convert("P", palette="ADAPTIVE")
+        transparency = img.info["transparency"]
+        assert img.to_bytes()[1] == transparency
+        assert img.getpalette("RGBA")[transparency * 4 + 3] == 0
+        assert img.convert("RGBA").to_bytes() == bytes([255, 0, 0, 255, 0, 0, 0, 0])
+
+    def test_non_palette_image(self):
+        """Test images of other modes have no palette."""
+        assert Image.new("RGB", (2, 2)).getpalette() is None
+
+    def test_new(self):
+        """Test Image.new("P") fills with an index or a single color."""
+        gray = Image.new("P", (2, 1), 7)
+        assert gray.to_bytes() == bytes([7, 7])
+        assert gray.convert("L").to_bytes() == bytes([7, 7])
+
+        red = Image.new("P", (2, 1), "red")
+        assert red.to_bytes() == bytes(2)
+        assert red.getpalette()[:3] == [255, 0, 0]
+
+    def test_operations_keep_palette(self):
+        """Test resizing, cropping and transposing keep mode "P"."""
+        img = _four_colors().convert("P", palette="ADAPTIVE")
+        palette = img.getpalette()
+        for result in [
+            img.resize((4, 4)),
+            img.crop((0, 0, 1, 2)),
+            img.transpose("FLIP_LEFT_RIGHT"),
+            img.copy(),
+        ]:
+            assert result.mode == "P"
+            assert result.getpalette() == palette
+        assert set(img.resize((8, 8)).to_bytes()) == set(img.to_bytes())
+
+
+class TestPutPalette:
+    """Test attaching palettes to images."""
+
+    def test_l_image(self):
+        """Test an "L" image becomes a "P" image of indices."""
+        img = Image.new("L", (2, 1), 1)
+        img.putpalette([0, 0, 0, 10, 20, 30])
+        assert img.mode == "P"
+        assert img.convert("RGB").to_bytes() == bytes([10, 20, 30] * 2)
+
+    def test_bytes(self):
+        """Test palettes can be given as bytes."""
+        img = Image.new("P", (1, 1), 0)
+        img.putpalette(b"\x01\x02\x03")
+        assert img.getpalette() == [1, 2, 3]
+
+    def test_rgba(self):
+        """Test an RGBA palette sets the alpha of each entry."""
+        img = Image.new("P", (2, 1), 1)
+        img.putpalette([255, 0, 0, 255, 0, 0, 0, 0], "RGBA")
+        assert img.info["transparency"] == 1
+        assert img.getpalette("RGBA") == [255, 0, 0, 255, 0, 0, 0, 0]
+        assert img.convert("RGBA").to_bytes() == bytes(8)
+
+    def test_rgb_keeps_alpha(self):
+        """Test an RGB palette keeps the alpha of each entry."""
+        img = Image.new("P", (1, 1), 0)
+        img.putpalette([0, 0, 0, 0, 9, 9, 9, 255], "RGBA")
+        img.putpalette([1, 2, 3, 4, 5, 6])
+        assert img.info["transparency"] == 0
+
+    def test_partial_alpha(self):
+        """Test entries can be partially transparent."""
+        img = Image.new("P", (2, 1), 1)
+        img.putpalette([0, 0, 0, 0, 9, 9, 9, 128], "RGBA")
+        assert img.info["transparency"] == bytes([0, 128])
+        assert img.convert("RGBA").to_bytes() == bytes([9, 9, 9, 128] * 2)
+
+    @pytest.mark.parametrize(
+        "mode,data,rawmode",
+        [
+            ("RGB", [0, 0, 0], "RGB"),
+            ("P", [0, 0], "RGB"),
+            ("P", [0] * 257 * 3, "RGB"),
+            ("P", [0, 0, 0], "RGBA"),
+            ("P", [0, 0, 0], "CMYK"),
+            ("P", [256, 0, 0], "RGB"),
+        ],
+    )
+    def test_invalid(self, mode, data, rawmode):
+        """Test invalid modes and palette data are rejected."""
+        img = Image.new(mode, (1, 1))
+        with pytest.raises(PuhuProcessingError):
+            img.putpalette(data, rawmode)
+
+
+class TestPaste:
+    """Test pasting into "P" images."""
+
+    def test_paste_palette_image(self):
+        """Test pasting a "P" image copies its indices."""
+        img = Image.new("P", (2, 1), 0)
+        img.paste(Image.new("P", (1, 1), 5), (1, 0))
+        assert img.to_bytes() == bytes([0, 5])
+
+    def test_paste_rgb(self):
+        """Test RGB images are mapped to the nearest palette entry."""
+        img = Image.new("L", (2, 1), 0)
+        img.putpalette([0, 0, 0, 250, 0, 0])
+        img.paste(Image.new("RGB", (1, 1), (240, 10, 10)), (1, 0))
+        img.paste((255, 0, 0), (0, 0, 1, 1))
+        assert img.to_bytes() == bytes([1, 1])
+
+    def test_paste_into_rgb(self):
+        """Test pasting a "P" image into an RGB image uses its colors."""
+        img = Image.new("RGB", (1, 1))
+        img.paste(Image.new("P", (1, 1), "blue"))
+        assert img.to_bytes() == bytes([0, 0, 255])
+
+
+class TestSave:
+    """Test "P" images are saved as indexed PNG and GIF."""
+
+    def test_png_indexed(self):
+        """Test PNG stores a palette and packs indices into few bits."""
+        img = _four_colors().convert("P", palette="ADAPTIVE")
+        data = saved(img, "PNG")
+        chunks = _png_chunks(data)
+        width, height, bit_depth, color_type = struct.unpack(
+            ">IIBB", chunks[b"IHDR"][:10]
+        )
+        assert (width, height) == (2, 2)
+        assert color_type == 3
+        assert bit_depth == 2
+        assert len(chunks[b"PLTE"]) == len(img.getpalette())
+
+        assert Image.open(data).convert("RGB").to_bytes() == _four_colors().to_bytes()
+
+    def test_png_smaller(self):
+        """Test an indexed PNG is smaller than the same image as RGB."""
+        img = _four_colors().resize((64, 64), "nearest")
+        palette = img.convert("P", palette="ADAPTIVE")
+        assert len(saved(palette, "PNG")) < len(saved(img, "PNG"))
+
+    def test_png_transparency(self):
+        """Test the transparent index is written to tRNS."""
+        rgba = Image.new("RGBA", (2, 1), (0, 0, 0, 0))
+        rgba.paste((255, 0, 0, 255), (0, 0, 1, 1))
+        img = rgba.convert("P", palette="ADAPTIVE")
+        data = saved(img, "PNG")
+        trns = _png_chunks(data)[b"tRNS"]
+        assert trns[img.info["transparency"]] == 0
+        assert Image.open(data).convert("RGBA").to_bytes() == rgba.to_bytes()
+
+    def test_png_save_all(self):
+        """Test a single "P" image saved with save_all is still indexed."""
+        img = Image.new("RGB", (8, 8)).convert("P")
+        chunks = _png_chunks(saved(img, "PNG", save_all=True))
+        assert chunks[b"IHDR"][9] == 3
+        assert b"PLTE" in chunks
+
+    def test_frames_save_all(self):
+        """Test "P" frames saved together keep their colors."""
+        img = _four_colors().convert("P", palette="ADAPTIVE")
+        blue = Image.new("RGB", (2, 2), "blue").convert("P")
+        for format in ("PNG", "GIF"):
+            result = Image.open(saved(img, format, save_all=True, append_images=[blue]))
+            assert result.n_frames == 2
+            assert result.convert("RGB").to_bytes() == _four_colors().to_bytes()
+            result.seek(1)
+            assert result.convert("RGB").to_bytes() == bytes([0, 0, 255]) * 4
+
+    def test_gif(self):
+        """Test GIF uses the image's palette without requantizing."""
+        img = _four_colors().convert("P", palette="ADAPTIVE")
+        data = saved(img, "GIF")
+        assert Image.open(data).convert("RGB").to_bytes() == _four_colors().to_bytes()
+
+    def test_other_formats(self):
+        """Test formats without palettes save the expanded colors."""
+        img = _four_colors().convert("P", palette="ADAPTIVE")
+        data = saved(img, "BMP")
+        assert Image.open(data).to_bytes() == _four_colors().to_bytes()
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
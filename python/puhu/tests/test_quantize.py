@@ -0,0 +1,185 @@
+"""
+Tests for quantize() with median cut, octree, NeuQuant and k-means
+"""
+
+import io
+
+import pytest
+
+from puhu import Image, Quantize
+from puhu._core import PuhuProcessingError
+
+METHODS = [Quantize.MEDIANCUT, Quantize.FASTOCTREE, Quantize.NEUQUANT]
+
+
+def _gradient(size=32):
+    """RGB image with a distinct color in every pixel."""
+    img = Image.new("RGB", (size, size))
+    for y in range(size):
+        for x in range(size):
+            color = (x * 255 // size, y * 255 // size, 128)
+            img.paste(color, (x, y, x + 1, y + 1))
+    return img
+
+
+def _mean_error(a, b):
+    errors = [abs(x - y) for x, y in zip(a.to_bytes(), b.to_bytes())]
+    return sum(errors) / len(errors)
+
+
+class TestQuantize:
+    """Test building palettes with each method."""
+
+    @pytest.mark.parametrize("method", METHODS)
+    def test_colors(self, method):
+        """Test the palette has at most the requested number of colors."""
+        img = _gradient()
+        result = img.quantize(16, method=method, dither="NONE")
+        assert result.mode == "P"
+        assert result.size == img.size
+        assert len(result.getpalette()) <= 16 * 3
+        assert max(result.to_bytes()) < 16
+        assert _mean_error(result.convert("RGB"), img) < 20
+
+    @pytest.mark.parametrize("method", METHODS)
+    def test_exact_colors(self, method):
+        """Test images with few colors keep them exactly."""
+        img = Image.new("RGB", (4, 4), (10, 20, 30))
+        img.paste((200, 100, 0), (0, 0, 2, 4))
+        result = img.quantize(8, method=method)
+        assert len(result.getpalette()) == 2 * 3
+        assert result.convert("RGB").to_bytes() == img.to_bytes()
+
+    @pytest.mark.parametrize("method", METHODS)
+    def test_palette_length(self, method):
+        """Test the palette has as many colors as asked for when there are enough."""
+        img = _gradient(8)
+        for colors in [1, 2, 3, 4, 7, 16, 100, 256]:
+            result = img.quantize(colors, method=method, dither="NONE")
+            assert len(result.getpalette()) == min(colors, 64) * 3
+
+    def test_default_colors(self):
+        """Test up to 256 colors are used by default."""
+        result = _gradient(20).quantize()
+        assert len(result.getpalette()) == 256 * 3
+
+    def test_pillow_constants(self):
+        """Test Pillow's integer method constants."""
+        img = _gradient(8)
+        assert img.quantize(4, method=0).to_bytes() == (
+            img.quantize(4, method=Quantize.MEDIANCUT).to_bytes()
+        )
+        assert img.quantize(4, method=2).mode == "P"
+
+    def test_kmeans(self):
+        """Test k-means refinement does not make the palette worse."""
+        img = _gradient()
+        plain = img.quantize(8, dither="NONE").convert("RGB")
+        refined = img.quantize(8, kmeans=5, dither="NONE").convert("RGB")
+        assert _mean_error(refined, img) <= _mean_error(plain, img) + 0.5
+
+    def test_dither(self):
+        """Test dithering keeps the average color closer to the source."""
+        img = Image.new("RGB", (16, 16), (100, 100, 100))
+        img.paste((0, 0, 0), (0, 0, 1, 1))
+        img.paste((255, 255, 255), (1, 0, 2, 1))
+        palette = Image.new("P", (1, 1))
+        palette.putpalette([0, 0, 0, 255, 255, 255])
+        plain = img.quantize(palette=palette, dither="NONE").convert("L")
+        dithered = img.quantize(palette=palette).convert("L")
+        assert set(plain.to_bytes()[2:]) == {0}
+        mean = sum(dithered.to_bytes()) / 256
+        assert abs(mean - 100) < 10
+
+
+class TestAlpha:
+    """Test alpha-aware quantization of RGBA images."""
+
+    def test_transparent_entry(self):
+        """Test fully transparent pixels share a transparent entry."""
+        img = _gradient(16).convert("RGBA")
+        img.paste((50, 60, 70, 0), (0, 0, 8, 16))
+        result = img.quantize(8)
+        transparency = result.info["transparency"]
+        assert result.getpalette("RGBA")[transparency * 4 + 3] == 0
+        rows = [result.to_bytes()[y * 16 : y * 16 + 16] for y in range(16)]
+        assert all(set(row[:8]) == {transparency} for row in rows)
+        assert all(transparency not in row[8:] for row in rows)
+
+    def test_partial_alpha(self):
+        """Test partially transparent colors keep their alpha."""
+        img = Image.new("RGBA", (4, 1), (255, 0, 0, 255))
+        img.paste((0, 0, 255, 100), (2, 0, 4, 1))
+        result = img.quantize(2)
+        assert result.convert("RGBA").to_bytes() == img.to_bytes()
+        assert sorted(result.getpalette("RGBA")[3::4]) == [100, 255]
+        assert result.info["transparency"] == bytes(result.getpalette("RGBA")[3::4])
+
+    @pytest.mark.parametrize("method", METHODS)
+    def test_many_colors(self, method):
+        """Test every method quantizes RGBA images with alpha."""
+        img = _gradient().convert("RGBA")
+        img.paste((0, 0, 0, 0), (0, 0, 4, 4))
+        result = img.quantize(16, method=method, dither="NONE")
+        assert len(result.getpalette()) <= 16 * 3
+        assert result.convert("RGBA").to_bytes()[3] == 0
+
+    def test_png_alpha(self):
+        """Test partial alpha is written to the PNG tRNS chunk."""
+        img = Image.new("RGBA", (4, 1), (255, 0, 0, 255))
+        img.paste((0, 0, 255, 100), (2, 0, 4, 1))
+        buffer = io.BytesIO()
+        img.quantize(2).save(buffer, format="PNG")
+        assert Image.open(buffer.getvalue()).convert("RGBA").to_bytes() == (
+            img.to_bytes()
+        )
+
+
+class TestPaletteImage:
+    """Test quantizing to the palette of another image."""
+
+    def test_palette_from_image(self):
+        """Test the other image's palette is used as is."""
+        source = Image.new("P", (1, 1))
+        source.putpalette([255, 0, 0, 0, 0, 255])
+        img = Image.new("RGB", (2, 1), (250, 10, 10))
+        img.paste((10, 10, 240), (1, 0, 2, 1))
+        result = img.quantize(palette=source, dither="NONE")
+        assert result.getpalette() == [255, 0, 0, 0, 0, 255]
+        assert result.to_bytes() == bytes([0, 1])
+
+    def test_not_palette_image(self):
+        """Test the palette image must be a "P" image."""
+        img = Image.new("RGB", (2, 2))
+        with pytest.raises(PuhuProcessingError):
+            img.quantize(palette=Image.new("RGB", (1, 1)))
+
+    def test_own_palette(self):
+        """Test a "P" image can be quantized to its own palette."""
+        img = _gradient(8).quantize(4)
+        assert img.quantize(palette=img).to_bytes() == img.to_bytes()
+
+
+class TestErrors:
+    """Test invalid quantize arguments."""
+
+    @pytest.mark.parametrize("colors", [0, 257])
+    def test_colors_range(self, colors):
+        """Test the number of colors must be between 1 and 256."""
+        with pytest.raises(PuhuProcessingError):
+            _gradient(4).quantize(colors)
+
+    @pytest.mark.parametrize("method", ["OCTREE", 1, 3])
+    def test_unknown_method(self, method):
+        """Test unknown and unsupported methods are rejected."""
+        with pytest.raises(PuhuProcessingError):
+            _gradient(4).quantize(4, method=method)
+
+    def test_unknown_dither(self):
+        """Test unknown dither methods are rejected."""
+        with pytest.raises(PuhuProcessingError):
+            _gradient(4).quantize(4, dither="RANDOM")
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
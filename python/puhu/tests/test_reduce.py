@@ -0,0 +1,208 @@
+"""
+Tests for Image.draft and Image.reduce
+"""
+
+import io
+import struct
+
+import pytest
+
+from puhu import DecompressionBombError, Image
+from puhu._core import PuhuProcessingError
+
+from .helpers import pixels, saved
+
+
+class TestDraft:
+    """Test configuring the JPEG decoder before loading."""
+
+    @pytest.mark.parametrize(
+        "requested, expected",
+        [
+            ((1000, 1000), (800, 600)),
+            ((400, 300), (400, 300)),
+            ((300, 200), (400, 300)),
+            ((200, 150), (200, 150)),
+            ((100, 75), (100, 75)),
+            ((10, 10), (100, 75)),
+        ],
+    )
+    def test_scale(self, requested, expected):
+        """Test the largest scale keeping the requested size is chosen."""
+        img = Image.open(saved(Image.new("RGB", (800, 600), "red"), "JPEG", quality=95))
+        mode, box = img.draft("RGB", requested)
+        assert mode == "RGB"
+        assert img.size == expected
+        scale = 800 // expected[0]
+        assert box == (0, 0, 800 / scale, 600 / scale)
+
+    def test_decoded_size(self):
+        """Test the decoded image has the reported size and colors."""
+        img = Image.open(saved(Image.new("RGB", (803, 601), "red"), "JPEG", quality=95))
+        img.draft(None, (100, 75))
+        assert img.size == (101, 76)
+        colors = pixels(img)
+        assert len(colors) == 101 * 76
+        assert all(abs(a - b) <= 3 for a, b in zip(colors[0], (255, 0, 0)))
+
+    def test_grayscale(self):
+        """Test color files are decoded to "L" on request."""
+        data = saved(Image.new("RGB", (64, 64), (200, 200, 200)), "JPEG", quality=95)
+        img = Image.open(data)
+        mode, _ = img.draft("L", None)
+        assert mode == "L"
+        assert img.mode == "L"
+        assert img.size == (64, 64)
+        assert all(abs(v - 200) <= 3 for v in img.to_bytes())
+
+    def test_redraft(self):
+        """Test a later call chooses the scale from the full size again."""
+        img = Image.open(saved(Image.new("RGB", (800, 600), "red"), "JPEG", quality=95))
+        img.draft("RGB", (100, 75))
+        img.draft("RGB", (400, 300))
+        assert img.size == (400, 300)
+
+    def test_unsupported_mode_ignored(self):
+        """Test modes the decoder cannot produce leave the mode as it is."""
+        img = Image.open(saved(Image.new("RGB", (64, 64), "red"), "JPEG", quality=95))
+        mode, _ = img.draft("P", None)
+        assert mode == "RGB"
+
+    def test_cmyk_kept(self):
+        """Test CMYK files keep their inks."""
+        data = saved(Image.new("CMYK", (64, 64), (0, 0, 0, 255)), "JPEG", quality=95)
+        img = Image.open(data)
+        mode, _ = img.draft("L", (16, 16))
+        assert mode == "CMYK"
+        assert img.size == (16, 16)
+
+    def test_matches_reduce(self):
+        """Test drafted colors match a full-size decode reduced by the scale."""
+        img = Image.new("RGB", (64, 64), "red")
+        img.paste(Image.new("RGB", (32, 16), (20, 200, 90)), (16, 32))
+        data = saved(img, "JPEG", quality=95)
+        for scale in (2, 4, 8):
+            img = Image.open(data)
+            img.draft("RGB", (64 // scale, 64 // scale))
+            expected = pixels(Image.open(data).reduce(scale))
+            for color, reduced in zip(pixels(img), expected):
+                assert all(abs(a - b) <= 3 for a, b in zip(color, reduced))
+
+    def test_progressive(self):
+        """Test progressive files, decoded at full size, are reduced too."""
+        data = saved(Image.new("RGB", (800, 600), "red"), "JPEG", progressive=True)
+        img = Image.open(data)
+        img.draft("RGB", (200, 150))
+        assert img.size == (200, 150)
+        assert all(abs(a - b) <= 3 for a, b in zip(pixels(img)[0], (255, 0, 0)))
+
+    def test_memory_limit(self):
+        """Test the file is decoded at the draft scale within the memory limit."""
+        data = saved(Image.new("RGB", (2000, 1500), "red"), "JPEG")
+        with pytest.raises(DecompressionBombError):
+            Image.open(data, max_alloc=1_000_000).to_bytes()
+        img = Image.open(data, max_alloc=1_000_000)
+        img.draft("RGB", (250, 187))
+        assert img.size == (250, 188)
+        assert len(img.to_bytes()) == 250 * 188 * 3
+
+    def test_decoded_image(self):
+        """Test images that are already decoded are left alone."""
+        img = Image.open(saved(Image.new("RGB", (64, 64), "red"), "JPEG", quality=95))
+        img.to_bytes()
+        assert img.draft("L", (8, 8)) is None
+        assert img.size == (64, 64)
+
+    def test_other_formats(self):
+        """Test formats without draft support are left alone."""
+        buf = io.BytesIO()
+        Image.new("RGB", (64, 64)).save(buf, format="PNG")
+        img = Image.open(buf.getvalue())
+        assert img.draft("L", (8, 8)) is None
+        assert (img.mode, img.size) == ("RGB", (64, 64))
+        assert Image.new("RGB", (64, 64)).draft("L", (8, 8)) is None
+
+
+class TestReduce:
+    """Test integer box-averaging downscaling."""
+
+    def test_averages_blocks(self):
+        """Test each output pixel is the mean of a block."""
+        img = Image.new("L", (4, 2), 0)
+        img.paste(100, (0, 0, 1, 1))
+        img.paste(200, (2, 0, 4, 2))
+        assert list(img.reduce(2).to_bytes()) == [25, 200]
+
+    def test_factor_pair(self):
+        """Test separate horizontal and vertical factors."""
+        img = Image.new("RGB", (9, 4), (10, 20, 30))
+        reduced = img.reduce((3, 2))
+        assert reduced.size == (3, 2)
+        assert set(pixels(reduced)) == {(10, 20, 30)}
+
+    def test_partial_edge_blocks(self):
+        """Test edge blocks smaller than the factor average their own pixels."""
+        img = Image.new("L", (5, 1), 0)
+        img.paste(90, (4, 0, 5, 1))
+        reduced = img.reduce((2, 1))
+        assert reduced.size == (3, 1)
+        assert list(reduced.to_bytes()) == [0, 0, 90]
+
+    def test_box(self):
+        """Test only the given region is reduced."""
+        img = Image.new("L", (8, 8), 0)
+        img.paste(255, (4, 0, 8, 8))
+        reduced = img.reduce(2, box=(4, 2, 8, 6))
+        assert reduced.size == (2, 2)
+        assert set(reduced.to_bytes()) == {255}
+
+    def test_factor_one(self):
+        """Test a factor of 1 copies the image or its box."""
+        img = Image.new("RGB", (6, 4), "blue")
+        assert img.reduce(1).to_bytes() == img.to_bytes()
+        assert img.reduce(1, (1, 1, 4, 3)).size == (3, 2)
+
+    def test_alpha_not_darkened(self):
+        """Test transparent pixels do not darken the reduced colors."""
+        img = Image.new("RGBA", (2, 1), (0, 0, 0, 0))
+        img.paste((255, 255, 255, 255), (0, 0, 1, 1))
+        assert pixels(img.reduce(2)) == [(255, 255, 255, 128)]
+
+    def test_int_values_rounded(self):
+        """Test "I" images keep whole values."""
+        img = Image.new("I", (2, 1), 0)
+        img.paste(3, (0, 0, 1, 1))
+        (value,) = struct.unpack("<i", img.reduce(2).to_bytes())
+        assert value == 2
+
+    @pytest.mark.parametrize("mode", ["LA", "I;16", "RGBA;16", "F", "CMYK"])
+    def test_modes(self, mode):
+        """Test the mode is kept."""
+        img = Image.new(mode, (6, 6))
+        reduced = img.reduce(3)
+        assert reduced.mode == mode
+        assert reduced.size == (2, 2)
+
+    def test_lazy_jpeg(self):
+        """Test images that are not yet decoded are reduced."""
+        img = Image.open(saved(Image.new("RGB", (100, 60), "red"), "JPEG", quality=95))
+        assert img.reduce(4).size == (25, 15)
+
+    @pytest.mark.parametrize(
+        "factor, box",
+        [(0, None), ((2, 0), None), (2, (0, 0, 9, 4)), (2, (2, 2, 2, 4))],
+    )
+    def test_invalid(self, factor, box):
+        """Test zero factors and boxes outside the image are rejected."""
+        with pytest.raises(PuhuProcessingError):
+            Image.new("RGB", (8, 4)).reduce(factor, box)
+
+    def test_palette(self):
+        """Test palette images must be converted first."""
+        img = Image.new("RGB", (4, 4)).convert("P")
+        with pytest.raises(PuhuProcessingError):
+            img.reduce(2)
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
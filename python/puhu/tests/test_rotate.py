@@ -0,0 +1,164 @@
+"""
+Tests for rotating images by arbitrary angles
+"""
+
+import struct
+
+import pytest
+
+from puhu import Image, operations
+from puhu._core import PuhuProcessingError
+
+from .helpers import pixels
+
+RED = (255, 0, 0)
+GREEN = (0, 255, 0)
+BLUE = (0, 0, 255)
+
+
+def _row(*colors):
+    """RGB image one pixel high with the given colors."""
+    img = Image.new("RGB", (len(colors), 1))
+    for x, color in enumerate(colors):
+        img.paste(color, (x, 0, x + 1, 1))
+    return img
+
+
+class TestQuarterTurns:
+    """Test exact rotations by multiples of 90 degrees."""
+
+    def test_counter_clockwise(self):
+        """Test positive angles turn counter-clockwise."""
+        rotated = _row(RED, BLUE).rotate(90, expand=True)
+        assert rotated.size == (1, 2)
+        assert pixels(rotated) == [BLUE, RED]
+
+    def test_clockwise(self):
+        """Test 270 degrees turns clockwise, like -90."""
+        img = _row(RED, BLUE)
+        assert pixels(img.rotate(270, expand=True)) == [RED, BLUE]
+        assert pixels(img.rotate(-90, expand=True)) == [RED, BLUE]
+
+    def test_half_turn(self):
+        """Test 180 degrees reverses the pixels."""
+        assert pixels(_row(RED, GREEN, BLUE).rotate(180)) == [BLUE, GREEN, RED]
+
+    def test_full_turn(self):
+        """Test whole turns keep the image as it is."""
+        img = _row(RED, GREEN, BLUE)
+        assert img.rotate(360).to_bytes() == img.to_bytes()
+
+    def test_without_expand_keeps_size(self):
+        """Test quarter turns of oblong images keep their size without expand."""
+        rotated = Image.new("RGB", (4, 2), "white").rotate(90)
+        assert rotated.size == (4, 2)
+        # The corners of the output are outside the rotated image
+        assert pixels(rotated)[0] == (0, 0, 0)
+
+
+class TestArbitraryAngles:
+    """Test rotations that sample the image."""
+
+    def test_expand_size(self):
+        """Test expand fits the bounding box of the rotated image."""
+        img = Image.new("RGB", (100, 100))
+        assert img.rotate(45).size == (100, 100)
+        assert img.rotate(45, expand=True).size == (142, 142)
+        assert Image.new("RGB", (40, 10)).rotate(2.7, expand=True).size == (42, 12)
+
+    @pytest.mark.parametrize("resample", ["NEAREST", "BILINEAR", "BICUBIC"])
+    def test_interior_kept(self, resample):
+        """Test each filter keeps the color of a uniform interior."""
+        img = Image.new("RGB", (20, 20), (10, 200, 30))
+        rotated = img.rotate(30, resample=resample)
+        assert pixels(rotated)[10 * 20 + 10] == (10, 200, 30)
+
+    def test_integer_resample(self):
+        """Test Pillow's integer filter constants are accepted."""
+        img = Image.new("L", (10, 10), 255)
+        assert img.rotate(10, resample=2).mode == "L"
+
+    def test_unsupported_resample(self):
+        """Test filters other than NEAREST, BILINEAR and BICUBIC are rejected."""
+        with pytest.raises(PuhuProcessingError):
+            Image.new("RGB", (10, 10)).rotate(10, resample="LANCZOS")
+
+    def test_fillcolor(self):
+        """Test the area outside the rotated image takes the fill color."""
+        rotated = Image.new("RGB", (10, 10), "white").rotate(45, fillcolor="red")
+        assert pixels(rotated)[0] == RED
+
+    def test_edges_blend_into_fill(self):
+        """Test interpolated edges mix the image with the fill color."""
+        rotated = Image.new("L", (20, 20), 255).rotate(30, resample="BILINEAR")
+        assert any(0 < v < 255 for v in rotated.to_bytes())
+
+    def test_translate(self):
+        """Test translate moves the image after rotating."""
+        rotated = _row(RED, GREEN, BLUE).rotate(0, translate=(1, 0))
+        assert pixels(rotated) == [(0, 0, 0), RED, GREEN]
+
+    def test_center(self):
+        """Test the image turns around the given center."""
+        img = Image.new("RGB", (3, 3), "white")
+        rotated = img.rotate(180, center=(0.5, 0.5), fillcolor="blue")
+        colors = pixels(rotated)
+        assert colors[0] == (255, 255, 255)
+        assert colors[1:] == [BLUE] * 8
+
+    def test_operations_rotate(self):
+        """Test the operations helper passes expand through."""
+        assert operations.rotate(Image.new("RGB", (4, 2)), 90, True).size == (2, 4)
+
+
+class TestModes:
+    """Test rotating images of different modes."""
+
+    @pytest.mark.parametrize("resample", ["BILINEAR", "BICUBIC"])
+    def test_alpha_edges_keep_color(self, resample):
+        """Test transparent fill fades RGBA edges without darkening them."""
+        img = Image.new("RGBA", (20, 20), (255, 255, 255, 255))
+        colors = pixels(img.rotate(30, resample=resample))
+        edges = [p for p in colors if 0 < p[3] < 255]
+        assert edges
+        assert all(p[:3] == (255, 255, 255) for p in edges)
+
+    def test_palette_uses_indices(self):
+        """Test palette images keep their indices and take an index as fill."""
+        img = Image.new("P", (10, 10), 1)
+        img.putpalette([0, 0, 0, 255, 0, 0, 0, 0, 255])
+        rotated = img.rotate(45, resample="BICUBIC", fillcolor=2)
+        assert rotated.mode == "P"
+        assert set(rotated.to_bytes()) == {1, 2}
+
+    def test_palette_color_fill(self):
+        """Test colors are mapped to the nearest palette entry."""
+        img = Image.new("P", (10, 10), 1)
+        img.putpalette([0, 0, 0, 255, 0, 0, 0, 0, 255])
+        assert img.rotate(45, fillcolor="blue").to_bytes()[0] == 2
+
+    @pytest.mark.parametrize("mode", ["I;16", "RGBA;16", "I", "F"])
+    def test_deep_modes(self, mode):
+        """Test deep modes keep their mode and the values of their interior."""
+        img = Image.new(mode, (10, 10), 1000 if mode == "I" else 0.5)
+        rotated = img.rotate(20, resample="BILINEAR")
+        assert rotated.mode == mode
+        assert rotated.size == (10, 10)
+
+    def test_int_interior(self):
+        """Test "I" images keep the values of their interior."""
+        img = Image.new("I", (10, 10), 100001)
+        data = img.rotate(20, resample="BICUBIC").to_bytes()
+        (value,) = struct.unpack("<i", data[(5 * 10 + 5) * 4 :][:4])
+        assert value == 100001
+
+    def test_cmyk_black_is_not_alpha(self):
+        """Test the black ink of CMYK images is interpolated like other inks."""
+        img = Image.new("CMYK", (10, 10), (0, 0, 0, 255))
+        rotated = img.rotate(30, resample="BILINEAR")
+        assert rotated.mode == "CMYK"
+        assert pixels(rotated)[5 * 10 + 5] == (0, 0, 0, 255)
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
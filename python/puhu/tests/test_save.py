@@ -0,0 +1,321 @@
+import io
+import tempfile
+from pathlib import Path
+
+import pytest
+
+from puhu import Image
+
+
+class TestSaveBuffer:
+    """Test cases for saving to buffers and file-like objects."""
+
+    def test_save_to_bytesio(self):
+        """Test saving PNG data into a BytesIO."""
+        img = Image.new("RGB", (20, 10), "red")
+        buf = io.BytesIO()
+        img.save(buf, format="PNG")
+
+        data = buf.getvalue()
+        assert data.startswith(b"\x89PNG\r\n\x1a\n")
+
+        loaded = Image.open(data)
+        assert loaded.size == (20, 10)
+        assert loaded.format == "PNG"
+
+    def test_save_to_bytearray(self):
+        """Test that encoded bytes are appended to a bytearray."""
+        img = Image.new("RGB", (8, 8), "blue")
+        buf = bytearray(b"prefix")
+        img.save(buf, format="JPEG")
+
+        assert buf.startswith(b"prefix")
+        assert buf[6:9] == b"\xff\xd8\xff"
+
+    def test_save_to_custom_writer(self):
+        """Test saving to any object with a write() method."""
+
+        class Writer:
+            def __init__(self):
+                self.chunks = []
+
+            def write(self, data):
+                self.chunks.append(data)
+                return len(data)
+
+        img = Image.new("L", (4, 4), 128)
+        writer = Writer()
+        img.save(writer, format="BMP")
+
+        data = b"".join(writer.chunks)
+        assert data.startswith(b"BM")
+
+    def test_save_to_open_file(self):
+        """Test saving to a file opened in binary mode."""
+        img = Image.new("RGB", (5, 5), "green")
+
+        with tempfile.TemporaryDirectory() as temp_dir:
+            file_path = Path(temp_dir) / "out.bin"
+            with open(file_path, "wb") as f:
+                img.save(f, format="PNG")
+
+            loaded = Image.open(file_path.read_bytes())
+            assert loaded.size == (5, 5)
+
+    def test_save_buffer_requires_format(self):
+        """Test that saving to a buffer without format fails."""
+        img = Image.new("RGB", (5, 5))
+        with pytest.raises(Exception, match="Format must be specified"):
+            img.save(io.BytesIO())
+
+    def test_save_buffer_unknown_format(self):
+        """Test that an unknown format is rejected."""
+        img = Image.new("RGB", (5, 5))
+        with pytest.raises(Exception, match="Unsupported format"):
+            img.save(io.BytesIO(), format="NOPE")
+
+    def test_save_invalid_target(self):
+        """Test that objects without write() are rejected."""
+        img = Image.new("RGB", (5, 5))
+        with pytest.raises(Exception, match="write\\(\\) method"):
+            img.save(12345, format="PNG")
+
+
+class TestSaveOptions:
+    """Test cases for format-specific save options."""
+
+    @staticmethod
+    def _gradient(size=(64, 64)):
+        img = Image.new("RGB", size)
+        for x in range(0, size[0], 8):
+            color = (x * 4 % 256, 255 - x * 4 % 256, x % 256)
+            img.paste(color, (x, 0, x + 8, size[1]))
+        return img
+
+    def _encode(self, img, fmt, **options):
+        buf = io.BytesIO()
+        img.save(buf, format=fmt, **options)
+        return buf.getvalue()
+
+    def test_jpeg_quality(self):
+        """Test that lower JPEG quality produces smaller output."""
+        img = self._gradient()
+        low = self._encode(img, "JPEG", quality=10)
+        high = self._encode(img, "JPEG", quality=95)
+        assert len(low) < len(high)
+
+    def test_jpeg_progressive(self):
+        """Test that progressive JPEGs use an SOF2 marker."""
+        img = self._gradient()
+        baseline = self._encode(img, "JPEG")
+        progressive = self._encode(img, "JPEG", progressive=True)
+        assert b"\xff\xc2" not in baseline
+        assert b"\xff\xc2" in progressive
+        assert Image.open(progressive).size == (64, 64)
+
+    def test_jpeg_subsampling_and_optimize(self):
+        """Test JPEG subsampling and optimize options."""
+        img = self._gradient()
+        for subsampling in (0, 1, 2, "4:2:0"):
+            data = self._encode(img, "JPEG", subsampling=subsampling, optimize=True)
+            assert Image.open(data).size == (64, 64)
+
+    def test_png_compress_level(self):
+        """Test that compress_level changes PNG output size."""
+        img = self._gradient()
+        stored = self._encode(img, "PNG", compress_level=0)
+        best = self._encode(img, "PNG", compress_level=9)
+        assert len(best) < len(stored)
+
+    def test_png_dpi(self):
+        """Test that dpi is written as a pHYs chunk."""
+        data = self._encode(Image.new("RGB", (4, 4)), "PNG", dpi=(300, 300))
+        # 300 dpi is 11811 pixels per metre
+        assert b"pHYs" + (11811).to_bytes(4, "big") * 2 + b"\x01" in data
+
+    def test_tiff_compression(self):
+        """Test TIFF compression options."""
+        img = Image.new("RGB", (64, 64), "white")
+        raw = self._encode(img, "TIFF")
+        for compression in ("tiff_lzw", "tiff_deflate", "packbits"):
+            data = self._encode(img, "TIFF", compression=compression)
+            assert len(data) < len(raw)
+            loaded = Image.open(data)
+            assert loaded.size == (64, 64)
+            assert loaded.to_bytes() == img.to_bytes()
+
+    def test_none_uses_default(self):
+        """Test that None is accepted as the default for any option."""
+        data = self._encode(Image.new("RGB", (4, 4)), "JPEG", quality=None)
+        assert data.startswith(b"\xff\xd8")
+
+    def test_unknown_option(self):
+        """Test that unknown options are rejected instead of ignored."""
+        img = Image.new("RGB", (4, 4))
+        with pytest.raises(Exception, match="Unknown save option"):
+            self._encode(img, "PNG", qualty=90)
+
+    def test_option_not_supported_for_format(self):
+        """Test that options are checked against the output format."""
+        from puhu._core import UnsupportedFormatError
+
+        img = Image.new("RGB", (4, 4))
+        with pytest.raises(UnsupportedFormatError):
+            self._encode(img, "PNG", quality=90)
+        with pytest.raises(UnsupportedFormatError):
+            self._encode(img, "BMP", optimize=True)
+
+    def test_invalid_option_values(self):
+        """Test that out-of-range values are rejected."""
+        img = Image.new("RGB", (4, 4))
+        with pytest.raises(Exception, match="quality"):
+            self._encode(img, "JPEG", quality=101)
+        with pytest.raises(Exception, match="compress_level"):
+            self._encode(img, "PNG", compress_level=10)
+        with pytest.raises(Exception, match="subsampling"):
+            self._encode(img, "JPEG", subsampling="4:1:1")
+        with pytest.raises(Exception, match="TIFF compression"):
+            self._encode(img, "TIFF", compression="jpeg")
+
+    def test_option_truthiness_errors(self):
+        """Test that exceptions raised by __bool__ are not swallowed."""
+
+        class Ambiguous:
+            def __bool__(self):
+                raise ValueError("ambiguous truth value")
+
+        img = Image.new("RGB", (4, 4))
+        with pytest.raises(ValueError, match="ambiguous"):
+            self._encode(img, "JPEG", optimize=Ambiguous())
+
+    @pytest.mark.parametrize("mode", ["RGBA", "LA", "RGBA;16"])
+    def test_jpeg_alpha_rejected(self, mode):
+        """Test that JPEG asks for images with alpha to be converted first."""
+        from puhu._core import PuhuProcessingError
+
+        with pytest.raises(PuhuProcessingError, match="Cannot write mode"):
+            self._encode(Image.new(mode, (4, 4)), "JPEG")
+
+    def test_webp_lossless(self):
+        """Test that lossless WebP is accepted and lossy WebP is rejected."""
+        from puhu._core import UnsupportedFormatError
+
+        img = Image.new("RGB", (4, 4), "red")
+        data = self._encode(img, "WEBP", lossless=True)
+        assert data[8:12] == b"WEBP"
+        with pytest.raises(UnsupportedFormatError):
+            self._encode(img, "WEBP", lossless=False)
+
+    def test_options_with_path(self):
+        """Test that options also apply when saving to a path."""
+        img = self._gradient()
+        with tempfile.TemporaryDirectory() as temp_dir:
+            low = Path(temp_dir) / "low.jpg"
+            high = Path(temp_dir) / "high.jpg"
+            img.save(low, quality=10)
+            img.save(high, quality=95)
+            assert low.stat().st_size < high.stat().st_size
+
+
+
+class TestMultiPageTiff:
+    """Test saving every frame as a TIFF page."""
+
+    def _save_all(self, img, **options):
+        buf = io.BytesIO()
+        img.save(buf, format="TIFF", save_all=True, **options)
+        return buf.getvalue()
+
+    def test_pages(self):
+        """Test each page keeps its own size and mode."""
+        first = Image.new("RGB", (4, 4), "red")
+        second = Image.new("L", (3, 2), 128)
+        loaded = Image.open(self._save_all(first, append_images=[second]))
+        assert loaded.n_frames == 2
+        assert loaded.to_bytes() == first.to_bytes()
+        loaded.seek(1)
+        assert loaded.size == (3, 2)
+        assert loaded.mode == "L"
+        assert loaded.to_bytes() == second.to_bytes()
+
+    def test_per_page_modes(self):
+        """Test each page is written in its own mode."""
+        pages = [
+            Image.new("F", (2, 1), 1.5),
+            Image.new("RGB", (3, 1), "red"),
+            Image.new("I", (2, 2), -70000),
+            Image.new("L", (1, 1), 9),
+        ]
+        loaded = Image.open(self._save_all(pages[0], append_images=pages[1:]))
+        assert loaded.n_frames == len(pages)
+        for i, page in enumerate(pages):
+            loaded.seek(i)
+            assert (loaded.mode, loaded.size) == (page.mode, page.size)
+            assert loaded.to_bytes() == page.to_bytes()
+
+    def test_per_page_compression(self):
+        """Test compression can be given for each page."""
+        pages = [Image.new("RGB", (64, 64), "white") for _ in range(2)]
+        raw = self._save_all(pages[0], append_images=pages[1:])
+        mixed = self._save_all(
+            pages[0], append_images=pages[1:], compression=["raw", "tiff_lzw"]
+        )
+        packed = self._save_all(pages[0], append_images=pages[1:], compression="lzw")
+        assert len(packed) < len(mixed) < len(raw)
+        loaded = Image.open(mixed)
+        loaded.seek(1)
+        assert loaded.to_bytes() == pages[1].to_bytes()
+
+    def test_compression_count(self):
+        """Test a compression list must have one value per page."""
+        img = Image.new("L", (4, 4))
+        with pytest.raises(Exception, match="compression"):
+            self._save_all(img, append_images=[img], compression=["raw"] * 3)
+
+
+class TestIco:
+    """Test saving ICO files with several icon sizes."""
+
+    @staticmethod
+    def _entries(data):
+        """Sizes in the ICO directory; a stored 0 means 256."""
+        count = int.from_bytes(data[4:6], "little")
+        entries = [data[6 + 16 * i : 8 + 16 * i] for i in range(count)]
+        return sorted((w or 256, h or 256) for w, h in entries)
+
+    def _encode(self, img, **options):
+        buf = io.BytesIO()
+        img.save(buf, format="ICO", **options)
+        return buf.getvalue()
+
+    def test_sizes(self):
+        """Test an icon is written for each requested size."""
+        img = Image.new("RGB", (256, 256), "blue")
+        data = self._encode(img, sizes=[(16, 16), (32, 32), (256, 256)])
+        assert self._entries(data) == [(16, 16), (32, 32), (256, 256)]
+        loaded = Image.open(data)
+        assert loaded.size == (256, 256)
+        assert loaded.convert("RGB").to_bytes()[:3] == b"\x00\x00\xff"
+
+    def test_default_sizes(self):
+        """Test sizes larger than the image are skipped."""
+        data = self._encode(Image.new("RGBA", (48, 48)))
+        assert self._entries(data) == [(16, 16), (24, 24), (32, 32), (48, 48)]
+
+    def test_small_image(self):
+        """Test an image smaller than every size is stored as is."""
+        data = self._encode(Image.new("RGB", (10, 12)))
+        assert self._entries(data) == [(10, 12)]
+
+    def test_aspect_ratio(self):
+        """Test icons keep the aspect ratio of the image."""
+        data = self._encode(Image.new("RGB", (64, 32)), sizes=[(32, 32)])
+        assert self._entries(data) == [(32, 16)]
+
+    def test_invalid_sizes(self):
+        """Test sizes must be between 1 and 256."""
+        with pytest.raises(Exception, match="sizes"):
+            self._encode(Image.new("RGB", (512, 512)), sizes=[(512, 512)])
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
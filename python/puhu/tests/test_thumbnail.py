@@ -0,0 +1,146 @@
+"""
+Tests for Image.thumbnail
+"""
+
+import io
+
+import pytest
+
+from puhu import Image
+from puhu._core import PuhuProcessingError
+
+from .helpers import saved
+
+
+class TestSize:
+    """Test the size thumbnails are given."""
+
+    @pytest.mark.parametrize(
+        "size, bounds, expected",
+        [
+            ((400, 300), (100, 100), (100, 75)),
+            ((300, 400), (100, 100), (75, 100)),
+            ((400, 300), (200, 50), (67, 50)),
+            ((1000, 10), (50, 50), (50, 1)),
+            ((10, 1000), (50, 50), (1, 50)),
+            ((99, 33), (50, 50), (50, 17)),
+        ],
+    )
+    def test_keeps_aspect_ratio(self, size, bounds, expected):
+        """Test the image fits the bounds with its aspect ratio kept."""
+        img = Image.new("RGB", size)
+        img.thumbnail(bounds)
+        assert img.size == expected
+
+    def test_never_enlarges(self):
+        """Test images that already fit are left as they are."""
+        img = Image.new("RGB", (40, 30), "blue")
+        img.thumbnail((100, 100))
+        assert img.size == (40, 30)
+
+    def test_float_bounds(self):
+        """Test fractional bounds are rounded down."""
+        img = Image.new("RGB", (400, 300))
+        img.thumbnail((100.9, 100.9))
+        assert img.size == (100, 75)
+
+    def test_zero_bounds(self):
+        """Test empty bounds are rejected."""
+        with pytest.raises(PuhuProcessingError):
+            Image.new("RGB", (10, 10)).thumbnail((0, 5))
+
+    def test_small_reducing_gap(self):
+        """Test reducing gaps below 1 are rejected."""
+        with pytest.raises(PuhuProcessingError):
+            Image.new("RGB", (10, 10)).thumbnail((5, 5), reducing_gap=0.5)
+
+
+class TestReducing:
+    """Test the reduction before the final filter."""
+
+    @pytest.mark.parametrize("reducing_gap", [None, 1.0, 2.0, 3.0])
+    def test_uniform_color_kept(self, reducing_gap):
+        """Test every reducing gap keeps the color of a uniform image."""
+        img = Image.new("RGB", (512, 384), (10, 200, 30))
+        img.thumbnail((64, 64), reducing_gap=reducing_gap)
+        assert img.size == (64, 48)
+        assert set(img.to_bytes()) == {10, 200, 30}
+
+    @pytest.mark.parametrize("resample", ["NEAREST", "BILINEAR", "BICUBIC", "LANCZOS"])
+    def test_filters(self, resample):
+        """Test each filter shrinks the image."""
+        img = Image.new("L", (300, 200), 128)
+        img.thumbnail((30, 30), resample=resample)
+        assert img.size == (30, 20)
+        assert set(img.to_bytes()) == {128}
+
+    def test_alpha(self):
+        """Test RGBA images keep their mode and opaque interior."""
+        img = Image.new("RGBA", (200, 100), (255, 255, 255, 255))
+        img.thumbnail((20, 20))
+        assert img.mode == "RGBA"
+        assert img.size == (20, 10)
+        assert set(img.to_bytes()) == {255}
+
+    def test_palette(self):
+        """Test palette images keep their palette entries."""
+        img = Image.new("RGB", (100, 100), "red").convert("P")
+        img.thumbnail((10, 10))
+        assert img.mode == "P"
+        assert img.size == (10, 10)
+
+    def test_deep_mode(self):
+        """Test 16-bit images keep their mode."""
+        img = Image.new("I;16", (100, 50), 40000)
+        img.thumbnail((10, 10))
+        assert img.mode == "I;16"
+        assert img.size == (10, 5)
+
+
+class TestJpeg:
+    """Test thumbnails of JPEG files that are not yet decoded."""
+
+    @pytest.mark.parametrize("reducing_gap", [None, 1.0, 2.0])
+    def test_shrink_on_load(self, reducing_gap):
+        """Test draft decoding gives the same size and colors."""
+        img = Image.open(saved(Image.new("RGB", (800, 600), "red"), "JPEG", quality=95))
+        img.thumbnail((100, 100), reducing_gap=reducing_gap)
+        assert img.size == (100, 75)
+        assert img.mode == "RGB"
+        data = img.to_bytes()
+        assert all(abs(a - b) <= 3 for a, b in zip(data[:3], (255, 0, 0)))
+
+    def test_odd_size(self):
+        """Test partial DCT blocks at the edges keep the aspect ratio."""
+        data = saved(Image.new("RGB", (1001, 333), "red"), "JPEG", quality=95)
+        img = Image.open(data)
+        img.thumbnail((64, 64))
+        assert img.size == (64, 21)
+
+    def test_grayscale(self):
+        """Test grayscale JPEGs stay grayscale."""
+        buf = io.BytesIO()
+        Image.new("L", (640, 480), 200).save(buf, format="JPEG")
+        img = Image.open(buf.getvalue())
+        img.thumbnail((80, 80))
+        assert img.mode == "L"
+        assert img.size == (80, 60)
+
+    def test_memory_limit(self):
+        """Test the file is decoded at the draft scale within the memory limit."""
+        data = saved(Image.new("RGB", (2000, 1500), "red"), "JPEG")
+        img = Image.open(data, max_alloc=1_000_000)
+        img.thumbnail((100, 100))
+        assert img.size == (100, 75)
+
+    def test_info_kept(self):
+        """Test metadata read from the file survives shrink-on-load."""
+        buf = io.BytesIO()
+        Image.new("RGB", (400, 400)).save(buf, format="JPEG", dpi=(300, 300))
+        img = Image.open(buf.getvalue())
+        img.thumbnail((50, 50))
+        assert img.info["dpi"] == (300, 300)
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,148 @@
+"""
+Tests for Image.transform with affine, extent, perspective, quad and mesh maps
+"""
+
+import pytest
+
+from puhu import Image, Transform
+from puhu._core import PuhuProcessingError
+
+from .helpers import pixels
+
+RED = (255, 0, 0)
+WHITE = (255, 255, 255)
+BLACK = (0, 0, 0)
+
+
+def _quadrants():
+    """4x4 RGB image, red in the upper left quadrant and white elsewhere."""
+    img = Image.new("RGB", (4, 4), "white")
+    img.paste(RED, (0, 0, 2, 2))
+    return img
+
+
+class TestMethods:
+    """Test each transform method."""
+
+    @pytest.mark.parametrize(
+        "method, data",
+        [
+            (Transform.AFFINE, (1, 0, 0, 0, 1, 0)),
+            (Transform.EXTENT, (0, 0, 4, 4)),
+            (Transform.PERSPECTIVE, (1, 0, 0, 0, 1, 0, 0, 0)),
+            (Transform.QUAD, (0, 0, 0, 4, 4, 4, 4, 0)),
+            (Transform.MESH, [((0, 0, 4, 4), (0, 0, 0, 4, 4, 4, 4, 0))]),
+        ],
+    )
+    def test_identity(self, method, data):
+        """Test identity data gives back the image."""
+        img = _quadrants()
+        assert img.transform((4, 4), method, data).to_bytes() == img.to_bytes()
+
+    def test_affine_shift(self):
+        """Test affine data maps output positions to source positions."""
+        result = _quadrants().transform((4, 4), Transform.AFFINE, (1, 0, 2, 0, 1, 2))
+        colors = pixels(result)
+        assert colors[0] == WHITE
+        assert colors[2] == BLACK
+        assert colors[2 * 4] == BLACK
+
+    def test_extent(self):
+        """Test a source rectangle is stretched over the output."""
+        result = _quadrants().transform((4, 4), Transform.EXTENT, (0, 0, 2, 2))
+        assert pixels(result) == [RED] * 16
+
+    def test_extent_downscale(self):
+        """Test the output size is independent of the source rectangle."""
+        result = _quadrants().transform((2, 2), Transform.EXTENT, (0, 0, 4, 4))
+        assert pixels(result) == [RED, WHITE, WHITE, WHITE]
+
+    def test_perspective(self):
+        """Test the perspective divide shrinks the image towards the right."""
+        img = Image.new("L", (8, 8), 255)
+        data = (1, 0, 0, 0, 1, 0, 0.1, 0)
+        result = img.transform((8, 8), Transform.PERSPECTIVE, data)
+        # Right-hand columns see further into the image, which stays in range
+        assert result.to_bytes()[7] == 255
+        assert result.size == (8, 8)
+
+    def test_quad(self):
+        """Test a source quadrilateral is mapped onto the whole output."""
+        quad = (0, 0, 0, 2, 2, 2, 2, 0)
+        result = _quadrants().transform((2, 2), Transform.QUAD, quad)
+        assert pixels(result) == [RED] * 4
+
+    def test_mesh_leaves_uncovered_fill(self):
+        """Test only the boxes of a mesh are sampled."""
+        mesh = [((0, 0, 2, 4), (2, 0, 2, 4, 4, 4, 4, 0))]
+        result = _quadrants().transform((4, 4), Transform.MESH, mesh, fillcolor="blue")
+        colors = pixels(result)
+        assert colors[0] == WHITE
+        assert colors[2] == (0, 0, 255)
+
+    def test_mesh_later_boxes_cover_earlier(self):
+        """Test overlapping boxes take the last quadrilateral."""
+        full = (0, 0, 4, 4)
+        mesh = [(full, (0, 0, 0, 2, 2, 2, 2, 0)), (full, (2, 2, 2, 4, 4, 4, 4, 2))]
+        result = _quadrants().transform((4, 4), Transform.MESH, mesh)
+        assert pixels(result) == [WHITE] * 16
+
+
+class TestArguments:
+    """Test the arguments of transform()."""
+
+    def test_integer_method(self):
+        """Test Pillow's integer method constants."""
+        img = _quadrants()
+        assert img.transform((4, 4), 0, (1, 0, 0, 0, 1, 0)).to_bytes() == img.to_bytes()
+
+    def test_getdata_object(self):
+        """Test objects with getdata() supply the method and data."""
+
+        class Shift:
+            def getdata(self):
+                return Transform.AFFINE, (1, 0, 2, 0, 1, 0)
+
+        result = _quadrants().transform((2, 2), Shift())
+        assert pixels(result) == [WHITE] * 4
+
+    def test_output_size(self):
+        """Test the output takes the requested size."""
+        result = _quadrants().transform((7, 3), Transform.EXTENT, (0, 0, 4, 4))
+        assert result.size == (7, 3)
+
+    def test_wrong_data_length(self):
+        """Test data with the wrong number of values is rejected."""
+        with pytest.raises(PuhuProcessingError):
+            _quadrants().transform((4, 4), Transform.AFFINE, (1, 0, 0))
+
+    def test_unknown_method(self):
+        """Test unknown methods are rejected."""
+        with pytest.raises(PuhuProcessingError):
+            _quadrants().transform((4, 4), 9, (1, 0, 0, 0, 1, 0))
+
+    def test_missing_data(self):
+        """Test data is required without a getdata() object."""
+        with pytest.raises(ValueError):
+            _quadrants().transform((4, 4), Transform.AFFINE)
+
+    def test_bilinear_edges(self):
+        """Test interpolated samples blend the image edge into the fill."""
+        img = Image.new("L", (4, 1), 255)
+        shift = (1, 0, 0.5, 0, 1, 0)
+        result = img.transform((4, 1), Transform.AFFINE, shift, resample=1)
+        assert list(result.to_bytes()) == [255, 255, 255, 128]
+
+    def test_palette_keeps_indices(self):
+        """Test palette images stay "P" with indices from the image or fill."""
+        img = Image.new("P", (4, 4), 1)
+        img.putpalette([0, 0, 0, 255, 0, 0, 0, 0, 255])
+        result = img.transform(
+            (4, 4), Transform.AFFINE, (1, 0, 2.5, 0, 1, 0), "BICUBIC", fillcolor=2
+        )
+        assert result.mode == "P"
+        assert set(result.to_bytes()) == {1, 2}
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,103 @@
+"""
+Tests for the transpose methods
+"""
+
+import pytest
+
+from puhu import Image, Transpose
+from puhu._core import PuhuProcessingError
+
+WIDTH, HEIGHT = 3, 2
+
+# Source pixel (x, y) shown at output pixel (x, y) for each method, given
+# the output size
+SOURCE = {
+    Transpose.FLIP_LEFT_RIGHT: lambda x, y: (WIDTH - 1 - x, y),
+    Transpose.FLIP_TOP_BOTTOM: lambda x, y: (x, HEIGHT - 1 - y),
+    Transpose.ROTATE_90: lambda x, y: (WIDTH - 1 - y, x),
+    Transpose.ROTATE_180: lambda x, y: (WIDTH - 1 - x, HEIGHT - 1 - y),
+    Transpose.ROTATE_270: lambda x, y: (y, HEIGHT - 1 - x),
+    Transpose.TRANSPOSE: lambda x, y: (y, x),
+    Transpose.TRANSVERSE: lambda x, y: (WIDTH - 1 - y, HEIGHT - 1 - x),
+}
+
+
+def _numbered(mode="L"):
+    """Image whose pixel (x, y) has the value 10 * y + x + 1."""
+    img = Image.new("L", (WIDTH, HEIGHT))
+    for y in range(HEIGHT):
+        for x in range(WIDTH):
+            img.paste(10 * y + x + 1, (x, y, x + 1, y + 1))
+    return img if mode == "L" else img.convert(mode)
+
+
+def _expected(method):
+    swapped = method in (
+        Transpose.ROTATE_90,
+        Transpose.ROTATE_270,
+        Transpose.TRANSPOSE,
+        Transpose.TRANSVERSE,
+    )
+    width, height = (HEIGHT, WIDTH) if swapped else (WIDTH, HEIGHT)
+    values = []
+    for y in range(height):
+        for x in range(width):
+            sx, sy = SOURCE[method](x, y)
+            values.append(10 * sy + sx + 1)
+    return (width, height), bytes(values)
+
+
+class TestTranspose:
+    """Test every transpose method moves pixels to the right place."""
+
+    @pytest.mark.parametrize("method", list(SOURCE))
+    def test_pixels(self, method):
+        """Test each method against the position of every pixel."""
+        result = _numbered().transpose(method)
+        size, data = _expected(method)
+        assert result.size == size
+        assert result.to_bytes() == data
+
+    @pytest.mark.parametrize("value", range(7))
+    def test_integer_constants(self, value):
+        """Test Pillow's integer constants select the same methods."""
+        img = _numbered()
+        expected = img.transpose(Transpose.from_int(value)).to_bytes()
+        assert img.transpose(value).to_bytes() == expected
+
+    def test_rotate_90_is_counter_clockwise(self):
+        """Test ROTATE_90 matches rotate(90, expand=True)."""
+        img = _numbered()
+        expected = img.rotate(90, expand=True).to_bytes()
+        assert img.transpose(Transpose.ROTATE_90).to_bytes() == expected
+
+    def test_transpose_is_its_own_inverse(self):
+        """Test mirroring twice along a diagonal restores the image."""
+        img = _numbered()
+        for method in (Transpose.TRANSPOSE, Transpose.TRANSVERSE):
+            assert img.transpose(method).transpose(method).to_bytes() == img.to_bytes()
+
+    @pytest.mark.parametrize("mode", ["RGB", "RGBA", "I;16", "F", "CMYK"])
+    def test_modes(self, mode):
+        """Test every channel of wider pixels moves together."""
+        img = _numbered(mode)
+        result = img.transpose(Transpose.TRANSVERSE)
+        assert result.mode == mode
+        back = result.transpose(Transpose.TRANSVERSE)
+        assert back.to_bytes() == img.to_bytes()
+
+    def test_palette(self):
+        """Test palette images keep their palette and mode."""
+        palette = _numbered().convert("P")
+        result = palette.transpose(Transpose.TRANSPOSE)
+        assert result.mode == "P"
+        assert result.getpalette() == palette.getpalette()
+
+    def test_unknown_method(self):
+        """Test unknown methods are rejected."""
+        with pytest.raises(PuhuProcessingError):
+            _numbered().transpose("ROTATE_45")
+
+
+if __name__ == "__main__":
+    pytest.main([__file__, "-v"])
@@ -0,0 +1,249 @@
+use crate::errors::PuhuError;
+use crate::io;
+use image::{DynamicImage, ImageBuffer};
+use moxcms::{
+    ColorProfile, DataColorSpace, Layout, ProfileText, RenderingIntent, Transform8BitExecutor,
+    TransformOptions,
+};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use rayon::prelude::*;
+use std::sync::Arc;
+
+fn cms_error(err: moxcms::CmsError) -> PuhuError {
+    PuhuError::ColorManagement(err.to_string())
+}
+
+/// Profiles compiled into the library, so common conversions need no profile files
+fn builtin_profile(name: &str) -> Option<ColorProfile> {
+    match name {
+        "sRGB" | "SRGB" => Some(ColorProfile::new_srgb()),
+        "P3" | "DISPLAY_P3" => Some(ColorProfile::new_display_p3()),
+        "ADOBE_RGB" => Some(ColorProfile::new_adobe_rgb()),
+        "PROPHOTO_RGB" => Some(ColorProfile::new_pro_photo_rgb()),
+        "BT2020" => Some(ColorProfile::new_bt2020()),
+        _ => None,
+    }
+}
+
+/// Pixel layout and channel count for an image mode
+fn mode_layout(mode: &str) -> Result<(Layout, usize), PuhuError> {
+    match mode {
+        "L" => Ok((Layout::Gray, 1)),
+        "LA" => Ok((Layout::GrayAlpha, 2)),
+        "RGB" => Ok((Layout::Rgb, 3)),
+        "RGBA" => Ok((Layout::Rgba, 4)),
+        // Four-channel data is laid out like RGBA
+        "CMYK" => Ok((Layout::Rgba, 4)),
+        _ => Err(PuhuError::ColorManagement(format!(
+            "Unsupported mode for colour transforms: '{}'. Supported modes: L, LA, RGB, RGBA, CMYK",
+            mode
+        ))),
+    }
+}
+
+fn parse_intent(intent: u32) -> Result<RenderingIntent, PuhuError> {
+    RenderingIntent::try_from(intent).map_err(|_| {
+        PuhuError::ColorManagement(format!(
+            "Invalid rendering intent {}; expected 0 (perceptual), 1 (relative colorimetric), 2 (saturation) or 3 (absolute colorimetric)",
+            intent
+        ))
+    })
+}
+
+/// The built-in sRGB profile as embedded in files
+pub fn srgb_profile_bytes() -> Result<Vec<u8>, PuhuError> {
+    ColorProfile::new_srgb().encode().map_err(cms_error)
+}
+
+/// Convert the inks of a "CMYK" image to sRGB through its ICC profile, with
+/// perceptual intent
+pub fn cmyk_to_srgb(image: &DynamicImage, profile: &[u8]) -> Result<DynamicImage, PuhuError> {
+    let profile = ColorProfile::new_from_slice(profile).map_err(cms_error)?;
+    if profile.color_space != DataColorSpace::Cmyk {
+        return Err(PuhuError::ColorManagement(
+            "Profile of a CMYK image must be a CMYK profile".to_string(),
+        ));
+    }
+    let executor = profile
+        .create_transform_8bit(
+            Layout::Rgba,
+            &ColorProfile::new_srgb(),
+            Layout::Rgb,
+            TransformOptions::default(),
+        )
+        .map_err(cms_error)?;
+
+    let (width, height) = (image.width(), image.height());
+    let inks = image.to_rgba8();
+    let mut rgb = vec![0u8; width as usize * height as usize * 3];
+    if !rgb.is_empty() {
+        inks.par_chunks(width as usize * 4)
+            .zip(rgb.par_chunks_mut(width as usize * 3))
+            .try_for_each(|(src, dst)| executor.transform(src, dst))
+            .map_err(cms_error)?;
+    }
+    let buf = ImageBuffer::from_raw(width, height, rgb).expect("buffer sized for the image");
+    Ok(DynamicImage::ImageRgb8(buf))
+}
+
+/// An ICC profile together with the bytes it was read from, which are
+/// embedded unchanged when saving
+#[pyclass(name = "CmsProfile", frozen)]
+pub struct PyCmsProfile {
+    profile: Arc<ColorProfile>,
+    data: Arc<Vec<u8>>,
+}
+
+#[pymethods]
+impl PyCmsProfile {
+    /// Built-in profile: "sRGB", "DISPLAY_P3" (or "P3"), "ADOBE_RGB",
+    /// "PROPHOTO_RGB" or "BT2020"
+    #[staticmethod]
+    fn builtin(name: &str) -> PyResult<Self> {
+        let profile = builtin_profile(name).ok_or_else(|| {
+            PuhuError::ColorManagement(format!(
+                "Unknown built-in profile: '{}'. Use 'sRGB', 'DISPLAY_P3', 'ADOBE_RGB', 'PROPHOTO_RGB' or 'BT2020'",
+                name
+            ))
+        })?;
+        let data = profile.encode().map_err(cms_error)?;
+        Ok(PyCmsProfile {
+            profile: Arc::new(profile),
+            data: Arc::new(data),
+        })
+    }
+
+    #[staticmethod]
+    fn frombytes(data: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let data = io::buffer_to_vec(data)?.ok_or_else(|| {
+            PuhuError::ColorManagement("ICC profile data must be bytes".to_string())
+        })?;
+        let profile = ColorProfile::new_from_slice(&data).map_err(cms_error)?;
+        Ok(PyCmsProfile {
+            profile: Arc::new(profile),
+            data: Arc::new(data),
+        })
+    }
+
+    fn tobytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.data)
+    }
+
+    /// Profile description (the 'desc' tag), if present
+    #[getter]
+    fn description(&self) -> Option<String> {
+        match self.profile.description.as_ref()? {
+            ProfileText::PlainString(text) => Some(text.clone()),
+            ProfileText::Localizable(strings) => strings.first().map(|s| s.value.clone()),
+            ProfileText::Description(description) => Some(description.ascii_string.clone()),
+        }
+    }
+
+    /// Colour space of the device data, e.g. "RGB" or "GRAY"
+    #[getter]
+    fn color_space(&self) -> String {
+        match self.profile.color_space {
+            DataColorSpace::Rgb => "RGB".to_string(),
+            DataColorSpace::Gray => "GRAY".to_string(),
+            DataColorSpace::Cmyk => "CMYK".to_string(),
+            DataColorSpace::Lab => "LAB".to_string(),
+            DataColorSpace::Xyz => "XYZ".to_string(),
+            other => format!("{:?}", other).to_uppercase(),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<CmsProfile {} '{}'>",
+            self.color_space(),
+            self.description().unwrap_or_default()
+        )
+    }
+}
+
+/// A prepared conversion between two profiles for fixed image modes
+#[derive(Clone)]
+pub struct CmsTransform {
+    executor: Arc<Transform8BitExecutor>,
+    pub input_mode: String,
+    pub output_mode: String,
+    /// Channels per output pixel
+    pub output_channels: usize,
+    /// Profile to embed in transformed images
+    pub output_profile: Arc<Vec<u8>>,
+}
+
+impl CmsTransform {
+    /// Transform whole pixels from the input to the output layout
+    pub fn transform(&self, src: &[u8], dst: &mut [u8]) -> Result<(), PuhuError> {
+        self.executor.transform(src, dst).map_err(cms_error)
+    }
+}
+
+#[pyclass(name = "CmsTransform", frozen)]
+pub struct PyCmsTransform {
+    transform: CmsTransform,
+}
+
+impl PyCmsTransform {
+    pub fn inner(&self) -> &CmsTransform {
+        &self.transform
+    }
+}
+
+#[pymethods]
+impl PyCmsTransform {
+    #[new]
+    #[pyo3(signature = (input_profile, output_profile, input_mode, output_mode, intent=0))]
+    fn new(
+        input_profile: &PyCmsProfile,
+        output_profile: &PyCmsProfile,
+        input_mode: &str,
+        output_mode: &str,
+        intent: u32,
+    ) -> PyResult<Self> {
+        let (input_layout, _) = mode_layout(input_mode)?;
+        let (output_layout, output_channels) = mode_layout(output_mode)?;
+        let options = TransformOptions {
+            rendering_intent: parse_intent(intent)?,
+            ..TransformOptions::default()
+        };
+
+        let executor = input_profile
+            .profile
+            .create_transform_8bit(
+                input_layout,
+                &output_profile.profile,
+                output_layout,
+                options,
+            )
+            .map_err(cms_error)?;
+        Ok(PyCmsTransform {
+            transform: CmsTransform {
+                executor: Arc::from(executor),
+                input_mode: input_mode.to_string(),
+                output_mode: output_mode.to_string(),
+                output_channels,
+                output_profile: Arc::clone(&output_profile.data),
+            },
+        })
+    }
+
+    #[getter]
+    fn input_mode(&self) -> &str {
+        &self.transform.input_mode
+    }
+
+    #[getter]
+    fn output_mode(&self) -> &str {
+        &self.transform.output_mode
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<CmsTransform {} -> {}>",
+            self.transform.input_mode, self.transform.output_mode
+        )
+    }
+}
@@ -0,0 +1,90 @@
+use crate::errors::PuhuError;
+use crate::limits::DecodeLimits;
+use crate::metadata::CmykStorage;
+use crate::modes;
+use image::{DynamicImage, ImageBuffer, ImageFormat};
+use std::fmt::Display;
+use std::io::Cursor;
+use tiff::decoder::{Decoder as TiffDecoder, DecodingResult};
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+fn decode_error(err: impl Display) -> PuhuError {
+    PuhuError::InvalidImage(err.to_string())
+}
+
+/// Decode a CMYK JPEG or the first page of a CMYK TIFF as an RGBA8 buffer
+/// of C, M, Y and K ink amounts.
+///
+/// The image crate converts these files to RGB, taking the samples of every
+/// CMYK JPEG as inverted; here only files with an Adobe marker are.
+pub fn decode(
+    data: &[u8],
+    format: ImageFormat,
+    storage: CmykStorage,
+    limits: DecodeLimits,
+) -> Result<DynamicImage, PuhuError> {
+    let (width, height, inks) = match format {
+        ImageFormat::Jpeg => decode_jpeg(data, storage, limits)?,
+        ImageFormat::Tiff => decode_tiff(data, limits)?,
+        _ => {
+            return Err(PuhuError::UnsupportedFormat(format!(
+                "Cannot decode {} images as CMYK",
+                format!("{:?}", format).to_uppercase()
+            )))
+        }
+    };
+    let buf = ImageBuffer::from_raw(width, height, inks)
+        .ok_or_else(|| PuhuError::InvalidImage("Truncated CMYK image data".to_string()))?;
+    Ok(DynamicImage::ImageRgba8(buf))
+}
+
+fn decode_jpeg(
+    data: &[u8],
+    storage: CmykStorage,
+    limits: DecodeLimits,
+) -> Result<(u32, u32, Vec<u8>), PuhuError> {
+    // Asking for the stored color space keeps zune-jpeg from converting to RGB
+    let colorspace = match storage {
+        CmykStorage::Ycck => ColorSpace::YCCK,
+        CmykStorage::Plain | CmykStorage::Inverted => ColorSpace::CMYK,
+    };
+    let options = DecoderOptions::default()
+        .set_strict_mode(false)
+        .set_max_width(limits.max_width.map_or(usize::MAX, |w| w as usize))
+        .set_max_height(limits.max_height.map_or(usize::MAX, |h| h as usize))
+        .jpeg_set_out_colorspace(colorspace);
+    let mut decoder = JpegDecoder::new_with_options(data, options);
+    let samples = decoder.decode().map_err(decode_error)?;
+    let (width, height) = decoder.dimensions().expect("headers decoded");
+
+    let inks = match storage {
+        CmykStorage::Plain => samples,
+        CmykStorage::Inverted => samples.iter().map(|v| 255 - v).collect(),
+        // Cyan, magenta and yellow are encoded as if they were RGB, and
+        // black is inverted
+        CmykStorage::Ycck => modes::map_pixels(&samples, |[y, cb, cr, k]| {
+            let [c, m, y] = modes::ycbcr_to_rgb([y, cb, cr]);
+            [c, m, y, 255 - k]
+        }),
+    };
+    Ok((width as u32, height as u32, inks))
+}
+
+fn decode_tiff(data: &[u8], limits: DecodeLimits) -> Result<(u32, u32, Vec<u8>), PuhuError> {
+    let mut decoder = TiffDecoder::new(Cursor::new(data))
+        .map_err(decode_error)?
+        .with_limits(limits.to_tiff_limits());
+    let (width, height) = decoder.dimensions().map_err(decode_error)?;
+    let inks = match decoder.read_image().map_err(decode_error)? {
+        DecodingResult::U8(samples) => samples,
+        DecodingResult::U16(samples) => samples.iter().map(|v| (v >> 8) as u8).collect(),
+        _ => {
+            return Err(PuhuError::UnsupportedFormat(
+                "CMYK TIFF images must have 8 or 16 bits per sample".to_string(),
+            ))
+        }
+    };
+    Ok((width, height, inks))
+}
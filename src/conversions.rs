@@ -1,6 +1,7 @@
+use crate::dither::Dither;
 use crate::errors::PuhuError;
-use image::imageops::colorops::{dither, grayscale, BiLevel};
-use image::DynamicImage;
+use image::imageops::colorops::grayscale;
+use image::{DynamicImage, GrayImage};
 use rayon::prelude::*;
 
 pub fn convert_with_matrix(
@@ -81,15 +82,21 @@ pub fn convert_with_matrix(
 
 pub fn convert_to_bilevel(
     image: &DynamicImage,
-    apply_dither: bool,
+    dither: &Dither,
 ) -> Result<DynamicImage, PuhuError> {
-    let mut luma = grayscale(image);
-    if apply_dither {
-        dither(&mut luma, &BiLevel);
-    } else {
-        for pixel in luma.pixels_mut() {
-            pixel[0] = if pixel[0] > 127 { 255 } else { 0 };
-        }
-    }
+    let luma = grayscale(image);
+    let (width, height) = luma.dimensions();
+    let data = dither.apply(
+        width as usize,
+        height as usize,
+        |x, y| luma.get_pixel(x as u32, y as u32).0,
+        255,
+        |[value]| {
+            let level = if value > 127 { 255 } else { 0 };
+            (level, Some([level]))
+        },
+    );
+    let luma = GrayImage::from_raw(width, height, data)
+        .ok_or_else(|| PuhuError::InvalidOperation("Failed to create bilevel image".to_string()))?;
     Ok(DynamicImage::ImageLuma8(luma))
 }
@@ -0,0 +1,243 @@
+use crate::errors::PuhuError;
+
+/// Error-diffusion kernel: the share of a pixel's error given to each
+/// neighbour that has not been visited yet
+#[derive(Debug, PartialEq, Eq)]
+pub struct Kernel {
+    /// (dx, dy, weight) of each neighbour, for scanning left to right
+    taps: &'static [(i32, usize, i32)],
+    divisor: i32,
+}
+
+pub const FLOYD_STEINBERG: Kernel = Kernel {
+    taps: &[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)],
+    divisor: 16,
+};
+
+/// Spreads only 3/4 of the error, keeping highlights and shadows clean
+pub const ATKINSON: Kernel = Kernel {
+    taps: &[
+        (1, 0, 1),
+        (2, 0, 1),
+        (-1, 1, 1),
+        (0, 1, 1),
+        (1, 1, 1),
+        (0, 2, 1),
+    ],
+    divisor: 8,
+};
+
+pub const JARVIS_JUDICE_NINKE: Kernel = Kernel {
+    taps: &[
+        (1, 0, 7),
+        (2, 0, 5),
+        (-2, 1, 3),
+        (-1, 1, 5),
+        (0, 1, 7),
+        (1, 1, 5),
+        (2, 1, 3),
+        (-2, 2, 1),
+        (-1, 2, 3),
+        (0, 2, 5),
+        (1, 2, 3),
+        (2, 2, 1),
+    ],
+    divisor: 48,
+};
+
+pub const STUCKI: Kernel = Kernel {
+    taps: &[
+        (1, 0, 8),
+        (2, 0, 4),
+        (-2, 1, 2),
+        (-1, 1, 4),
+        (0, 1, 8),
+        (1, 1, 4),
+        (2, 1, 2),
+        (-2, 2, 1),
+        (-1, 2, 2),
+        (0, 2, 4),
+        (1, 2, 2),
+        (2, 2, 1),
+    ],
+    divisor: 42,
+};
+
+pub const SIERRA: Kernel = Kernel {
+    taps: &[
+        (1, 0, 5),
+        (2, 0, 3),
+        (-2, 1, 2),
+        (-1, 1, 4),
+        (0, 1, 5),
+        (1, 1, 4),
+        (2, 1, 2),
+        (-1, 2, 2),
+        (0, 2, 3),
+        (1, 2, 2),
+    ],
+    divisor: 32,
+};
+
+pub const SIERRA_TWO_ROW: Kernel = Kernel {
+    taps: &[
+        (1, 0, 4),
+        (2, 0, 3),
+        (-2, 1, 1),
+        (-1, 1, 2),
+        (0, 1, 3),
+        (1, 1, 2),
+        (2, 1, 1),
+    ],
+    divisor: 16,
+};
+
+pub const SIERRA_LITE: Kernel = Kernel {
+    taps: &[(1, 0, 2), (-1, 1, 1), (0, 1, 1)],
+    divisor: 4,
+};
+
+/// How quantization error is hidden when reducing colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Map each pixel to its nearest color
+    None,
+    /// Spread each pixel's error over its neighbours
+    Diffusion(&'static Kernel),
+    /// Offset each pixel by a threshold from a Bayer matrix of this size
+    Ordered(usize),
+}
+
+/// Dithering options of `convert()` and `quantize()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dither {
+    pub method: Method,
+    /// Scan every other row right to left, for error diffusion
+    pub serpentine: bool,
+}
+
+impl Dither {
+    /// Parse a dither method, defaulting to Floyd-Steinberg
+    pub fn parse(name: Option<&str>, serpentine: bool) -> Result<Self, PuhuError> {
+        let method = match name.map(str::to_ascii_uppercase).as_deref() {
+            Some("NONE") => Method::None,
+            Some("FLOYDSTEINBERG") | None => Method::Diffusion(&FLOYD_STEINBERG),
+            Some("ATKINSON") => Method::Diffusion(&ATKINSON),
+            Some("JARVIS_JUDICE_NINKE") | Some("JARVIS") => Method::Diffusion(&JARVIS_JUDICE_NINKE),
+            Some("STUCKI") => Method::Diffusion(&STUCKI),
+            Some("SIERRA") => Method::Diffusion(&SIERRA),
+            Some("SIERRA_TWO_ROW") => Method::Diffusion(&SIERRA_TWO_ROW),
+            Some("SIERRA_LITE") => Method::Diffusion(&SIERRA_LITE),
+            Some("BAYER2") => Method::Ordered(2),
+            Some("BAYER4") => Method::Ordered(4),
+            Some("ORDERED") | Some("BAYER8") => Method::Ordered(8),
+            Some(_) => {
+                return Err(PuhuError::InvalidOperation(format!(
+                    "Unsupported dither method: '{}'. Use 'NONE', 'FLOYDSTEINBERG', 'ATKINSON', \
+                     'JARVIS_JUDICE_NINKE', 'STUCKI', 'SIERRA', 'SIERRA_TWO_ROW', 'SIERRA_LITE', \
+                     'ORDERED', 'BAYER2', 'BAYER4' or 'BAYER8'",
+                    name.unwrap_or_default()
+                )))
+            }
+        };
+        Ok(Dither { method, serpentine })
+    }
+
+    /// Map every pixel of a `width` x `height` image to an output value.
+    ///
+    /// `nearest` returns the output value for a color and the color it
+    /// stands for, whose difference is the error to diffuse; `None` keeps the
+    /// error from spreading. Ordered dithering offsets up to three channels
+    /// by at most half of `spread`, the distance between output colors.
+    pub fn apply<const N: usize>(
+        &self,
+        width: usize,
+        height: usize,
+        pixel: impl Fn(usize, usize) -> [u8; N],
+        spread: i32,
+        mut nearest: impl FnMut([u8; N]) -> (u8, Option<[u8; N]>),
+    ) -> Vec<u8> {
+        let mut output = vec![0; width * height];
+        match self.method {
+            Method::None => {
+                for y in 0..height {
+                    for x in 0..width {
+                        output[y * width + x] = nearest(pixel(x, y)).0;
+                    }
+                }
+            }
+            Method::Ordered(size) => {
+                let matrix = bayer_matrix(size);
+                let levels = (size * size) as i32;
+                for y in 0..height {
+                    for x in 0..width {
+                        // Thresholds centered on zero, from -spread/2 to spread/2
+                        let threshold = matrix[(y % size) * size + x % size] as i32;
+                        let offset = (2 * threshold + 1 - levels) * spread / (2 * levels);
+                        let mut color = pixel(x, y);
+                        for value in color.iter_mut().take(3) {
+                            *value = (*value as i32 + offset).clamp(0, 255) as u8;
+                        }
+                        output[y * width + x] = nearest(color).0;
+                    }
+                }
+            }
+            Method::Diffusion(kernel) => {
+                let rows = kernel.taps.iter().map(|&(_, dy, _)| dy).max().unwrap_or(0) + 1;
+                let mut errors = vec![vec![[0i32; N]; width]; rows];
+                for y in 0..height {
+                    let reverse = self.serpentine && y % 2 == 1;
+                    for i in 0..width {
+                        let x = if reverse { width - 1 - i } else { i };
+                        let mut color = pixel(x, y);
+                        for (value, error) in color.iter_mut().zip(errors[y % rows][x]) {
+                            *value = (*value as i32 + error).clamp(0, 255) as u8;
+                        }
+                        let (value, entry) = nearest(color);
+                        output[y * width + x] = value;
+                        let Some(entry) = entry else {
+                            continue;
+                        };
+
+                        let error: [i32; N] =
+                            std::array::from_fn(|c| color[c] as i32 - entry[c] as i32);
+                        for &(dx, dy, weight) in kernel.taps {
+                            let dx = if reverse { -dx } else { dx };
+                            let nx = x as i64 + dx as i64;
+                            if nx < 0 || nx >= width as i64 || y + dy >= height {
+                                continue;
+                            }
+                            let target = &mut errors[(y + dy) % rows][nx as usize];
+                            for (e, error) in target.iter_mut().zip(error) {
+                                *e += error * weight / kernel.divisor;
+                            }
+                        }
+                    }
+                    errors[y % rows].fill([0; N]);
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Bayer threshold matrix of a power-of-two size, with values 0 to size² - 1
+fn bayer_matrix(size: usize) -> Vec<u32> {
+    let mut matrix = vec![0];
+    let mut n = 1;
+    while n < size {
+        let mut next = vec![0; 4 * n * n];
+        for y in 0..n {
+            for x in 0..n {
+                let value = 4 * matrix[y * n + x];
+                next[y * 2 * n + x] = value;
+                next[y * 2 * n + x + n] = value + 2;
+                next[(y + n) * 2 * n + x] = value + 3;
+                next[(y + n) * 2 * n + x + n] = value + 1;
+            }
+        }
+        matrix = next;
+        n *= 2;
+    }
+    matrix
+}
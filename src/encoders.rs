@@ -0,0 +1,1642 @@
+use crate::errors::PuhuError;
+use crate::exif::{
+    Exif, ExifValue, IfdKind, PyExif, EXIF_HEADER, EXIF_IFD_POINTER, GPS_IFD_POINTER,
+    INTEROP_IFD_POINTER,
+};
+use crate::io;
+use crate::metadata::{ImageInfo, Transparency};
+use crate::modes::{self, ExtendedMode, Mode};
+use crate::nearest::{Mapper, Metric};
+use crate::palette::{self, Palette};
+use image::error::{EncodingError, ImageFormatHint};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageEncoder, ImageError, ImageFormat, RgbaImage};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::borrow::Cow;
+use std::io::Cursor;
+
+/// Chroma subsampling used by the JPEG encoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsampling {
+    /// 4:4:4, no subsampling
+    S444,
+    /// 4:2:2, half horizontal chroma resolution
+    S422,
+    /// 4:2:0, half horizontal and vertical chroma resolution
+    S420,
+}
+
+/// Compression schemes supported by the TIFF encoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TiffCompression {
+    Raw,
+    Lzw,
+    Deflate,
+    Packbits,
+}
+
+/// Encoder options for `save`, parsed from Pillow-style keyword arguments
+#[derive(Debug, Clone, Default)]
+pub struct SaveOptions {
+    /// JPEG quality (1-100)
+    pub quality: Option<u8>,
+    /// Optimized Huffman tables for JPEG, maximum compression for PNG
+    pub optimize: bool,
+    /// Progressive JPEG encoding
+    pub progressive: bool,
+    /// PNG zlib compression level (0-9)
+    pub compress_level: Option<u8>,
+    /// JPEG chroma subsampling
+    pub subsampling: Option<Subsampling>,
+    /// TIFF compression scheme of each page; a single value applies to
+    /// every page
+    pub compression: Vec<TiffCompression>,
+    /// WebP lossless encoding
+    pub lossless: Option<bool>,
+    /// Resolution in dots per inch as (x, y)
+    pub dpi: Option<(f64, f64)>,
+    /// PNG gamma, written as a gAMA chunk
+    pub gamma: Option<f64>,
+    /// Transparent color, written as a PNG tRNS chunk
+    pub transparency: Option<Transparency>,
+    /// PNG text chunks
+    pub pnginfo: Vec<(String, String)>,
+    /// JPEG or GIF comment
+    pub comment: Option<Vec<u8>>,
+    /// Frame display time in milliseconds; a single value applies to every frame
+    pub duration: Vec<u32>,
+    /// Animation loop count, 0 meaning forever
+    pub loop_count: Option<u16>,
+    /// GIF disposal method or APNG dispose_op of each frame; a single value
+    /// applies to every frame
+    pub disposal: Vec<u8>,
+    /// GIF background color index
+    pub background: Option<u8>,
+    /// EXIF block as TIFF-structured data, without the `Exif\0\0` prefix
+    pub exif: Option<Vec<u8>>,
+    /// ICC colour profile; an empty profile means none is written
+    pub icc_profile: Option<Vec<u8>>,
+    /// ICO icon sizes as (width, height)
+    pub sizes: Vec<(u32, u32)>,
+    /// Palette of a "P" image, whose pixels are then indices into it. Set
+    /// from the image rather than from a keyword argument.
+    pub palette: Option<Palette>,
+    /// Extended mode of each frame, whose pixels are then values or inks
+    /// rather than colors; a single value applies to every frame. Set from
+    /// the images like `palette`.
+    pub extended: Vec<Option<ExtendedMode>>,
+}
+
+/// Icon sizes written by default, as in Pillow
+const DEFAULT_ICO_SIZES: &[(u32, u32)] = &[
+    (16, 16),
+    (24, 24),
+    (32, 32),
+    (48, 48),
+    (64, 64),
+    (128, 128),
+    (256, 256),
+];
+
+const KNOWN_OPTIONS: &[&str] = &[
+    "quality",
+    "optimize",
+    "progressive",
+    "compress_level",
+    "subsampling",
+    "compression",
+    "lossless",
+    "dpi",
+    "gamma",
+    "transparency",
+    "pnginfo",
+    "comment",
+    "duration",
+    "loop",
+    "disposal",
+    "background",
+    "exif",
+    "icc_profile",
+    "sizes",
+];
+
+/// Options understood by the encoder for each output format
+fn supported_options(format: ImageFormat) -> &'static [&'static str] {
+    match format {
+        ImageFormat::Jpeg => &[
+            "quality",
+            "optimize",
+            "progressive",
+            "subsampling",
+            "dpi",
+            "comment",
+            "exif",
+            "icc_profile",
+        ],
+        ImageFormat::Png => &[
+            "optimize",
+            "compress_level",
+            "dpi",
+            "gamma",
+            "transparency",
+            "pnginfo",
+            "exif",
+            "icc_profile",
+            "duration",
+            "loop",
+            "disposal",
+        ],
+        ImageFormat::Gif => &[
+            "optimize",
+            "comment",
+            "duration",
+            "loop",
+            "disposal",
+            "background",
+        ],
+        ImageFormat::Tiff => &["compression", "dpi", "exif", "icc_profile"],
+        ImageFormat::Ico => &["sizes"],
+        ImageFormat::WebP => &[
+            "optimize",
+            "lossless",
+            "exif",
+            "icc_profile",
+            "duration",
+            "loop",
+        ],
+        _ => &[],
+    }
+}
+
+fn invalid_value(key: &str, expected: &str) -> PuhuError {
+    PuhuError::InvalidOperation(format!(
+        "Invalid value for save option '{}': expected {}",
+        key, expected
+    ))
+}
+
+impl SaveOptions {
+    /// Parse and validate keyword arguments for the given output format.
+    ///
+    /// Unknown keys and invalid values raise `InvalidOperation`; known keys
+    /// that the format's encoder cannot honour raise `UnsupportedFormat`.
+    pub fn from_kwargs(
+        format: ImageFormat,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> Result<Self, PuhuError> {
+        let mut options = SaveOptions::default();
+        let kwargs = match kwargs {
+            Some(kwargs) => kwargs,
+            None => return Ok(options),
+        };
+
+        let supported = supported_options(format);
+        for (key, value) in kwargs.iter() {
+            let key: String = key.extract().map_err(|_| {
+                PuhuError::InvalidOperation("Option names must be strings".to_string())
+            })?;
+
+            if !KNOWN_OPTIONS.contains(&key.as_str()) {
+                return Err(PuhuError::InvalidOperation(format!(
+                    "Unknown save option: '{}'",
+                    key
+                )));
+            }
+            if !supported.contains(&key.as_str()) {
+                return Err(PuhuError::UnsupportedFormat(format!(
+                    "Save option '{}' is not supported for {:?}",
+                    key, format
+                )));
+            }
+            // Pillow treats None as "use the default" for every option
+            if value.is_none() {
+                continue;
+            }
+
+            match key.as_str() {
+                "quality" => {
+                    let quality: i64 = value
+                        .extract()
+                        .map_err(|_| invalid_value(&key, "an integer between 0 and 100"))?;
+                    if !(0..=100).contains(&quality) {
+                        return Err(invalid_value(&key, "an integer between 0 and 100"));
+                    }
+                    options.quality = Some(quality.max(1) as u8);
+                }
+                "optimize" => options.optimize = value.is_truthy()?,
+                "progressive" => options.progressive = value.is_truthy()?,
+                "compress_level" => {
+                    let level: i64 = value
+                        .extract()
+                        .map_err(|_| invalid_value(&key, "an integer between 0 and 9"))?;
+                    if !(0..=9).contains(&level) {
+                        return Err(invalid_value(&key, "an integer between 0 and 9"));
+                    }
+                    options.compress_level = Some(level as u8);
+                }
+                "subsampling" => {
+                    options.subsampling = Some(parse_subsampling(&value)?);
+                }
+                "compression" => {
+                    let names: Vec<String> = frame_values(&value).map_err(|_| {
+                        invalid_value(&key, "a compression name or a sequence of them")
+                    })?;
+                    options.compression = names
+                        .iter()
+                        .map(|name| parse_tiff_compression(name))
+                        .collect::<Result<_, _>>()?;
+                }
+                "lossless" => {
+                    let lossless = value.is_truthy()?;
+                    if !lossless {
+                        return Err(PuhuError::UnsupportedFormat(
+                            "Lossy WebP encoding is not supported; use lossless=True".to_string(),
+                        ));
+                    }
+                    options.lossless = Some(lossless);
+                }
+                "dpi" => {
+                    let (x, y): (f64, f64) = value
+                        .extract()
+                        .map_err(|_| invalid_value(&key, "a (x, y) tuple"))?;
+                    if x <= 0.0 || y <= 0.0 {
+                        return Err(invalid_value(&key, "positive resolution values"));
+                    }
+                    options.dpi = Some((x, y));
+                }
+                "gamma" => {
+                    let gamma: f64 = value
+                        .extract()
+                        .map_err(|_| invalid_value(&key, "a positive number"))?;
+                    if gamma <= 0.0 {
+                        return Err(invalid_value(&key, "a positive number"));
+                    }
+                    options.gamma = Some(gamma);
+                }
+                "transparency" => {
+                    let expected = "an integer or an (r, g, b) tuple";
+                    options.transparency = Some(if let Ok(level) = value.extract::<u16>() {
+                        Transparency::Gray(level)
+                    } else if let Ok((r, g, b)) = value.extract::<(u16, u16, u16)>() {
+                        Transparency::Rgb(r, g, b)
+                    } else {
+                        return Err(invalid_value(&key, expected));
+                    });
+                }
+                "pnginfo" => {
+                    let expected = "a mapping of str keys to str values";
+                    let items = value
+                        .call_method0("items")
+                        .map_err(|_| invalid_value(&key, expected))?;
+                    for item in items.try_iter()? {
+                        let (keyword, text): (String, String) =
+                            item?.extract().map_err(|_| invalid_value(&key, expected))?;
+                        options.pnginfo.push((keyword, text));
+                    }
+                }
+                "comment" => {
+                    let comment = match value.extract::<String>() {
+                        Ok(text) => text.into_bytes(),
+                        Err(_) => io::buffer_to_vec(&value)?
+                            .ok_or_else(|| invalid_value(&key, "bytes or str"))?,
+                    };
+                    options.comment = Some(comment);
+                }
+                "duration" => {
+                    let expected = "a non-negative number or a sequence of them";
+                    let durations: Vec<f64> =
+                        frame_values(&value).map_err(|_| invalid_value(&key, expected))?;
+                    if durations
+                        .iter()
+                        .any(|d| !(0.0..=u32::MAX as f64).contains(d))
+                    {
+                        return Err(invalid_value(&key, expected));
+                    }
+                    options.duration = durations.iter().map(|d| d.round() as u32).collect();
+                }
+                "loop" => {
+                    let loop_count: u16 = value
+                        .extract()
+                        .map_err(|_| invalid_value(&key, "an integer between 0 and 65535"))?;
+                    options.loop_count = Some(loop_count);
+                }
+                "disposal" => {
+                    options.disposal = frame_values(&value).map_err(|_| {
+                        invalid_value(&key, "a disposal method or a sequence of them")
+                    })?;
+                }
+                "background" => {
+                    let background: u8 = value
+                        .extract()
+                        .map_err(|_| invalid_value(&key, "a palette index between 0 and 255"))?;
+                    options.background = Some(background);
+                }
+                "exif" => {
+                    let data = match value.downcast::<PyExif>() {
+                        Ok(exif) => exif.borrow().exif().to_bytes(),
+                        Err(_) => io::buffer_to_vec(&value)?
+                            .ok_or_else(|| invalid_value(&key, "bytes or an Exif object"))?,
+                    };
+                    let data = data.strip_prefix(EXIF_HEADER).unwrap_or(&data);
+                    if !data.starts_with(b"II*\0") && !data.starts_with(b"MM\0*") {
+                        return Err(invalid_value(&key, "EXIF data with a TIFF header"));
+                    }
+                    options.exif = Some(data.to_vec());
+                }
+                "icc_profile" => {
+                    let profile =
+                        io::buffer_to_vec(&value)?.ok_or_else(|| invalid_value(&key, "bytes"))?;
+                    options.icc_profile = Some(profile);
+                }
+                "sizes" => {
+                    let expected = "a sequence of (width, height) tuples between 1 and 256";
+                    let sizes: Vec<(u32, u32)> =
+                        value.extract().map_err(|_| invalid_value(&key, expected))?;
+                    if sizes
+                        .iter()
+                        .any(|&(w, h)| !(1..=256).contains(&w) || !(1..=256).contains(&h))
+                    {
+                        return Err(invalid_value(&key, expected));
+                    }
+                    options.sizes = sizes;
+                }
+                _ => unreachable!("Checked against KNOWN_OPTIONS"),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Carry the image's ICC profile over unless one was given, as the
+    /// profile describes the pixel values rather than optional metadata
+    pub fn inherit(&mut self, format: ImageFormat, info: &ImageInfo) {
+        if self.icc_profile.is_none() && supported_options(format).contains(&"icc_profile") {
+            self.icc_profile = info.icc_profile.clone();
+        }
+        self.icc_profile = self.icc_profile.take().filter(|p| !p.is_empty());
+    }
+}
+
+/// A value that is either given once for all frames or as a sequence with
+/// one value per frame
+fn frame_values<'py, T: FromPyObject<'py>>(value: &Bound<'py, PyAny>) -> PyResult<Vec<T>> {
+    match value.extract::<T>() {
+        Ok(value) => Ok(vec![value]),
+        Err(_) => value.extract(),
+    }
+}
+
+fn parse_subsampling(value: &Bound<'_, PyAny>) -> Result<Subsampling, PuhuError> {
+    let expected = "0, 1, 2, '4:4:4', '4:2:2' or '4:2:0'";
+    if let Ok(level) = value.extract::<i64>() {
+        match level {
+            0 => Ok(Subsampling::S444),
+            1 => Ok(Subsampling::S422),
+            2 => Ok(Subsampling::S420),
+            _ => Err(invalid_value("subsampling", expected)),
+        }
+    } else if let Ok(name) = value.extract::<String>() {
+        match name.as_str() {
+            "4:4:4" => Ok(Subsampling::S444),
+            "4:2:2" => Ok(Subsampling::S422),
+            "4:2:0" => Ok(Subsampling::S420),
+            _ => Err(invalid_value("subsampling", expected)),
+        }
+    } else {
+        Err(invalid_value("subsampling", expected))
+    }
+}
+
+fn parse_tiff_compression(name: &str) -> Result<TiffCompression, PuhuError> {
+    match name {
+        "raw" | "none" => Ok(TiffCompression::Raw),
+        "tiff_lzw" | "lzw" => Ok(TiffCompression::Lzw),
+        "tiff_deflate" | "tiff_adobe_deflate" | "deflate" => Ok(TiffCompression::Deflate),
+        "packbits" => Ok(TiffCompression::Packbits),
+        _ => Err(PuhuError::InvalidOperation(format!(
+            "Unsupported TIFF compression: '{}'. Use 'raw', 'tiff_lzw', 'tiff_deflate' or 'packbits'",
+            name
+        ))),
+    }
+}
+
+/// Wrap a codec-specific error as an image encoding error
+fn encoding_error<E>(format: ImageFormat, err: E) -> PuhuError
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    PuhuError::ImageError(ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(format),
+        err,
+    )))
+}
+
+/// Encode an image into an in-memory buffer using the given format and options
+pub fn encode(
+    image: &DynamicImage,
+    format: ImageFormat,
+    options: &SaveOptions,
+) -> Result<Vec<u8>, PuhuError> {
+    if let Some(palette) = &options.palette {
+        // Only PNG and GIF store palette images as they are
+        if !matches!(format, ImageFormat::Png | ImageFormat::Gif) {
+            let options = SaveOptions {
+                palette: None,
+                ..options.clone()
+            };
+            return encode(&palette.expand(image), format, &options);
+        }
+    }
+    if let Some(mode) = frame_value(&options.extended, 0).flatten() {
+        // TIFF stores the values as they are, JPEG stores CMYK inks, and PNG
+        // "I" images as 16-bit gray. YCbCr is only the encoding of a color,
+        // so those images are written as RGB.
+        match (format, mode) {
+            (_, ExtendedMode::YCbCr) => {
+                let options = SaveOptions {
+                    extended: Vec::new(),
+                    ..options.clone()
+                };
+                return encode(
+                    &modes::convert(image, Mode::YCbCr, Mode::Rgb),
+                    format,
+                    &options,
+                );
+            }
+            (ImageFormat::Tiff, _) | (ImageFormat::Jpeg, ExtendedMode::Cmyk) => {}
+            (ImageFormat::Png, ExtendedMode::Int32) => {
+                let options = SaveOptions {
+                    extended: Vec::new(),
+                    ..options.clone()
+                };
+                return encode(&modes::convert(image, Mode::I, Mode::L16), format, &options);
+            }
+            _ => {
+                return Err(PuhuError::InvalidOperation(format!(
+                    "Cannot write mode {} as {}",
+                    Mode::from(mode).name(),
+                    format!("{:?}", format).to_uppercase()
+                )))
+            }
+        }
+    }
+    let frames = std::slice::from_ref(image);
+    match format {
+        ImageFormat::Jpeg => encode_jpeg(image, options),
+        ImageFormat::Png => encode_png(frames, options),
+        ImageFormat::Tiff => encode_tiff(frames, options),
+        ImageFormat::Gif => encode_gif(frames, options),
+        ImageFormat::WebP => encode_webp(image, options),
+        ImageFormat::Ico => encode_ico(image, options),
+        _ => {
+            let mut cursor = Cursor::new(Vec::new());
+            image
+                .write_to(&mut cursor, format)
+                .map_err(PuhuError::ImageError)?;
+            Ok(cursor.into_inner())
+        }
+    }
+}
+
+/// Encode the frames of an animation or the pages of a multi-page file, or a
+/// single image if there is only one
+pub fn encode_all(
+    frames: &[DynamicImage],
+    format: ImageFormat,
+    options: &SaveOptions,
+) -> Result<Vec<u8>, PuhuError> {
+    if let [image] = frames {
+        return encode(image, format, options);
+    }
+    // TIFF pages keep the values of "I" and "F" and the inks of "CMYK";
+    // animations and YCbCr pages are written from their colors
+    let stored = |mode| format == ImageFormat::Tiff && mode != ExtendedMode::YCbCr;
+    let extended: Vec<Option<ExtendedMode>> = (0..frames.len())
+        .map(|i| frame_value(&options.extended, i).flatten())
+        .collect();
+    if extended.iter().flatten().any(|&mode| !stored(mode)) {
+        let frames: Vec<DynamicImage> = frames
+            .iter()
+            .zip(&extended)
+            .map(|(frame, &mode)| match mode {
+                Some(mode) if !stored(mode) => modes::colors(frame, mode),
+                _ => frame.clone(),
+            })
+            .collect();
+        let options = SaveOptions {
+            extended: extended
+                .into_iter()
+                .map(|mode| mode.filter(|&mode| stored(mode)))
+                .collect(),
+            ..options.clone()
+        };
+        return encode_all(&frames, format, &options);
+    }
+    match format {
+        ImageFormat::Png => encode_png(frames, options),
+        ImageFormat::Gif => encode_gif(frames, options),
+        ImageFormat::WebP => encode_animated_webp(frames, options),
+        ImageFormat::Tiff => encode_tiff(frames, options),
+        _ => Err(PuhuError::UnsupportedFormat(format!(
+            "Saving multiple frames is not supported for {:?}",
+            format
+        ))),
+    }
+}
+
+/// Area of the canvas covered by an animation frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FrameRegion {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Value for frame `index` of an option given once or once per frame
+fn frame_value<T: Copy>(values: &[T], index: usize) -> Option<T> {
+    match values {
+        [value] => Some(*value),
+        _ => values.get(index).copied(),
+    }
+}
+
+/// Check options given per frame have one value or one for every frame
+fn check_frame_options(options: &SaveOptions, frames: usize) -> Result<(), PuhuError> {
+    for (key, len) in [
+        ("duration", options.duration.len()),
+        ("disposal", options.disposal.len()),
+        ("compression", options.compression.len()),
+    ] {
+        if len > 1 && len != frames {
+            return Err(PuhuError::InvalidOperation(format!(
+                "Save option '{}' has {} values for {} frames",
+                key, len, frames
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Check disposal values against the largest one the format defines
+fn check_disposal(options: &SaveOptions, max_disposal: u8) -> Result<(), PuhuError> {
+    if options.disposal.iter().any(|&d| d > max_disposal) {
+        return Err(invalid_value(
+            "disposal",
+            &format!("values between 0 and {}", max_disposal),
+        ));
+    }
+    Ok(())
+}
+
+/// Frames with the size and color type of the first one, as animations
+/// require. If any frame differs, every frame is converted to RGBA and
+/// drawn at the top left of a transparent canvas.
+fn uniform_frames(frames: &[DynamicImage]) -> Vec<Cow<'_, DynamicImage>> {
+    let first = &frames[0];
+    if frames
+        .iter()
+        .all(|f| f.dimensions() == first.dimensions() && f.color() == first.color())
+    {
+        return frames.iter().map(Cow::Borrowed).collect();
+    }
+    frames
+        .iter()
+        .map(|frame| {
+            let mut canvas = RgbaImage::new(first.width(), first.height());
+            image::imageops::replace(&mut canvas, &frame.to_rgba8(), 0, 0);
+            Cow::Owned(DynamicImage::ImageRgba8(canvas))
+        })
+        .collect()
+}
+
+/// Smallest region containing every pixel that differs between two frames
+/// of the same size and color type, or `None` if they are identical
+fn changed_region(previous: &DynamicImage, frame: &DynamicImage) -> Option<FrameRegion> {
+    let pixel_len = frame.color().bytes_per_pixel() as usize;
+    let row_len = frame.width() as usize * pixel_len;
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+
+    let rows = previous
+        .as_bytes()
+        .chunks_exact(row_len)
+        .zip(frame.as_bytes().chunks_exact(row_len));
+    for (y, (old, new)) in rows.enumerate() {
+        if old == new {
+            continue;
+        }
+        let pixels = || old.chunks_exact(pixel_len).zip(new.chunks_exact(pixel_len));
+        let first = pixels().position(|(a, b)| a != b).unwrap_or(0);
+        let last = pixels().rposition(|(a, b)| a != b).unwrap_or(first);
+        bounds = Some(match bounds {
+            None => (first, y, last, y),
+            Some((left, top, right, _)) => (left.min(first), top, right.max(last), y),
+        });
+    }
+
+    bounds.map(|(left, top, right, bottom)| FrameRegion {
+        x: left as u32,
+        y: top as u32,
+        width: (right - left + 1) as u32,
+        height: (bottom - top + 1) as u32,
+    })
+}
+
+/// Region to encode for each frame. With `optimize`, frames drawn over an
+/// undisposed previous frame are cropped to the pixels that changed.
+fn frame_regions(
+    frames: &[Cow<'_, DynamicImage>],
+    optimize: bool,
+    keeps_canvas: impl Fn(usize) -> bool,
+) -> Vec<FrameRegion> {
+    let full = FrameRegion {
+        x: 0,
+        y: 0,
+        width: frames[0].width(),
+        height: frames[0].height(),
+    };
+    let mut regions = vec![full];
+    for i in 1..frames.len() {
+        regions.push(if optimize && keeps_canvas(i - 1) {
+            // An unchanged frame still needs a pixel to carry its timing
+            changed_region(&frames[i - 1], &frames[i]).unwrap_or(FrameRegion {
+                width: 1,
+                height: 1,
+                ..full
+            })
+        } else {
+            full
+        });
+    }
+    regions
+}
+
+fn crop_frame(frame: &DynamicImage, region: FrameRegion) -> DynamicImage {
+    frame.crop_imm(region.x, region.y, region.width, region.height)
+}
+
+fn encode_jpeg(image: &DynamicImage, options: &SaveOptions) -> Result<Vec<u8>, PuhuError> {
+    let (width, height) = (image.width(), image.height());
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(PuhuError::InvalidOperation(format!(
+            "Image too large for JPEG ({}x{}), maximum is 65535x65535",
+            width, height
+        )));
+    }
+
+    // JPEG has no alpha channel, and dropping it would silently change how
+    // the image looks, so ask for an explicit conversion as Pillow does
+    let extended = frame_value(&options.extended, 0).flatten();
+    let mode = Mode::of(image, extended);
+    if mode.has_alpha() {
+        return Err(PuhuError::InvalidOperation(format!(
+            "Cannot write mode {} as JPEG; convert it to RGB or L first",
+            mode.name()
+        )));
+    }
+
+    let mut buffer = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut buffer, options.quality.unwrap_or(75));
+    encoder.set_progressive(options.progressive);
+    encoder.set_optimized_huffman_tables(options.optimize);
+    if let Some(subsampling) = options.subsampling {
+        encoder.set_sampling_factor(match subsampling {
+            Subsampling::S444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+            Subsampling::S422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+            Subsampling::S420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+        });
+    }
+    if let Some((x, y)) = options.dpi {
+        encoder.set_density(jpeg_encoder::PixelDensity {
+            density: (x.round() as u16, y.round() as u16),
+            unit: jpeg_encoder::PixelDensityUnit::Inches,
+        });
+    }
+    if let Some(exif) = &options.exif {
+        encoder
+            .add_exif_metadata(exif)
+            .map_err(|e| encoding_error(ImageFormat::Jpeg, e))?;
+    }
+    if let Some(profile) = &options.icc_profile {
+        encoder
+            .add_icc_profile(profile)
+            .map_err(|e| encoding_error(ImageFormat::Jpeg, e))?;
+    }
+
+    // CMYK inks are stored inverted with an Adobe marker, like Adobe
+    // applications
+    let (data, color_type): (Cow<[u8]>, jpeg_encoder::ColorType) = match image {
+        DynamicImage::ImageRgba8(buf) if extended == Some(ExtendedMode::Cmyk) => {
+            (Cow::Borrowed(buf.as_raw()), jpeg_encoder::ColorType::Cmyk)
+        }
+        DynamicImage::ImageLuma8(buf) => {
+            (Cow::Borrowed(buf.as_raw()), jpeg_encoder::ColorType::Luma)
+        }
+        DynamicImage::ImageRgb8(buf) => (Cow::Borrowed(buf.as_raw()), jpeg_encoder::ColorType::Rgb),
+        DynamicImage::ImageLuma16(_) => (
+            Cow::Owned(image.to_luma8().into_raw()),
+            jpeg_encoder::ColorType::Luma,
+        ),
+        _ => (
+            Cow::Owned(image.to_rgb8().into_raw()),
+            jpeg_encoder::ColorType::Rgb,
+        ),
+    };
+
+    encoder
+        .encode(&data, width as u16, height as u16, color_type)
+        .map_err(|e| encoding_error(ImageFormat::Jpeg, e))?;
+
+    if let Some(comment) = &options.comment {
+        insert_jpeg_comment(&mut buffer, comment)?;
+    }
+    Ok(buffer)
+}
+
+/// Insert a COM segment after the JFIF header of an encoded JPEG
+fn insert_jpeg_comment(buffer: &mut Vec<u8>, comment: &[u8]) -> Result<(), PuhuError> {
+    if comment.len() > u16::MAX as usize - 2 {
+        return Err(PuhuError::InvalidOperation(
+            "JPEG comments are limited to 65533 bytes".to_string(),
+        ));
+    }
+
+    // SOI is followed by the APP0 segment written by the encoder
+    let mut offset = 2;
+    if buffer.get(2..4) == Some(&[0xFF, 0xE0]) {
+        offset += 2 + u16::from_be_bytes([buffer[4], buffer[5]]) as usize;
+    }
+
+    let mut segment = vec![0xFF, 0xFE];
+    segment.extend(((comment.len() + 2) as u16).to_be_bytes());
+    segment.extend_from_slice(comment);
+    buffer.splice(offset..offset, segment);
+    Ok(())
+}
+
+/// Raw PNG sample layout for an image, converting unsupported layouts
+fn png_layout(image: &DynamicImage) -> (png::ColorType, png::BitDepth, Cow<'_, [u8]>) {
+    fn be_bytes(samples: &[u16]) -> Cow<'static, [u8]> {
+        Cow::Owned(samples.iter().flat_map(|v| v.to_be_bytes()).collect())
+    }
+
+    match image {
+        DynamicImage::ImageLuma8(buf) => (
+            png::ColorType::Grayscale,
+            png::BitDepth::Eight,
+            Cow::Borrowed(buf.as_raw()),
+        ),
+        DynamicImage::ImageLumaA8(buf) => (
+            png::ColorType::GrayscaleAlpha,
+            png::BitDepth::Eight,
+            Cow::Borrowed(buf.as_raw()),
+        ),
+        DynamicImage::ImageRgb8(buf) => (
+            png::ColorType::Rgb,
+            png::BitDepth::Eight,
+            Cow::Borrowed(buf.as_raw()),
+        ),
+        DynamicImage::ImageRgba8(buf) => (
+            png::ColorType::Rgba,
+            png::BitDepth::Eight,
+            Cow::Borrowed(buf.as_raw()),
+        ),
+        DynamicImage::ImageLuma16(buf) => (
+            png::ColorType::Grayscale,
+            png::BitDepth::Sixteen,
+            be_bytes(buf.as_raw()),
+        ),
+        DynamicImage::ImageLumaA16(buf) => (
+            png::ColorType::GrayscaleAlpha,
+            png::BitDepth::Sixteen,
+            be_bytes(buf.as_raw()),
+        ),
+        DynamicImage::ImageRgb16(buf) => (
+            png::ColorType::Rgb,
+            png::BitDepth::Sixteen,
+            be_bytes(buf.as_raw()),
+        ),
+        DynamicImage::ImageRgb32F(_) => (
+            png::ColorType::Rgb,
+            png::BitDepth::Sixteen,
+            be_bytes(image.to_rgb16().as_raw()),
+        ),
+        DynamicImage::ImageRgba16(buf) => (
+            png::ColorType::Rgba,
+            png::BitDepth::Sixteen,
+            be_bytes(buf.as_raw()),
+        ),
+        _ => (
+            png::ColorType::Rgba,
+            png::BitDepth::Sixteen,
+            be_bytes(image.to_rgba16().as_raw()),
+        ),
+    }
+}
+
+/// Encode a PNG, or an APNG if there are several frames
+fn encode_png(frames: &[DynamicImage], options: &SaveOptions) -> Result<Vec<u8>, PuhuError> {
+    let to_err = |e: png::EncodingError| encoding_error(ImageFormat::Png, e);
+    check_frame_options(options, frames.len())?;
+    check_disposal(options, 2)?;
+    let frames = uniform_frames(frames);
+    let image = &frames[0];
+    let palette = options.palette.as_ref().filter(|_| frames.len() == 1);
+    // A transparency option is the transparent index of palette images
+    let transparency = options.transparency.clone().or_else(|| {
+        let alpha = palette?.alphas();
+        (!alpha.is_empty()).then(|| Transparency::Palette(alpha.to_vec()))
+    });
+    let plte = palette.map(|palette| {
+        let last_alpha = match &transparency {
+            Some(Transparency::Gray(index)) => u8::try_from(*index).ok(),
+            Some(Transparency::Palette(alpha)) => alpha.len().checked_sub(1).map(|i| i as u8),
+            _ => None,
+        };
+        padded_palette(image.as_bytes(), palette, last_alpha)
+    });
+    let (color_type, bit_depth, data) = match &plte {
+        Some(plte) => {
+            let (bit_depth, data) = indexed_png_layout(image, plte.len() / 3);
+            (png::ColorType::Indexed, bit_depth, Cow::Owned(data))
+        }
+        None => png_layout(image),
+    };
+
+    let mut buffer = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut buffer, image.width(), image.height());
+        encoder.set_color(color_type);
+        encoder.set_depth(bit_depth);
+        if let Some(plte) = plte {
+            encoder.set_palette(plte);
+        }
+        if frames.len() > 1 {
+            let plays = options.loop_count.unwrap_or(0);
+            encoder
+                .set_animated(frames.len() as u32, plays.into())
+                .map_err(to_err)?;
+        }
+
+        // Pillow defaults to zlib level 6, which matches png's balanced setting
+        match (options.compress_level, options.optimize) {
+            (Some(0), _) => encoder.set_deflate_compression(png::DeflateCompression::NoCompression),
+            (Some(level), _) => {
+                encoder.set_deflate_compression(png::DeflateCompression::Level(level))
+            }
+            (None, true) => encoder.set_deflate_compression(png::DeflateCompression::Level(9)),
+            (None, false) => {}
+        }
+
+        if let Some(gamma) = options.gamma {
+            encoder.set_source_gamma(png::ScaledFloat::new(gamma as f32));
+        }
+        if let Some(transparency) = &transparency {
+            let trns = match (transparency, color_type) {
+                (Transparency::Gray(level), png::ColorType::Grayscale) => {
+                    level.to_be_bytes().to_vec()
+                }
+                (&Transparency::Gray(index), png::ColorType::Indexed) if index < 256 => {
+                    // Alpha of each entry up to the transparent one
+                    let mut alpha = vec![255; index as usize];
+                    alpha.push(0);
+                    alpha
+                }
+                (Transparency::Palette(alpha), png::ColorType::Indexed) => alpha.clone(),
+                (Transparency::Rgb(r, g, b), png::ColorType::Rgb) => {
+                    [r, g, b].iter().flat_map(|v| v.to_be_bytes()).collect()
+                }
+                _ => {
+                    return Err(PuhuError::InvalidOperation(
+                        "PNG transparency must be an integer for L and P images or an (r, g, b) tuple for RGB images".to_string(),
+                    ))
+                }
+            };
+            encoder.set_trns(trns);
+        }
+        for (keyword, text) in &options.pnginfo {
+            // tEXt is Latin-1 only; anything else needs an iTXt chunk
+            if text.chars().all(|c| (c as u32) <= 0xFF) {
+                encoder
+                    .add_text_chunk(keyword.clone(), text.clone())
+                    .map_err(to_err)?;
+            } else {
+                encoder
+                    .add_itxt_chunk(keyword.clone(), text.clone())
+                    .map_err(to_err)?;
+            }
+        }
+
+        if let Some((x, y)) = options.dpi {
+            // pHYs stores pixels per metre
+            encoder.set_pixel_dims(Some(png::PixelDimensions {
+                xppu: (x / 0.0254).round() as u32,
+                yppu: (y / 0.0254).round() as u32,
+                unit: png::Unit::Meter,
+            }));
+        }
+
+        let mut writer = encoder.write_header().map_err(to_err)?;
+        if let Some(exif) = &options.exif {
+            writer.write_chunk(png::chunk::eXIf, exif).map_err(to_err)?;
+        }
+        if let Some(profile) = &options.icc_profile {
+            // Profile name, compression method 0 and the zlib stream
+            let mut iccp = b"ICC Profile\0\0".to_vec();
+            iccp.extend(fdeflate::compress_to_vec(profile));
+            writer
+                .write_chunk(png::chunk::iCCP, &iccp)
+                .map_err(to_err)?;
+        }
+        if frames.len() == 1 {
+            writer.write_image_data(&data).map_err(to_err)?;
+        } else {
+            let disposal = |i| frame_value(&options.disposal, i).unwrap_or(0);
+            let regions = frame_regions(&frames, options.optimize, |i| disposal(i) == 0);
+            for (i, (frame, region)) in frames.iter().zip(regions).enumerate() {
+                let (numerator, denominator) =
+                    apng_delay(frame_value(&options.duration, i).unwrap_or(0));
+                writer
+                    .set_frame_delay(numerator, denominator)
+                    .map_err(to_err)?;
+                let dispose_op = match disposal(i) {
+                    1 => png::DisposeOp::Background,
+                    2 => png::DisposeOp::Previous,
+                    _ => png::DisposeOp::None,
+                };
+                writer.set_dispose_op(dispose_op).map_err(to_err)?;
+                // The position is checked against the current size, so
+                // move to the origin before resizing
+                writer.set_frame_position(0, 0).map_err(to_err)?;
+                writer
+                    .set_frame_dimension(region.width, region.height)
+                    .map_err(to_err)?;
+                writer
+                    .set_frame_position(region.x, region.y)
+                    .map_err(to_err)?;
+                let patch = crop_frame(frame, region);
+                let (_, _, data) = png_layout(&patch);
+                writer.write_image_data(&data).map_err(to_err)?;
+            }
+        }
+        writer.finish().map_err(to_err)?;
+    }
+    Ok(buffer)
+}
+
+/// Palette colors, padded with black to cover every index in use and the
+/// last index with an alpha value
+fn padded_palette(indices: &[u8], palette: &Palette, last_alpha: Option<u8>) -> Vec<u8> {
+    let used = indices
+        .iter()
+        .chain(&last_alpha)
+        .max()
+        .map_or(0, |&i| i as usize + 1);
+    let mut colors = palette.colors().to_vec();
+    if colors.len() < used * 3 {
+        colors.resize(used * 3, 0);
+    }
+    if colors.is_empty() {
+        colors.extend([0, 0, 0]);
+    }
+    colors
+}
+
+/// PNG rows of palette indices, packed to the smallest bit depth that holds
+/// every entry
+fn indexed_png_layout(indices: &DynamicImage, entries: usize) -> (png::BitDepth, Vec<u8>) {
+    let (bit_depth, bits) = match entries {
+        0..=2 => (png::BitDepth::One, 1),
+        3..=4 => (png::BitDepth::Two, 2),
+        5..=16 => (png::BitDepth::Four, 4),
+        _ => return (png::BitDepth::Eight, indices.as_bytes().to_vec()),
+    };
+    let per_byte = 8 / bits;
+    let data = indices
+        .as_bytes()
+        .chunks_exact(indices.width() as usize)
+        .flat_map(|row| row.chunks(per_byte))
+        .map(|chunk| {
+            chunk.iter().enumerate().fold(0u8, |byte, (i, &index)| {
+                byte | index << (8 - bits * (i + 1))
+            })
+        })
+        .collect();
+    (bit_depth, data)
+}
+
+/// APNG frame delay as a fraction of a second
+fn apng_delay(duration: u32) -> (u16, u16) {
+    match u16::try_from(duration) {
+        Ok(milliseconds) => (milliseconds, 1000),
+        Err(_) => ((duration / 10).min(u16::MAX as u32) as u16, 100),
+    }
+}
+
+fn encode_webp(image: &DynamicImage, options: &SaveOptions) -> Result<Vec<u8>, PuhuError> {
+    let mut buffer = Vec::new();
+    let mut encoder = image::codecs::webp::WebPEncoder::new_lossless(&mut buffer);
+    if let Some(exif) = &options.exif {
+        encoder
+            .set_exif_metadata(exif.clone())
+            .map_err(|e| PuhuError::ImageError(ImageError::Unsupported(e)))?;
+    }
+    if let Some(profile) = &options.icc_profile {
+        encoder
+            .set_icc_profile(profile.clone())
+            .map_err(|e| PuhuError::ImageError(ImageError::Unsupported(e)))?;
+    }
+    image
+        .write_with_encoder(encoder)
+        .map_err(PuhuError::ImageError)?;
+    Ok(buffer)
+}
+
+/// Append a RIFF chunk, padded to an even length
+fn riff_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend((data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Encode an animated WebP, with each frame stored losslessly
+fn encode_animated_webp(
+    frames: &[DynamicImage],
+    options: &SaveOptions,
+) -> Result<Vec<u8>, PuhuError> {
+    let to_err = |e: image_webp::EncodingError| encoding_error(ImageFormat::WebP, e);
+    check_frame_options(options, frames.len())?;
+    let frames = uniform_frames(frames);
+    let (width, height) = frames[0].dimensions();
+    if width > 16384 || height > 16384 {
+        return Err(PuhuError::InvalidOperation(format!(
+            "Image too large for WebP ({}x{}), maximum is 16384x16384",
+            width, height
+        )));
+    }
+    let has_alpha = frames[0].color().has_alpha();
+
+    // Feature flags: ICC profile, alpha, EXIF and animation
+    let mut flags = 0x02;
+    if options.icc_profile.is_some() {
+        flags |= 0x20;
+    }
+    if has_alpha {
+        flags |= 0x10;
+    }
+    if options.exif.is_some() {
+        flags |= 0x08;
+    }
+    let mut vp8x = vec![flags, 0, 0, 0];
+    vp8x.extend(&(width - 1).to_le_bytes()[..3]);
+    vp8x.extend(&(height - 1).to_le_bytes()[..3]);
+
+    let mut chunks = Vec::new();
+    riff_chunk(&mut chunks, b"VP8X", &vp8x);
+    if let Some(profile) = &options.icc_profile {
+        riff_chunk(&mut chunks, b"ICCP", profile);
+    }
+    // Transparent background color, then the loop count
+    let mut anim = vec![0; 4];
+    anim.extend(options.loop_count.unwrap_or(0).to_le_bytes());
+    riff_chunk(&mut chunks, b"ANIM", &anim);
+
+    let regions = frame_regions(&frames, options.optimize, |_| true);
+    for (i, (frame, region)) in frames.iter().zip(regions).enumerate() {
+        // Frame offsets are stored halved, so round them down to even values
+        let region = FrameRegion {
+            x: region.x & !1,
+            y: region.y & !1,
+            width: region.width + (region.x & 1),
+            height: region.height + (region.y & 1),
+        };
+        let patch = crop_frame(frame, region);
+        let mut encoded = Vec::new();
+        let encoder = image_webp::WebPEncoder::new(&mut encoded);
+        if has_alpha {
+            let pixels = patch.to_rgba8();
+            encoder.encode(
+                &pixels,
+                region.width,
+                region.height,
+                image_webp::ColorType::Rgba8,
+            )
+        } else {
+            let pixels = patch.to_rgb8();
+            encoder.encode(
+                &pixels,
+                region.width,
+                region.height,
+                image_webp::ColorType::Rgb8,
+            )
+        }
+        .map_err(to_err)?;
+
+        let duration = frame_value(&options.duration, i)
+            .unwrap_or(0)
+            .min(0xFF_FFFF);
+        let mut anmf = Vec::new();
+        for value in [
+            region.x / 2,
+            region.y / 2,
+            region.width - 1,
+            region.height - 1,
+            duration,
+        ] {
+            anmf.extend(&value.to_le_bytes()[..3]);
+        }
+        // Replace the area without blending and keep it for the next frame
+        anmf.push(0x02);
+        // The frame's own VP8L chunk follows the RIFF header
+        anmf.extend_from_slice(&encoded[12..]);
+        riff_chunk(&mut chunks, b"ANMF", &anmf);
+    }
+    if let Some(exif) = &options.exif {
+        riff_chunk(&mut chunks, b"EXIF", exif);
+    }
+
+    let mut buffer = b"RIFF".to_vec();
+    buffer.extend((chunks.len() as u32 + 4).to_le_bytes());
+    buffer.extend_from_slice(b"WEBP");
+    buffer.extend(chunks);
+    Ok(buffer)
+}
+
+/// Quantize an RGBA frame with its own palette of up to 256 colors, using
+/// the exact colors when there are few enough. Pixels with alpha below 128
+/// become transparent.
+fn gif_frame(image: &RgbaImage) -> gif::Frame<'static> {
+    let opaque = |p: &&image::Rgba<u8>| p[3] >= 128;
+    let has_transparency = image.pixels().any(|p| !opaque(&p));
+    let max_colors = if has_transparency { 255 } else { 256 };
+
+    let colors = image.pixels().filter(opaque).map(|p| [p[0], p[1], p[2]]);
+    let mut palette = palette::exact_palette(colors, max_colors).unwrap_or_else(|| {
+        let image = DynamicImage::ImageRgba8(image.clone());
+        palette::generate_adaptive_palette(&image, max_colors as u32)
+    });
+    let transparent_index = (palette.len() / 3) as u8;
+    if has_transparency {
+        palette.extend([0, 0, 0]);
+    }
+
+    let entries = Palette::new(
+        palette.clone(),
+        has_transparency.then_some(transparent_index),
+    )
+    .expect("at most 256 entries");
+    let mut mapper = Mapper::new(&entries, Metric::Rgb);
+    let buffer = image.pixels().map(|p| mapper.nearest(p.0)).collect();
+
+    gif::Frame {
+        width: image.width() as u16,
+        height: image.height() as u16,
+        buffer: Cow::Owned(buffer),
+        palette: Some(palette),
+        transparent: has_transparency.then_some(transparent_index),
+        ..gif::Frame::default()
+    }
+}
+
+/// GIF frame of the indices of a palette image. GIF has a single transparent
+/// index, so entries with alpha below 128 are drawn with the first of them.
+fn indexed_gif_frame(indices: &DynamicImage, palette: &Palette) -> gif::Frame<'static> {
+    let transparent = palette.alphas().iter().position(|&a| a < 128);
+    let transparent = transparent.map(|i| i as u8);
+    let buffer: Vec<u8> = match transparent {
+        Some(transparent) => indices
+            .as_bytes()
+            .iter()
+            .map(|&i| {
+                if palette.alpha(i) < 128 {
+                    transparent
+                } else {
+                    i
+                }
+            })
+            .collect(),
+        None => indices.as_bytes().to_vec(),
+    };
+    gif::Frame {
+        width: indices.width() as u16,
+        height: indices.height() as u16,
+        palette: Some(padded_palette(&buffer, palette, transparent)),
+        buffer: Cow::Owned(buffer),
+        transparent,
+        ..gif::Frame::default()
+    }
+}
+
+fn encode_gif(frames: &[DynamicImage], options: &SaveOptions) -> Result<Vec<u8>, PuhuError> {
+    let to_err = |e: gif::EncodingError| encoding_error(ImageFormat::Gif, e);
+    check_frame_options(options, frames.len())?;
+    check_disposal(options, 3)?;
+    let frames = uniform_frames(frames);
+    let (width, height) = frames[0].dimensions();
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(PuhuError::InvalidOperation(format!(
+            "Image too large for GIF ({}x{}), maximum is 65535x65535",
+            width, height
+        )));
+    }
+    let (width, height) = (width as u16, height as u16);
+    // Without a disposal method frames stay on the canvas, where pixels
+    // left transparent by the next frame would keep showing; like Pillow,
+    // such frames are cleared to the background instead
+    let cleared: Vec<bool> = (0..frames.len())
+        .map(|i| {
+            options.disposal.is_empty()
+                && frames
+                    .get(i + 1)
+                    .is_some_and(|next| uncovers(&frames[i], next))
+        })
+        .collect();
+    let disposal = |i| frame_value(&options.disposal, i).unwrap_or(if cleared[i] { 2 } else { 0 });
+    // Disposal methods 0 and 1 leave the frame on the canvas
+    let regions = frame_regions(&frames, options.optimize, |i| disposal(i) <= 1);
+    let palette = options.palette.as_ref().filter(|_| frames.len() == 1);
+
+    let mut buffer = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut buffer, width, height, &[]).map_err(to_err)?;
+        if let Some(loop_count) = options.loop_count {
+            encoder
+                .set_repeat(match loop_count {
+                    0 => gif::Repeat::Infinite,
+                    n => gif::Repeat::Finite(n),
+                })
+                .map_err(to_err)?;
+        }
+        if let Some(comment) = &options.comment {
+            encoder
+                .write_raw_extension(gif::Extension::Comment.into(), &[comment])
+                .map_err(|e| encoding_error(ImageFormat::Gif, e))?;
+        }
+        for (i, (frame, region)) in frames.iter().zip(regions).enumerate() {
+            let patch = crop_frame(frame, region);
+            let mut gif_frame = match palette {
+                Some(palette) => indexed_gif_frame(&patch, palette),
+                None => gif_frame(&patch.to_rgba8()),
+            };
+            gif_frame.left = region.x as u16;
+            gif_frame.top = region.y as u16;
+            if let Some(duration) = frame_value(&options.duration, i) {
+                gif_frame.delay = (duration / 10).min(u16::MAX as u32) as u16;
+            }
+            gif_frame.dispose = match disposal(i) {
+                1 => gif::DisposalMethod::Keep,
+                2 => gif::DisposalMethod::Background,
+                3 => gif::DisposalMethod::Previous,
+                _ => gif::DisposalMethod::Any,
+            };
+            encoder.write_frame(&gif_frame).map_err(to_err)?;
+        }
+    }
+
+    if let Some(background) = options.background {
+        // The encoder always writes index 0 into the logical screen descriptor
+        buffer[11] = background;
+    }
+    Ok(buffer)
+}
+
+/// Whether a GIF frame is transparent where the previous frame is opaque,
+/// using the alpha threshold of `gif_frame`
+fn uncovers(previous: &DynamicImage, frame: &DynamicImage) -> bool {
+    if !frame.color().has_alpha() {
+        return false;
+    }
+    let (previous, frame) = (previous.to_rgba8(), frame.to_rgba8());
+    previous
+        .pixels()
+        .zip(frame.pixels())
+        .any(|(p, f)| p[3] >= 128 && f[3] < 128)
+}
+
+/// Encode an ICO with the image resized to each icon size.
+///
+/// Like Pillow, sizes larger than the image are skipped and the aspect ratio
+/// is kept, so a size may produce a smaller icon. If no size fits, the image
+/// is stored at its own size, limited to 256x256.
+fn encode_ico(image: &DynamicImage, options: &SaveOptions) -> Result<Vec<u8>, PuhuError> {
+    use image::codecs::ico::{IcoEncoder, IcoFrame};
+
+    let requested = match options.sizes.as_slice() {
+        [] => DEFAULT_ICO_SIZES,
+        sizes => sizes,
+    };
+    let (width, height) = image.dimensions();
+    let mut sizes: Vec<(u32, u32)> = requested
+        .iter()
+        .copied()
+        .filter(|&(w, h)| w <= width && h <= height)
+        .collect();
+    if sizes.is_empty() {
+        sizes.push((width.min(256), height.min(256)));
+    }
+
+    let mut icons: Vec<DynamicImage> = Vec::new();
+    for (w, h) in sizes {
+        let icon = if (w, h) == (width, height) {
+            image.to_rgba8().into()
+        } else {
+            DynamicImage::ImageRgba8(image.resize(w, h, FilterType::Lanczos3).to_rgba8())
+        };
+        if !icons.iter().any(|i| i.dimensions() == icon.dimensions()) {
+            icons.push(icon);
+        }
+    }
+    let frames = icons
+        .iter()
+        .map(|icon| {
+            IcoFrame::as_png(
+                icon.as_bytes(),
+                icon.width(),
+                icon.height(),
+                image::ExtendedColorType::Rgba8,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(PuhuError::ImageError)?;
+
+    let mut buffer = Vec::new();
+    IcoEncoder::new(&mut buffer)
+        .encode_images(&frames)
+        .map_err(PuhuError::ImageError)?;
+    Ok(buffer)
+}
+
+/// Encode a TIFF with one page per frame
+fn encode_tiff(frames: &[DynamicImage], options: &SaveOptions) -> Result<Vec<u8>, PuhuError> {
+    use tiff::encoder::{compression::DeflateLevel, Compression, TiffEncoder};
+
+    let to_err = |e: tiff::TiffError| encoding_error(ImageFormat::Tiff, e);
+    check_frame_options(options, frames.len())?;
+    let tags = TiffTags {
+        dpi: options.dpi,
+        exif: options.exif.as_deref().map(Exif::parse).transpose()?,
+        icc_profile: options.icc_profile.as_deref(),
+    };
+
+    let mut cursor = Cursor::new(Vec::new());
+    {
+        let mut encoder = TiffEncoder::new(&mut cursor).map_err(to_err)?;
+        for (i, image) in frames.iter().enumerate() {
+            let compression = match frame_value(&options.compression, i) {
+                None | Some(TiffCompression::Raw) => Compression::Uncompressed,
+                Some(TiffCompression::Lzw) => Compression::Lzw,
+                Some(TiffCompression::Deflate) => Compression::Deflate(DeflateLevel::default()),
+                Some(TiffCompression::Packbits) => Compression::Packbits,
+            };
+            encoder = encoder.with_compression(compression);
+            let extended = frame_value(&options.extended, i).flatten();
+            write_tiff_image(&mut encoder, image, extended, &tags).map_err(to_err)?;
+        }
+    }
+    Ok(cursor.into_inner())
+}
+
+/// Write an image as the next TIFF page in a color type the encoder
+/// supports, with the values of "I" and "F" images as signed integer and
+/// float samples and the inks of "CMYK" images as separated samples
+fn write_tiff_image<W>(
+    encoder: &mut tiff::encoder::TiffEncoder<W>,
+    image: &DynamicImage,
+    extended: Option<ExtendedMode>,
+    tags: &TiffTags<'_>,
+) -> tiff::TiffResult<()>
+where
+    W: std::io::Write + std::io::Seek,
+{
+    use tiff::encoder::colortype;
+
+    let (width, height) = (image.width(), image.height());
+    match extended {
+        Some(ExtendedMode::Int32) => {
+            let values: Vec<i32> = modes::values(image, Mode::I)
+                .iter()
+                .map(|&v| v as i32)
+                .collect();
+            return write_tiff_page::<colortype::GrayI32, _>(encoder, width, height, &values, tags);
+        }
+        Some(ExtendedMode::Float32) => {
+            let values: Vec<f32> = modes::values(image, Mode::F)
+                .iter()
+                .map(|&v| v as f32)
+                .collect();
+            return write_tiff_page::<colortype::Gray32Float, _>(
+                encoder, width, height, &values, tags,
+            );
+        }
+        Some(ExtendedMode::Cmyk) => {
+            let inks = image.to_rgba8();
+            return write_tiff_page::<colortype::CMYK8, _>(encoder, width, height, &inks, tags);
+        }
+        Some(ExtendedMode::YCbCr) => unreachable!("YCbCr images are written as RGB"),
+        None => {}
+    }
+    match image {
+        DynamicImage::ImageLuma8(buf) => {
+            write_tiff_page::<colortype::Gray8, _>(encoder, width, height, buf.as_raw(), tags)
+        }
+        DynamicImage::ImageRgb8(buf) => {
+            write_tiff_page::<colortype::RGB8, _>(encoder, width, height, buf.as_raw(), tags)
+        }
+        DynamicImage::ImageRgba8(buf) => {
+            write_tiff_page::<colortype::RGBA8, _>(encoder, width, height, buf.as_raw(), tags)
+        }
+        DynamicImage::ImageLuma16(buf) => {
+            write_tiff_page::<colortype::Gray16, _>(encoder, width, height, buf.as_raw(), tags)
+        }
+        DynamicImage::ImageRgb16(buf) => {
+            write_tiff_page::<colortype::RGB16, _>(encoder, width, height, buf.as_raw(), tags)
+        }
+        DynamicImage::ImageRgba16(buf) => {
+            write_tiff_page::<colortype::RGBA16, _>(encoder, width, height, buf.as_raw(), tags)
+        }
+        DynamicImage::ImageRgb32F(buf) => {
+            write_tiff_page::<colortype::RGB32Float, _>(encoder, width, height, buf.as_raw(), tags)
+        }
+        DynamicImage::ImageRgba32F(buf) => {
+            write_tiff_page::<colortype::RGBA32Float, _>(encoder, width, height, buf.as_raw(), tags)
+        }
+        DynamicImage::ImageLumaA16(_) => write_tiff_page::<colortype::RGBA16, _>(
+            encoder,
+            width,
+            height,
+            image.to_rgba16().as_raw(),
+            tags,
+        ),
+        // TIFF has no grayscale + alpha colortype in the encoder
+        _ => write_tiff_page::<colortype::RGBA8, _>(
+            encoder,
+            width,
+            height,
+            image.to_rgba8().as_raw(),
+            tags,
+        ),
+    }
+}
+
+/// Metadata tags written to each TIFF page
+struct TiffTags<'a> {
+    dpi: Option<(f64, f64)>,
+    exif: Option<Exif>,
+    icc_profile: Option<&'a [u8]>,
+}
+
+fn write_tiff_page<C, W>(
+    encoder: &mut tiff::encoder::TiffEncoder<W>,
+    width: u32,
+    height: u32,
+    data: &[C::Inner],
+    tags: &TiffTags<'_>,
+) -> tiff::TiffResult<()>
+where
+    C: tiff::encoder::colortype::ColorType,
+    [C::Inner]: tiff::encoder::TiffValue,
+    W: std::io::Write + std::io::Seek,
+{
+    use tiff::encoder::Rational;
+    use tiff::tags::{ResolutionUnit, Tag};
+
+    // The Exif and GPS IFDs are written ahead of the page, which refers to
+    // them by offset
+    let pointers = match &tags.exif {
+        Some(exif) => write_exif_directories(encoder, exif)?,
+        None => Vec::new(),
+    };
+    let mut page = encoder.new_image::<C>(width, height)?;
+    if let Some(exif) = &tags.exif {
+        for (tag, value) in exif.tiff_entries(IfdKind::Primary) {
+            write_exif_tag(page.encoder(), tag, value)?;
+        }
+    }
+    for (tag, offset) in pointers {
+        page.encoder()
+            .write_tag(Tag::from_u16_exhaustive(tag), offset)?;
+    }
+    if let Some((x, y)) = tags.dpi {
+        // Store resolution with two decimal places of precision
+        page.resolution_unit(ResolutionUnit::Inch);
+        page.x_resolution(Rational {
+            n: (x * 100.0).round() as u32,
+            d: 100,
+        });
+        page.y_resolution(Rational {
+            n: (y * 100.0).round() as u32,
+            d: 100,
+        });
+    }
+    if let Some(profile) = tags.icc_profile {
+        page.encoder().write_tag(Tag::IccProfile, profile)?;
+    }
+    page.write_data(data)
+}
+
+/// Write the Exif (with its Interop IFD) and GPS IFDs of EXIF data as TIFF
+/// directories outside the page chain, returning the pointer tags that
+/// refer to them
+fn write_exif_directories<W>(
+    encoder: &mut tiff::encoder::TiffEncoder<W>,
+    exif: &Exif,
+) -> tiff::TiffResult<Vec<(u16, u32)>>
+where
+    W: std::io::Write + std::io::Seek,
+{
+    let mut write_ifd = |kind: IfdKind, pointer: Option<(u16, u32)>| {
+        let mut directory = encoder.extra_directory()?;
+        for (tag, value) in exif.tiff_entries(kind) {
+            write_exif_tag(&mut directory, tag, value)?;
+        }
+        if let Some((tag, offset)) = pointer {
+            directory.write_tag(tiff::tags::Tag::from_u16_exhaustive(tag), offset)?;
+        }
+        directory
+            .finish_with_offsets()
+            .map(|offsets| offsets.offset)
+    };
+
+    let mut pointers = Vec::new();
+    if exif.has_entries(IfdKind::Exif) {
+        let interop = if exif.has_entries(IfdKind::Interop) {
+            Some((INTEROP_IFD_POINTER, write_ifd(IfdKind::Interop, None)?))
+        } else {
+            None
+        };
+        pointers.push((EXIF_IFD_POINTER, write_ifd(IfdKind::Exif, interop)?));
+    }
+    if exif.has_entries(IfdKind::Gps) {
+        pointers.push((GPS_IFD_POINTER, write_ifd(IfdKind::Gps, None)?));
+    }
+    Ok(pointers)
+}
+
+/// Write an EXIF value as a tag of a TIFF directory with its own field type
+fn write_exif_tag<W, K>(
+    directory: &mut tiff::encoder::DirectoryEncoder<'_, W, K>,
+    tag: u16,
+    value: &ExifValue,
+) -> tiff::TiffResult<()>
+where
+    W: std::io::Write + std::io::Seek,
+    K: tiff::encoder::TiffKind,
+{
+    use tiff::encoder::{Rational, SRational};
+
+    let tag = tiff::tags::Tag::from_u16_exhaustive(tag);
+    match value {
+        ExifValue::Byte(v) => directory.write_tag(tag, &v[..]),
+        // The encoder only writes plain ASCII text, so other text is
+        // written as its UTF-8 bytes
+        ExifValue::Ascii(s) if s.is_ascii() && !s.contains('\0') => {
+            directory.write_tag(tag, s.as_str())
+        }
+        ExifValue::Ascii(s) => {
+            let mut text = s.as_bytes().to_vec();
+            text.push(0);
+            directory.write_tag(tag, RawTiffValue::<true>(&text))
+        }
+        ExifValue::Short(v) => directory.write_tag(tag, &v[..]),
+        ExifValue::Long(v) => directory.write_tag(tag, &v[..]),
+        ExifValue::Rational(v) => {
+            let v: Vec<Rational> = v.iter().map(|&(n, d)| Rational { n, d }).collect();
+            directory.write_tag(tag, &v[..])
+        }
+        ExifValue::SByte(v) => directory.write_tag(tag, &v[..]),
+        ExifValue::Undefined(v) => directory.write_tag(tag, RawTiffValue::<false>(v)),
+        ExifValue::SShort(v) => directory.write_tag(tag, &v[..]),
+        ExifValue::SLong(v) => directory.write_tag(tag, &v[..]),
+        ExifValue::SRational(v) => {
+            let v: Vec<SRational> = v.iter().map(|&(n, d)| SRational { n, d }).collect();
+            directory.write_tag(tag, &v[..])
+        }
+        ExifValue::Float(v) => directory.write_tag(tag, &v[..]),
+        ExifValue::Double(v) => directory.write_tag(tag, &v[..]),
+    }
+}
+
+/// Bytes written as a TIFF field of a type the encoder has no value type
+/// for: ASCII when `ASCII` is set, UNDEFINED otherwise
+struct RawTiffValue<'a, const ASCII: bool>(&'a [u8]);
+
+impl<const ASCII: bool> tiff::encoder::TiffValue for RawTiffValue<'_, ASCII> {
+    const BYTE_LEN: u8 = 1;
+    const FIELD_TYPE: tiff::tags::Type = if ASCII {
+        tiff::tags::Type::ASCII
+    } else {
+        tiff::tags::Type::UNDEFINED
+    };
+
+    fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    fn data(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0)
+    }
+}
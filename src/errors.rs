@@ -1,4 +1,4 @@
-use pyo3::exceptions::PyException;
+use pyo3::exceptions::{PyException, PyRuntimeWarning};
 use pyo3::prelude::*;
 use thiserror::Error;
 
@@ -14,6 +14,12 @@ pub enum PuhuError {
     ImageError(#[from] image::ImageError),
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
+    #[error("Decompression bomb: {0}")]
+    DecompressionBomb(String),
+    #[error("Colour management error: {0}")]
+    ColorManagement(String),
+    #[error("Python error: {0}")]
+    Python(#[from] PyErr),
 }
 
 impl From<PuhuError> for PyErr {
@@ -22,8 +28,15 @@ impl From<PuhuError> for PyErr {
             PuhuError::InvalidImage(msg) => InvalidImageError::new_err(msg),
             PuhuError::UnsupportedFormat(msg) => UnsupportedFormatError::new_err(msg),
             PuhuError::Io(err) => PuhuIOError::new_err(err.to_string()),
+            // Decoder limits are the hard limits configured on open()
+            PuhuError::ImageError(err @ image::ImageError::Limits(_)) => {
+                DecompressionBombError::new_err(err.to_string())
+            }
             PuhuError::ImageError(err) => PuhuProcessingError::new_err(err.to_string()),
             PuhuError::InvalidOperation(msg) => PuhuProcessingError::new_err(msg),
+            PuhuError::DecompressionBomb(msg) => DecompressionBombError::new_err(msg),
+            PuhuError::ColorManagement(msg) => PyCMSError::new_err(msg),
+            PuhuError::Python(err) => err,
         }
     }
 }
@@ -33,3 +46,6 @@ pyo3::create_exception!(puhu_core, PuhuProcessingError, PyException);
 pyo3::create_exception!(puhu_core, InvalidImageError, PuhuProcessingError);
 pyo3::create_exception!(puhu_core, UnsupportedFormatError, PuhuProcessingError);
 pyo3::create_exception!(puhu_core, PuhuIOError, PuhuProcessingError);
+pyo3::create_exception!(puhu_core, DecompressionBombError, PuhuProcessingError);
+pyo3::create_exception!(puhu_core, DecompressionBombWarning, PyRuntimeWarning);
+pyo3::create_exception!(puhu_core, PyCMSError, PuhuProcessingError);
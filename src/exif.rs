@@ -0,0 +1,877 @@
+use crate::errors::PuhuError;
+use crate::io;
+use pyo3::exceptions::PyKeyError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyList, PyString, PyTuple};
+use pyo3::IntoPyObjectExt;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+/// Prefix of EXIF payloads in JPEG APP1 segments and Pillow's `Exif.tobytes()`
+pub const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+pub const EXIF_IFD_POINTER: u16 = 0x8769;
+pub const GPS_IFD_POINTER: u16 = 0x8825;
+pub const INTEROP_IFD_POINTER: u16 = 0xA005;
+pub const ORIENTATION: u16 = 0x0112;
+
+/// Upper bound on entries per IFD, guarding against corrupt counts
+const MAX_IFD_ENTRIES: usize = 4096;
+
+/// Upper bound for the pages read from a TIFF file
+const MAX_TIFF_PAGES: usize = 65536;
+
+/// TIFF tags describing the pixel layout of a TIFF file, which are not
+/// meaningful once its EXIF data is copied to another image
+const TIFF_LAYOUT_TAGS: &[u16] = &[
+    254, 255, 256, 257, 258, 259, 262, 266, 273, 277, 278, 279, 280, 281, 284, 317, 320, 322, 323,
+    324, 325, 330, 338, 339,
+];
+
+/// A typed EXIF field value
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExifValue {
+    Byte(Vec<u8>),
+    Ascii(String),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    SByte(Vec<i8>),
+    Undefined(Vec<u8>),
+    SShort(Vec<i16>),
+    SLong(Vec<i32>),
+    SRational(Vec<(i32, i32)>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+/// Byte size of one value of a TIFF field type
+fn type_size(type_id: u16) -> Option<usize> {
+    match type_id {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        // Type 13 is an IFD offset, stored like a LONG
+        4 | 9 | 11 | 13 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+impl ExifValue {
+    fn type_id(&self) -> u16 {
+        match self {
+            ExifValue::Byte(_) => 1,
+            ExifValue::Ascii(_) => 2,
+            ExifValue::Short(_) => 3,
+            ExifValue::Long(_) => 4,
+            ExifValue::Rational(_) => 5,
+            ExifValue::SByte(_) => 6,
+            ExifValue::Undefined(_) => 7,
+            ExifValue::SShort(_) => 8,
+            ExifValue::SLong(_) => 9,
+            ExifValue::SRational(_) => 10,
+            ExifValue::Float(_) => 11,
+            ExifValue::Double(_) => 12,
+        }
+    }
+
+    /// Little-endian encoding of the value and its TIFF count
+    fn encode(&self) -> (u32, Vec<u8>) {
+        fn flat<T, const N: usize>(values: &[T], f: impl Fn(&T) -> [u8; N]) -> Vec<u8> {
+            values.iter().flat_map(f).collect()
+        }
+
+        let data = match self {
+            ExifValue::Byte(v) | ExifValue::Undefined(v) => v.clone(),
+            ExifValue::Ascii(s) => {
+                let mut data = s.as_bytes().to_vec();
+                data.push(0);
+                data
+            }
+            ExifValue::Short(v) => flat(v, |x| x.to_le_bytes()),
+            ExifValue::Long(v) => flat(v, |x| x.to_le_bytes()),
+            ExifValue::Rational(v) => v
+                .iter()
+                .flat_map(|(n, d)| [n.to_le_bytes(), d.to_le_bytes()].concat())
+                .collect(),
+            ExifValue::SByte(v) => flat(v, |x| x.to_le_bytes()),
+            ExifValue::SShort(v) => flat(v, |x| x.to_le_bytes()),
+            ExifValue::SLong(v) => flat(v, |x| x.to_le_bytes()),
+            ExifValue::SRational(v) => v
+                .iter()
+                .flat_map(|(n, d)| [n.to_le_bytes(), d.to_le_bytes()].concat())
+                .collect(),
+            ExifValue::Float(v) => flat(v, |x| x.to_le_bytes()),
+            ExifValue::Double(v) => flat(v, |x| x.to_le_bytes()),
+        };
+        let size = type_size(self.type_id()).expect("known type");
+        ((data.len() / size) as u32, data)
+    }
+
+    fn decode(type_id: u16, data: &[u8], big_endian: bool) -> Option<Self> {
+        fn chunks<const N: usize>(data: &[u8]) -> impl Iterator<Item = [u8; N]> + '_ {
+            data.chunks_exact(N)
+                .map(|c| c.try_into().expect("exact chunk"))
+        }
+        let u16_of = |b: [u8; 2]| {
+            if big_endian {
+                u16::from_be_bytes(b)
+            } else {
+                u16::from_le_bytes(b)
+            }
+        };
+        let u32_of = |b: [u8; 4]| {
+            if big_endian {
+                u32::from_be_bytes(b)
+            } else {
+                u32::from_le_bytes(b)
+            }
+        };
+        let u64_of = |b: [u8; 8]| {
+            if big_endian {
+                u64::from_be_bytes(b)
+            } else {
+                u64::from_le_bytes(b)
+            }
+        };
+        let pair = |b: [u8; 8]| {
+            (
+                u32_of([b[0], b[1], b[2], b[3]]),
+                u32_of([b[4], b[5], b[6], b[7]]),
+            )
+        };
+
+        Some(match type_id {
+            1 => ExifValue::Byte(data.to_vec()),
+            2 => {
+                let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                ExifValue::Ascii(String::from_utf8_lossy(&data[..end]).into_owned())
+            }
+            3 => ExifValue::Short(chunks(data).map(u16_of).collect()),
+            4 | 13 => ExifValue::Long(chunks(data).map(u32_of).collect()),
+            5 => ExifValue::Rational(chunks(data).map(pair).collect()),
+            6 => ExifValue::SByte(data.iter().map(|&b| b as i8).collect()),
+            7 => ExifValue::Undefined(data.to_vec()),
+            8 => ExifValue::SShort(chunks(data).map(|b| u16_of(b) as i16).collect()),
+            9 => ExifValue::SLong(chunks(data).map(|b| u32_of(b) as i32).collect()),
+            10 => ExifValue::SRational(
+                chunks(data)
+                    .map(|b| {
+                        let (n, d) = pair(b);
+                        (n as i32, d as i32)
+                    })
+                    .collect(),
+            ),
+            11 => ExifValue::Float(chunks(data).map(|b| f32::from_bits(u32_of(b))).collect()),
+            12 => ExifValue::Double(chunks(data).map(|b| f64::from_bits(u64_of(b))).collect()),
+            _ => return None,
+        })
+    }
+
+    /// First value as an unsigned integer, for IFD pointers and enumerated tags
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            ExifValue::Byte(v) => v.first().map(|&x| x as u32),
+            ExifValue::Short(v) => v.first().map(|&x| x as u32),
+            ExifValue::Long(v) => v.first().copied(),
+            _ => None,
+        }
+    }
+
+    /// Python representation: str for ASCII, bytes for BYTE/UNDEFINED,
+    /// and a number or tuple of numbers otherwise
+    fn to_object(&self, py: Python<'_>) -> PyResult<PyObject> {
+        fn scalar_or_tuple<'py, T: IntoPyObject<'py>>(
+            py: Python<'py>,
+            values: Vec<T>,
+        ) -> PyResult<PyObject> {
+            if values.len() == 1 {
+                values
+                    .into_iter()
+                    .next()
+                    .expect("one value")
+                    .into_py_any(py)
+            } else {
+                PyTuple::new(py, values)?.into_py_any(py)
+            }
+        }
+        let ratio = |n: f64, d: f64| if d == 0.0 { f64::NAN } else { n / d };
+
+        match self {
+            ExifValue::Byte(v) | ExifValue::Undefined(v) => PyBytes::new(py, v).into_py_any(py),
+            ExifValue::Ascii(s) => s.into_py_any(py),
+            ExifValue::Short(v) => scalar_or_tuple(py, v.clone()),
+            ExifValue::Long(v) => scalar_or_tuple(py, v.clone()),
+            ExifValue::Rational(v) => scalar_or_tuple(
+                py,
+                v.iter().map(|&(n, d)| ratio(n as f64, d as f64)).collect(),
+            ),
+            ExifValue::SByte(v) => scalar_or_tuple(py, v.clone()),
+            ExifValue::SShort(v) => scalar_or_tuple(py, v.clone()),
+            ExifValue::SLong(v) => scalar_or_tuple(py, v.clone()),
+            ExifValue::SRational(v) => scalar_or_tuple(
+                py,
+                v.iter().map(|&(n, d)| ratio(n as f64, d as f64)).collect(),
+            ),
+            ExifValue::Float(v) => scalar_or_tuple(py, v.clone()),
+            ExifValue::Double(v) => scalar_or_tuple(py, v.clone()),
+        }
+    }
+
+    /// Convert a Python value, keeping the type of the value it replaces where possible
+    fn from_object(
+        value: &Bound<'_, PyAny>,
+        previous: Option<&ExifValue>,
+    ) -> Result<Self, PuhuError> {
+        if let Ok(text) = value.downcast::<PyString>() {
+            return Ok(ExifValue::Ascii(text.to_cow()?.into_owned()));
+        }
+        if let Some(bytes) = io::buffer_to_vec(value)? {
+            return Ok(match previous {
+                Some(ExifValue::Byte(_)) => ExifValue::Byte(bytes),
+                _ => ExifValue::Undefined(bytes),
+            });
+        }
+
+        let items: Vec<Bound<'_, PyAny>> =
+            if value.downcast::<PyTuple>().is_ok() || value.downcast::<PyList>().is_ok() {
+                value.try_iter()?.collect::<PyResult<_>>()?
+            } else {
+                vec![value.clone()]
+            };
+        let unsupported = || {
+            PuhuError::InvalidOperation(format!(
+                "Unsupported EXIF value: {}; expected str, bytes, a number or a tuple of numbers",
+                value
+            ))
+        };
+        if items.is_empty() {
+            return Err(unsupported());
+        }
+
+        if let Ok(ints) = items
+            .iter()
+            .map(|item| item.extract::<i64>())
+            .collect::<PyResult<Vec<_>>>()
+        {
+            return integer_value(ints, previous).ok_or_else(unsupported);
+        }
+        if let Ok(floats) = items
+            .iter()
+            .map(|item| item.extract::<f64>())
+            .collect::<PyResult<Vec<_>>>()
+        {
+            return real_value(floats, previous).ok_or_else(unsupported);
+        }
+        Err(unsupported())
+    }
+}
+
+fn integer_value(values: Vec<i64>, previous: Option<&ExifValue>) -> Option<ExifValue> {
+    fn all<T: TryFrom<i64>>(values: &[i64]) -> Option<Vec<T>> {
+        values.iter().map(|&v| T::try_from(v).ok()).collect()
+    }
+
+    let same_type = match previous {
+        Some(ExifValue::Byte(_)) => all(&values).map(ExifValue::Byte),
+        Some(ExifValue::Short(_)) => all(&values).map(ExifValue::Short),
+        Some(ExifValue::Long(_)) => all(&values).map(ExifValue::Long),
+        Some(ExifValue::SByte(_)) => all(&values).map(ExifValue::SByte),
+        Some(ExifValue::SShort(_)) => all(&values).map(ExifValue::SShort),
+        Some(ExifValue::SLong(_)) => all(&values).map(ExifValue::SLong),
+        Some(ExifValue::Rational(_)) => all::<u32>(&values)
+            .map(|v| ExifValue::Rational(v.into_iter().map(|n| (n, 1)).collect())),
+        Some(ExifValue::SRational(_)) => all::<i32>(&values)
+            .map(|v| ExifValue::SRational(v.into_iter().map(|n| (n, 1)).collect())),
+        Some(ExifValue::Float(_)) => {
+            Some(ExifValue::Float(values.iter().map(|&v| v as f32).collect()))
+        }
+        Some(ExifValue::Double(_)) => Some(ExifValue::Double(
+            values.iter().map(|&v| v as f64).collect(),
+        )),
+        _ => None,
+    };
+
+    same_type
+        .or_else(|| all(&values).map(ExifValue::Short))
+        .or_else(|| all(&values).map(ExifValue::Long))
+        .or_else(|| all(&values).map(ExifValue::SLong))
+}
+
+fn real_value(values: Vec<f64>, previous: Option<&ExifValue>) -> Option<ExifValue> {
+    match previous {
+        Some(ExifValue::Float(_)) => {
+            return Some(ExifValue::Float(values.iter().map(|&v| v as f32).collect()))
+        }
+        Some(ExifValue::Double(_)) => return Some(ExifValue::Double(values)),
+        _ => {}
+    }
+
+    let signed =
+        matches!(previous, Some(ExifValue::SRational(_))) || values.iter().any(|&v| v < 0.0);
+    if signed {
+        values
+            .iter()
+            .map(|&v| {
+                let (n, d) = approximate_fraction(v)?;
+                Some((i32::try_from(n).ok()?, i32::try_from(d).ok()?))
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(ExifValue::SRational)
+    } else {
+        values
+            .iter()
+            .map(|&v| {
+                let (n, d) = approximate_fraction(v)?;
+                Some((u32::try_from(n).ok()?, u32::try_from(d).ok()?))
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(ExifValue::Rational)
+    }
+}
+
+/// Closest fraction with a denominator of at most one million, using continued fractions
+fn approximate_fraction(value: f64) -> Option<(i64, i64)> {
+    const MAX_DENOMINATOR: i64 = 1_000_000;
+    if !value.is_finite() || value.abs() > u32::MAX as f64 {
+        return None;
+    }
+
+    let (mut h0, mut h1, mut k0, mut k1) = (0i64, 1i64, 1i64, 0i64);
+    let mut x = value.abs();
+    loop {
+        let a = x.floor() as i64;
+        let (h2, k2) = (a * h1 + h0, a * k1 + k0);
+        if k2 > MAX_DENOMINATOR {
+            break;
+        }
+        (h0, h1, k0, k1) = (h1, h2, k1, k2);
+        let fraction = x - x.floor();
+        if fraction < 1e-9 {
+            break;
+        }
+        x = 1.0 / fraction;
+    }
+    Some((if value < 0.0 { -h1 } else { h1 }, k1))
+}
+
+/// The IFDs of an EXIF block that are exposed for reading and writing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IfdKind {
+    Primary,
+    Exif,
+    Gps,
+    Interop,
+}
+
+impl IfdKind {
+    /// The IFD a pointer tag in this IFD refers to
+    fn child(self, tag: u16) -> Option<IfdKind> {
+        match (self, tag) {
+            (IfdKind::Primary, EXIF_IFD_POINTER) => Some(IfdKind::Exif),
+            (IfdKind::Primary, GPS_IFD_POINTER) => Some(IfdKind::Gps),
+            (IfdKind::Exif, INTEROP_IFD_POINTER) => Some(IfdKind::Interop),
+            _ => None,
+        }
+    }
+}
+
+type Ifd = BTreeMap<u16, ExifValue>;
+
+/// Parsed EXIF data. Pointer tags are resolved into separate IFDs and are
+/// regenerated when serializing; the thumbnail IFD is not kept.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Exif {
+    primary: Ifd,
+    exif: Ifd,
+    gps: Ifd,
+    interop: Ifd,
+}
+
+/// Bounds-checked reads from TIFF-structured data, seeking to each
+/// structure so whole files need not be in memory
+struct TiffReader<R> {
+    reader: R,
+    big_endian: bool,
+}
+
+impl<R: Read + Seek> TiffReader<R> {
+    /// Check the byte order mark of a TIFF header
+    fn new(mut reader: R) -> Option<Self> {
+        let mut header = [0u8; 4];
+        reader.seek(SeekFrom::Start(0)).ok()?;
+        reader.read_exact(&mut header).ok()?;
+        let big_endian = match &header {
+            b"II*\0" => false,
+            b"MM\0*" => true,
+            _ => return None,
+        };
+        Some(TiffReader { reader, big_endian })
+    }
+
+    /// `len` bytes at `offset`; the read is bounded by the data, so corrupt
+    /// lengths cannot allocate more than is there
+    fn read(&mut self, offset: usize, len: usize) -> Option<Vec<u8>> {
+        self.reader.seek(SeekFrom::Start(offset as u64)).ok()?;
+        let mut data = Vec::new();
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut data)
+            .ok()?;
+        (data.len() == len).then_some(data)
+    }
+
+    fn u16_from(&self, b: [u8; 2]) -> u16 {
+        if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    }
+
+    fn u32_from(&self, b: [u8; 4]) -> u32 {
+        if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+
+    fn u16(&mut self, offset: usize) -> Option<u16> {
+        let b = self.read(offset, 2)?.try_into().ok()?;
+        Some(self.u16_from(b))
+    }
+
+    fn u32(&mut self, offset: usize) -> Option<u32> {
+        let b = self.read(offset, 4)?.try_into().ok()?;
+        Some(self.u32_from(b))
+    }
+
+    /// Read the entries of an IFD, skipping malformed ones
+    fn read_ifd(&mut self, offset: usize) -> Ifd {
+        let mut ifd = Ifd::new();
+        let count = self.u16(offset).map_or(0, |c| c as usize);
+        let Some(entries) = self.read(offset + 2, count.min(MAX_IFD_ENTRIES) * 12) else {
+            return ifd;
+        };
+        for entry in entries.chunks_exact(12) {
+            if let Some((tag, value)) =
+                self.read_entry(entry.try_into().expect("12-byte IFD entry"))
+            {
+                ifd.insert(tag, value);
+            }
+        }
+        ifd
+    }
+
+    fn read_entry(&mut self, entry: [u8; 12]) -> Option<(u16, ExifValue)> {
+        let field = |i: usize| [entry[i], entry[i + 1], entry[i + 2], entry[i + 3]];
+        let tag = self.u16_from([entry[0], entry[1]]);
+        let type_id = self.u16_from([entry[2], entry[3]]);
+        let count = self.u32_from(field(4)) as usize;
+        let len = type_size(type_id)?.checked_mul(count)?;
+        let data = if len <= 4 {
+            entry[8..8 + len].to_vec()
+        } else {
+            let start = self.u32_from(field(8)) as usize;
+            self.read(start, len)?
+        };
+        Some((tag, ExifValue::decode(type_id, &data, self.big_endian)?))
+    }
+}
+
+/// Offsets of the image directories chained from a TIFF header, one per page
+pub fn tiff_page_offsets<R: Read + Seek>(reader: R) -> Vec<u32> {
+    let Some(mut reader) = TiffReader::new(reader) else {
+        return Vec::new();
+    };
+    let mut offsets = Vec::new();
+    let mut seen = HashSet::new();
+    let mut offset = reader.u32(4).unwrap_or(0);
+    // Stop at the end of the chain, at a loop, or at a directory outside the file
+    while offset != 0 && offsets.len() < MAX_TIFF_PAGES && seen.insert(offset) {
+        let Some(count) = reader.u16(offset as usize) else {
+            break;
+        };
+        offsets.push(offset);
+        offset = reader
+            .u32(offset as usize + 2 + count as usize * 12)
+            .unwrap_or(0);
+    }
+    offsets
+}
+
+impl Exif {
+    /// Parse a TIFF-structured EXIF block, with or without the `Exif\0\0` prefix
+    pub fn parse(data: &[u8]) -> Result<Self, PuhuError> {
+        let data = data.strip_prefix(EXIF_HEADER).unwrap_or(data);
+        Exif::read(Cursor::new(data))
+    }
+
+    /// Parse the IFDs of TIFF-structured data from a reader
+    fn read<R: Read + Seek>(reader: R) -> Result<Self, PuhuError> {
+        let mut reader = TiffReader::new(reader).ok_or_else(|| {
+            PuhuError::InvalidImage("EXIF data does not start with a TIFF header".to_string())
+        })?;
+        let ifd0 = reader.u32(4).unwrap_or(0) as usize;
+
+        let mut exif = Exif {
+            primary: reader.read_ifd(ifd0),
+            ..Exif::default()
+        };
+        let mut take_ifd = |ifd: &mut Ifd, tag: u16| {
+            ifd.remove(&tag)
+                .and_then(|pointer| pointer.as_u32())
+                .map(|offset| reader.read_ifd(offset as usize))
+                .unwrap_or_default()
+        };
+        exif.exif = take_ifd(&mut exif.primary, EXIF_IFD_POINTER);
+        exif.gps = take_ifd(&mut exif.primary, GPS_IFD_POINTER);
+        exif.interop = take_ifd(&mut exif.exif, INTEROP_IFD_POINTER);
+        Ok(exif)
+    }
+
+    /// Parse the first IFD of a TIFF file, seeking to its structures
+    /// rather than reading the whole file
+    pub fn from_tiff_file<R: Read + Seek>(reader: R) -> Result<Self, PuhuError> {
+        Exif::read(reader)
+    }
+
+    /// Drop the tags that describe the pixel data of a TIFF file, leaving
+    /// the EXIF data that applies to any copy of the image
+    pub fn remove_tiff_layout(&mut self) {
+        self.primary
+            .retain(|tag, _| !TIFF_LAYOUT_TAGS.contains(tag));
+    }
+
+    /// Entries of an IFD to copy into a TIFF page. Pointer tags are left to
+    /// the writer, as are the layout tags of the primary IFD, which the
+    /// encoder writes for the pixels of the page.
+    pub fn tiff_entries(&self, kind: IfdKind) -> impl Iterator<Item = (u16, &ExifValue)> {
+        self.ifd(kind)
+            .iter()
+            .map(|(&tag, value)| (tag, value))
+            .filter(move |(tag, _)| kind != IfdKind::Primary || !TIFF_LAYOUT_TAGS.contains(tag))
+    }
+
+    /// Value of a tag in the primary IFD
+    pub fn get(&self, tag: u16) -> Option<&ExifValue> {
+        self.primary.get(&tag)
+    }
+
+    /// Remove a tag from the primary IFD
+    pub fn remove(&mut self, tag: u16) -> Option<ExifValue> {
+        self.primary.remove(&tag)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.primary.is_empty()
+            && self.exif.is_empty()
+            && self.gps.is_empty()
+            && self.interop.is_empty()
+    }
+
+    fn ifd(&self, kind: IfdKind) -> &Ifd {
+        match kind {
+            IfdKind::Primary => &self.primary,
+            IfdKind::Exif => &self.exif,
+            IfdKind::Gps => &self.gps,
+            IfdKind::Interop => &self.interop,
+        }
+    }
+
+    fn ifd_mut(&mut self, kind: IfdKind) -> &mut Ifd {
+        match kind {
+            IfdKind::Primary => &mut self.primary,
+            IfdKind::Exif => &mut self.exif,
+            IfdKind::Gps => &mut self.gps,
+            IfdKind::Interop => &mut self.interop,
+        }
+    }
+
+    /// Whether an IFD has entries, counting the IFDs it points to
+    pub fn has_entries(&self, kind: IfdKind) -> bool {
+        match kind {
+            IfdKind::Exif => !self.exif.is_empty() || !self.interop.is_empty(),
+            _ => !self.ifd(kind).is_empty(),
+        }
+    }
+
+    fn clear(&mut self, kind: IfdKind) {
+        self.ifd_mut(kind).clear();
+        if kind == IfdKind::Exif {
+            self.interop.clear();
+        }
+    }
+
+    /// Tags of an IFD in ascending order, including pointers to non-empty IFDs
+    fn keys(&self, kind: IfdKind) -> Vec<u16> {
+        let mut keys: Vec<u16> = self.ifd(kind).keys().copied().collect();
+        for pointer in [EXIF_IFD_POINTER, GPS_IFD_POINTER, INTEROP_IFD_POINTER] {
+            if kind
+                .child(pointer)
+                .is_some_and(|child| self.has_entries(child))
+            {
+                keys.push(pointer);
+            }
+        }
+        keys.sort_unstable();
+        keys
+    }
+
+    /// The image orientation (1-8) from the primary IFD
+    pub fn orientation(&self) -> Option<u16> {
+        self.primary
+            .get(&ORIENTATION)
+            .and_then(ExifValue::as_u32)
+            .map(|v| v as u16)
+    }
+
+    pub fn remove_orientation(&mut self) {
+        self.primary.remove(&ORIENTATION);
+    }
+
+    /// Serialize as a little-endian TIFF structure, without the `Exif\0\0` prefix
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Pointer entries are inserted with placeholder offsets and patched
+        // once the position of each IFD is known
+        let mut primary = self.primary.clone();
+        let mut exif = self.exif.clone();
+        if !self.interop.is_empty() {
+            exif.insert(INTEROP_IFD_POINTER, ExifValue::Long(vec![0]));
+        }
+        if !exif.is_empty() {
+            primary.insert(EXIF_IFD_POINTER, ExifValue::Long(vec![0]));
+        }
+        if !self.gps.is_empty() {
+            primary.insert(GPS_IFD_POINTER, ExifValue::Long(vec![0]));
+        }
+
+        let exif_offset = 8 + ifd_len(&primary);
+        let gps_offset = exif_offset + if exif.is_empty() { 0 } else { ifd_len(&exif) };
+        let interop_offset = gps_offset
+            + if self.gps.is_empty() {
+                0
+            } else {
+                ifd_len(&self.gps)
+            };
+        let patch = |ifd: &mut Ifd, tag: u16, offset: usize| {
+            if let Some(pointer) = ifd.get_mut(&tag) {
+                *pointer = ExifValue::Long(vec![offset as u32]);
+            }
+        };
+        patch(&mut primary, EXIF_IFD_POINTER, exif_offset);
+        patch(&mut primary, GPS_IFD_POINTER, gps_offset);
+        patch(&mut exif, INTEROP_IFD_POINTER, interop_offset);
+
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+        for ifd in [&primary, &exif, &self.gps, &self.interop] {
+            // The primary IFD is always written so the header offset stays valid
+            if !ifd.is_empty() || out.len() == 8 {
+                write_ifd(&mut out, ifd);
+            }
+        }
+        out
+    }
+}
+
+/// Encoded size of an IFD including its out-of-line values
+fn ifd_len(ifd: &Ifd) -> usize {
+    let values: usize = ifd
+        .values()
+        .map(|value| value.encode().1.len())
+        .filter(|&len| len > 4)
+        .map(|len| len + len % 2)
+        .sum();
+    2 + ifd.len() * 12 + 4 + values
+}
+
+fn write_ifd(out: &mut Vec<u8>, ifd: &Ifd) {
+    let mut data_offset = out.len() + 2 + ifd.len() * 12 + 4;
+    let mut data = Vec::new();
+
+    out.extend((ifd.len() as u16).to_le_bytes());
+    for (tag, value) in ifd {
+        let (count, bytes) = value.encode();
+        out.extend(tag.to_le_bytes());
+        out.extend(value.type_id().to_le_bytes());
+        out.extend(count.to_le_bytes());
+        if bytes.len() <= 4 {
+            let mut inline = [0u8; 4];
+            inline[..bytes.len()].copy_from_slice(&bytes);
+            out.extend(inline);
+        } else {
+            out.extend((data_offset as u32).to_le_bytes());
+            data_offset += bytes.len() + bytes.len() % 2;
+            data.extend(&bytes);
+            // Values start on word boundaries
+            if bytes.len() % 2 == 1 {
+                data.push(0);
+            }
+        }
+    }
+    // No next IFD; the thumbnail IFD is not written
+    out.extend(0u32.to_le_bytes());
+    out.extend(data);
+}
+
+/// Mapping view of one IFD of an EXIF block, returned by `Image.getexif()`.
+///
+/// Views returned by `get_ifd()` share their data with the parent, so
+/// changes to the GPS or Exif IFD are included in `tobytes()`.
+#[pyclass(name = "Exif")]
+pub struct PyExif {
+    data: Arc<Mutex<Exif>>,
+    ifd: IfdKind,
+}
+
+impl PyExif {
+    pub fn from_exif(exif: Exif) -> Self {
+        PyExif {
+            data: Arc::new(Mutex::new(exif)),
+            ifd: IfdKind::Primary,
+        }
+    }
+
+    /// A copy of the complete EXIF data this view belongs to
+    pub fn exif(&self) -> Exif {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Exif> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn view(&self, ifd: IfdKind) -> Self {
+        PyExif {
+            data: Arc::clone(&self.data),
+            ifd,
+        }
+    }
+}
+
+#[pymethods]
+impl PyExif {
+    #[new]
+    #[pyo3(signature = (data=None))]
+    fn __new__(data: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+        let exif = match data {
+            Some(data) => {
+                let bytes = io::buffer_to_vec(data)?.ok_or_else(|| {
+                    PuhuError::InvalidOperation("Expected EXIF data as bytes".to_string())
+                })?;
+                Exif::parse(&bytes)?
+            }
+            None => Exif::default(),
+        };
+        Ok(PyExif::from_exif(exif))
+    }
+
+    fn __len__(&self) -> usize {
+        self.lock().keys(self.ifd).len()
+    }
+
+    fn __contains__(&self, tag: u16) -> bool {
+        self.lock().keys(self.ifd).contains(&tag)
+    }
+
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        Ok(self.keys(py)?.try_iter()?.into_any())
+    }
+
+    fn __getitem__(&self, py: Python<'_>, tag: u16) -> PyResult<PyObject> {
+        if let Some(child) = self.ifd.child(tag) {
+            if self.lock().has_entries(child) {
+                return self.view(child).into_py_any(py);
+            }
+        }
+        match self.lock().ifd(self.ifd).get(&tag) {
+            Some(value) => value.to_object(py),
+            None => Err(PyKeyError::new_err(tag)),
+        }
+    }
+
+    fn __setitem__(&self, tag: u16, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        if self.ifd.child(tag).is_some() {
+            return Err(PuhuError::InvalidOperation(format!(
+                "Tag {:#06x} points to another IFD; modify it through get_ifd()",
+                tag
+            ))
+            .into());
+        }
+        let mut exif = self.lock();
+        let ifd = exif.ifd_mut(self.ifd);
+        let value = ExifValue::from_object(value, ifd.get(&tag))?;
+        ifd.insert(tag, value);
+        Ok(())
+    }
+
+    fn __delitem__(&self, tag: u16) -> PyResult<()> {
+        let mut exif = self.lock();
+        if let Some(child) = self.ifd.child(tag) {
+            if exif.has_entries(child) {
+                exif.clear(child);
+                return Ok(());
+            }
+        }
+        match exif.ifd_mut(self.ifd).remove(&tag) {
+            Some(_) => Ok(()),
+            None => Err(PyKeyError::new_err(tag)),
+        }
+    }
+
+    fn keys<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        PyList::new(py, self.lock().keys(self.ifd))
+    }
+
+    fn values<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        // The lock must be released before __getitem__ takes it again
+        let keys = self.lock().keys(self.ifd);
+        let values = keys
+            .into_iter()
+            .map(|tag| self.__getitem__(py, tag))
+            .collect::<PyResult<Vec<_>>>()?;
+        PyList::new(py, values)
+    }
+
+    fn items<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let keys = self.lock().keys(self.ifd);
+        let items = keys
+            .into_iter()
+            .map(|tag| Ok((tag, self.__getitem__(py, tag)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        PyList::new(py, items)
+    }
+
+    #[pyo3(signature = (tag, default=None))]
+    fn get(&self, py: Python<'_>, tag: u16, default: Option<PyObject>) -> PyResult<PyObject> {
+        if self.__contains__(tag) {
+            self.__getitem__(py, tag)
+        } else {
+            Ok(default.unwrap_or_else(|| py.None()))
+        }
+    }
+
+    /// Get the Exif (0x8769), GPS (0x8825) or Interop (0xA005) IFD, which
+    /// is empty if the image has none
+    fn get_ifd(&self, tag: u16) -> PyResult<Self> {
+        let child = IfdKind::Primary
+            .child(tag)
+            .or_else(|| IfdKind::Exif.child(tag))
+            .ok_or_else(|| PyKeyError::new_err(tag))?;
+        Ok(self.view(child))
+    }
+
+    /// Serialize the complete EXIF data, prefixed with `Exif\0\0`
+    fn tobytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let mut data = EXIF_HEADER.to_vec();
+        data.extend(self.lock().to_bytes());
+        PyBytes::new(py, &data)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<Exif with {} tags>", self.__len__())
+    }
+}
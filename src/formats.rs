@@ -22,3 +22,8 @@ pub fn parse_format(format_str: &str) -> Result<ImageFormat, PuhuError> {
         ))),
     }
 }
+
+/// Guess the image format from the leading bytes of the data
+pub fn guess_format(data: &[u8]) -> Option<ImageFormat> {
+    image::guess_format(data).ok()
+}
@@ -0,0 +1,575 @@
+use crate::errors::PuhuError;
+use crate::limits::DecodeLimits;
+use crate::metadata::FrameInfo;
+use crate::modes::ExtendedMode;
+use image::{DynamicImage, ImageBuffer, ImageFormat, Rgba, RgbaImage};
+use std::fmt::Display;
+use std::io::{Cursor, Read, Seek};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use tiff::decoder::{Decoder as TiffDecoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::ColorType;
+
+fn decode_error(err: impl Display) -> PuhuError {
+    PuhuError::InvalidImage(err.to_string())
+}
+
+/// Encoded data of a multi-frame image
+#[derive(Clone, Debug)]
+pub enum SourceData {
+    Path(PathBuf),
+    Bytes(Arc<[u8]>),
+}
+
+/// Where the frames of an opened image are decoded from
+#[derive(Clone, Debug)]
+pub struct FrameSource {
+    pub data: SourceData,
+    pub format: ImageFormat,
+    pub limits: DecodeLimits,
+}
+
+/// Area of the canvas covered by a frame
+#[derive(Clone, Copy, Debug)]
+struct Region {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Region {
+    /// Canvas coordinates of the region's pixels, row by row
+    fn pixels(self) -> impl Iterator<Item = (u32, u32)> {
+        let right = self.x.saturating_add(self.width);
+        let bottom = self.y.saturating_add(self.height);
+        (self.y..bottom).flat_map(move |y| (self.x..right).map(move |x| (x, y)))
+    }
+
+    fn clear(self, canvas: &mut RgbaImage) {
+        for (x, y) in self.pixels() {
+            if x < canvas.width() && y < canvas.height() {
+                canvas.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+            }
+        }
+    }
+}
+
+/// Decodes the frames of an image one after another, each composited onto
+/// the frames before it
+trait FrameReader: Send {
+    fn next_frame(&mut self) -> Result<DynamicImage, PuhuError>;
+
+    /// Advance past the next frame without returning it
+    fn skip_frame(&mut self) -> Result<(), PuhuError> {
+        self.next_frame().map(|_| ())
+    }
+
+    /// Extended mode of the frame returned last
+    fn extended(&self) -> Option<ExtendedMode> {
+        None
+    }
+}
+
+struct GifFrames {
+    decoder: gif::Decoder<Cursor<Arc<[u8]>>>,
+    canvas: RgbaImage,
+    /// Canvas to restore when the last frame is disposed to the previous state
+    saved: Option<RgbaImage>,
+    dispose: Option<(gif::DisposalMethod, Region)>,
+}
+
+impl GifFrames {
+    fn new(data: Arc<[u8]>, limits: DecodeLimits) -> Result<Self, PuhuError> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let decoder = options.read_info(Cursor::new(data)).map_err(decode_error)?;
+        let (width, height) = (decoder.width() as u32, decoder.height() as u32);
+        limits.check_pixels(width, height)?;
+        Ok(GifFrames {
+            decoder,
+            canvas: RgbaImage::new(width, height),
+            saved: None,
+            dispose: None,
+        })
+    }
+}
+
+impl FrameReader for GifFrames {
+    fn next_frame(&mut self) -> Result<DynamicImage, PuhuError> {
+        match self.dispose.take() {
+            Some((gif::DisposalMethod::Background, region)) => region.clear(&mut self.canvas),
+            Some((gif::DisposalMethod::Previous, _)) => {
+                if let Some(saved) = self.saved.take() {
+                    self.canvas = saved;
+                }
+            }
+            _ => {}
+        }
+
+        let frame = self
+            .decoder
+            .read_next_frame()
+            .map_err(decode_error)?
+            .ok_or_else(|| PuhuError::InvalidImage("GIF has fewer frames".to_string()))?;
+        if frame.dispose == gif::DisposalMethod::Previous {
+            self.saved = Some(self.canvas.clone());
+        }
+
+        let region = Region {
+            x: frame.left.into(),
+            y: frame.top.into(),
+            width: frame.width.into(),
+            height: frame.height.into(),
+        };
+        let (width, height) = self.canvas.dimensions();
+        for ((x, y), pixel) in region.pixels().zip(frame.buffer.chunks_exact(4)) {
+            // Transparent pixels leave the canvas visible
+            if x < width && y < height && pixel[3] != 0 {
+                self.canvas
+                    .put_pixel(x, y, Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]));
+            }
+        }
+        self.dispose = Some((frame.dispose, region));
+        Ok(DynamicImage::ImageRgba8(self.canvas.clone()))
+    }
+}
+
+struct ApngFrames {
+    reader: png::Reader<Cursor<Arc<[u8]>>>,
+    canvas: RgbaImage,
+    /// Canvas to restore when the last frame is disposed to the previous state
+    saved: Option<RgbaImage>,
+    dispose: Option<(png::DisposeOp, Region)>,
+    /// The default image is not part of the animation and must be skipped
+    skip_default: bool,
+    /// Frames are returned in the PNG's own color type
+    color_type: png::ColorType,
+}
+
+impl ApngFrames {
+    fn new(data: Arc<[u8]>, limits: DecodeLimits) -> Result<Self, PuhuError> {
+        let mut decoder = png::Decoder::new(Cursor::new(data));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let reader = decoder.read_info().map_err(decode_error)?;
+        let info = reader.info();
+        limits.check_pixels(info.width, info.height)?;
+        Ok(ApngFrames {
+            canvas: RgbaImage::new(info.width, info.height),
+            skip_default: info.frame_control.is_none(),
+            color_type: reader.output_color_type().0,
+            saved: None,
+            dispose: None,
+            reader,
+        })
+    }
+
+    /// Decode the next frame's pixels as RGBA
+    fn read_frame(&mut self) -> Result<RgbaImage, PuhuError> {
+        let size = self
+            .reader
+            .output_buffer_size()
+            .ok_or_else(|| PuhuError::InvalidImage("APNG frame is too large".to_string()))?;
+        let mut buffer = vec![0; size];
+        let output = self.reader.next_frame(&mut buffer).map_err(decode_error)?;
+        buffer.truncate(output.buffer_size());
+
+        let pixels: Vec<u8> = match output.color_type {
+            png::ColorType::Grayscale => buffer.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            _ => buffer,
+        };
+        RgbaImage::from_raw(output.width, output.height, pixels)
+            .ok_or_else(|| PuhuError::InvalidImage("Truncated APNG frame".to_string()))
+    }
+}
+
+impl FrameReader for ApngFrames {
+    fn next_frame(&mut self) -> Result<DynamicImage, PuhuError> {
+        if self.skip_default {
+            self.read_frame()?;
+            self.skip_default = false;
+        }
+        match self.dispose.take() {
+            Some((png::DisposeOp::Background, region)) => region.clear(&mut self.canvas),
+            Some((png::DisposeOp::Previous, _)) => {
+                if let Some(saved) = self.saved.take() {
+                    self.canvas = saved;
+                }
+            }
+            _ => {}
+        }
+
+        let frame = self.read_frame()?;
+        let control = self
+            .reader
+            .info()
+            .frame_control
+            .ok_or_else(|| PuhuError::InvalidImage("APNG frame has no fcTL".to_string()))?;
+        if control.dispose_op == png::DisposeOp::Previous {
+            self.saved = Some(self.canvas.clone());
+        }
+
+        let region = Region {
+            x: control.x_offset,
+            y: control.y_offset,
+            width: control.width,
+            height: control.height,
+        };
+        let (width, height) = self.canvas.dimensions();
+        for ((x, y), source) in region.pixels().zip(frame.pixels()) {
+            if x >= width || y >= height {
+                continue;
+            }
+            let pixel = match control.blend_op {
+                png::BlendOp::Source => *source,
+                png::BlendOp::Over => blend_over(*self.canvas.get_pixel(x, y), *source),
+            };
+            self.canvas.put_pixel(x, y, pixel);
+        }
+        self.dispose = Some((control.dispose_op, region));
+
+        let canvas = DynamicImage::ImageRgba8(self.canvas.clone());
+        Ok(match self.color_type {
+            png::ColorType::Grayscale => DynamicImage::ImageLuma8(canvas.to_luma8()),
+            png::ColorType::GrayscaleAlpha => DynamicImage::ImageLumaA8(canvas.to_luma_alpha8()),
+            png::ColorType::Rgb => DynamicImage::ImageRgb8(canvas.to_rgb8()),
+            _ => canvas,
+        })
+    }
+}
+
+/// Composite `source` over `dest` as specified for APNG `APNG_BLEND_OP_OVER`
+fn blend_over(dest: Rgba<u8>, source: Rgba<u8>) -> Rgba<u8> {
+    let source_alpha = source[3] as u32;
+    match source_alpha {
+        255 => return source,
+        0 => return dest,
+        _ => {}
+    }
+    let dest_alpha = dest[3] as u32 * (255 - source_alpha) / 255;
+    let alpha = source_alpha + dest_alpha;
+    let channel =
+        |i: usize| ((source[i] as u32 * source_alpha + dest[i] as u32 * dest_alpha) / alpha) as u8;
+    Rgba([channel(0), channel(1), channel(2), alpha as u8])
+}
+
+struct WebPFrames {
+    decoder: image_webp::WebPDecoder<Cursor<Arc<[u8]>>>,
+}
+
+impl WebPFrames {
+    fn new(data: Arc<[u8]>, limits: DecodeLimits) -> Result<Self, PuhuError> {
+        let decoder = image_webp::WebPDecoder::new(Cursor::new(data)).map_err(decode_error)?;
+        if !decoder.is_animated() {
+            return Err(PuhuError::InvalidImage(
+                "WebP has frames but no animation header".to_string(),
+            ));
+        }
+        let (width, height) = decoder.dimensions();
+        limits.check_pixels(width, height)?;
+        Ok(WebPFrames { decoder })
+    }
+}
+
+impl FrameReader for WebPFrames {
+    fn next_frame(&mut self) -> Result<DynamicImage, PuhuError> {
+        let (width, height) = self.decoder.dimensions();
+        let size = self
+            .decoder
+            .output_buffer_size()
+            .ok_or_else(|| PuhuError::InvalidImage("WebP frame is too large".to_string()))?;
+        let mut buffer = vec![0; size];
+        self.decoder.read_frame(&mut buffer).map_err(decode_error)?;
+
+        let image = if self.decoder.has_alpha() {
+            RgbaImage::from_raw(width, height, buffer).map(DynamicImage::ImageRgba8)
+        } else {
+            image::RgbImage::from_raw(width, height, buffer).map(DynamicImage::ImageRgb8)
+        };
+        image.ok_or_else(|| PuhuError::InvalidImage("Truncated WebP frame".to_string()))
+    }
+}
+
+/// TIFF pages are independent images, decoded in turn by one decoder that
+/// seeks to each page's image file directory
+struct TiffPages {
+    decoder: TiffDecoder<Cursor<Arc<[u8]>>>,
+    /// Index of the page the decoder is positioned at
+    current: usize,
+    next: usize,
+    limits: DecodeLimits,
+    extended: Option<ExtendedMode>,
+}
+
+impl TiffPages {
+    fn new(data: Arc<[u8]>, limits: DecodeLimits) -> Result<Self, PuhuError> {
+        let decoder = TiffDecoder::new(Cursor::new(data))
+            .map_err(decode_error)?
+            .with_limits(limits.to_tiff_limits());
+        Ok(TiffPages {
+            decoder,
+            current: 0,
+            next: 0,
+            limits,
+            extended: None,
+        })
+    }
+}
+
+impl FrameReader for TiffPages {
+    fn next_frame(&mut self) -> Result<DynamicImage, PuhuError> {
+        if self.next == self.current + 1 && self.decoder.more_images() {
+            self.decoder.next_image().map_err(decode_error)?;
+        } else if self.next != self.current {
+            self.decoder
+                .seek_to_image(self.next)
+                .map_err(|_| PuhuError::InvalidImage("TIFF has fewer pages".to_string()))?;
+        }
+        self.current = self.next;
+        self.next += 1;
+
+        let (image, extended) = decode_tiff_page(&mut self.decoder, self.limits)?;
+        self.extended = extended;
+        Ok(image)
+    }
+
+    fn skip_frame(&mut self) -> Result<(), PuhuError> {
+        self.next += 1;
+        Ok(())
+    }
+
+    fn extended(&self) -> Option<ExtendedMode> {
+        self.extended
+    }
+}
+
+/// Decode the first page of a TIFF file, for files the image crate cannot
+/// decode
+pub fn decode_tiff(
+    data: &[u8],
+    limits: DecodeLimits,
+) -> Result<(DynamicImage, Option<ExtendedMode>), PuhuError> {
+    let mut decoder = TiffDecoder::new(Cursor::new(data))
+        .map_err(decode_error)?
+        .with_limits(limits.to_tiff_limits());
+    decode_tiff_page(&mut decoder, limits)
+}
+
+/// Size of the first page of a TIFF file
+pub fn tiff_dimensions<R: Read + Seek>(reader: R) -> Result<(u32, u32), PuhuError> {
+    TiffDecoder::new(reader)
+        .and_then(|mut decoder| decoder.dimensions())
+        .map_err(decode_error)
+}
+
+/// Decode the current page of a TIFF decoder in the color types the image
+/// crate decodes TIFF files to. Pages of 32-bit integer or float gray
+/// values and CMYK pages, which the image crate cannot decode, are decoded
+/// as "I", "F" and "CMYK" images.
+fn decode_tiff_page<R: Read + Seek>(
+    decoder: &mut TiffDecoder<R>,
+    limits: DecodeLimits,
+) -> Result<(DynamicImage, Option<ExtendedMode>), PuhuError> {
+    let (width, height) = decoder.dimensions().map_err(decode_error)?;
+    limits
+        .to_image_limits()
+        .check_dimensions(width, height)
+        .map_err(PuhuError::ImageError)?;
+    limits.check_pixels(width, height)?;
+
+    let color_type = decoder.colortype().map_err(decode_error)?;
+    let planar = decoder
+        .find_tag_unsigned::<u16>(Tag::PlanarConfiguration)
+        .map_err(decode_error)?;
+    if planar.is_some_and(|planar| planar > 1) {
+        return Err(PuhuError::UnsupportedFormat(
+            "TIFF pages with separate sample planes are not supported".to_string(),
+        ));
+    }
+
+    let truncated = || PuhuError::InvalidImage("Truncated TIFF page".to_string());
+    let samples = decoder.read_image().map_err(decode_error)?;
+    let extended = match (color_type, &samples) {
+        (ColorType::Gray(32), DecodingResult::I32(_)) => Some(ExtendedMode::Int32),
+        (ColorType::Gray(32), DecodingResult::F32(_)) => Some(ExtendedMode::Float32),
+        (ColorType::CMYK(8), DecodingResult::U8(_)) => Some(ExtendedMode::Cmyk),
+        _ => None,
+    };
+    let image = match (color_type, samples) {
+        // "I" and "F" images store the bytes of their values
+        (ColorType::Gray(32), DecodingResult::I32(values)) => {
+            let bytes = values.iter().flat_map(|v| v.to_ne_bytes()).collect();
+            ImageBuffer::from_raw(width, height, bytes).map(DynamicImage::ImageRgba8)
+        }
+        (ColorType::Gray(32), DecodingResult::F32(values)) => {
+            let bytes = values.iter().flat_map(|v| v.to_ne_bytes()).collect();
+            ImageBuffer::from_raw(width, height, bytes).map(DynamicImage::ImageRgba8)
+        }
+        (ColorType::Gray(1), DecodingResult::U8(packed)) => {
+            // Rows start on byte boundaries; set bits are white
+            let row_len = width.div_ceil(8) as usize;
+            let samples = packed
+                .chunks_exact(row_len)
+                .flat_map(|row| (0..width as usize).map(move |x| (row[x / 8] >> (7 - x % 8)) & 1))
+                .map(|bit| bit * 255)
+                .collect();
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLuma8)
+        }
+        (ColorType::Gray(8), DecodingResult::U8(samples)) => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLuma8)
+        }
+        (ColorType::Gray(16), DecodingResult::U16(samples)) => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLuma16)
+        }
+        (ColorType::GrayA(8), DecodingResult::U8(samples)) => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLumaA8)
+        }
+        (ColorType::GrayA(16), DecodingResult::U16(samples)) => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLumaA16)
+        }
+        (ColorType::RGB(8), DecodingResult::U8(samples)) => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb8)
+        }
+        (ColorType::RGB(16), DecodingResult::U16(samples)) => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb16)
+        }
+        (ColorType::RGB(32), DecodingResult::F32(samples)) => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb32F)
+        }
+        (ColorType::RGBA(8), DecodingResult::U8(samples)) => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba8)
+        }
+        (ColorType::RGBA(16), DecodingResult::U16(samples)) => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba16)
+        }
+        (ColorType::RGBA(32), DecodingResult::F32(samples)) => {
+            ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba32F)
+        }
+        (ColorType::CMYK(8), DecodingResult::U8(inks)) => {
+            ImageBuffer::from_raw(width, height, inks).map(DynamicImage::ImageRgba8)
+        }
+        (color_type, _) => {
+            return Err(PuhuError::UnsupportedFormat(format!(
+                "Unsupported TIFF color type {:?}",
+                color_type
+            )))
+        }
+    };
+    image.ok_or_else(truncated).map(|image| (image, extended))
+}
+
+/// Frame position of an opened multi-frame image, with a reader kept open
+/// so stepping forward decodes only the frames in between
+pub struct FrameState {
+    source: FrameSource,
+    frames: Vec<FrameInfo>,
+    current: usize,
+    /// Reader and the index of the frame it returns next. Only used through
+    /// `&mut self`; the mutex makes the state shareable between threads.
+    reader: Mutex<Option<(Box<dyn FrameReader>, usize)>>,
+}
+
+impl Clone for FrameState {
+    fn clone(&self) -> Self {
+        // Readers cannot be shared; the copy reopens the source when it seeks
+        FrameState {
+            source: self.source.clone(),
+            frames: self.frames.clone(),
+            current: self.current,
+            reader: Mutex::new(None),
+        }
+    }
+}
+
+impl FrameState {
+    pub fn new(source: FrameSource, frames: Vec<FrameInfo>) -> Self {
+        FrameState {
+            source,
+            frames,
+            current: 0,
+            reader: Mutex::new(None),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Timing of the current frame
+    pub fn frame_info(&self) -> FrameInfo {
+        self.frames[self.current]
+    }
+
+    fn open_reader(&self) -> Result<Box<dyn FrameReader>, PuhuError> {
+        let data: Arc<[u8]> = match &self.source.data {
+            SourceData::Path(path) => std::fs::read(path)?.into(),
+            SourceData::Bytes(data) => Arc::clone(data),
+        };
+        let limits = self.source.limits;
+        Ok(match self.source.format {
+            ImageFormat::Gif => Box::new(GifFrames::new(data, limits)?),
+            ImageFormat::Png => Box::new(ApngFrames::new(data, limits)?),
+            ImageFormat::WebP => Box::new(WebPFrames::new(data, limits)?),
+            ImageFormat::Tiff => Box::new(TiffPages::new(data, limits)?),
+            format => {
+                return Err(PuhuError::UnsupportedFormat(format!(
+                    "{:?} images have a single frame",
+                    format
+                )))
+            }
+        })
+    }
+
+    /// Decode every frame with its extended mode, using a separate reader
+    /// so the current position is unchanged
+    pub fn decode_all(&self) -> Result<Vec<(DynamicImage, Option<ExtendedMode>)>, PuhuError> {
+        let mut reader = self.open_reader()?;
+        (0..self.len())
+            .map(|_| Ok((reader.next_frame()?, reader.extended())))
+            .collect()
+    }
+
+    /// Decode frame `index`, which must be less than `len()`, with its
+    /// extended mode.
+    ///
+    /// Seeking forward continues from the current position; seeking
+    /// backward decodes again from the first frame.
+    pub fn seek(
+        &mut self,
+        index: usize,
+    ) -> Result<(DynamicImage, Option<ExtendedMode>), PuhuError> {
+        let open = self
+            .reader
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (mut reader, mut next) = match open.take() {
+            Some((reader, next)) if next <= index => (reader, next),
+            _ => (self.open_reader()?, 0),
+        };
+        while next < index {
+            reader.skip_frame()?;
+            next += 1;
+        }
+        let frame = reader.next_frame()?;
+        let extended = reader.extended();
+        *self
+            .reader
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner) = Some((reader, index + 1));
+        self.current = index;
+        Ok((frame, extended))
+    }
+}
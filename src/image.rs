@@ -1,16 +1,121 @@
+use crate::cms::{self, PyCmsTransform};
+use crate::cmyk;
 use crate::conversions;
+use crate::dither::Dither;
+use crate::encoders::{self, SaveOptions};
 use crate::errors::PuhuError;
+use crate::exif::{Exif, PyExif};
 use crate::formats;
-use crate::operations;
-use crate::palette;
+use crate::frames::{self, FrameSource, FrameState, SourceData};
+use crate::io;
+use crate::jpeg;
+use crate::limits::{self, DecodeLimits};
+use crate::metadata::{CmykStorage, ImageInfo};
+use crate::modes::{self, ExtendedMode, Mode};
+use crate::nearest::Metric;
+use crate::operations::{self, Symmetry};
+use crate::palette::{self, Palette};
+use crate::quantize;
+use crate::transform::{self, Interpolation, Quad, Transform};
 use crate::utils::{
-    color_type_to_mode_string, convert_mode, fill_region, parse_color, paste_with_mask,
+    apply_color_transform, color_type_to_mode_string, fill_region, overlay, parse_color,
+    paste_with_mask,
 };
-use image::{DynamicImage, ImageFormat};
+use image::imageops::{self, FilterType};
+use image::{ColorType, DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use pyo3::exceptions::PyEOFError;
 use pyo3::prelude::*;
-use pyo3::types::{PyBytes, PyType};
-use std::io::Cursor;
-use std::path::PathBuf;
+use pyo3::types::{PyBytes, PyDict, PyList, PyType};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn invalid_rawmode(rawmode: &str) -> PuhuError {
+    PuhuError::InvalidOperation(format!(
+        "Unsupported palette rawmode: '{}'. Use 'RGB' or 'RGBA'",
+        rawmode
+    ))
+}
+
+/// Number of leading bytes read from a stream to identify its format
+const SNIFF_LEN: usize = 16;
+
+/// Image properties read from the decoder header without decoding pixels
+#[derive(Clone, Copy, Debug)]
+struct ImageHeader {
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    format: Option<ImageFormat>,
+}
+
+impl ImageHeader {
+    fn from_image(image: &DynamicImage) -> Self {
+        ImageHeader {
+            width: image.width(),
+            height: image.height(),
+            color_type: image.color(),
+            format: None,
+        }
+    }
+
+    /// Read the header through the decoder for the reader's format
+    fn probe<R: BufRead + Seek>(reader: ImageReader<R>) -> Result<Self, PuhuError> {
+        let format = reader.format();
+        let decoder = reader.into_decoder().map_err(PuhuError::ImageError)?;
+        let (width, height) = decoder.dimensions();
+        Ok(ImageHeader {
+            width,
+            height,
+            color_type: decoder.color_type(),
+            format,
+        })
+    }
+}
+
+/// Header and metadata of an opened source, read together on first access
+#[derive(Clone, Debug)]
+struct Probe {
+    header: ImageHeader,
+    info: ImageInfo,
+}
+
+impl Probe {
+    /// Read the metadata, then the header through the decoder, from one
+    /// reader, checking the size against the limits
+    fn read<R: BufRead + Seek>(
+        reader: ImageReader<R>,
+        options: &DecodeOptions,
+    ) -> Result<Self, PuhuError> {
+        let format = reader.format();
+        let mut inner = reader.into_inner();
+        let info = ImageInfo::read(&mut inner, format);
+        inner.seek(SeekFrom::Start(0)).map_err(PuhuError::Io)?;
+        let mut reader = ImageReader::new(inner);
+        if let Some(format) = format {
+            reader.set_format(format);
+        }
+        let header = match info.tiff_values {
+            // The image crate cannot read pages of integer or float values;
+            // they are stored as RGBA8
+            Some(_) => {
+                options.check_format(format)?;
+                let (width, height) = frames::tiff_dimensions(reader.into_inner())?;
+                ImageHeader {
+                    width,
+                    height,
+                    color_type: ColorType::Rgba8,
+                    format,
+                }
+            }
+            None => ImageHeader::probe(options.configure(reader)?)?,
+        };
+        options.limits.check_pixels(header.width, header.height)?;
+        Ok(Probe { header, info })
+    }
+}
 
 #[derive(Clone)]
 enum LazyImage {
@@ -18,39 +123,352 @@ enum LazyImage {
     /// Image data stored as file path
     Path {
         path: PathBuf,
+        probe: Option<Probe>,
+        options: DecodeOptions,
     },
     /// Image data stored as bytes
     Bytes {
-        data: Vec<u8>,
+        data: Arc<[u8]>,
+        probe: Option<Probe>,
+        options: DecodeOptions,
+    },
+    /// Image data read on demand from a Python file-like object
+    Reader {
+        reader: Arc<Py<PyAny>>,
+        /// Leading bytes already consumed from the reader for format sniffing
+        prefix: Vec<u8>,
+        options: DecodeOptions,
     },
 }
 
+/// Settings applied when decoding an opened image
+#[derive(Clone, Debug)]
+struct DecodeOptions {
+    limits: DecodeLimits,
+    /// Formats the image may be decoded as; `None` allows every decoder
+    formats: Option<Vec<ImageFormat>>,
+    /// Reduction of JPEG sources at decode time (1, 2, 4 or 8), as the
+    /// DCT scaling of Pillow's draft mode
+    scale: u32,
+    /// Convert color JPEG sources to "L" once decoded, as the grayscale
+    /// draft mode
+    luma: bool,
+}
+
+impl DecodeOptions {
+    /// Fail unless the detected format is in the allow-list
+    fn check_format(&self, format: Option<ImageFormat>) -> Result<(), PuhuError> {
+        let Some(allowed) = &self.formats else {
+            return Ok(());
+        };
+        match format {
+            Some(format) if allowed.contains(&format) => Ok(()),
+            Some(format) => Err(PuhuError::UnsupportedFormat(format!(
+                "{} images are not in the allowed formats",
+                format!("{:?}", format).to_uppercase()
+            ))),
+            None => Err(PuhuError::UnsupportedFormat(
+                "Cannot identify image format".to_string(),
+            )),
+        }
+    }
+
+    /// Restrict a reader to the allowed formats and apply the decode limits
+    fn configure<R: BufRead + Seek>(
+        &self,
+        mut reader: ImageReader<R>,
+    ) -> Result<ImageReader<R>, PuhuError> {
+        self.check_format(reader.format())?;
+        reader.limits(self.limits.to_image_limits());
+        Ok(reader)
+    }
+
+    /// Header of the decoded image, which differs from the file's in JPEG
+    /// draft mode
+    fn drafted(&self, header: ImageHeader) -> ImageHeader {
+        if header.format != Some(ImageFormat::Jpeg) {
+            return header;
+        }
+        ImageHeader {
+            width: header.width.div_ceil(self.scale),
+            height: header.height.div_ceil(self.scale),
+            color_type: match header.color_type {
+                ColorType::Rgb8 if self.luma => ColorType::L8,
+                other => other,
+            },
+            ..header
+        }
+    }
+}
+
+/// A frame to save, with its timing and how its pixels are stored
+struct SavedFrame {
+    image: DynamicImage,
+    duration: Option<u32>,
+    /// Palette of the indices of a "P" image
+    palette: Option<Palette>,
+    extended: Option<ExtendedMode>,
+}
+
+/// Reader for a file, with the format guessed from its content and falling
+/// back to the extension
+fn path_reader(
+    path: &Path,
+    options: &DecodeOptions,
+) -> Result<ImageReader<BufReader<File>>, PuhuError> {
+    let reader = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(PuhuError::Io)?;
+    options.configure(reader)
+}
+
+/// Reader for in-memory data, with the format guessed from its content
+fn bytes_reader<'a>(
+    data: &'a [u8],
+    options: &DecodeOptions,
+) -> Result<ImageReader<Cursor<&'a [u8]>>, PuhuError> {
+    let reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(PuhuError::Io)?;
+    options.configure(reader)
+}
+
+/// Decode the image of a reader, keeping the inks of CMYK files and the
+/// values of TIFF files of integer or float values
+fn decode<R: BufRead + Seek>(
+    reader: ImageReader<R>,
+    cmyk: Option<CmykStorage>,
+    tiff_values: Option<ExtendedMode>,
+    options: &DecodeOptions,
+) -> Result<DynamicImage, PuhuError> {
+    let format = reader.format();
+    let read_data = |reader: ImageReader<R>| {
+        let mut data = Vec::new();
+        reader
+            .into_inner()
+            .read_to_end(&mut data)
+            .map_err(PuhuError::Io)?;
+        Ok::<_, PuhuError>(data)
+    };
+    let image = match (cmyk, tiff_values, format) {
+        (Some(storage), _, Some(format)) => {
+            let image = cmyk::decode(&read_data(reader)?, format, storage, options.limits)?;
+            // The scaled decoder takes no four-component files, so CMYK
+            // drafts are box-reduced after a full-size decode
+            match options.scale {
+                scale if format == ImageFormat::Jpeg && scale > 1 => {
+                    operations::reduce(&image, (scale, scale), false)
+                }
+                _ => image,
+            }
+        }
+        (None, Some(_), Some(ImageFormat::Tiff)) => {
+            frames::decode_tiff(&read_data(reader)?, options.limits)?.0
+        }
+        (None, _, Some(ImageFormat::Jpeg)) if options.scale > 1 => {
+            let data = read_data(reader)?;
+            match jpeg::decode_scaled(&data, options.scale, options.limits)? {
+                Some(image) => image,
+                // Progressive and other files the scaled decoder leaves out
+                // are decoded at full size and box-reduced
+                None => {
+                    let image = bytes_reader(&data, options)?
+                        .decode()
+                        .map_err(PuhuError::ImageError)?;
+                    operations::reduce(&image, (options.scale, options.scale), false)
+                }
+            }
+        }
+        _ => reader.decode().map_err(PuhuError::ImageError)?,
+    };
+    if format != Some(ImageFormat::Jpeg) {
+        return Ok(image);
+    }
+    Ok(match image {
+        DynamicImage::ImageRgb8(_) if options.luma => DynamicImage::ImageLuma8(image.to_luma8()),
+        image => image,
+    })
+}
+
 impl LazyImage {
-    /// Ensure the image is loaded
-    fn ensure_loaded(&mut self) -> Result<&DynamicImage, PuhuError> {
+    /// Ensure the image is loaded, given how the samples of a CMYK source
+    /// are stored and the mode of a TIFF source of integer or float values
+    fn ensure_loaded(
+        &mut self,
+        cmyk: Option<CmykStorage>,
+        tiff_values: Option<ExtendedMode>,
+    ) -> Result<&DynamicImage, PuhuError> {
+        // Check the pixel count against the limits before allocating anything
+        self.header()?;
         match self {
             LazyImage::Loaded(img) => Ok(img),
-            LazyImage::Path { path } => {
-                let img = image::open(path).map_err(|e| PuhuError::ImageError(e))?;
+            LazyImage::Path { path, options, .. } => {
+                let img = decode(path_reader(path, options)?, cmyk, tiff_values, options)?;
                 *self = LazyImage::Loaded(img);
                 match self {
                     LazyImage::Loaded(img) => Ok(img),
                     _ => unreachable!("Just set to Loaded variant"),
                 }
             }
-            LazyImage::Bytes { data } => {
-                let cursor = Cursor::new(data);
-                let reader = image::io::Reader::new(cursor)
-                    .with_guessed_format()
-                    .map_err(|e| PuhuError::Io(e))?;
-                let img = reader.decode().map_err(|e| PuhuError::ImageError(e))?;
+            LazyImage::Bytes { data, options, .. } => {
+                let img = decode(bytes_reader(data, options)?, cmyk, tiff_values, options)?;
                 *self = LazyImage::Loaded(img);
                 match self {
                     LazyImage::Loaded(img) => Ok(img),
                     _ => unreachable!("Just set to Loaded variant"),
                 }
             }
+            LazyImage::Reader { .. } => {
+                self.read_stream()?;
+                self.ensure_loaded(cmyk, tiff_values)
+            }
+        }
+    }
+
+    /// Read the header and metadata of a file or bytes source, caching
+    /// them; None for decoded images
+    fn probe(&mut self) -> Result<Option<&Probe>, PuhuError> {
+        self.read_stream()?;
+        match self {
+            LazyImage::Path {
+                path,
+                probe,
+                options,
+            } => {
+                if probe.is_none() {
+                    *probe = Some(Probe::read(path_reader(path, options)?, options)?);
+                }
+                Ok(probe.as_ref())
+            }
+            LazyImage::Bytes {
+                data,
+                probe,
+                options,
+            } => {
+                if probe.is_none() {
+                    *probe = Some(Probe::read(bytes_reader(data, options)?, options)?);
+                }
+                Ok(probe.as_ref())
+            }
+            LazyImage::Loaded(_) | LazyImage::Reader { .. } => Ok(None),
+        }
+    }
+
+    /// Get the image header, probing and caching it without decoding pixels
+    fn header(&mut self) -> Result<ImageHeader, PuhuError> {
+        self.probe()?;
+        match self {
+            LazyImage::Loaded(img) => Ok(ImageHeader::from_image(img)),
+            LazyImage::Path {
+                probe: Some(probe),
+                options,
+                ..
+            }
+            | LazyImage::Bytes {
+                probe: Some(probe),
+                options,
+                ..
+            } => Ok(options.drafted(probe.header)),
+            _ => unreachable!("Sources are probed and streams read by probe()"),
+        }
+    }
+
+    /// Have a JPEG source decoded at the largest DCT scale (1/2, 1/4 or 1/8)
+    /// that keeps it at least `requested`, as Pillow's draft mode
+    ///
+    /// Returns the scale and the full-size header, or None for decoded
+    /// images and other formats. Without `requested` the source is decoded
+    /// at full size.
+    fn draft(
+        &mut self,
+        requested: Option<(u32, u32)>,
+    ) -> Result<Option<(u32, ImageHeader)>, PuhuError> {
+        self.read_stream()?;
+        let (LazyImage::Path { options, .. } | LazyImage::Bytes { options, .. }) = self else {
+            return Ok(None);
+        };
+        // Scales are chosen from the full size of the file
+        options.scale = 1;
+        let header = self.header()?;
+        if header.format != Some(ImageFormat::Jpeg) {
+            return Ok(None);
+        }
+        let fits = requested.map_or(1, |(width, height)| {
+            (header.width / width.max(1)).min(header.height / height.max(1))
+        });
+        let scale = [8, 4, 2].into_iter().find(|&s| fits >= s).unwrap_or(1);
+        if let LazyImage::Path { options, .. } | LazyImage::Bytes { options, .. } = self {
+            options.scale = scale;
+        }
+        Ok(Some((scale, header)))
+    }
+
+    /// Have a color JPEG source converted to "L" as it is decoded, as
+    /// Pillow's draft mode. Returns whether the source is converted.
+    fn draft_luma(&mut self) -> Result<bool, PuhuError> {
+        self.read_stream()?;
+        let (LazyImage::Path { options, .. } | LazyImage::Bytes { options, .. }) = self else {
+            return Ok(false);
+        };
+        options.luma = false;
+        let header = self.header()?;
+        let luma = header.format == Some(ImageFormat::Jpeg) && header.color_type == ColorType::Rgb8;
+        if let LazyImage::Path { options, .. } | LazyImage::Bytes { options, .. } = self {
+            options.luma = luma;
+        }
+        Ok(luma)
+    }
+
+    /// Read metadata from the source without decoding pixels
+    fn info(&mut self) -> Result<ImageInfo, PuhuError> {
+        Ok(self
+            .probe()?
+            .map(|probe| probe.info.clone())
+            .unwrap_or_default())
+    }
+
+    /// Source to decode further frames from, for images opened from a file or bytes
+    fn frame_source(&mut self) -> Result<Option<FrameSource>, PuhuError> {
+        let Some(format) = self.probe()?.and_then(|probe| probe.header.format) else {
+            return Ok(None);
+        };
+        let (data, options) = match self {
+            LazyImage::Path { path, options, .. } => (SourceData::Path(path.clone()), options),
+            LazyImage::Bytes { data, options, .. } => {
+                (SourceData::Bytes(Arc::clone(data)), options)
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(FrameSource {
+            data,
+            format,
+            limits: options.limits,
+        }))
+    }
+
+    /// Read the rest of a Python stream into memory, turning `Reader` into `Bytes`.
+    ///
+    /// Streams can only be consumed once, so this must happen before the
+    /// lazy image is cloned.
+    fn read_stream(&mut self) -> Result<(), PuhuError> {
+        if let LazyImage::Reader {
+            reader,
+            prefix,
+            options,
+        } = self
+        {
+            let mut data = prefix.clone();
+            Python::with_gil(|py| -> PyResult<()> {
+                data.extend(io::read_from_pyobject(reader.bind(py), None)?);
+                Ok(())
+            })?;
+            *self = LazyImage::Bytes {
+                data: data.into(),
+                probe: None,
+                options: options.clone(),
+            };
         }
+        Ok(())
     }
 }
 
@@ -58,11 +476,159 @@ impl LazyImage {
 pub struct PyImage {
     lazy_image: LazyImage,
     format: Option<ImageFormat>,
+    /// Metadata, or `None` until it is read from the source
+    info: Option<ImageInfo>,
+    /// Position in an opened animation or multi-page file; `None` for
+    /// single frames and for images derived from another image
+    frames: Option<FrameState>,
+    /// Palette of a "P" image, whose pixels are then indices into it
+    palette: Option<Palette>,
+    /// Mode of an "I", "F", "CMYK" or "YCbCr" image, whose pixels are then
+    /// not RGB colors
+    extended: Option<ExtendedMode>,
 }
 
 impl PyImage {
     fn get_image(&mut self) -> Result<&DynamicImage, PuhuError> {
-        self.lazy_image.ensure_loaded()
+        // Metadata must be read before the source is replaced by the decoded image
+        let info = self.get_info()?;
+        let (cmyk, tiff_values) = (info.cmyk, info.tiff_values);
+        self.lazy_image.ensure_loaded(cmyk, tiff_values)
+    }
+
+    /// Get the extended mode; opened CMYK files are recognized from their metadata
+    fn get_extended(&mut self) -> Result<Option<ExtendedMode>, PuhuError> {
+        self.get_info()?;
+        Ok(self.extended)
+    }
+
+    /// Get the pixels as colors, expanding the indices of "P" images,
+    /// clamping the values of "I" and "F" images to "L" and converting
+    /// "CMYK" and "YCbCr" images to RGB
+    fn get_pixels(&mut self) -> Result<Cow<'_, DynamicImage>, PuhuError> {
+        let palette = self.palette.clone();
+        let extended = self.get_extended()?;
+        let image = self.get_image()?;
+        Ok(match (palette, extended) {
+            (Some(palette), _) => Cow::Owned(palette.expand(image)),
+            (None, Some(mode)) => Cow::Owned(modes::colors(image, mode)),
+            (None, None) => Cow::Borrowed(image),
+        })
+    }
+
+    /// Get the pixels with their mode, expanding the indices of "P" images
+    /// but keeping the samples of extended modes
+    fn get_colors(&mut self) -> Result<(Cow<'_, DynamicImage>, Mode), PuhuError> {
+        if let Some(mode) = self.get_extended()? {
+            return Ok((Cow::Borrowed(self.get_image()?), mode.into()));
+        }
+        let pixels = self.get_pixels()?;
+        let mode = Mode::of(&pixels, None);
+        Ok((pixels, mode))
+    }
+
+    /// Mode name from the header and metadata, without decoding pixels
+    fn mode_name(&mut self, header: &ImageHeader) -> Result<String, PuhuError> {
+        let extended = self.get_extended()?;
+        Ok(match (&self.palette, extended) {
+            (Some(_), _) => "P".to_string(),
+            (None, Some(mode)) => Mode::from(mode).name().to_string(),
+            (None, None) => color_type_to_mode_string(header.color_type),
+        })
+    }
+
+    /// Mode, interpolation and fill of a geometric transform of this image
+    fn transform_options(
+        &mut self,
+        resample: Option<String>,
+        fillcolor: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<(Mode, Interpolation, [f64; 4])> {
+        let extended = self.get_extended()?;
+        let mode = Mode::of(self.get_image()?, extended);
+
+        // Indices cannot be interpolated, so palette images use the nearest
+        // pixel and take an index or the nearest entry as fill
+        let Some(palette) = &self.palette else {
+            let filter =
+                operations::parse_resample_filter(Some(resample.as_deref().unwrap_or("NEAREST")))?;
+            let fill = modes::parse_fill(fillcolor, mode)?;
+            return Ok((mode, Interpolation::from_filter(filter)?, fill));
+        };
+        let index = match fillcolor {
+            None => 0,
+            Some(color) => match color.extract::<u8>() {
+                Ok(index) => index,
+                Err(_) => {
+                    let (r, g, b, a) = parse_color(color)?;
+                    palette.nearest([r, g, b, a])
+                }
+            },
+        };
+        Ok((mode, Interpolation::Nearest, [index as f64; 4]))
+    }
+
+    /// Get the image metadata, reading it from the source on first use
+    fn get_info(&mut self) -> Result<&ImageInfo, PuhuError> {
+        let info = match self.info.take() {
+            Some(info) => info,
+            None => {
+                let info = self.lazy_image.info()?;
+                if info.cmyk.is_some() {
+                    self.extended = Some(ExtendedMode::Cmyk);
+                }
+                if info.tiff_values.is_some() {
+                    self.extended = info.tiff_values;
+                }
+                if info.frames.len() > 1 {
+                    self.frames = self
+                        .lazy_image
+                        .frame_source()?
+                        .map(|source| FrameState::new(source, info.frames.clone()));
+                }
+                info
+            }
+        };
+        Ok(self.info.insert(info))
+    }
+
+    /// Every frame with its duration, for saving animations and multi-page
+    /// files. Frames keep the indices of "P" images, with their palette, and
+    /// the samples of extended modes, with the mode.
+    fn all_frames(&mut self) -> Result<Vec<SavedFrame>, PuhuError> {
+        let info = self.get_info()?;
+        let durations: Vec<Option<u32>> = info.frames.iter().map(|f| f.duration).collect();
+        let duration = info.duration;
+        match &self.frames {
+            Some(state) => {
+                let frames = Python::with_gil(|py| py.allow_threads(|| state.decode_all()))?;
+                Ok(frames
+                    .into_iter()
+                    .zip(durations)
+                    .map(|((image, extended), duration)| SavedFrame {
+                        image,
+                        duration,
+                        palette: None,
+                        extended,
+                    })
+                    .collect())
+            }
+            None => Ok(vec![SavedFrame {
+                image: self.get_image()?.clone(),
+                duration,
+                palette: self.palette.clone(),
+                extended: self.get_extended()?,
+            }]),
+        }
+    }
+
+    /// Get size, mode and format information without decoding pixels
+    fn get_header(&mut self) -> Result<ImageHeader, PuhuError> {
+        let header = self.lazy_image.header()?;
+        // The sniffed format is more reliable than the file extension
+        if header.format.is_some() {
+            self.format = header.format;
+        }
+        Ok(header)
     }
 }
 
@@ -75,6 +641,10 @@ impl PyImage {
         PyImage {
             lazy_image: LazyImage::Loaded(image),
             format: None,
+            info: Some(ImageInfo::default()),
+            frames: None,
+            palette: None,
+            extended: None,
         }
     }
 
@@ -95,6 +665,19 @@ impl PyImage {
             .into());
         }
 
+        // Deep modes take their own color values, which may not fit in 8 bits
+        if let Some(deep) = Mode::parse(mode).filter(|m| m.is_deep()) {
+            let fill = modes::parse_fill(color, deep)?;
+            return Ok(PyImage {
+                lazy_image: LazyImage::Loaded(modes::filled(deep, width, height, fill)),
+                format: None,
+                info: Some(ImageInfo::default()),
+                frames: None,
+                palette: None,
+                extended: deep.extended(),
+            });
+        }
+
         let parsed_color = if let Some(c) = color {
             parse_color(c)?
         } else {
@@ -102,6 +685,7 @@ impl PyImage {
         };
         let (r, g, b, a) = parsed_color;
 
+        let mut palette = None;
         let image = match mode {
             "RGB" => DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
                 width,
@@ -128,6 +712,25 @@ impl PyImage {
                 height,
                 image::LumaA([r, a]),
             )),
+            "P" => {
+                // Integers are indices into a grayscale palette; any other
+                // color becomes the only palette entry
+                let index = match color {
+                    Some(c) if c.extract::<u8>().is_err() => {
+                        palette = Some(Palette::new(vec![r, g, b], None)?);
+                        0
+                    }
+                    _ => {
+                        palette = Some(Palette::grayscale());
+                        r
+                    }
+                };
+                DynamicImage::ImageLuma8(image::GrayImage::from_pixel(
+                    width,
+                    height,
+                    image::Luma([index]),
+                ))
+            }
             _ => {
                 return Err(PuhuError::InvalidOperation(format!(
                     "Unsupported image mode: {}",
@@ -140,64 +743,189 @@ impl PyImage {
         Ok(PyImage {
             lazy_image: LazyImage::Loaded(image),
             format: None,
+            info: Some(ImageInfo::default()),
+            frames: None,
+            palette,
+            extended: None,
         })
     }
 
     #[classmethod]
-    fn open(_cls: &Bound<'_, PyType>, path_or_bytes: &Bound<'_, PyAny>) -> PyResult<Self> {
-        if let Ok(path) = path_or_bytes.extract::<String>() {
-            // Store path for lazy loading
-            let path_buf = PathBuf::from(&path);
-            let format = ImageFormat::from_path(&path).ok();
+    #[pyo3(signature = (
+        fp,
+        formats=None,
+        max_image_pixels=Some(limits::DEFAULT_MAX_IMAGE_PIXELS),
+        max_width=None,
+        max_height=None,
+        max_alloc=None,
+    ))]
+    fn open(
+        _cls: &Bound<'_, PyType>,
+        fp: &Bound<'_, PyAny>,
+        formats: Option<Vec<String>>,
+        max_image_pixels: Option<u64>,
+        max_width: Option<u32>,
+        max_height: Option<u32>,
+        max_alloc: Option<u64>,
+    ) -> PyResult<Self> {
+        let options = DecodeOptions {
+            limits: DecodeLimits {
+                max_image_pixels,
+                max_width,
+                max_height,
+                max_alloc,
+            },
+            formats: formats
+                .map(|names| {
+                    names
+                        .iter()
+                        .map(|name| formats::parse_format(name))
+                        .collect()
+                })
+                .transpose()?,
+            scale: 1,
+            luma: false,
+        };
+        if let Some(data) = io::buffer_to_vec(fp)? {
+            // Store bytes for lazy loading, guessing the format from the header
+            let format = formats::guess_format(&data);
+            options.check_format(format)?;
             Ok(PyImage {
-                lazy_image: LazyImage::Path { path: path_buf },
+                lazy_image: LazyImage::Bytes {
+                    data: data.into(),
+                    probe: None,
+                    options,
+                },
                 format,
+                info: None,
+                frames: None,
+                palette: None,
+                extended: None,
             })
-        } else if let Ok(bytes) = path_or_bytes.downcast::<PyBytes>() {
-            // Store bytes for lazy loading
-            let data = bytes.as_bytes().to_vec();
-            // Try to guess format from bytes header
-            let format = {
-                let cursor = Cursor::new(&data);
-                image::io::Reader::new(cursor)
-                    .with_guessed_format()
-                    .ok()
-                    .and_then(|r| r.format())
-            };
+        } else if let Ok(path) = fp.extract::<PathBuf>() {
+            // Store path (str or os.PathLike) for lazy loading, sniffing the
+            // format from the content and falling back to the extension
+            let mut prefix = Vec::with_capacity(SNIFF_LEN);
+            File::open(&path)
+                .and_then(|file| file.take(SNIFF_LEN as u64).read_to_end(&mut prefix))
+                .map_err(PuhuError::Io)?;
+            let format =
+                formats::guess_format(&prefix).or_else(|| ImageFormat::from_path(&path).ok());
+            options.check_format(format)?;
+            Ok(PyImage {
+                lazy_image: LazyImage::Path {
+                    path,
+                    probe: None,
+                    options,
+                },
+                format,
+                info: None,
+                frames: None,
+                palette: None,
+                extended: None,
+            })
+        } else if fp.hasattr("read")? {
+            // Only read enough to sniff the format; the rest is read on load
+            let prefix = io::read_from_pyobject(fp, Some(SNIFF_LEN))?;
+            let format = formats::guess_format(&prefix);
+            options.check_format(format)?;
             Ok(PyImage {
-                lazy_image: LazyImage::Bytes { data },
+                lazy_image: LazyImage::Reader {
+                    reader: Arc::new(fp.clone().unbind()),
+                    prefix,
+                    options,
+                },
                 format,
+                info: None,
+                frames: None,
+                palette: None,
+                extended: None,
             })
         } else {
-            Err(PuhuError::InvalidOperation("Expected file path (str) or bytes".to_string()).into())
+            Err(PuhuError::InvalidOperation(
+                "Expected file path, bytes or file-like object".to_string(),
+            )
+            .into())
         }
     }
 
-    #[pyo3(signature = (path_or_buffer, format=None))]
-    fn save(&mut self, path_or_buffer: &Bound<'_, PyAny>, format: Option<String>) -> PyResult<()> {
-        if let Ok(path) = path_or_buffer.extract::<String>() {
-            // Save to file path
-            let save_format = if let Some(fmt) = format {
-                formats::parse_format(&fmt)?
-            } else {
-                ImageFormat::from_path(&path).map_err(|_| {
-                    PuhuError::UnsupportedFormat("Cannot determine format from path".to_string())
-                })?
-            };
+    #[pyo3(signature = (path_or_buffer, format=None, save_all=false, append_images=None, **options))]
+    fn save(
+        slf: &Bound<'_, Self>,
+        path_or_buffer: &Bound<'_, PyAny>,
+        format: Option<String>,
+        save_all: bool,
+        append_images: Option<Vec<Bound<'_, PyImage>>>,
+        options: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        let path = path_or_buffer.extract::<PathBuf>().ok();
 
-            // Ensure image is loaded before saving
-            let image = self.get_image()?;
+        let save_format = match (format, &path) {
+            (Some(fmt), _) => formats::parse_format(&fmt)?,
+            (None, Some(path)) => ImageFormat::from_path(path).map_err(|_| {
+                PuhuError::UnsupportedFormat("Cannot determine format from path".to_string())
+            })?,
+            // Saving to a buffer; there is no extension to infer the format from
+            (None, None) => {
+                return Err(PuhuError::UnsupportedFormat(
+                    "Format must be specified when saving to a buffer".to_string(),
+                )
+                .into())
+            }
+        };
+        let mut save_options = SaveOptions::from_kwargs(save_format, options)?;
+        let mut this = slf.borrow_mut();
+        save_options.inherit(save_format, this.get_info()?);
 
-            Python::with_gil(|py| {
-                py.allow_threads(|| {
-                    image
-                        .save_with_format(&path, save_format)
-                        .map_err(|e| PuhuError::ImageError(e))
-                        .map_err(|e| e.into())
+        let data = if save_all {
+            let mut frames = this.all_frames()?;
+            for image in append_images.into_iter().flatten() {
+                // The image may be appended to itself, which is already borrowed
+                if image.is(slf) {
+                    frames.extend(this.all_frames()?);
+                } else {
+                    frames.extend(image.borrow_mut().all_frames()?);
+                }
+            }
+            // Keep the timing of the source frames unless it is overridden
+            if save_options.duration.is_empty() && frames.iter().any(|f| f.duration.is_some()) {
+                save_options.duration = frames.iter().map(|f| f.duration.unwrap_or(0)).collect();
+            }
+            if save_options.loop_count.is_none() {
+                save_options.loop_count = this.get_info()?.loop_count;
+            }
+            save_options.extended = frames.iter().map(|f| f.extended).collect();
+            // Several frames are written from their colors, so only a single
+            // image keeps its palette
+            if let [frame] = &frames[..] {
+                save_options.palette = frame.palette.clone();
+            }
+            let single = frames.len() == 1;
+            let frames: Vec<DynamicImage> = frames
+                .into_iter()
+                .map(|frame| match frame.palette {
+                    Some(palette) if !single => palette.expand(&frame.image),
+                    _ => frame.image,
                 })
-            })
+                .collect();
+            Python::with_gil(|py| {
+                py.allow_threads(|| encoders::encode_all(&frames, save_format, &save_options))
+            })?
         } else {
-            Err(PuhuError::InvalidOperation("Buffer saving not yet implemented".to_string()).into())
+            save_options.palette = this.palette.clone();
+            save_options.extended = vec![this.extended];
+            // Ensure image is loaded before saving
+            let image = this.get_image()?;
+            Python::with_gil(|py| {
+                py.allow_threads(|| encoders::encode(image, save_format, &save_options))
+            })?
+        };
+
+        match path {
+            Some(path) => std::fs::write(&path, &data)
+                .map_err(PuhuError::Io)
+                .map_err(|e| e.into()),
+            None => io::write_to_pyobject(path_or_buffer, &data),
         }
     }
 
@@ -205,6 +933,9 @@ impl PyImage {
     fn resize(&mut self, size: (u32, u32), resample: Option<String>) -> PyResult<Self> {
         let (width, height) = size;
         let format = self.format;
+        let palette = self.palette.clone();
+        let extended = self.get_extended()?;
+        let info = self.get_info()?.clone();
 
         // Load image to check dimensions
         let image = self.get_image()?;
@@ -214,17 +945,206 @@ impl PyImage {
             return Ok(PyImage {
                 lazy_image: LazyImage::Loaded(image.clone()),
                 format,
+                info: Some(info),
+                frames: None,
+                palette,
+                extended,
             });
         }
 
         let filter = operations::parse_resample_filter(resample.as_deref())?;
+        // Indices cannot be interpolated, so palette images use the nearest pixel
+        let filter = match palette {
+            Some(_) => FilterType::Nearest,
+            None => filter,
+        };
 
+        let mode = Mode::of(image, extended);
         Ok(Python::with_gil(|py| {
             py.allow_threads(|| {
-                let resized = image.resize(width, height, filter);
+                let resized =
+                    modes::resample(image, mode, |image| image.resize(width, height, filter));
                 PyImage {
                     lazy_image: LazyImage::Loaded(resized),
                     format,
+                    info: Some(info),
+                    frames: None,
+                    palette,
+                    extended,
+                }
+            })
+        }))
+    }
+
+    /// Shrink the image in place to fit within `size`, keeping its aspect
+    /// ratio, as Pillow's `thumbnail`.
+    ///
+    /// With a `reducing_gap`, JPEG files not yet decoded are decoded at a
+    /// reduced scale, and the image is box-reduced by whole factors to at
+    /// least `reducing_gap` times the final size before the filter runs.
+    #[pyo3(signature = (size, resample=None, reducing_gap=Some(2.0)))]
+    fn thumbnail(
+        &mut self,
+        size: (f64, f64),
+        resample: Option<String>,
+        reducing_gap: Option<f64>,
+    ) -> PyResult<()> {
+        let bounds = (
+            size.0.floor().max(0.0) as u32,
+            size.1.floor().max(0.0) as u32,
+        );
+        if bounds.0 == 0 || bounds.1 == 0 {
+            return Err(PuhuError::InvalidOperation(
+                "Thumbnail size must be at least 1x1".to_string(),
+            )
+            .into());
+        }
+        if reducing_gap.is_some_and(|gap| gap < 1.0) {
+            return Err(PuhuError::InvalidOperation(
+                "reducing_gap must be 1.0 or greater".to_string(),
+            )
+            .into());
+        }
+        let header = self.get_header()?;
+        let Some(target) = operations::thumbnail_size((header.width, header.height), bounds) else {
+            return Ok(());
+        };
+
+        let filter =
+            operations::parse_resample_filter(Some(resample.as_deref().unwrap_or("BICUBIC")))?;
+        // Indices cannot be interpolated, so palette images use the nearest pixel
+        let filter = match self.palette {
+            Some(_) => FilterType::Nearest,
+            None => filter,
+        };
+        if let Some(gap) = reducing_gap {
+            let requested = (
+                (size.0 * gap).max(0.0) as u32,
+                (size.1 * gap).max(0.0) as u32,
+            );
+            self.lazy_image.draft(Some(requested))?;
+        }
+        let extended = self.get_extended()?;
+        let image = self.get_image()?;
+        let mode = Mode::of(image, extended);
+        let alpha = mode.has_alpha();
+
+        let thumbnail = Python::with_gil(|py| {
+            py.allow_threads(|| {
+                modes::resample(image, mode, |image| {
+                    let mut image = Cow::Borrowed(image);
+                    if let Some(gap) = reducing_gap.filter(|_| filter != FilterType::Nearest) {
+                        let factor =
+                            |from: u32, to: u32| ((from as f64 / to as f64 / gap) as u32).max(1);
+                        let factor = (
+                            factor(image.width(), target.0),
+                            factor(image.height(), target.1),
+                        );
+                        if factor != (1, 1) {
+                            image = Cow::Owned(operations::reduce(&image, factor, alpha));
+                        }
+                    }
+                    if (image.width(), image.height()) == target {
+                        return image.into_owned();
+                    }
+                    image.resize_exact(target.0, target.1, filter)
+                })
+            })
+        });
+        self.lazy_image = LazyImage::Loaded(thumbnail);
+        Ok(())
+    }
+
+    /// Have a JPEG file that is not yet decoded decoded at a reduced scale
+    /// no smaller than `size`, and converted to "L" if `mode` asks for it,
+    /// as Pillow's `draft`.
+    ///
+    /// Returns the mode and the box of the file the image is decoded from,
+    /// in decoded pixels, or None if the image is not a lazy JPEG.
+    #[pyo3(signature = (mode, size=None))]
+    fn draft(
+        &mut self,
+        mode: Option<String>,
+        size: Option<(u32, u32)>,
+    ) -> PyResult<Option<(String, [f64; 4])>> {
+        // CMYK files keep their inks
+        if mode.as_deref() == Some("L") && self.get_extended()?.is_none() {
+            self.lazy_image.draft_luma()?;
+        }
+        let Some((scale, file)) = self.lazy_image.draft(size)? else {
+            return Ok(None);
+        };
+        let header = self.get_header()?;
+        let scale = scale as f64;
+        let bounds = (file.width as f64 / scale, file.height as f64 / scale);
+        Ok(Some((
+            self.mode_name(&header)?,
+            [0.0, 0.0, bounds.0, bounds.1],
+        )))
+    }
+
+    /// Shrink the image by whole factors, averaging each block of pixels, as
+    /// Pillow's `reduce`. `box_coords` is the (left, top, right, bottom)
+    /// region to reduce, the whole image by default.
+    #[pyo3(signature = (factor, box_coords=None))]
+    fn reduce(
+        &mut self,
+        factor: (u32, u32),
+        box_coords: Option<(u32, u32, u32, u32)>,
+    ) -> PyResult<Self> {
+        if factor.0 == 0 || factor.1 == 0 {
+            return Err(PuhuError::InvalidOperation(format!(
+                "Reduction factors must be at least 1, got {}x{}",
+                factor.0, factor.1
+            ))
+            .into());
+        }
+        if self.palette.is_some() {
+            return Err(PuhuError::InvalidOperation(
+                "Palette images cannot be reduced; convert them first".to_string(),
+            )
+            .into());
+        }
+        let format = self.format;
+        let extended = self.get_extended()?;
+        let info = self.get_info()?.clone();
+        let image = self.get_image()?;
+        let mode = Mode::of(image, extended);
+
+        let (left, top, right, bottom) =
+            box_coords.unwrap_or((0, 0, image.width(), image.height()));
+        if left >= right || top >= bottom || right > image.width() || bottom > image.height() {
+            return Err(PuhuError::InvalidOperation(format!(
+                "Reduce box ({}, {}, {}, {}) is empty or exceeds image bounds ({}x{})",
+                left,
+                top,
+                right,
+                bottom,
+                image.width(),
+                image.height()
+            ))
+            .into());
+        }
+
+        Ok(Python::with_gil(|py| {
+            py.allow_threads(|| {
+                let mut image = Cow::Borrowed(image);
+                if (left, top, right, bottom) != (0, 0, image.width(), image.height()) {
+                    image = Cow::Owned(image.crop_imm(left, top, right - left, bottom - top));
+                }
+                let reduced = match factor {
+                    (1, 1) => image.into_owned(),
+                    _ => modes::resample(&image, mode, |image| {
+                        operations::reduce(image, factor, mode.has_alpha())
+                    }),
+                };
+                PyImage {
+                    lazy_image: LazyImage::Loaded(reduced),
+                    format,
+                    info: Some(info),
+                    frames: None,
+                    palette: None,
+                    extended,
                 }
             })
         }))
@@ -233,6 +1153,9 @@ impl PyImage {
     fn crop(&mut self, box_coords: (u32, u32, u32, u32)) -> PyResult<Self> {
         let (x, y, width, height) = box_coords;
         let format = self.format;
+        let palette = self.palette.clone();
+        let extended = self.get_extended()?;
+        let info = self.get_info()?.clone();
 
         let image = self.get_image()?;
 
@@ -263,60 +1186,191 @@ impl PyImage {
                 PyImage {
                     lazy_image: LazyImage::Loaded(cropped),
                     format,
+                    info: Some(info),
+                    frames: None,
+                    palette,
+                    extended,
                 }
             })
         }))
     }
 
-    fn rotate(&mut self, angle: f64) -> PyResult<Self> {
+    #[pyo3(signature = (angle, resample=None, expand=false, center=None, translate=None, fillcolor=None))]
+    fn rotate(
+        &mut self,
+        angle: f64,
+        resample: Option<String>,
+        expand: bool,
+        center: Option<(f64, f64)>,
+        translate: Option<(f64, f64)>,
+        fillcolor: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
         let format = self.format;
-        let image = self.get_image()?;
+        let palette = self.palette.clone();
+        let extended = self.get_extended()?;
+        let info = self.get_info()?.clone();
+        let (mode, interpolation, fill) = self.transform_options(resample, fillcolor)?;
 
+        let image = self.get_image()?;
+        let angle = angle.rem_euclid(360.0);
+        let size = (image.width(), image.height());
         Python::with_gil(|py| {
             py.allow_threads(|| {
-                let rotated = if (angle - 90.0).abs() < f64::EPSILON {
-                    image.rotate90()
-                } else if (angle - 180.0).abs() < f64::EPSILON {
-                    image.rotate180()
-                } else if (angle - 270.0).abs() < f64::EPSILON {
-                    image.rotate270()
-                } else {
-                    return Err(PuhuError::InvalidOperation(
-                        "Only 90, 180, 270 degree rotations supported".to_string(),
-                    )
-                    .into());
+                // Quarter turns that keep every pixel need no sampling
+                let exact = center.is_none() && translate.is_none();
+                let rotated = match angle {
+                    0.0 if exact => image.clone(),
+                    180.0 if exact => Symmetry::Rotate180.apply(image),
+                    90.0 if exact && (expand || size.0 == size.1) => {
+                        Symmetry::Rotate90.apply(image)
+                    }
+                    270.0 if exact && (expand || size.0 == size.1) => {
+                        Symmetry::Rotate270.apply(image)
+                    }
+                    _ => {
+                        let translate = translate.unwrap_or((0.0, 0.0));
+                        let (size, matrix) =
+                            transform::rotation(size, angle, center, translate, expand);
+                        let rotation = Transform::Affine(matrix);
+                        transform::transform(image, mode, size, &rotation, interpolation, fill)
+                    }
                 };
                 Ok(PyImage {
                     lazy_image: LazyImage::Loaded(rotated),
                     format,
+                    info: Some(info),
+                    frames: None,
+                    palette,
+                    extended,
                 })
             })
         })
     }
 
-    fn transpose(&mut self, method: String) -> PyResult<Self> {
+    #[pyo3(signature = (size, method, data, resample=None, fillcolor=None))]
+    fn transform(
+        &mut self,
+        size: (u32, u32),
+        method: &str,
+        data: &Bound<'_, PyAny>,
+        resample: Option<String>,
+        fillcolor: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        if size.0 == 0 || size.1 == 0 {
+            return Err(PuhuError::InvalidOperation(
+                "Image dimensions must be greater than 0".to_string(),
+            )
+            .into());
+        }
+        let values = |count: usize| -> PyResult<Vec<f64>> {
+            let values: Vec<f64> = data.extract()?;
+            if values.len() != count {
+                return Err(PuhuError::InvalidOperation(format!(
+                    "{} data must have {} values, got {}",
+                    method,
+                    count,
+                    values.len()
+                ))
+                .into());
+            }
+            Ok(values)
+        };
+        let transform = match method {
+            "AFFINE" => Transform::Affine(values(6)?.try_into().expect("6 values")),
+            "EXTENT" => Transform::extent(size, values(4)?.try_into().expect("4 values")),
+            "PERSPECTIVE" => Transform::Perspective(values(8)?.try_into().expect("8 values")),
+            "QUAD" => Transform::quad(size, values(8)?.try_into().expect("8 values")),
+            "MESH" => {
+                let mesh: Vec<([i64; 4], [f64; 8])> = data.extract()?;
+                let quads = mesh
+                    .into_iter()
+                    .map(|(rect, corners)| Quad::new(rect, corners));
+                Transform::Mesh(quads.collect())
+            }
+            other => {
+                return Err(PuhuError::InvalidOperation(format!(
+                    "Unsupported transform method: {}",
+                    other
+                ))
+                .into())
+            }
+        };
+
+        let format = self.format;
+        let palette = self.palette.clone();
+        let extended = self.get_extended()?;
+        let info = self.get_info()?.clone();
+        let (mode, interpolation, fill) = self.transform_options(resample, fillcolor)?;
+        let image = self.get_image()?;
+
+        Ok(Python::with_gil(|py| {
+            py.allow_threads(|| PyImage {
+                lazy_image: LazyImage::Loaded(transform::transform(
+                    image,
+                    mode,
+                    size,
+                    &transform,
+                    interpolation,
+                    fill,
+                )),
+                format,
+                info: Some(info),
+                frames: None,
+                palette,
+                extended,
+            })
+        }))
+    }
+
+    fn transpose(&mut self, method: &str) -> PyResult<Self> {
+        let method = Symmetry::parse(method)?;
         let format = self.format;
+        let palette = self.palette.clone();
+        let extended = self.get_extended()?;
+        let info = self.get_info()?.clone();
+        let image = self.get_image()?;
+
+        Ok(Python::with_gil(|py| {
+            py.allow_threads(|| PyImage {
+                lazy_image: LazyImage::Loaded(method.apply(image)),
+                format,
+                info: Some(info),
+                frames: None,
+                palette,
+                extended,
+            })
+        }))
+    }
+
+    /// Rotate or flip the image upright according to its EXIF orientation.
+    ///
+    /// The orientation tag is removed from the result's EXIF data. Images
+    /// without an orientation are returned as a copy.
+    fn exif_transpose(&mut self) -> PyResult<Self> {
+        let format = self.format;
+        let palette = self.palette.clone();
+        let extended = self.get_extended()?;
+        let mut info = self.get_info()?.clone();
+        let mut exif = match &info.exif {
+            Some(data) => Exif::parse(data).unwrap_or_default(),
+            None => Exif::default(),
+        };
+        let Some(method) = exif.orientation().and_then(Symmetry::from_orientation) else {
+            return self.copy();
+        };
+        exif.remove_orientation();
+        info.exif = Some(exif.to_bytes());
         let image = self.get_image()?;
 
         Python::with_gil(|py| {
             py.allow_threads(|| {
-                let transposed = match method.as_str() {
-                    "FLIP_LEFT_RIGHT" => image.fliph(),
-                    "FLIP_TOP_BOTTOM" => image.flipv(),
-                    "ROTATE_90" => image.rotate90(),
-                    "ROTATE_180" => image.rotate180(),
-                    "ROTATE_270" => image.rotate270(),
-                    _ => {
-                        return Err(PuhuError::InvalidOperation(format!(
-                            "Unsupported transpose method: {}",
-                            method
-                        ))
-                        .into())
-                    }
-                };
                 Ok(PyImage {
-                    lazy_image: LazyImage::Loaded(transposed),
+                    lazy_image: LazyImage::Loaded(method.apply(image)),
                     format,
+                    info: Some(info),
+                    frames: None,
+                    palette,
+                    extended,
                 })
             })
         })
@@ -324,26 +1378,24 @@ impl PyImage {
 
     #[getter]
     fn size(&mut self) -> PyResult<(u32, u32)> {
-        let img = self.get_image()?;
-        Ok((img.width(), img.height()))
+        let header = self.get_header()?;
+        Ok((header.width, header.height))
     }
 
     #[getter]
     fn width(&mut self) -> PyResult<u32> {
-        let img = self.get_image()?;
-        Ok(img.width())
+        Ok(self.get_header()?.width)
     }
 
     #[getter]
     fn height(&mut self) -> PyResult<u32> {
-        let img = self.get_image()?;
-        Ok(img.height())
+        Ok(self.get_header()?.height)
     }
 
     #[getter]
     fn mode(&mut self) -> PyResult<String> {
-        let img = self.get_image()?;
-        Ok(color_type_to_mode_string(img.color()))
+        let header = self.get_header()?;
+        Ok(self.mode_name(&header)?)
     }
 
     #[getter]
@@ -351,22 +1403,235 @@ impl PyImage {
         self.format.map(|f| format!("{:?}", f).to_uppercase())
     }
 
+    /// Number of frames in an animation or pages in a multi-page file
+    #[getter]
+    fn n_frames(&mut self) -> PyResult<usize> {
+        self.get_info()?;
+        Ok(self.frames.as_ref().map_or(1, FrameState::len))
+    }
+
+    #[getter]
+    fn is_animated(&mut self) -> PyResult<bool> {
+        Ok(self.n_frames()? > 1)
+    }
+
+    /// Index of the current frame
+    fn tell(&self) -> usize {
+        self.frames.as_ref().map_or(0, FrameState::current)
+    }
+
+    /// Move to another frame, decoding it on demand.
+    ///
+    /// Stepping forward continues decoding from the current frame, while
+    /// seeking backward starts again from the first.
+    fn seek(&mut self, frame: usize) -> PyResult<()> {
+        self.get_info()?;
+        let n_frames = self.frames.as_ref().map_or(1, FrameState::len);
+        if frame >= n_frames {
+            return Err(PyEOFError::new_err("attempt to seek outside sequence"));
+        }
+        let Some(state) = self.frames.as_mut() else {
+            return Ok(());
+        };
+        if frame == state.current() {
+            return Ok(());
+        }
+
+        let (image, extended) = Python::with_gil(|py| py.allow_threads(|| state.seek(frame)))?;
+        let duration = state.frame_info().duration;
+        self.lazy_image = LazyImage::Loaded(image);
+        // Pages of a CMYK TIFF are decoded as RGB, while TIFF pages of
+        // integer or float values keep them
+        self.extended = extended;
+        if let Some(info) = self.info.as_mut() {
+            info.duration = duration.or(info.duration);
+        }
+        Ok(())
+    }
+
+    /// Format-specific disposal of the current frame: the GIF disposal
+    /// method, APNG dispose_op or WebP dispose flag
+    #[getter]
+    fn disposal(&mut self) -> PyResult<Option<u8>> {
+        let current = self.tell();
+        Ok(self
+            .get_info()?
+            .frames
+            .get(current)
+            .and_then(|frame| frame.disposal))
+    }
+
+    #[getter]
+    fn info<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = self.get_info()?.to_dict(py)?;
+        if let Some(palette) = &self.palette {
+            // Like PNG, a single transparent entry is given by its index
+            match palette.transparency() {
+                Some(index) => dict.set_item("transparency", index)?,
+                None if palette.has_alpha() => {
+                    dict.set_item("transparency", PyBytes::new(py, palette.alphas()))?
+                }
+                None => {}
+            }
+        }
+        Ok(dict)
+    }
+
+    /// PNG text chunks as a dictionary
+    #[getter]
+    fn text<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (key, value) in &self.get_info()?.text {
+            dict.set_item(key, value)?;
+        }
+        Ok(dict)
+    }
+
+    /// EXIF data as a mutable mapping; empty if the image has none or it is malformed
+    fn getexif(&mut self) -> PyResult<PyExif> {
+        let exif = match &self.get_info()?.exif {
+            Some(data) => Exif::parse(data).unwrap_or_default(),
+            None => Exif::default(),
+        };
+        Ok(PyExif::from_exif(exif))
+    }
+
+    /// Palette as a flat list of values, or `None` for images without one.
+    ///
+    /// With rawmode "RGBA", each entry is followed by its alpha.
+    #[pyo3(signature = (rawmode="RGB"))]
+    fn getpalette<'py>(
+        &self,
+        py: Python<'py>,
+        rawmode: &str,
+    ) -> PyResult<Option<Bound<'py, PyList>>> {
+        let Some(palette) = &self.palette else {
+            return Ok(None);
+        };
+        let values = match rawmode {
+            "RGB" => palette.colors().to_vec(),
+            "RGBA" => palette
+                .colors()
+                .chunks_exact(3)
+                .enumerate()
+                .flat_map(|(i, rgb)| [rgb[0], rgb[1], rgb[2], palette.alpha(i as u8)])
+                .collect(),
+            _ => return Err(invalid_rawmode(rawmode).into()),
+        };
+        Ok(Some(PyList::new(py, values)?))
+    }
+
+    /// Attach a palette to a "P" or "L" image, making it a "P" image whose
+    /// pixel values are indices into the palette.
+    ///
+    /// With rawmode "RGBA", each entry is followed by its alpha; otherwise
+    /// the alpha of the current palette is kept.
+    #[pyo3(signature = (data, rawmode="RGB"))]
+    fn putpalette(&mut self, data: &Bound<'_, PyAny>, rawmode: &str) -> PyResult<()> {
+        let data = match io::buffer_to_vec(data)? {
+            Some(data) => data,
+            None => data.extract::<Vec<u8>>().map_err(|_| {
+                PuhuError::InvalidOperation(
+                    "Palette data must be bytes or a sequence of integers between 0 and 255"
+                        .to_string(),
+                )
+            })?,
+        };
+        let (colors, alpha) = match rawmode {
+            "RGB" => {
+                let alpha = self.palette.as_ref().map(|p| p.alphas().to_vec());
+                (data, alpha.unwrap_or_default())
+            }
+            "RGBA" => {
+                if !data.len().is_multiple_of(4) {
+                    return Err(PuhuError::InvalidOperation(format!(
+                        "RGBA palette data must have 4 values per entry, got {} values",
+                        data.len()
+                    ))
+                    .into());
+                }
+                let entries = data.chunks_exact(4);
+                let alpha = entries.clone().map(|rgba| rgba[3]).collect();
+                let colors = entries
+                    .flat_map(|rgba| [rgba[0], rgba[1], rgba[2]])
+                    .collect();
+                (colors, alpha)
+            }
+            _ => return Err(invalid_rawmode(rawmode).into()),
+        };
+
+        let mode = self.mode()?;
+        if mode != "P" && mode != "L" {
+            return Err(PuhuError::InvalidOperation(format!(
+                "Cannot put a palette on a '{}' image; use a 'P' or 'L' image",
+                mode
+            ))
+            .into());
+        }
+        self.palette = Some(Palette::with_alpha(colors, alpha)?);
+        Ok(())
+    }
+
+    #[allow(clippy::wrong_self_convention)]
     fn to_bytes(&mut self) -> PyResult<Py<PyBytes>> {
         let image = self.get_image()?;
         Python::with_gil(|py| {
             let bytes = py.allow_threads(|| image.as_bytes().to_vec());
-            Ok(PyBytes::new_bound(py, &bytes).into())
+            Ok(PyBytes::new(py, &bytes).into())
         })
     }
 
-    fn copy(&self) -> Self {
-        PyImage {
+    /// Copy the current frame
+    fn copy(&mut self) -> PyResult<Self> {
+        self.lazy_image.read_stream()?;
+        self.get_info()?;
+        Ok(PyImage {
             lazy_image: self.lazy_image.clone(),
             format: self.format,
+            info: self.info.clone(),
+            frames: None,
+            palette: self.palette.clone(),
+            extended: self.extended,
+        })
+    }
+
+    /// Convert pixels between ICC profiles with a transform from `ImageCms.buildTransform`.
+    ///
+    /// The result carries the transform's output profile.
+    fn apply_color_transform(&mut self, transform: &PyCmsTransform) -> PyResult<Self> {
+        let transform = transform.inner().clone();
+        if self.palette.is_some() {
+            return Err(PuhuError::InvalidOperation(format!(
+                "Image mode '{}' does not match the transform input mode '{}'",
+                self.mode()?,
+                transform.input_mode
+            ))
+            .into());
         }
+        let format = self.format;
+        let mut info = self.get_info()?.clone();
+        info.icc_profile = Some(transform.output_profile.to_vec());
+        let extended = self.get_extended()?;
+        let image = self.get_image()?;
+        let mode = Mode::of(image, extended);
+
+        Python::with_gil(|py| {
+            py.allow_threads(|| {
+                let converted = apply_color_transform(image, mode, &transform)?;
+                Ok(PyImage {
+                    lazy_image: LazyImage::Loaded(converted),
+                    format,
+                    info: Some(info),
+                    frames: None,
+                    palette: None,
+                    extended: Mode::parse(&transform.output_mode).and_then(Mode::extended),
+                })
+            })
+        })
     }
 
-    #[pyo3(signature = (mode, matrix=None, dither=None, palette=None, colors=None))]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (mode, matrix=None, dither=None, palette=None, colors=None, serpentine=false, metric=None))]
     fn convert(
         &mut self,
         mode: &str,
@@ -374,9 +1639,12 @@ impl PyImage {
         dither: Option<String>,
         palette: Option<String>,
         colors: Option<u32>,
+        serpentine: bool,
+        metric: Option<String>,
     ) -> PyResult<Self> {
         let format = self.format;
-        let image = self.get_image()?;
+        let mut info = self.get_info()?.clone();
+        let current_mode = self.mode()?;
 
         // Validate matrix if provided
         if let Some(ref mat) = matrix {
@@ -388,71 +1656,169 @@ impl PyImage {
             }
         }
 
-        let current_mode = color_type_to_mode_string(image.color());
-
         // Early return if converting to the same mode (and no matrix)
         if current_mode == mode && matrix.is_none() {
+            let palette = self.palette.clone();
             return Ok(PyImage {
-                lazy_image: LazyImage::Loaded(image.clone()),
+                lazy_image: LazyImage::Loaded(self.get_image()?.clone()),
                 format,
+                info: Some(info),
+                frames: None,
+                palette,
+                extended: self.extended,
             });
         }
 
+        // Palette images are converted from their colors. "I" and "F" images
+        // keep their values when converted to another color or value mode,
+        // and are clamped to "L" for the other conversions.
+        let target = Mode::parse(mode).filter(|_| matrix.is_none());
+        let (image, source) = match target {
+            Some(_) => {
+                let (image, source) = self.get_colors()?;
+                (image, Some(source))
+            }
+            None => (self.get_pixels()?, None),
+        };
+        let image = &*image;
+        let mut new_palette = None;
+        let extended = target.and_then(Mode::extended);
+        // CMYK images with an embedded CMYK profile are converted to sRGB
+        // through it rather than with the plain formulas
+        let cmyk_profile = match (source, target) {
+            (Some(Mode::Cmyk), Some(target)) if target != Mode::Cmyk => info
+                .icc_profile
+                .clone()
+                .filter(|profile| profile.get(16..20) == Some(Mode::Cmyk.icc_color_space())),
+            _ => None,
+        };
+
         Python::with_gil(|py| {
             py.allow_threads(|| {
-                let converted = if let Some(mat) = matrix {
+                let converted = if let (Some(target), Some(source)) = (target, source) {
+                    match cmyk_profile.map(|profile| cms::cmyk_to_srgb(image, &profile)) {
+                        Some(Ok(rgb)) => {
+                            info.icc_profile = Some(cms::srgb_profile_bytes()?);
+                            modes::convert(&rgb, Mode::Rgb, target)
+                        }
+                        // Profiles the transform cannot be built from are ignored
+                        _ => modes::convert(image, source, target),
+                    }
+                } else if let Some(mat) = matrix {
                     conversions::convert_with_matrix(image, mode, &mat)?
                 } else {
                     match mode {
-                        "L" => DynamicImage::ImageLuma8(image.to_luma8()),
-                        "LA" => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
-                        "RGB" => DynamicImage::ImageRgb8(image.to_rgb8()),
-                        "RGBA" => DynamicImage::ImageRgba8(image.to_rgba8()),
                         "1" => {
                             // bilevel
-                            let apply_dither = match dither.as_deref() {
-                                Some("NONE") | Some("none") => false,
-                                Some("FLOYDSTEINBERG") | Some("floydsteinberg") => true,
-                                None => true,
-                                Some(other) => {
-                                    return Err(PuhuError::InvalidOperation(
-                                        format!("Unsupported dither method: '{}'. Use 'NONE' or 'FLOYDSTEINBERG'", other)
-                                    ).into());
-                                }
-                            };
-                            conversions::convert_to_bilevel(image, apply_dither)?
+                            let dither = Dither::parse(dither.as_deref(), serpentine)?;
+                            conversions::convert_to_bilevel(image, &dither)?
                         }
                         "P" => {
                             let palette_type = palette.as_deref().unwrap_or("WEB");
                             let num_colors = colors.unwrap_or(256);
-                            let apply_dither = match dither.as_deref() {
-                                Some("NONE") | Some("none") => false,
-                                Some("FLOYDSTEINBERG") | Some("floydsteinberg") => true,
-                                None => true,
-                                Some(other) => {
-                                    return Err(PuhuError::InvalidOperation(
-                                        format!("Unsupported dither method: '{}'. Use 'NONE' or 'FLOYDSTEINBERG'", other)
-                                    ).into());
-                                }
-                            };
-                            palette::convert_to_palette(image, palette_type, num_colors, apply_dither)?
+                            let dither = Dither::parse(dither.as_deref(), serpentine)?;
+                            let metric = Metric::parse(metric.as_deref())?;
+                            let (indices, palette) = palette::convert_to_palette(
+                                image,
+                                palette_type,
+                                num_colors,
+                                &dither,
+                                metric,
+                            )?;
+                            new_palette = Some(palette);
+                            indices
                         }
                         _ => {
-                            return Err(PuhuError::InvalidOperation(
-                                format!("Unsupported conversion mode: '{}'. Supported modes: L, LA, RGB, RGBA, 1, P", mode)
-                            ).into());
+                            return Err(PuhuError::InvalidOperation(format!(
+                                "Unsupported conversion mode: '{}'. Supported modes: L, LA, RGB, \
+                                 RGBA, I;16, LA;16, RGB;16, RGBA;16, RGB;F, RGBA;F, I, F, CMYK, \
+                                 YCbCr, 1, P",
+                                mode
+                            ))
+                            .into());
                         }
                     }
                 };
+                match new_palette {
+                    // The transparent color is now a palette entry
+                    Some(_) => info.transparency = None,
+                    None => info.drop_mismatched_icc_profile(Mode::of(&converted, extended)),
+                }
 
                 Ok(PyImage {
                     lazy_image: LazyImage::Loaded(converted),
                     format,
+                    info: Some(info),
+                    frames: None,
+                    palette: new_palette,
+                    extended,
                 })
             })
         })
     }
 
+    /// Reduce the image to a "P" image of up to `colors` colors, or to the
+    /// palette of another "P" image
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (colors=256, method=None, kmeans=0, palette=None, dither=None, serpentine=false, metric=None))]
+    fn quantize(
+        slf: &Bound<'_, Self>,
+        colors: u32,
+        method: Option<String>,
+        kmeans: u32,
+        palette: Option<Bound<'_, PyImage>>,
+        dither: Option<String>,
+        serpentine: bool,
+        metric: Option<String>,
+    ) -> PyResult<Self> {
+        if !(1..=256).contains(&colors) {
+            return Err(PuhuError::InvalidOperation(format!(
+                "Number of colors must be between 1 and 256, got {}",
+                colors
+            ))
+            .into());
+        }
+        let dither = Dither::parse(dither.as_deref(), serpentine)?;
+        let metric = Metric::parse(metric.as_deref())?;
+        // Read the other palette first; it may be this image
+        let target = match palette {
+            Some(image) => Some(image.borrow().palette.clone().ok_or_else(|| {
+                PuhuError::InvalidOperation("Palette image must be a 'P' image".to_string())
+            })?),
+            None => None,
+        };
+
+        let mut this = slf.borrow_mut();
+        let format = this.format;
+        let mut info = this.get_info()?.clone();
+        // The transparent color is now a palette entry
+        info.transparency = None;
+        let image = &*this.get_pixels()?;
+        let (indices, palette) = Python::with_gil(|py| {
+            py.allow_threads(|| {
+                let palette = match target {
+                    Some(palette) => palette,
+                    None => {
+                        let has_alpha = image.color().has_alpha();
+                        let method = quantize::parse_method(method.as_deref(), has_alpha)?;
+                        quantize::quantize(image, colors, method, kmeans)?
+                    }
+                };
+                let indices = palette.dither(image, &dither, metric);
+                Ok::<_, PuhuError>((indices, palette))
+            })
+        })?;
+
+        Ok(PyImage {
+            lazy_image: LazyImage::Loaded(indices),
+            format,
+            info: Some(info),
+            frames: None,
+            palette: Some(palette),
+            extended: None,
+        })
+    }
+
     #[pyo3(signature = (im, box_coords=None, mask=None))]
     fn paste(
         &mut self,
@@ -477,11 +1843,28 @@ impl PyImage {
         enum PasteSource {
             Image(DynamicImage),
             Color((u8, u8, u8, u8)),
+            /// Color values of a deep destination mode
+            Fill([f64; 4]),
         }
 
+        let extended = self.get_extended()?;
+        let dest_mode = Mode::of(self.get_image()?, extended);
         let source = if let Ok(img_ref) = im.downcast::<PyImage>() {
             let mut img = img_ref.borrow_mut();
-            PasteSource::Image(img.get_image()?.clone())
+            // Indices are pasted between palette images as they are; other
+            // images are mapped to the nearest entries of the destination
+            // or converted to its mode
+            let pixels = match (&self.palette, &img.palette) {
+                (Some(_), Some(_)) => img.get_image()?.clone(),
+                (Some(palette), None) => palette.remap(&*img.get_pixels()?, Metric::Rgb),
+                (None, _) => {
+                    let (pixels, mode) = img.get_colors()?;
+                    modes::convert(&pixels, mode, dest_mode)
+                }
+            };
+            PasteSource::Image(pixels)
+        } else if self.palette.is_none() && dest_mode.is_deep() {
+            PasteSource::Fill(modes::parse_fill(Some(im), dest_mode)?)
         } else if let Ok(color) = parse_color(im) {
             PasteSource::Color(color)
         } else {
@@ -494,7 +1877,7 @@ impl PyImage {
         // Step 3: Get source dimensions
         let (src_width, src_height) = match &source {
             PasteSource::Image(img) => (img.width(), img.height()),
-            PasteSource::Color(_) => {
+            PasteSource::Color(_) | PasteSource::Fill(_) => {
                 // Get dimensions from box or mask for color fill
                 if let Some(mask_bound) = actual_mask {
                     let mask_ref = mask_bound.downcast::<PyImage>()?;
@@ -547,20 +1930,18 @@ impl PyImage {
 
         // Step 5: Get and prepare destination image
         let mut dest = self.get_image()?.clone();
-        let dest_mode = color_type_to_mode_string(dest.color());
+
+        // Deep modes are filled by pasting an image of the color
+        let source = match source {
+            PasteSource::Fill(color) => {
+                PasteSource::Image(modes::filled(dest_mode, src_width, src_height, color))
+            }
+            source => source,
+        };
 
         // Step 6: Handle source based on type
         match source {
-            PasteSource::Image(src_img) => {
-                // Mode conversion if needed
-                let src_mode = color_type_to_mode_string(src_img.color());
-                let source_converted = if dest_mode != src_mode {
-                    // Convert source to destination mode
-                    convert_mode(&src_img, &dest_mode)?
-                } else {
-                    src_img
-                };
-
+            PasteSource::Image(source_converted) => {
                 // Paste with or without mask
                 if let Some(mask_bound) = actual_mask {
                     let mask_ref = mask_bound.downcast::<PyImage>()?;
@@ -581,20 +1962,35 @@ impl PyImage {
                         .into());
                     }
 
-                    // Perform masked paste
-                    paste_with_mask(&mut dest, &source_converted, paste_x, paste_y, mask_img)?;
+                    // Perform masked paste; the stored bytes of "I" and "F"
+                    // values cannot be blended, so their values are
+                    if matches!(dest_mode, Mode::I | Mode::F) {
+                        let source = modes::float_image(&source_converted, dest_mode);
+                        let mut values = modes::float_image(&dest, dest_mode);
+                        paste_with_mask(&mut values, &source, paste_x, paste_y, mask_img)?;
+                        dest = modes::from_float_image(&values, dest_mode);
+                    } else {
+                        paste_with_mask(&mut dest, &source_converted, paste_x, paste_y, mask_img)?;
+                    }
+                } else if matches!(dest_mode, Mode::Cmyk | Mode::I | Mode::F) {
+                    // The fourth channel is black ink or a byte of a value
+                    // rather than alpha
+                    imageops::replace(&mut dest, &source_converted, paste_x as i64, paste_y as i64);
                 } else {
-                    // Direct overlay using imageops
-                    image::imageops::overlay(
-                        &mut dest,
-                        &source_converted,
-                        paste_x as i64,
-                        paste_y as i64,
-                    );
+                    overlay(&mut dest, &source_converted, paste_x, paste_y);
                 }
             }
             PasteSource::Color(color) => {
-                if dest_mode == "L"
+                // Palette images are filled with an index; integers are one already
+                let color = match &self.palette {
+                    Some(palette) if im.extract::<u8>().is_err() => {
+                        let index = palette.nearest([color.0, color.1, color.2, color.3]);
+                        (index, index, index, 255)
+                    }
+                    _ => color,
+                };
+                if self.palette.is_none()
+                    && dest_mode == Mode::L
                     && (im.extract::<(u8, u8, u8)>().is_ok()
                         || im.extract::<(u8, u8, u8, u8)>().is_ok())
                 {
@@ -613,6 +2009,7 @@ impl PyImage {
                     color,
                 )?;
             }
+            PasteSource::Fill(_) => unreachable!("Filled above"),
         }
 
         // Update the image
@@ -621,14 +2018,13 @@ impl PyImage {
     }
 
     fn __repr__(&mut self) -> String {
-        match self.get_image() {
-            Ok(img) => {
-                let (width, height) = (img.width(), img.height());
-                let mode = color_type_to_mode_string(img.color());
+        let header = self.get_header();
+        match header.and_then(|header| Ok((header, self.mode_name(&header)?))) {
+            Ok((header, mode)) => {
                 let format = self.format().unwrap_or_else(|| "Unknown".to_string());
                 format!(
                     "<Image size={}x{} mode={} format={}>",
-                    width, height, mode, format
+                    header.width, header.height, mode, format
                 )
             }
             Err(_) => "<Image [Error loading image]>".to_string(),
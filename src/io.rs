@@ -0,0 +1,56 @@
+use crate::errors::PuhuError;
+use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyBytes, PyMemoryView};
+
+/// Write encoded bytes to a Python buffer target.
+///
+/// Supports `bytearray` (bytes are appended) and any object exposing a
+/// `write()` method, such as `io.BytesIO` or an HTTP response stream.
+pub fn write_to_pyobject(target: &Bound<'_, PyAny>, data: &[u8]) -> PyResult<()> {
+    let py = target.py();
+    if let Ok(array) = target.downcast::<PyByteArray>() {
+        array.call_method1("extend", (PyBytes::new(py, data),))?;
+        Ok(())
+    } else if target.hasattr("write")? {
+        target.call_method1("write", (PyBytes::new(py, data),))?;
+        Ok(())
+    } else {
+        Err(PuhuError::InvalidOperation(
+            "Expected file path, bytearray or object with a write() method".to_string(),
+        )
+        .into())
+    }
+}
+
+/// Copy the contents of an in-memory Python buffer.
+///
+/// Handles `bytes`, `bytearray` and `memoryview`; returns `None` for any
+/// other object so callers can fall back to paths or readers.
+pub fn buffer_to_vec(obj: &Bound<'_, PyAny>) -> PyResult<Option<Vec<u8>>> {
+    if let Ok(bytes) = obj.downcast::<PyBytes>() {
+        Ok(Some(bytes.as_bytes().to_vec()))
+    } else if let Ok(array) = obj.downcast::<PyByteArray>() {
+        Ok(Some(array.to_vec()))
+    } else if obj.downcast::<PyMemoryView>().is_ok() {
+        let bytes = obj.call_method0("tobytes")?;
+        Ok(Some(bytes.downcast::<PyBytes>()?.as_bytes().to_vec()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Read from a Python object with a `read()` method.
+///
+/// Reads at most `size` bytes, or everything up to EOF when `size` is `None`.
+pub fn read_from_pyobject(reader: &Bound<'_, PyAny>, size: Option<usize>) -> PyResult<Vec<u8>> {
+    let data = match size {
+        Some(size) => reader.call_method1("read", (size,))?,
+        None => reader.call_method0("read")?,
+    };
+    buffer_to_vec(&data)?.ok_or_else(|| {
+        PuhuError::InvalidOperation(
+            "read() must return bytes; open the file in binary mode".to_string(),
+        )
+        .into()
+    })
+}
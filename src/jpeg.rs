@@ -0,0 +1,640 @@
+use crate::errors::PuhuError;
+use crate::limits::DecodeLimits;
+use crate::modes;
+use image::{DynamicImage, ImageBuffer};
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+/// Index in a block, row by row, of each coefficient in zigzag order
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Bits of the codes looked up at once when decoding Huffman symbols
+const LOOKUP_BITS: u32 = 9;
+
+fn invalid(message: &str) -> PuhuError {
+    PuhuError::InvalidImage(format!("Invalid JPEG data: {}", message))
+}
+
+/// Decode a baseline JPEG at 1/`scale` of its size (2, 4 or 8), as Pillow's
+/// draft mode does with libjpeg. Each block goes through an inverse DCT of
+/// only its lowest frequencies, so the full-size image is never allocated.
+///
+/// Returns None for files this decoder leaves to the full-size decoder:
+/// progressive, lossless, arithmetic-coded and 12-bit files, and files with
+/// other than one or three components.
+pub fn decode_scaled(
+    data: &[u8],
+    scale: u32,
+    limits: DecodeLimits,
+) -> Result<Option<DynamicImage>, PuhuError> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err(invalid("no start of image marker"));
+    }
+    let idct = Idct::new(8 / scale as usize);
+    let mut quant = [[0u16; 64]; 4];
+    let mut dc_tables: [Option<Huffman>; 4] = Default::default();
+    let mut ac_tables: [Option<Huffman>; 4] = Default::default();
+    let mut frame: Option<Frame> = None;
+    let mut restart_interval = 0;
+    let mut adobe_transform = None;
+
+    let mut pos = 2;
+    // Files without an end-of-image marker end after their last scan
+    while let Some(marker) = next_marker(data, &mut pos) {
+        match marker {
+            0x01 | 0xD0..=0xD8 => continue,
+            0xD9 => break,
+            _ => {}
+        }
+        let len = match data.get(pos..pos + 2) {
+            Some(len) => u16::from_be_bytes([len[0], len[1]]) as usize,
+            None => break,
+        };
+        let segment = data
+            .get(pos + 2..pos + len.max(2))
+            .ok_or_else(|| invalid("truncated segment"))?;
+        pos += len.max(2);
+
+        match marker {
+            0xDB => read_quant_tables(segment, &mut quant)?,
+            0xC4 => read_huffman_tables(segment, &mut dc_tables, &mut ac_tables)?,
+            0xC0 | 0xC1 => match Frame::read(segment, scale, limits)? {
+                Some(read) => frame = Some(read),
+                None => return Ok(None),
+            },
+            // Progressive, lossless, hierarchical and arithmetic-coded frames
+            0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Ok(None),
+            0xDD if segment.len() >= 2 => {
+                restart_interval = u16::from_be_bytes([segment[0], segment[1]]) as usize;
+            }
+            0xEE if segment.len() >= 12 && segment.starts_with(b"Adobe") => {
+                adobe_transform = Some(segment[11]);
+            }
+            0xDA => {
+                let frame = frame
+                    .as_mut()
+                    .ok_or_else(|| invalid("scan before the frame header"))?;
+                let scan = Scan {
+                    quant: &quant,
+                    dc_tables: &dc_tables,
+                    ac_tables: &ac_tables,
+                    restart_interval,
+                    idct: &idct,
+                };
+                pos = scan.decode(data, pos, segment, frame)?;
+            }
+            _ => {}
+        }
+    }
+    let frame = frame.ok_or_else(|| invalid("no frame header"))?;
+    Ok(Some(frame.into_image(scale, adobe_transform)))
+}
+
+/// Move past the next marker, skipping any data before it and the fill
+/// bytes that may precede it, and return its code
+fn next_marker(data: &[u8], pos: &mut usize) -> Option<u8> {
+    loop {
+        let offset = data.get(*pos..)?.iter().position(|&b| b == 0xFF)?;
+        *pos += offset + 1;
+        while data.get(*pos) == Some(&0xFF) {
+            *pos += 1;
+        }
+        let marker = *data.get(*pos)?;
+        *pos += 1;
+        // 0xFF00 is a stuffed 0xFF byte in entropy-coded data
+        if marker != 0 {
+            return Some(marker);
+        }
+    }
+}
+
+fn read_quant_tables(mut segment: &[u8], quant: &mut [[u16; 64]; 4]) -> Result<(), PuhuError> {
+    while let Some(&spec) = segment.first() {
+        let (precision, id) = (spec >> 4, (spec & 0x0F) as usize);
+        let len = if precision == 0 { 64 } else { 128 };
+        let values = segment
+            .get(1..1 + len)
+            .ok_or_else(|| invalid("truncated quantization table"))?;
+        let table = quant
+            .get_mut(id)
+            .ok_or_else(|| invalid("quantization table id"))?;
+        for (k, value) in table.iter_mut().enumerate() {
+            *value = match precision {
+                0 => values[k] as u16,
+                _ => u16::from_be_bytes([values[2 * k], values[2 * k + 1]]),
+            };
+        }
+        segment = &segment[1 + len..];
+    }
+    Ok(())
+}
+
+fn read_huffman_tables(
+    mut segment: &[u8],
+    dc_tables: &mut [Option<Huffman>; 4],
+    ac_tables: &mut [Option<Huffman>; 4],
+) -> Result<(), PuhuError> {
+    while segment.len() >= 17 {
+        let (class, id) = (segment[0] >> 4, (segment[0] & 0x0F) as usize);
+        let counts: [u8; 16] = segment[1..17].try_into().expect("16 code counts");
+        let total: usize = counts.iter().map(|&c| c as usize).sum();
+        let values = segment
+            .get(17..17 + total)
+            .ok_or_else(|| invalid("truncated Huffman table"))?;
+        let tables = if class == 0 {
+            &mut *dc_tables
+        } else {
+            &mut *ac_tables
+        };
+        *tables
+            .get_mut(id)
+            .ok_or_else(|| invalid("Huffman table id"))? = Some(Huffman::new(&counts, values));
+        segment = &segment[17 + total..];
+    }
+    Ok(())
+}
+
+/// Huffman table decoded by the procedure of ITU T.81 F.2.2.3, with the
+/// short codes also in a lookup table
+struct Huffman {
+    /// Largest code of each length, or -1 for lengths without codes
+    max_code: [i32; 17],
+    /// Index in `values` of the first code of each length, less that code
+    offset: [i32; 17],
+    values: Vec<u8>,
+    /// Length and value of the code starting each `LOOKUP_BITS` bits, or a
+    /// length of 0 for longer codes
+    lookup: Vec<(u8, u8)>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8; 16], values: &[u8]) -> Self {
+        let mut max_code = [-1; 17];
+        let mut offset = [0; 17];
+        let mut lookup = vec![(0, 0); 1 << LOOKUP_BITS];
+        let (mut code, mut index) = (0i32, 0i32);
+        for len in 1..=16 {
+            let count = counts[len - 1] as i32;
+            offset[len] = index - code;
+            for i in 0..count {
+                if len as u32 <= LOOKUP_BITS {
+                    let shift = LOOKUP_BITS - len as u32;
+                    let first = ((code + i) << shift) as usize;
+                    let entry = (len as u8, values[(index + i) as usize]);
+                    lookup[first..first + (1 << shift)].fill(entry);
+                }
+            }
+            if count > 0 {
+                max_code[len] = code + count - 1;
+            }
+            code = (code + count) << 1;
+            index += count;
+        }
+        Huffman {
+            max_code,
+            offset,
+            values: values.to_vec(),
+            lookup,
+        }
+    }
+
+    fn decode(&self, bits: &mut Bits<'_>) -> Result<u8, PuhuError> {
+        let (len, value) = self.lookup[bits.peek(LOOKUP_BITS) as usize];
+        if len > 0 {
+            bits.consume(len as u32);
+            return Ok(value);
+        }
+        let mut code = bits.peek(LOOKUP_BITS) as i32;
+        bits.consume(LOOKUP_BITS);
+        for len in LOOKUP_BITS as usize + 1..=16 {
+            code = (code << 1) | bits.peek(1) as i32;
+            bits.consume(1);
+            if code <= self.max_code[len] {
+                return self
+                    .values
+                    .get((code + self.offset[len]) as usize)
+                    .copied()
+                    .ok_or_else(|| invalid("Huffman code"));
+            }
+        }
+        Err(invalid("Huffman code"))
+    }
+}
+
+/// Reader of entropy-coded data, which ends at the first marker
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Bits not yet consumed, starting at the most significant bit
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Bits {
+            data,
+            pos,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// Fill the buffer, with zeros once a marker or the end of the data is
+    /// reached
+    fn fill(&mut self) {
+        while self.count <= 56 {
+            let mut byte = 0;
+            if let Some(&next) = self.data.get(self.pos) {
+                if next != 0xFF {
+                    byte = next;
+                    self.pos += 1;
+                } else if self.data.get(self.pos + 1) == Some(&0) {
+                    byte = 0xFF;
+                    self.pos += 2;
+                }
+            }
+            self.buffer |= (byte as u64) << (56 - self.count);
+            self.count += 8;
+        }
+    }
+
+    fn peek(&mut self, count: u32) -> u32 {
+        if self.count < count {
+            self.fill();
+        }
+        (self.buffer >> (64 - count)) as u32
+    }
+
+    fn consume(&mut self, count: u32) {
+        self.buffer <<= count;
+        self.count -= count;
+    }
+
+    /// Read a value of `count` bits, extended to its sign as in ITU T.81
+    /// F.2.2.1
+    fn receive_extend(&mut self, count: u8) -> i32 {
+        if count == 0 {
+            return 0;
+        }
+        let count = count as u32;
+        let value = self.peek(count) as i32;
+        self.consume(count);
+        if value < 1 << (count - 1) {
+            value - (1 << count) + 1
+        } else {
+            value
+        }
+    }
+
+    /// Drop the bits left before a restart marker and move past the marker
+    fn restart(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+        let mut pos = self.pos;
+        if next_marker(self.data, &mut pos).is_some_and(|m| (0xD0..=0xD7).contains(&m)) {
+            self.pos = pos;
+        }
+    }
+}
+
+/// Inverse DCT giving `size` by `size` pixels from the lowest frequencies of
+/// a block, which is the full transform averaged over each `8 / size`
+/// square of pixels
+struct Idct {
+    size: usize,
+    /// Weight of frequency `u` at position `x`, at `x * 8 + u`
+    weights: [f32; 64],
+}
+
+impl Idct {
+    fn new(size: usize) -> Self {
+        let mut weights = [0.0; 64];
+        for x in 0..size {
+            for u in 0..size {
+                let c = if u == 0 { FRAC_1_SQRT_2 } else { 1.0 };
+                let angle = (2 * x + 1) as f32 * u as f32 * PI / (2 * size) as f32;
+                weights[x * 8 + u] = c * angle.cos() / 2.0;
+            }
+        }
+        Idct { size, weights }
+    }
+
+    fn apply(
+        &self,
+        coefficients: &[f32; 64],
+        plane: &mut [u8],
+        stride: usize,
+        x0: usize,
+        y0: usize,
+    ) {
+        let n = self.size;
+        let mut rows = [0.0f32; 64];
+        for v in 0..n {
+            for x in 0..n {
+                rows[v * 8 + x] = (0..n)
+                    .map(|u| coefficients[v * 8 + u] * self.weights[x * 8 + u])
+                    .sum();
+            }
+        }
+        for y in 0..n {
+            let row = &mut plane[(y0 + y) * stride + x0..][..n];
+            for (x, sample) in row.iter_mut().enumerate() {
+                let value: f32 = (0..n)
+                    .map(|v| self.weights[y * 8 + v] * rows[v * 8 + x])
+                    .sum();
+                *sample = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant: usize,
+    /// Reduced samples of every block, padded to whole MCUs
+    plane: Vec<u8>,
+    blocks_w: usize,
+    blocks_h: usize,
+}
+
+struct Frame {
+    width: usize,
+    height: usize,
+    h_max: usize,
+    v_max: usize,
+    /// Pixels per side of each reduced block
+    size: usize,
+    components: Vec<Component>,
+}
+
+impl Frame {
+    /// Read a baseline frame header and allocate the reduced planes, or
+    /// None for frames left to the full-size decoder
+    fn read(segment: &[u8], scale: u32, limits: DecodeLimits) -> Result<Option<Self>, PuhuError> {
+        if segment.len() < 6 {
+            return Err(invalid("truncated frame header"));
+        }
+        let height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+        let width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+        let count = segment[5] as usize;
+        // Heights given by a later DNL marker are not supported either
+        if segment[0] != 8 || !matches!(count, 1 | 3) || height == 0 {
+            return Ok(None);
+        }
+        if width == 0 {
+            return Err(invalid("zero width"));
+        }
+        let specs = segment
+            .get(6..6 + 3 * count)
+            .ok_or_else(|| invalid("truncated frame header"))?;
+        let mut components: Vec<Component> = specs
+            .chunks_exact(3)
+            .map(|spec| {
+                let (h, v) = ((spec[1] >> 4) as usize, (spec[1] & 0x0F) as usize);
+                if !(1..=4).contains(&h) || !(1..=4).contains(&v) || spec[2] > 3 {
+                    return Err(invalid("component parameters"));
+                }
+                Ok(Component {
+                    id: spec[0],
+                    h,
+                    v,
+                    quant: spec[2] as usize,
+                    plane: Vec::new(),
+                    blocks_w: 0,
+                    blocks_h: 0,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        let h_max = components.iter().map(|c| c.h).max().expect("components");
+        let v_max = components.iter().map(|c| c.v).max().expect("components");
+        let size = 8 / scale as usize;
+        let mcus_x = width.div_ceil(8 * h_max);
+        let mcus_y = height.div_ceil(8 * v_max);
+        let planes: usize = components
+            .iter()
+            .map(|c| mcus_x * c.h * mcus_y * c.v * size * size)
+            .sum();
+        let out_width = width.div_ceil(scale as usize);
+        let out_height = height.div_ceil(scale as usize);
+        let output = out_width * out_height * count;
+        let mut image_limits = limits.to_image_limits();
+        image_limits
+            .check_dimensions(out_width as u32, out_height as u32)
+            .and_then(|_| image_limits.reserve((planes + output) as u64))
+            .map_err(PuhuError::ImageError)?;
+
+        for c in &mut components {
+            c.blocks_w = mcus_x * c.h;
+            c.blocks_h = mcus_y * c.v;
+            c.plane = vec![0; c.blocks_w * c.blocks_h * size * size];
+        }
+        Ok(Some(Frame {
+            width,
+            height,
+            h_max,
+            v_max,
+            size,
+            components,
+        }))
+    }
+
+    /// Reduced image, converting YCbCr samples to RGB unless the component
+    /// ids or an Adobe marker say they are RGB already
+    fn into_image(self, scale: u32, adobe_transform: Option<u8>) -> DynamicImage {
+        let width = self.width.div_ceil(scale as usize);
+        let height = self.height.div_ceil(scale as usize);
+        let sample = |c: &Component, x: usize, y: usize| {
+            let stride = c.blocks_w * self.size;
+            c.plane[y * c.v / self.v_max * stride + x * c.h / self.h_max]
+        };
+        let (w, h) = (width as u32, height as u32);
+        if let [gray] = &self.components[..] {
+            let data = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| sample(gray, x, y))
+                .collect();
+            let buf = ImageBuffer::from_raw(w, h, data).expect("buffer sized for the image");
+            return DynamicImage::ImageLuma8(buf);
+        }
+        let ids: Vec<u8> = self.components.iter().map(|c| c.id).collect();
+        let rgb = match adobe_transform {
+            Some(transform) => transform == 0,
+            None => ids == b"RGB",
+        };
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let samples = [0, 1, 2].map(|i| sample(&self.components[i], x, y));
+                if rgb {
+                    samples
+                } else {
+                    modes::ycbcr_to_rgb(samples)
+                }
+            })
+            .collect();
+        let buf = ImageBuffer::from_raw(w, h, data).expect("buffer sized for the image");
+        DynamicImage::ImageRgb8(buf)
+    }
+}
+
+/// Tables in effect for a scan
+struct Scan<'a> {
+    quant: &'a [[u16; 64]; 4],
+    dc_tables: &'a [Option<Huffman>; 4],
+    ac_tables: &'a [Option<Huffman>; 4],
+    restart_interval: usize,
+    idct: &'a Idct,
+}
+
+impl Scan<'_> {
+    /// Decode the entropy-coded data of a scan starting at `pos`, returning
+    /// the position of the marker that ends it
+    fn decode(
+        &self,
+        data: &[u8],
+        pos: usize,
+        header: &[u8],
+        frame: &mut Frame,
+    ) -> Result<usize, PuhuError> {
+        let count = *header.first().ok_or_else(|| invalid("empty scan header"))? as usize;
+        let specs = header
+            .get(1..1 + 2 * count)
+            .ok_or_else(|| invalid("truncated scan header"))?;
+        // Component index, DC and AC table of each component in the scan
+        let mut members = Vec::with_capacity(count);
+        for spec in specs.chunks_exact(2) {
+            let index = frame
+                .components
+                .iter()
+                .position(|c| c.id == spec[0])
+                .ok_or_else(|| invalid("scan component"))?;
+            let dc = huffman_table(self.dc_tables, spec[1] >> 4);
+            let ac = huffman_table(self.ac_tables, spec[1] & 0x0F);
+            match (dc, ac) {
+                (Some(dc), Some(ac)) => members.push((index, dc, ac)),
+                _ => return Err(invalid("Huffman table reference")),
+            }
+        }
+        if members.is_empty() {
+            return Err(invalid("empty scan header"));
+        }
+
+        let mut bits = Bits::new(data, pos);
+        let mut predictions = vec![0i32; members.len()];
+        let mut coefficients = [0.0f32; 64];
+        let size = frame.size;
+        // Blocks of each MCU as (member, block x, block y) offsets
+        let (mcus_x, mcus_y, blocks): (usize, usize, Vec<(usize, usize, usize)>) =
+            if let [(index, _, _)] = members[..] {
+                // A single component is coded block by block, without the
+                // padding of interleaved MCUs
+                let c = &frame.components[index];
+                let width = (frame.width * c.h).div_ceil(frame.h_max);
+                let height = (frame.height * c.v).div_ceil(frame.v_max);
+                (width.div_ceil(8), height.div_ceil(8), vec![(0, 0, 0)])
+            } else {
+                let blocks = members
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(m, &(index, _, _))| {
+                        let c = &frame.components[index];
+                        (0..c.v).flat_map(move |y| (0..c.h).map(move |x| (m, x, y)))
+                    })
+                    .collect();
+                (
+                    frame.width.div_ceil(8 * frame.h_max),
+                    frame.height.div_ceil(8 * frame.v_max),
+                    blocks,
+                )
+            };
+
+        for mcu in 0..mcus_x * mcus_y {
+            if self.restart_interval > 0 && mcu > 0 && mcu % self.restart_interval == 0 {
+                bits.restart();
+                predictions.fill(0);
+            }
+            let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+            for &(m, x, y) in &blocks {
+                let (index, dc, ac) = members[m];
+                let c = &mut frame.components[index];
+                let quant = &self.quant[c.quant];
+                decode_block(
+                    &mut bits,
+                    dc,
+                    ac,
+                    quant,
+                    &mut predictions[m],
+                    &mut coefficients,
+                )?;
+                // Blocks of a single component scan are its own MCUs
+                let (block_x, block_y) = match members.len() {
+                    1 => (mcu_x, mcu_y),
+                    _ => (mcu_x * c.h + x, mcu_y * c.v + y),
+                };
+                let stride = c.blocks_w * size;
+                self.idct.apply(
+                    &coefficients,
+                    &mut c.plane,
+                    stride,
+                    block_x * size,
+                    block_y * size,
+                );
+            }
+        }
+        let mut end = bits.pos;
+        while end < data.len() && !(data[end] == 0xFF && data.get(end + 1) != Some(&0)) {
+            end += 1;
+        }
+        Ok(end)
+    }
+}
+
+fn huffman_table(tables: &[Option<Huffman>; 4], id: u8) -> Option<&Huffman> {
+    tables.get(id as usize).and_then(Option::as_ref)
+}
+
+/// Decode the coefficients of one block, dequantized, into `coefficients`
+/// in row order
+fn decode_block(
+    bits: &mut Bits<'_>,
+    dc: &Huffman,
+    ac: &Huffman,
+    quant: &[u16; 64],
+    prediction: &mut i32,
+    coefficients: &mut [f32; 64],
+) -> Result<(), PuhuError> {
+    coefficients.fill(0.0);
+    let size = dc.decode(bits)?;
+    if size > 11 {
+        return Err(invalid("DC difference"));
+    }
+    *prediction += bits.receive_extend(size);
+    coefficients[0] = *prediction as f32 * quant[0] as f32;
+    let mut k = 1;
+    while k < 64 {
+        let symbol = ac.decode(bits)?;
+        let (run, size) = ((symbol >> 4) as usize, symbol & 0x0F);
+        if size == 0 {
+            // End of block, or a run of 16 zeros
+            if run != 15 {
+                break;
+            }
+            k += 16;
+            continue;
+        }
+        k += run;
+        if k > 63 || size > 10 {
+            return Err(invalid("AC coefficient"));
+        }
+        coefficients[ZIGZAG[k]] = bits.receive_extend(size) as f32 * quant[k] as f32;
+        k += 1;
+    }
+    Ok(())
+}
@@ -1,12 +1,26 @@
 use pyo3::prelude::*;
 use pyo3::types::PyModule;
 
+mod cms;
+mod cmyk;
 mod conversions;
+mod dither;
+mod encoders;
 mod errors;
+mod exif;
 mod formats;
+mod frames;
 mod image;
+mod io;
+mod jpeg;
+mod limits;
+mod metadata;
+mod modes;
+mod nearest;
 mod operations;
 mod palette;
+mod quantize;
+mod transform;
 mod utils;
 
 pub use errors::PuhuError;
@@ -15,21 +29,31 @@ pub use image::PyImage;
 #[pymodule]
 fn _core(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyImage>()?;
+    m.add_class::<exif::PyExif>()?;
+    m.add_class::<cms::PyCmsProfile>()?;
+    m.add_class::<cms::PyCmsTransform>()?;
     m.add(
         "PuhuProcessingError",
-        m.py().get_type_bound::<errors::PuhuProcessingError>(),
+        m.py().get_type::<errors::PuhuProcessingError>(),
     )?;
     m.add(
         "InvalidImageError",
-        m.py().get_type_bound::<errors::InvalidImageError>(),
+        m.py().get_type::<errors::InvalidImageError>(),
     )?;
     m.add(
         "UnsupportedFormatError",
-        m.py().get_type_bound::<errors::UnsupportedFormatError>(),
+        m.py().get_type::<errors::UnsupportedFormatError>(),
     )?;
+    m.add("PuhuIOError", m.py().get_type::<errors::PuhuIOError>())?;
     m.add(
-        "PuhuIOError",
-        m.py().get_type_bound::<errors::PuhuIOError>(),
+        "DecompressionBombError",
+        m.py().get_type::<errors::DecompressionBombError>(),
     )?;
+    m.add(
+        "DecompressionBombWarning",
+        m.py().get_type::<errors::DecompressionBombWarning>(),
+    )?;
+    m.add("PyCMSError", m.py().get_type::<errors::PyCMSError>())?;
+    m.add("MAX_IMAGE_PIXELS", limits::DEFAULT_MAX_IMAGE_PIXELS)?;
     Ok(())
 }
@@ -0,0 +1,71 @@
+use crate::errors::{DecompressionBombWarning, PuhuError};
+use image::Limits;
+use pyo3::prelude::*;
+use std::ffi::CString;
+
+/// Default soft limit on decoded pixels, matching Pillow (about 89.5 megapixels)
+pub const DEFAULT_MAX_IMAGE_PIXELS: u64 = 1024 * 1024 * 1024 / 4 / 3;
+
+/// Limits applied when decoding untrusted image data
+#[derive(Clone, Copy, Debug)]
+pub struct DecodeLimits {
+    /// Images above this pixel count emit a `DecompressionBombWarning`, and
+    /// images above twice this count are rejected. `None` disables the check.
+    pub max_image_pixels: Option<u64>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    /// Maximum bytes the decoder may allocate; `None` keeps the image crate default
+    pub max_alloc: Option<u64>,
+}
+
+impl DecodeLimits {
+    /// Decoder limits for the image crate
+    pub fn to_image_limits(self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_image_width = self.max_width;
+        limits.max_image_height = self.max_height;
+        if self.max_alloc.is_some() {
+            limits.max_alloc = self.max_alloc;
+        }
+        limits
+    }
+
+    /// Decoder limits for the tiff crate, used where TIFF data is decoded
+    /// without the image crate
+    pub fn to_tiff_limits(self) -> tiff::decoder::Limits {
+        let mut limits = tiff::decoder::Limits::default();
+        if let Some(max_alloc) = self.max_alloc {
+            limits.decoding_buffer_size = max_alloc as usize;
+        }
+        limits
+    }
+
+    /// Check the pixel count of an image before decoding it.
+    ///
+    /// Warns above the soft limit and fails above twice the soft limit.
+    pub fn check_pixels(self, width: u32, height: u32) -> Result<(), PuhuError> {
+        let Some(max_pixels) = self.max_image_pixels else {
+            return Ok(());
+        };
+        let pixels = u64::from(width) * u64::from(height);
+
+        if pixels > max_pixels.saturating_mul(2) {
+            return Err(PuhuError::DecompressionBomb(format!(
+                "Image size ({} pixels) exceeds limit of {} pixels, could be decompression bomb DOS attack.",
+                pixels,
+                max_pixels.saturating_mul(2)
+            )));
+        }
+        if pixels > max_pixels {
+            let message = format!(
+                "Image size ({} pixels) exceeds limit of {} pixels, could be decompression bomb DOS attack.",
+                pixels, max_pixels
+            );
+            let message = CString::new(message).expect("message has no NUL bytes");
+            Python::with_gil(|py| {
+                PyErr::warn(py, &py.get_type::<DecompressionBombWarning>(), &message, 1)
+            })?;
+        }
+        Ok(())
+    }
+}
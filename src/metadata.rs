@@ -0,0 +1,587 @@
+use crate::exif::{self, Exif, ExifValue, EXIF_HEADER};
+use crate::modes::{ExtendedMode, Mode};
+use image::ImageFormat;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Upper bound for decompressed PNG text chunks, guarding against zlib bombs
+const MAX_TEXT_LEN: usize = 2 * 1024 * 1024;
+
+/// Upper bound for decompressed PNG iCCP profiles
+const MAX_ICC_PROFILE_LEN: usize = 16 * 1024 * 1024;
+
+/// Signature starting each ICC profile chunk in a JPEG APP2 segment
+const ICC_JPEG_HEADER: &[u8] = b"ICC_PROFILE\0";
+
+/// TIFF tag holding an embedded ICC profile
+const TIFF_ICC_PROFILE: u16 = 34675;
+
+/// TIFF tags and the values marking a page of CMYK samples
+const TIFF_PHOTOMETRIC: u16 = 262;
+const TIFF_PHOTOMETRIC_SEPARATED: u32 = 5;
+const TIFF_SAMPLES_PER_PIXEL: u16 = 277;
+
+/// TIFF tags and the values marking a page of 32-bit signed integer or
+/// float samples
+const TIFF_BITS_PER_SAMPLE: u16 = 258;
+const TIFF_SAMPLE_FORMAT: u16 = 339;
+const TIFF_SAMPLE_FORMAT_INT: u32 = 2;
+const TIFF_SAMPLE_FORMAT_FLOAT: u32 = 3;
+
+/// Transparent color stored in a PNG tRNS chunk
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transparency {
+    /// Gray level of transparent pixels
+    Gray(u16),
+    /// RGB color of transparent pixels
+    Rgb(u16, u16, u16),
+    /// Alpha value of each palette entry
+    Palette(Vec<u8>),
+}
+
+/// Fields of a JPEG JFIF (APP0) header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Jfif {
+    /// Version as major * 256 + minor
+    pub version: u16,
+    /// 0 for aspect ratio only, 1 for dots per inch, 2 for dots per cm
+    pub unit: u8,
+    pub density: (u16, u16),
+}
+
+/// How the samples of a CMYK JPEG or TIFF file are stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmykStorage {
+    /// Amounts of ink, as in TIFF files and JPEG files without an Adobe marker
+    Plain,
+    /// Inverted amounts of ink, as written by Adobe applications
+    Inverted,
+    /// Inverted amounts of ink with cyan, magenta and yellow stored as
+    /// YCbCr (Adobe color transform 2)
+    Ycck,
+}
+
+/// Timing of one frame of an animated or multi-page image
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameInfo {
+    /// Display time in milliseconds
+    pub duration: Option<u32>,
+    /// How the frame is cleared before the next one is drawn: the GIF
+    /// disposal method (0-3), APNG dispose_op (0-2) or WebP dispose flag (0-1)
+    pub disposal: Option<u8>,
+}
+
+/// Metadata exposed through `Image.info`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImageInfo {
+    /// Resolution in dots per inch as (x, y)
+    pub dpi: Option<(f64, f64)>,
+    /// PNG gAMA value
+    pub gamma: Option<f64>,
+    pub transparency: Option<Transparency>,
+    /// PNG tEXt, zTXt and iTXt chunks in file order
+    pub text: Vec<(String, String)>,
+    pub jfif: Option<Jfif>,
+    /// JPEG COM segment or GIF comment extension
+    pub comment: Option<Vec<u8>>,
+    /// Display time of the current frame in milliseconds
+    pub duration: Option<u32>,
+    /// Animation loop count, 0 meaning forever
+    pub loop_count: Option<u16>,
+    /// GIF background color index
+    pub background: Option<u8>,
+    /// EXIF block as TIFF-structured data, without the `Exif\0\0` prefix
+    pub exif: Option<Vec<u8>>,
+    /// Embedded ICC colour profile
+    pub icc_profile: Option<Vec<u8>>,
+    /// Every frame or page in the file; not part of the `info` dictionary
+    pub frames: Vec<FrameInfo>,
+    /// Storage of a CMYK image's samples; not part of the `info` dictionary
+    pub cmyk: Option<CmykStorage>,
+    /// Mode of a TIFF image of 32-bit integer or float values, which the
+    /// image crate cannot decode; not part of the `info` dictionary
+    pub tiff_values: Option<ExtendedMode>,
+}
+
+impl ImageInfo {
+    /// Read metadata from an encoded image.
+    ///
+    /// This is best effort: malformed or truncated metadata is skipped, and
+    /// decoding errors are left for the pixel decoder to report.
+    pub fn read<R: Read + Seek>(mut reader: R, format: Option<ImageFormat>) -> Self {
+        let mut info = ImageInfo::default();
+        let _ = match format {
+            Some(ImageFormat::Png) => read_png(&mut reader, &mut info),
+            Some(ImageFormat::Jpeg) => read_jpeg(&mut reader, &mut info),
+            Some(ImageFormat::Gif) => read_gif(&mut reader, &mut info),
+            Some(ImageFormat::WebP) => read_webp(&mut reader, &mut info),
+            Some(ImageFormat::Tiff) => read_tiff(&mut reader, &mut info),
+            _ => Ok(()),
+        };
+        if info.duration.is_none() {
+            info.duration = info.frames.first().and_then(|frame| frame.duration);
+        }
+        info
+    }
+
+    /// Build the Pillow-style `info` dictionary
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (key, value) in &self.text {
+            dict.set_item(key, value)?;
+        }
+        if let Some(dpi) = self.dpi {
+            dict.set_item("dpi", dpi)?;
+        }
+        if let Some(gamma) = self.gamma {
+            dict.set_item("gamma", gamma)?;
+        }
+        match &self.transparency {
+            Some(Transparency::Gray(value)) => dict.set_item("transparency", value)?,
+            Some(Transparency::Rgb(r, g, b)) => dict.set_item("transparency", (r, g, b))?,
+            Some(Transparency::Palette(alpha)) => {
+                dict.set_item("transparency", PyBytes::new(py, alpha))?
+            }
+            None => {}
+        }
+        if let Some(jfif) = self.jfif {
+            dict.set_item("jfif", jfif.version)?;
+            dict.set_item("jfif_version", (jfif.version >> 8, jfif.version & 0xFF))?;
+            dict.set_item("jfif_unit", jfif.unit)?;
+            dict.set_item("jfif_density", jfif.density)?;
+        }
+        if let Some(comment) = &self.comment {
+            dict.set_item("comment", PyBytes::new(py, comment))?;
+        }
+        if let Some(duration) = self.duration {
+            dict.set_item("duration", duration)?;
+        }
+        if let Some(loop_count) = self.loop_count {
+            dict.set_item("loop", loop_count)?;
+        }
+        if let Some(background) = self.background {
+            dict.set_item("background", background)?;
+        }
+        if let Some(exif) = &self.exif {
+            dict.set_item("exif", PyBytes::new(py, &[EXIF_HEADER, exif].concat()))?;
+        }
+        if let Some(profile) = &self.icc_profile {
+            dict.set_item("icc_profile", PyBytes::new(py, profile))?;
+        }
+        Ok(dict)
+    }
+
+    /// Drop the ICC profile if its colour space does not match an image of
+    /// the given mode, e.g. an RGB profile after converting to grayscale
+    pub fn drop_mismatched_icc_profile(&mut self, mode: Mode) {
+        // The data colour space signature is at offset 16 of the profile header
+        let color_space = self.icc_profile.as_ref().and_then(|p| p.get(16..20));
+        if color_space.is_some_and(|space| space != mode.icc_color_space()) {
+            self.icc_profile = None;
+        }
+    }
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+/// Read `len` bytes, growing the buffer as data arrives so that a corrupt
+/// length cannot allocate more than the file holds
+fn read_vec<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+/// Latin-1 decoding used by tEXt and zTXt chunks
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// Split a PNG text chunk into its keyword and the data after the separator
+fn split_keyword(data: &[u8]) -> Option<(String, &[u8])> {
+    let nul = data.iter().position(|&b| b == 0)?;
+    Some((latin1(&data[..nul]), &data[nul + 1..]))
+}
+
+/// Strip the `Exif\0\0` prefix some writers add to PNG and WebP EXIF chunks
+fn exif_payload(data: &[u8]) -> Vec<u8> {
+    data.strip_prefix(EXIF_HEADER).unwrap_or(data).to_vec()
+}
+
+fn inflate(data: &[u8], limit: usize) -> Option<Vec<u8>> {
+    fdeflate::decompress_to_vec_bounded(data, limit).ok()
+}
+
+fn inflate_text(data: &[u8]) -> Option<Vec<u8>> {
+    inflate(data, MAX_TEXT_LEN)
+}
+
+fn parse_itxt(data: &[u8]) -> Option<(String, String)> {
+    let (keyword, rest) = split_keyword(data)?;
+    let (&compressed, rest) = rest.split_first()?;
+    // Skip the compression method, language tag and translated keyword
+    let rest = rest.get(1..)?;
+    let language_end = rest.iter().position(|&b| b == 0)?;
+    let rest = &rest[language_end + 1..];
+    let translated_end = rest.iter().position(|&b| b == 0)?;
+    let text = &rest[translated_end + 1..];
+
+    let text = if compressed != 0 {
+        String::from_utf8(inflate_text(text)?).ok()?
+    } else {
+        String::from_utf8(text.to_vec()).ok()?
+    };
+    Some((keyword, text))
+}
+
+/// Walk the PNG chunk list, skipping over image data
+fn read_png<R: Read + Seek>(reader: &mut R, info: &mut ImageInfo) -> io::Result<()> {
+    let signature = read_vec(reader, 8)?;
+    if signature != b"\x89PNG\r\n\x1a\n" {
+        return Ok(());
+    }
+
+    let mut color_type = 0u8;
+    let mut animated = false;
+    loop {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let chunk_type = &header[4..8];
+
+        match chunk_type {
+            b"IEND" => {
+                // Frame controls only describe an animation if there is an acTL chunk
+                if !animated {
+                    info.frames.clear();
+                }
+                return Ok(());
+            }
+            b"IHDR" | b"gAMA" | b"pHYs" | b"tRNS" | b"tEXt" | b"zTXt" | b"iTXt" | b"acTL"
+            | b"fcTL" | b"eXIf" | b"iCCP" => {}
+            _ => {
+                // Skip image data and unknown chunks with their CRC without
+                // reading them
+                reader.seek(SeekFrom::Current(len as i64 + 4))?;
+                continue;
+            }
+        }
+
+        let data = read_vec(reader, len)?;
+        reader.seek(SeekFrom::Current(4))?;
+
+        match chunk_type {
+            b"IHDR" if data.len() >= 10 => color_type = data[9],
+            b"gAMA" if data.len() >= 4 => {
+                let gamma = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                info.gamma = Some(gamma as f64 / 100000.0);
+            }
+            // Only metre-based pHYs chunks carry a physical resolution
+            b"pHYs" if data.len() >= 9 && data[8] == 1 => {
+                let x = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                let y = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+                info.dpi = Some((x as f64 * 0.0254, y as f64 * 0.0254));
+            }
+            b"tRNS" => {
+                let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+                info.transparency = match color_type {
+                    0 if data.len() >= 2 => Some(Transparency::Gray(sample(0))),
+                    2 if data.len() >= 6 => {
+                        Some(Transparency::Rgb(sample(0), sample(2), sample(4)))
+                    }
+                    3 => Some(Transparency::Palette(data)),
+                    _ => None,
+                };
+            }
+            b"tEXt" => {
+                if let Some((keyword, text)) = split_keyword(&data) {
+                    info.text.push((keyword, latin1(text)));
+                }
+            }
+            b"zTXt" => {
+                // A compression method byte precedes the zlib stream
+                let text = split_keyword(&data)
+                    .and_then(|(keyword, rest)| Some((keyword, inflate_text(rest.get(1..)?)?)));
+                if let Some((keyword, text)) = text {
+                    info.text.push((keyword, latin1(&text)));
+                }
+            }
+            b"iTXt" => {
+                if let Some(entry) = parse_itxt(&data) {
+                    info.text.push(entry);
+                }
+            }
+            b"acTL" if data.len() >= 8 => {
+                animated = true;
+                let plays = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+                info.loop_count = Some(u16::try_from(plays).unwrap_or(u16::MAX));
+            }
+            b"fcTL" if data.len() >= 26 => {
+                let delay = u16::from_be_bytes([data[20], data[21]]) as u32;
+                // A zero denominator means hundredths of a second
+                let scale = match u16::from_be_bytes([data[22], data[23]]) {
+                    0 => 100,
+                    scale => scale as u32,
+                };
+                info.frames.push(FrameInfo {
+                    duration: Some(delay * 1000 / scale),
+                    disposal: Some(data[24]),
+                });
+            }
+            b"eXIf" => info.exif = Some(exif_payload(&data)),
+            b"iCCP" => {
+                // The profile name is followed by a compression method byte
+                info.icc_profile = split_keyword(&data)
+                    .and_then(|(_, rest)| inflate(rest.get(1..)?, MAX_ICC_PROFILE_LEN));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Walk the JPEG marker segments up to the start of the scan data
+fn read_jpeg<R: Read>(reader: &mut R, info: &mut ImageInfo) -> io::Result<()> {
+    let soi = read_vec(reader, 2)?;
+    if soi != [0xFF, 0xD8] {
+        return Ok(());
+    }
+    // Profiles larger than a segment are split into numbered APP2 chunks
+    let mut icc_chunks: Vec<(u8, Vec<u8>)> = Vec::new();
+    // Color transform of an Adobe APP14 segment, which also marks the
+    // samples of four-component images as inverted
+    let mut adobe_transform = None;
+
+    loop {
+        if read_u8(reader)? != 0xFF {
+            return Ok(());
+        }
+        let mut marker = read_u8(reader)?;
+        // Markers may be preceded by any number of fill bytes
+        while marker == 0xFF {
+            marker = read_u8(reader)?;
+        }
+
+        match marker {
+            // Standalone markers without a length
+            0x01 | 0xD0..=0xD8 => continue,
+            // Start of scan or end of image; no more headers follow
+            0xDA | 0xD9 => break,
+            _ => {}
+        }
+
+        let mut len = [0u8; 2];
+        reader.read_exact(&mut len)?;
+        let len = u16::from_be_bytes(len) as usize;
+        if len < 2 {
+            return Ok(());
+        }
+        let data = read_vec(reader, len - 2)?;
+
+        match marker {
+            0xE0 if data.len() >= 12 && data.starts_with(b"JFIF\0") => {
+                let jfif = Jfif {
+                    version: u16::from_be_bytes([data[5], data[6]]),
+                    unit: data[7],
+                    density: (
+                        u16::from_be_bytes([data[8], data[9]]),
+                        u16::from_be_bytes([data[10], data[11]]),
+                    ),
+                };
+                let (x, y) = (jfif.density.0 as f64, jfif.density.1 as f64);
+                match jfif.unit {
+                    1 => info.dpi = Some((x, y)),
+                    2 => info.dpi = Some((x * 2.54, y * 2.54)),
+                    _ => {}
+                }
+                info.jfif = Some(jfif);
+            }
+            0xE1 if info.exif.is_none() && data.starts_with(EXIF_HEADER) => {
+                info.exif = Some(data[EXIF_HEADER.len()..].to_vec());
+            }
+            0xE2 if data.len() > ICC_JPEG_HEADER.len() + 2 && data.starts_with(ICC_JPEG_HEADER) => {
+                let sequence = data[ICC_JPEG_HEADER.len()];
+                icc_chunks.push((sequence, data[ICC_JPEG_HEADER.len() + 2..].to_vec()));
+                icc_chunks.sort_by_key(|(sequence, _)| *sequence);
+                info.icc_profile = Some(icc_chunks.iter().flat_map(|(_, c)| c).copied().collect());
+            }
+            0xEE if data.len() >= 12 && data.starts_with(b"Adobe") => {
+                adobe_transform = Some(data[11]);
+            }
+            // Start of frame with four components; DHT, JPG and DAC share the range
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) && data.get(5) == Some(&4) => {
+                info.cmyk = Some(CmykStorage::Plain);
+            }
+            0xFE => info.comment = Some(data),
+            _ => {}
+        }
+    }
+    if info.cmyk.is_some() {
+        info.cmyk = match adobe_transform {
+            Some(2) => Some(CmykStorage::Ycck),
+            Some(_) => Some(CmykStorage::Inverted),
+            None => Some(CmykStorage::Plain),
+        };
+    }
+    Ok(())
+}
+
+/// Read GIF data sub-blocks up to the block terminator
+fn read_sub_blocks<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    loop {
+        let len = read_u8(reader)? as usize;
+        if len == 0 {
+            return Ok(data);
+        }
+        data.extend(read_vec(reader, len)?);
+    }
+}
+
+/// Skip GIF data sub-blocks up to the block terminator
+fn skip_sub_blocks<R: Read + Seek>(reader: &mut R) -> io::Result<()> {
+    loop {
+        let len = read_u8(reader)?;
+        if len == 0 {
+            return Ok(());
+        }
+        reader.seek(SeekFrom::Current(len as i64))?;
+    }
+}
+
+/// Walk the GIF blocks, reading metadata up to the first frame and the
+/// timing of every frame
+fn read_gif<R: Read + Seek>(reader: &mut R, info: &mut ImageInfo) -> io::Result<()> {
+    let header = read_vec(reader, 13)?;
+    if !header.starts_with(b"GIF") {
+        return Ok(());
+    }
+    let flags = header[10];
+    info.background = Some(header[11]);
+    if flags & 0x80 != 0 {
+        // Skip the global color table
+        let table_len = 3 * (1i64 << ((flags & 0x07) + 1));
+        reader.seek(SeekFrom::Current(table_len))?;
+    }
+
+    // Graphic control extension applying to the next frame
+    let mut control = FrameInfo::default();
+    loop {
+        match read_u8(reader)? {
+            0x21 => {
+                let label = read_u8(reader)?;
+                let data = read_sub_blocks(reader)?;
+                match label {
+                    // The delay is in hundredths of a second
+                    0xF9 if data.len() >= 3 => {
+                        let delay = u16::from_le_bytes([data[1], data[2]]);
+                        control = FrameInfo {
+                            duration: Some(delay as u32 * 10),
+                            disposal: Some((data[0] >> 2) & 0x07),
+                        };
+                    }
+                    0xFE if info.frames.is_empty() => info.comment = Some(data),
+                    0xFF if data.len() >= 14
+                        && data.starts_with(b"NETSCAPE2.0")
+                        && data[11] == 1 =>
+                    {
+                        info.loop_count = Some(u16::from_le_bytes([data[12], data[13]]));
+                    }
+                    _ => {}
+                }
+            }
+            0x2C => {
+                info.frames.push(std::mem::take(&mut control));
+                let descriptor = read_vec(reader, 9)?;
+                if descriptor[8] & 0x80 != 0 {
+                    // Skip the local color table
+                    let table_len = 3 * (1i64 << ((descriptor[8] & 0x07) + 1));
+                    reader.seek(SeekFrom::Current(table_len))?;
+                }
+                // LZW minimum code size, then the image data
+                read_u8(reader)?;
+                skip_sub_blocks(reader)?;
+            }
+            // Trailer
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Walk the RIFF chunks of a WebP file
+fn read_webp<R: Read + Seek>(reader: &mut R, info: &mut ImageInfo) -> io::Result<()> {
+    let header = read_vec(reader, 12)?;
+    if !header.starts_with(b"RIFF") || &header[8..12] != b"WEBP" {
+        return Ok(());
+    }
+
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk)?;
+        let len = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+        // Chunks are padded to an even length
+        let padding = len as i64 % 2;
+        match &chunk[..4] {
+            b"EXIF" => info.exif = Some(exif_payload(&read_vec(reader, len)?)),
+            b"ICCP" => info.icc_profile = Some(read_vec(reader, len)?),
+            // Background color, then the loop count
+            b"ANIM" if len >= 6 => {
+                let data = read_vec(reader, 6)?;
+                info.loop_count = Some(u16::from_le_bytes([data[4], data[5]]));
+                reader.seek(SeekFrom::Current(len as i64 - 6))?;
+            }
+            b"ANMF" if len >= 16 => {
+                // Frame position and size, then a 24-bit duration and the flags
+                let data = read_vec(reader, 16)?;
+                info.frames.push(FrameInfo {
+                    duration: Some(u32::from_le_bytes([data[12], data[13], data[14], 0])),
+                    disposal: Some(data[15] & 0x01),
+                });
+                reader.seek(SeekFrom::Current(len as i64 - 16))?;
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(len as i64 + padding))?;
+                continue;
+            }
+        }
+        reader.seek(SeekFrom::Current(padding))?;
+    }
+}
+
+/// Read the tags of the first TIFF directory as EXIF, its ICC profile, and
+/// the number of pages, seeking to each directory
+fn read_tiff<R: Read + Seek>(reader: &mut R, info: &mut ImageInfo) -> io::Result<()> {
+    info.frames = vec![FrameInfo::default(); exif::tiff_page_offsets(&mut *reader).len()];
+    let Ok(mut exif) = Exif::from_tiff_file(reader) else {
+        return Ok(());
+    };
+    let value = |tag| exif.get(tag).and_then(ExifValue::as_u32);
+    if value(TIFF_PHOTOMETRIC) == Some(TIFF_PHOTOMETRIC_SEPARATED)
+        && value(TIFF_SAMPLES_PER_PIXEL) == Some(4)
+    {
+        info.cmyk = Some(CmykStorage::Plain);
+    }
+    if value(TIFF_BITS_PER_SAMPLE) == Some(32) && value(TIFF_SAMPLES_PER_PIXEL).unwrap_or(1) == 1 {
+        info.tiff_values = match value(TIFF_SAMPLE_FORMAT) {
+            Some(TIFF_SAMPLE_FORMAT_INT) => Some(ExtendedMode::Int32),
+            Some(TIFF_SAMPLE_FORMAT_FLOAT) => Some(ExtendedMode::Float32),
+            _ => None,
+        };
+    }
+    exif.remove_tiff_layout();
+    info.icc_profile = match exif.remove(TIFF_ICC_PROFILE) {
+        Some(ExifValue::Undefined(profile) | ExifValue::Byte(profile)) => Some(profile),
+        _ => None,
+    };
+    if !exif.is_empty() {
+        info.exif = Some(exif.to_bytes());
+    }
+    Ok(())
+}
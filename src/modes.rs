@@ -0,0 +1,515 @@
+use crate::errors::PuhuError;
+use crate::utils::parse_color;
+use image::{DynamicImage, ImageBuffer, Luma, LumaA, Rgb, Rgba};
+use pyo3::prelude::*;
+use rayon::prelude::*;
+
+/// Mode of an image whose pixels are not colors in the range of their color
+/// type, stored alongside the pixels like the palette of a "P" image.
+///
+/// "I" and "F" images are stored as RGBA8 buffers holding the native-endian
+/// bytes of one i32 or f32 value per pixel, so copying, cropping and
+/// flipping keep their values exactly; operations that sample or average
+/// pixels go through floats with `resample`. "CMYK" images are stored as
+/// RGBA8 buffers of C, M, Y and K ink amounts, and "YCbCr" images as RGB8
+/// buffers of Y, Cb and Cr.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtendedMode {
+    /// 32-bit signed integer grayscale ("I")
+    Int32,
+    /// 32-bit float grayscale ("F")
+    Float32,
+    /// 8-bit cyan, magenta, yellow and black ("CMYK")
+    Cmyk,
+    /// 8-bit JPEG YCbCr ("YCbCr")
+    YCbCr,
+}
+
+/// Mode of an image whose pixels are colors or gray values, as opposed to
+/// bilevel ("1") and palette ("P") images
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    L,
+    La,
+    Rgb,
+    Rgba,
+    L16,
+    La16,
+    Rgb16,
+    Rgba16,
+    Rgb32F,
+    Rgba32F,
+    I,
+    F,
+    Cmyk,
+    YCbCr,
+}
+
+impl Mode {
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "L" => Mode::L,
+            "LA" => Mode::La,
+            "RGB" => Mode::Rgb,
+            "RGBA" => Mode::Rgba,
+            "I;16" => Mode::L16,
+            "LA;16" => Mode::La16,
+            "RGB;16" => Mode::Rgb16,
+            "RGBA;16" => Mode::Rgba16,
+            "RGB;F" => Mode::Rgb32F,
+            "RGBA;F" => Mode::Rgba32F,
+            "I" => Mode::I,
+            "F" => Mode::F,
+            "CMYK" => Mode::Cmyk,
+            "YCbCr" => Mode::YCbCr,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::L => "L",
+            Mode::La => "LA",
+            Mode::Rgb => "RGB",
+            Mode::Rgba => "RGBA",
+            Mode::L16 => "I;16",
+            Mode::La16 => "LA;16",
+            Mode::Rgb16 => "RGB;16",
+            Mode::Rgba16 => "RGBA;16",
+            Mode::Rgb32F => "RGB;F",
+            Mode::Rgba32F => "RGBA;F",
+            Mode::I => "I",
+            Mode::F => "F",
+            Mode::Cmyk => "CMYK",
+            Mode::YCbCr => "YCbCr",
+        }
+    }
+
+    /// Mode of an image, given the extended mode stored with it
+    pub fn of(image: &DynamicImage, extended: Option<ExtendedMode>) -> Self {
+        if let Some(mode) = extended {
+            return mode.into();
+        }
+        match image {
+            DynamicImage::ImageLuma8(_) => Mode::L,
+            DynamicImage::ImageLumaA8(_) => Mode::La,
+            DynamicImage::ImageRgb8(_) => Mode::Rgb,
+            DynamicImage::ImageLuma16(_) => Mode::L16,
+            DynamicImage::ImageLumaA16(_) => Mode::La16,
+            DynamicImage::ImageRgb16(_) => Mode::Rgb16,
+            DynamicImage::ImageRgba16(_) => Mode::Rgba16,
+            DynamicImage::ImageRgb32F(_) => Mode::Rgb32F,
+            DynamicImage::ImageRgba32F(_) => Mode::Rgba32F,
+            _ => Mode::Rgba,
+        }
+    }
+
+    /// Extended mode to store with images of this mode
+    pub fn extended(self) -> Option<ExtendedMode> {
+        match self {
+            Mode::I => Some(ExtendedMode::Int32),
+            Mode::F => Some(ExtendedMode::Float32),
+            Mode::Cmyk => Some(ExtendedMode::Cmyk),
+            Mode::YCbCr => Some(ExtendedMode::YCbCr),
+            _ => None,
+        }
+    }
+
+    /// Whether samples are wider than 8 bits or are not colors
+    pub fn is_deep(self) -> bool {
+        !matches!(self, Mode::L | Mode::La | Mode::Rgb | Mode::Rgba)
+    }
+
+    pub fn has_color(self) -> bool {
+        !matches!(
+            self,
+            Mode::L | Mode::La | Mode::L16 | Mode::La16 | Mode::I | Mode::F
+        )
+    }
+
+    /// Whether the last channel is alpha; the black ink of "CMYK" is not
+    pub fn has_alpha(self) -> bool {
+        matches!(
+            self,
+            Mode::La | Mode::Rgba | Mode::La16 | Mode::Rgba16 | Mode::Rgba32F
+        )
+    }
+
+    /// Data colour space signature of ICC profiles for images of this mode
+    pub fn icc_color_space(self) -> &'static [u8; 4] {
+        match self {
+            Mode::Cmyk => b"CMYK",
+            _ if self.has_color() => b"RGB ",
+            _ => b"GRAY",
+        }
+    }
+
+    /// Value of white in this mode; "I" and "F" use the 8-bit range
+    fn max(self) -> f64 {
+        match self {
+            Mode::L16 | Mode::La16 | Mode::Rgb16 | Mode::Rgba16 => 65535.0,
+            Mode::Rgb32F | Mode::Rgba32F => 1.0,
+            _ => 255.0,
+        }
+    }
+}
+
+impl From<ExtendedMode> for Mode {
+    fn from(mode: ExtendedMode) -> Self {
+        match mode {
+            ExtendedMode::Int32 => Mode::I,
+            ExtendedMode::Float32 => Mode::F,
+            ExtendedMode::Cmyk => Mode::Cmyk,
+            ExtendedMode::YCbCr => Mode::YCbCr,
+        }
+    }
+}
+
+/// Pixel values of an "I" or "F" image, in row-major order. f64 holds the
+/// values of both modes exactly.
+pub fn values(image: &DynamicImage, mode: Mode) -> Vec<f64> {
+    let bytes = image.as_bytes().chunks_exact(4);
+    match mode {
+        Mode::I => bytes
+            .map(|b| i32::from_ne_bytes(b.try_into().expect("4 bytes")) as f64)
+            .collect(),
+        _ => bytes
+            .map(|b| f32::from_ne_bytes(b.try_into().expect("4 bytes")) as f64)
+            .collect(),
+    }
+}
+
+/// Stored bytes of a value of an "I" or "F" pixel, rounding and clamping
+/// it to an integer for "I"
+fn value_bytes(value: f64, mode: Mode) -> [u8; 4] {
+    match mode {
+        Mode::I => (value.round().clamp(i32::MIN as f64, i32::MAX as f64) as i32).to_ne_bytes(),
+        _ => (value as f32).to_ne_bytes(),
+    }
+}
+
+/// "I" or "F" image of the given values
+fn from_values(width: u32, height: u32, values: &[f64], mode: Mode) -> DynamicImage {
+    let data = values.iter().flat_map(|&v| value_bytes(v, mode)).collect();
+    let buf = ImageBuffer::from_raw(width, height, data).expect("buffer sized for the image");
+    DynamicImage::ImageRgba8(buf)
+}
+
+/// RGB32F image with the value of each pixel of an "I" or "F" image in
+/// every channel
+pub fn float_image(image: &DynamicImage, mode: Mode) -> DynamicImage {
+    scaled_float_image(
+        &values(image, mode),
+        image.width(),
+        image.height(),
+        0.0,
+        1.0,
+    )
+}
+
+/// "I" or "F" image of the first channel of a float image
+pub fn from_float_image(image: &DynamicImage, mode: Mode) -> DynamicImage {
+    from_scaled_float_image(image, mode, 0.0, 1.0)
+}
+
+/// RGB32F image of values mapped from `low..=low + scale` to `0.0..=1.0`
+fn scaled_float_image(
+    values: &[f64],
+    width: u32,
+    height: u32,
+    low: f64,
+    scale: f64,
+) -> DynamicImage {
+    let data = values
+        .iter()
+        .flat_map(|&v| [((v - low) / scale) as f32; 3])
+        .collect();
+    let buf = ImageBuffer::from_raw(width, height, data).expect("buffer sized for the image");
+    DynamicImage::ImageRgb32F(buf)
+}
+
+/// "I" or "F" image of the first channel of a float image, mapped back from
+/// `0.0..=1.0` to `low..=low + scale`
+fn from_scaled_float_image(image: &DynamicImage, mode: Mode, low: f64, scale: f64) -> DynamicImage {
+    let values: Vec<f64> = image
+        .to_rgb32f()
+        .pixels()
+        .map(|p| p[0] as f64 * scale + low)
+        .collect();
+    from_values(image.width(), image.height(), &values, mode)
+}
+
+/// Run an operation that samples or averages pixels. The stored bytes of
+/// "I" and "F" images cannot be interpolated, so those images are given
+/// to it as a float image of their values, and "I" results are rounded.
+/// The image crate's filters clamp float samples to `0.0..=1.0`, so values
+/// outside it are scaled into it and back; the operation must be linear.
+pub fn resample(
+    image: &DynamicImage,
+    mode: Mode,
+    operation: impl FnOnce(&DynamicImage) -> DynamicImage,
+) -> DynamicImage {
+    if !matches!(mode, Mode::I | Mode::F) {
+        return operation(image);
+    }
+    let values = values(image, mode);
+    let (low, high) = values.iter().fold((0.0f64, 1.0f64), |(low, high), &v| {
+        (low.min(v), high.max(v))
+    });
+    let scale = high - low;
+    let floats = scaled_float_image(&values, image.width(), image.height(), low, scale);
+    from_scaled_float_image(&operation(&floats), mode, low, scale)
+}
+
+/// Gray level of each pixel in the range of the image's color type, or the
+/// value of "I" and "F" pixels
+fn gray_values(image: &DynamicImage, mode: Mode) -> Vec<f64> {
+    match mode {
+        Mode::I | Mode::F => values(image, mode),
+        Mode::L | Mode::La | Mode::Rgb | Mode::Rgba => {
+            image.to_luma8().iter().map(|&v| v as f64).collect()
+        }
+        Mode::L16 | Mode::La16 | Mode::Rgb16 | Mode::Rgba16 => {
+            image.to_luma16().iter().map(|&v| v as f64).collect()
+        }
+        Mode::Rgb32F | Mode::Rgba32F => image.to_luma32f().iter().map(|&v| v as f64).collect(),
+        Mode::Cmyk | Mode::YCbCr => unreachable!("Converted through RGB first"),
+    }
+}
+
+/// Grayscale image of "I" or "F" values in a color type of the target mode's
+/// depth, clamping integers and scaling 8-bit values to floats
+fn values_to_gray(image: &DynamicImage, from: Mode, to: Mode) -> DynamicImage {
+    let (width, height) = (image.width(), image.height());
+    let values = values(image, from);
+    match to {
+        Mode::L16 | Mode::La16 | Mode::Rgb16 | Mode::Rgba16 => {
+            let data = values.iter().map(|&v| v.round().clamp(0.0, 65535.0) as u16);
+            let buf = ImageBuffer::from_raw(width, height, data.collect());
+            DynamicImage::ImageLuma16(buf.expect("buffer sized for the image"))
+        }
+        Mode::Rgb32F | Mode::Rgba32F => {
+            let data = values.iter().flat_map(|&v| [(v / 255.0) as f32; 3]);
+            let buf = ImageBuffer::from_raw(width, height, data.collect());
+            DynamicImage::ImageRgb32F(buf.expect("buffer sized for the image"))
+        }
+        _ => {
+            let data = values.iter().map(|&v| v.round().clamp(0.0, 255.0) as u8);
+            let buf = ImageBuffer::from_raw(width, height, data.collect());
+            DynamicImage::ImageLuma8(buf.expect("buffer sized for the image"))
+        }
+    }
+}
+
+/// CMYK ink amounts of an RGB color, using no black ink as Pillow does
+pub fn rgb_to_cmyk([r, g, b]: [u8; 3]) -> [u8; 4] {
+    [255 - r, 255 - g, 255 - b, 0]
+}
+
+/// RGB color of CMYK ink amounts, taking the inks as ideal filters
+pub fn cmyk_to_rgb([c, m, y, k]: [u8; 4]) -> [u8; 3] {
+    let white = 255 - k as u32;
+    [c, m, y].map(|v| (((255 - v as u32) * white + 127) / 255) as u8)
+}
+
+/// Full-range YCbCr of an RGB color, as used by JPEG
+pub fn rgb_to_ycbcr([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b,
+        128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b,
+    ]
+    .map(|v| v.round().clamp(0.0, 255.0) as u8)
+}
+
+/// RGB color of a full-range YCbCr color
+pub fn ycbcr_to_rgb([y, cb, cr]: [u8; 3]) -> [u8; 3] {
+    let (y, cb, cr) = (y as f32, cb as f32 - 128.0, cr as f32 - 128.0);
+    [
+        y + 1.402 * cr,
+        y - 0.344136 * cb - 0.714136 * cr,
+        y + 1.772 * cb,
+    ]
+    .map(|v| v.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Samples of an 8-bit image with a function applied to every pixel
+pub fn map_pixels<const N: usize, const M: usize>(
+    samples: &[u8],
+    f: impl Fn([u8; N]) -> [u8; M] + Sync,
+) -> Vec<u8> {
+    samples
+        .par_chunks_exact(N)
+        .flat_map_iter(|pixel| f(pixel.try_into().expect("chunk of one pixel")))
+        .collect()
+}
+
+/// RGB image of the colors of a "CMYK" or "YCbCr" image
+fn to_rgb(image: &DynamicImage, from: Mode) -> DynamicImage {
+    let data = match from {
+        Mode::Cmyk => map_pixels(image.to_rgba8().as_raw(), cmyk_to_rgb),
+        _ => map_pixels(image.to_rgb8().as_raw(), ycbcr_to_rgb),
+    };
+    let buf = ImageBuffer::from_raw(image.width(), image.height(), data);
+    DynamicImage::ImageRgb8(buf.expect("buffer sized for the image"))
+}
+
+/// "CMYK" or "YCbCr" image of the colors of an RGB image
+fn from_rgb(image: &DynamicImage, to: Mode) -> DynamicImage {
+    let (width, height) = (image.width(), image.height());
+    let rgb = image.to_rgb8();
+    match to {
+        Mode::Cmyk => {
+            let buf = ImageBuffer::from_raw(width, height, map_pixels(&rgb, rgb_to_cmyk));
+            DynamicImage::ImageRgba8(buf.expect("buffer sized for the image"))
+        }
+        _ => {
+            let buf = ImageBuffer::from_raw(width, height, map_pixels(&rgb, rgb_to_ycbcr));
+            DynamicImage::ImageRgb8(buf.expect("buffer sized for the image"))
+        }
+    }
+}
+
+/// Convert an image between modes.
+///
+/// Color types are converted like the image crate does, scaling between
+/// 8-bit, 16-bit and float ranges. Converting to "I" or "F" keeps the gray
+/// level in the source's range, and converting from them clamps values to
+/// the target's range, as in Pillow. "CMYK" and "YCbCr" are converted
+/// through 8-bit RGB without a colour profile.
+pub fn convert(image: &DynamicImage, from: Mode, to: Mode) -> DynamicImage {
+    if from == to {
+        return image.clone();
+    }
+    if matches!(from, Mode::Cmyk | Mode::YCbCr) {
+        return convert(&to_rgb(image, from), Mode::Rgb, to);
+    }
+    if matches!(to, Mode::Cmyk | Mode::YCbCr) {
+        return from_rgb(&convert(image, from, Mode::Rgb), to);
+    }
+    if matches!(to, Mode::I | Mode::F) {
+        let values = gray_values(image, from);
+        return from_values(image.width(), image.height(), &values, to);
+    }
+
+    let gray;
+    let image = match from {
+        Mode::I | Mode::F => {
+            gray = values_to_gray(image, from, to);
+            &gray
+        }
+        _ => image,
+    };
+    match to {
+        Mode::L => DynamicImage::ImageLuma8(image.to_luma8()),
+        Mode::La => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        Mode::Rgb => DynamicImage::ImageRgb8(image.to_rgb8()),
+        Mode::Rgba => DynamicImage::ImageRgba8(image.to_rgba8()),
+        Mode::L16 => DynamicImage::ImageLuma16(image.to_luma16()),
+        Mode::La16 => DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+        Mode::Rgb16 => DynamicImage::ImageRgb16(image.to_rgb16()),
+        Mode::Rgba16 => DynamicImage::ImageRgba16(image.to_rgba16()),
+        Mode::Rgb32F => DynamicImage::ImageRgb32F(image.to_rgb32f()),
+        Mode::Rgba32F => DynamicImage::ImageRgba32F(image.to_rgba32f()),
+        Mode::I | Mode::F => unreachable!("Converted to values above"),
+        Mode::Cmyk | Mode::YCbCr => unreachable!("Converted from RGB above"),
+    }
+}
+
+/// Colors of an image in an extended mode, for uses that only take colors:
+/// "CMYK" and "YCbCr" as RGB, and the values of "I" and "F" clamped to "L"
+pub fn colors(image: &DynamicImage, mode: ExtendedMode) -> DynamicImage {
+    let mode = Mode::from(mode);
+    let to = if mode.has_color() { Mode::Rgb } else { Mode::L };
+    convert(image, mode, to)
+}
+
+/// Channel values of a fill color for an image of the given mode, with
+/// transparent black when there is no color.
+///
+/// Numbers are taken as values of the mode, while color strings are 8-bit
+/// RGB, scaled to the range of the mode or converted to "CMYK" and "YCbCr".
+/// A missing fourth channel is opaque alpha, or no black ink for "CMYK".
+pub fn parse_fill(color: Option<&Bound<'_, PyAny>>, mode: Mode) -> PyResult<[f64; 4]> {
+    let max = mode.max();
+    let Some(color) = color else {
+        return Ok([0.0; 4]);
+    };
+    if color.extract::<String>().is_ok() {
+        let (r, g, b, a) = parse_color(color)?;
+        return Ok(match mode {
+            Mode::Cmyk => rgb_to_cmyk([r, g, b]).map(f64::from),
+            Mode::YCbCr => {
+                let [y, cb, cr] = rgb_to_ycbcr([r, g, b]);
+                [y, cb, cr, a].map(f64::from)
+            }
+            _ => [r, g, b, a].map(|v| v as f64 * max / 255.0),
+        });
+    }
+    let last = if mode == Mode::Cmyk { 0.0 } else { max };
+    if let Ok(value) = color.extract::<f64>() {
+        return Ok([value, value, value, last]);
+    }
+    match color.extract::<Vec<f64>>().as_deref() {
+        Ok(&[value]) => Ok([value, value, value, last]),
+        Ok(&[r, g, b]) => Ok([r, g, b, last]),
+        Ok(&[r, g, b, a]) => Ok([r, g, b, a]),
+        _ => Err(PuhuError::InvalidOperation(
+            "Color must be a string, number, or tuple (1-item/RGB/RGBA)".to_string(),
+        )
+        .into()),
+    }
+}
+
+/// Image of a single color, given as channel values of the mode
+pub fn filled(mode: Mode, width: u32, height: u32, color: [f64; 4]) -> DynamicImage {
+    let u8s = color.map(|v| v.round().clamp(0.0, 255.0) as u8);
+    let u16s = color.map(|v| v.round().clamp(0.0, 65535.0) as u16);
+    let f32s = color.map(|v| v as f32);
+    match mode {
+        Mode::L => DynamicImage::ImageLuma8(ImageBuffer::from_pixel(width, height, Luma([u8s[0]]))),
+        Mode::La => DynamicImage::ImageLumaA8(ImageBuffer::from_pixel(
+            width,
+            height,
+            LumaA([u8s[0], u8s[3]]),
+        )),
+        Mode::Rgb | Mode::YCbCr => DynamicImage::ImageRgb8(ImageBuffer::from_pixel(
+            width,
+            height,
+            Rgb([u8s[0], u8s[1], u8s[2]]),
+        )),
+        Mode::Rgba | Mode::Cmyk => {
+            DynamicImage::ImageRgba8(ImageBuffer::from_pixel(width, height, Rgba(u8s)))
+        }
+        Mode::L16 => {
+            DynamicImage::ImageLuma16(ImageBuffer::from_pixel(width, height, Luma([u16s[0]])))
+        }
+        Mode::La16 => DynamicImage::ImageLumaA16(ImageBuffer::from_pixel(
+            width,
+            height,
+            LumaA([u16s[0], u16s[3]]),
+        )),
+        Mode::Rgb16 => DynamicImage::ImageRgb16(ImageBuffer::from_pixel(
+            width,
+            height,
+            Rgb([u16s[0], u16s[1], u16s[2]]),
+        )),
+        Mode::Rgba16 => {
+            DynamicImage::ImageRgba16(ImageBuffer::from_pixel(width, height, Rgba(u16s)))
+        }
+        Mode::Rgb32F => DynamicImage::ImageRgb32F(ImageBuffer::from_pixel(
+            width,
+            height,
+            Rgb([f32s[0], f32s[1], f32s[2]]),
+        )),
+        Mode::Rgba32F => {
+            DynamicImage::ImageRgba32F(ImageBuffer::from_pixel(width, height, Rgba(f32s)))
+        }
+        Mode::I | Mode::F => DynamicImage::ImageRgba8(ImageBuffer::from_pixel(
+            width,
+            height,
+            Rgba(value_bytes(color[0], mode)),
+        )),
+    }
+}
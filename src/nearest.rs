@@ -0,0 +1,390 @@
+use crate::errors::PuhuError;
+use crate::palette::Palette;
+use std::sync::OnceLock;
+
+/// Distance used to find the palette entry nearest to a color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Metric {
+    /// Euclidean distance between sRGB values
+    #[default]
+    Rgb,
+    /// CIEDE2000 color difference in CIELAB
+    Ciede2000,
+    /// Euclidean distance in OKLab
+    Oklab,
+}
+
+impl Metric {
+    /// Parse a color metric, defaulting to RGB
+    pub fn parse(name: Option<&str>) -> Result<Self, PuhuError> {
+        match name.map(str::to_ascii_uppercase).as_deref() {
+            Some("RGB") | None => Ok(Metric::Rgb),
+            Some("CIEDE2000") | Some("LAB") => Ok(Metric::Ciede2000),
+            Some("OKLAB") => Ok(Metric::Oklab),
+            Some(_) => Err(PuhuError::InvalidOperation(format!(
+                "Unsupported color metric: '{}'. Use 'RGB', 'CIEDE2000' or 'OKLAB'",
+                name.unwrap_or_default()
+            ))),
+        }
+    }
+
+    /// Coordinates of an RGBA color in the metric's space, with alpha scaled
+    /// to the range of lightness
+    fn coordinates(self, [r, g, b, a]: [u8; 4]) -> [f32; 4] {
+        match self {
+            Metric::Rgb => [r as f32, g as f32, b as f32, a as f32],
+            Metric::Ciede2000 => {
+                let [l, a_star, b_star] = lab(r, g, b);
+                [l, a_star, b_star, a as f32 * 100.0 / 255.0]
+            }
+            Metric::Oklab => {
+                let [l, a_ok, b_ok] = oklab(r, g, b);
+                [l, a_ok, b_ok, a as f32 / 255.0]
+            }
+        }
+    }
+}
+
+/// sRGB component to linear light
+fn linear(value: u8) -> f64 {
+    static TABLE: OnceLock<[f64; 256]> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        std::array::from_fn(|i| {
+            let v = i as f64 / 255.0;
+            if v <= 0.04045 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    });
+    table[value as usize]
+}
+
+/// CIELAB of an sRGB color, relative to the D65 white point
+fn lab(r: u8, g: u8, b: u8) -> [f32; 3] {
+    let (r, g, b) = (linear(r), linear(g), linear(b));
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = (0.0193339 * r + 0.119192 * g + 0.9503041 * b) / 1.08883;
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)].map(|v| v as f32)
+}
+
+/// OKLab of an sRGB color
+fn oklab(r: u8, g: u8, b: u8) -> [f32; 3] {
+    let (r, g, b) = (linear(r), linear(g), linear(b));
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.793617785 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.428592205 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.808675766 * s,
+    ]
+    .map(|v| v as f32)
+}
+
+/// CIEDE2000 difference between two CIELAB colors
+fn ciede2000([l1, a1, b1]: [f32; 3], [l2, a2, b2]: [f32; 3]) -> f32 {
+    let (l1, a1, b1, l2, a2, b2) = (
+        l1 as f64, a1 as f64, b1 as f64, l2 as f64, a2 as f64, b2 as f64,
+    );
+    let c_mean = ((a1.hypot(b1) + a2.hypot(b2)) / 2.0).powi(7);
+    let g = 0.5 * (1.0 - (c_mean / (c_mean + 25f64.powi(7))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |b: f64, a: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 <= h1 {
+        h2 - h1 + 360.0
+    } else {
+        h2 - h1 - 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+    let t = 1.0 - 0.17 * (h_mean - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_mean).to_radians().cos()
+        + 0.32 * (3.0 * h_mean + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_mean - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let c_mean7 = c_mean.powi(7);
+    let r_c = 2.0 * (c_mean7 / (c_mean7 + 25f64.powi(7))).sqrt();
+    let s_l = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean;
+    let s_h = 1.0 + 0.015 * c_mean * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).sqrt() as f32
+}
+
+/// Most entries in a leaf of a k-d tree, which are compared one by one
+const LEAF_SIZE: usize = 6;
+
+/// Node of a k-d tree
+enum Node {
+    /// Entries below `value` along `axis` go left, the others right
+    Split {
+        axis: usize,
+        value: f32,
+        left: usize,
+        right: usize,
+    },
+    /// A range of the tree's entries
+    Leaf { start: usize, end: usize },
+}
+
+/// k-d tree of palette entries in a metric's space
+struct KdTree {
+    entries: Vec<([f32; 4], u8)>,
+    nodes: Vec<Node>,
+}
+
+impl KdTree {
+    fn new(mut entries: Vec<([f32; 4], u8)>) -> Self {
+        let mut nodes = Vec::new();
+        if !entries.is_empty() {
+            Self::build(&mut nodes, &mut entries, 0);
+        }
+        KdTree { entries, nodes }
+    }
+
+    /// Split the entries along their widest axis at the median, returning
+    /// the node of the subtree
+    fn build(nodes: &mut Vec<Node>, entries: &mut [([f32; 4], u8)], start: usize) -> usize {
+        let end = start + entries.len();
+        if entries.len() <= LEAF_SIZE {
+            nodes.push(Node::Leaf { start, end });
+            return nodes.len() - 1;
+        }
+        let axis = (0..4)
+            .max_by(|&a, &b| {
+                let range = |axis: usize| {
+                    let values = entries.iter().map(|(p, _)| p[axis]);
+                    let max = values.clone().fold(f32::MIN, f32::max);
+                    max - values.fold(f32::MAX, f32::min)
+                };
+                range(a).total_cmp(&range(b))
+            })
+            .unwrap_or(0);
+        entries.sort_by(|a, b| a.0[axis].total_cmp(&b.0[axis]));
+        let median = entries.len() / 2;
+        let value = entries[median].0[axis];
+        let (below, above) = entries.split_at_mut(median);
+        let left = Self::build(nodes, below, start);
+        let right = Self::build(nodes, above, start + median);
+        nodes.push(Node::Split {
+            axis,
+            value,
+            left,
+            right,
+        });
+        nodes.len() - 1
+    }
+
+    /// The `k` entries nearest to a point, up to `MAX_CANDIDATES`
+    fn nearest(&self, point: [f32; 4], k: usize) -> Candidates {
+        let mut best = Candidates {
+            k: k.min(MAX_CANDIDATES),
+            len: 0,
+            entries: [(0.0, 0); MAX_CANDIDATES],
+        };
+        if !self.nodes.is_empty() {
+            self.search(self.nodes.len() - 1, point, &mut best);
+        }
+        best
+    }
+
+    fn search(&self, node: usize, point: [f32; 4], best: &mut Candidates) {
+        match self.nodes[node] {
+            Node::Leaf { start, end } => {
+                for &(entry, index) in &self.entries[start..end] {
+                    let distance = (0..4).map(|i| (entry[i] - point[i]).powi(2)).sum();
+                    best.offer(distance, index);
+                }
+            }
+            Node::Split {
+                axis,
+                value,
+                left,
+                right,
+            } => {
+                let offset = point[axis] - value;
+                let (near, far) = if offset < 0.0 {
+                    (left, right)
+                } else {
+                    (right, left)
+                };
+                self.search(near, point, best);
+                // The far side can only hold entries at least this far away
+                if offset * offset <= best.worst() {
+                    self.search(far, point, best);
+                }
+            }
+        }
+    }
+}
+
+/// Most entries a k-d tree search keeps
+const MAX_CANDIDATES: usize = 8;
+
+/// Entries nearest to a point as (squared distance, index), nearest first,
+/// with ties going to the lower index
+struct Candidates {
+    k: usize,
+    len: usize,
+    entries: [(f32, u8); MAX_CANDIDATES],
+}
+
+impl Candidates {
+    fn worst(&self) -> f32 {
+        if self.len < self.k {
+            f32::INFINITY
+        } else {
+            self.entries[self.len - 1].0
+        }
+    }
+
+    fn offer(&mut self, distance: f32, index: u8) {
+        let mut position = self.len;
+        while position > 0 && (distance, index) < self.entries[position - 1] {
+            position -= 1;
+        }
+        if position == self.k {
+            return;
+        }
+        self.len = (self.len + 1).min(self.k);
+        self.entries
+            .copy_within(position..self.len - 1, position + 1);
+        self.entries[position] = (distance, index);
+    }
+
+    fn indices(&self) -> impl Iterator<Item = u8> + '_ {
+        self.entries[..self.len].iter().map(|&(_, index)| index)
+    }
+}
+
+/// Number of candidates nearest in CIELAB that are ranked by CIEDE2000,
+/// which is not a Euclidean distance. This is an approximation: the entry
+/// nearest by CIEDE2000 can lie outside the candidates, which happens for
+/// a few percent of random colors against random palettes.
+const CIEDE2000_CANDIDATES: usize = MAX_CANDIDATES;
+
+/// Number of colors remembered by a mapper, each in the slot of its hash
+const CACHE_SLOTS: usize = 1 << 16;
+
+/// Finds the palette entry nearest to each color, remembering the answer
+/// for colors seen before
+pub struct Mapper<'a> {
+    palette: &'a Palette,
+    metric: Metric,
+    /// Coordinates of each entry in the metric's space
+    points: Vec<[f32; 4]>,
+    /// Entries with alpha of at least 128, and the others
+    visible: KdTree,
+    hidden: KdTree,
+    /// Recently mapped colors and their entries, plus one; 0 for empty slots
+    cache: Vec<(u32, u16)>,
+}
+
+impl<'a> Mapper<'a> {
+    pub fn new(palette: &'a Palette, metric: Metric) -> Self {
+        let points: Vec<[f32; 4]> = (0..palette.colors().len() / 3)
+            .map(|index| metric.coordinates(palette.rgba(index as u8)))
+            .collect();
+        let (mut visible, mut hidden) = (Vec::new(), Vec::new());
+        for (index, &point) in points.iter().enumerate() {
+            let index = index as u8;
+            if palette.alpha(index) >= 128 {
+                visible.push((point, index));
+            } else {
+                hidden.push((point, index));
+            }
+        }
+        Mapper {
+            palette,
+            metric,
+            points,
+            visible: KdTree::new(visible),
+            hidden: KdTree::new(hidden),
+            cache: vec![(0, 0); CACHE_SLOTS],
+        }
+    }
+
+    /// Index of the entry nearest to an RGBA color.
+    ///
+    /// Colors with alpha below 128 only match entries with alpha below 128,
+    /// and the others only opaque-looking entries, unless the palette has
+    /// none of them. Alpha is ignored for palettes without it.
+    pub fn nearest(&mut self, color: [u8; 4]) -> u8 {
+        let color = if self.palette.has_alpha() {
+            color
+        } else {
+            [color[0], color[1], color[2], 255]
+        };
+        let key = u32::from_le_bytes(color);
+        let slot = (key.wrapping_mul(0x9E37_79B1) >> 16) as usize % CACHE_SLOTS;
+        if let (cached, index @ 1..) = self.cache[slot] {
+            if cached == key {
+                return (index - 1) as u8;
+            }
+        }
+        let (own, other) = if color[3] >= 128 {
+            (&self.visible, &self.hidden)
+        } else {
+            (&self.hidden, &self.visible)
+        };
+        let tree = if own.nodes.is_empty() { other } else { own };
+        let point = self.metric.coordinates(color);
+        let index = match self.metric {
+            Metric::Ciede2000 => {
+                let [l, a, b, _] = point;
+                tree.nearest(point, CIEDE2000_CANDIDATES)
+                    .indices()
+                    .map(|index| {
+                        let [el, ea, eb, alpha] = self.points[index as usize];
+                        let delta_alpha = alpha - point[3];
+                        let delta_e = ciede2000([l, a, b], [el, ea, eb]);
+                        (delta_e * delta_e + delta_alpha * delta_alpha, index)
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))
+                    .map_or(0, |(_, index)| index)
+            }
+            Metric::Rgb | Metric::Oklab => tree.nearest(point, 1).indices().next().unwrap_or(0),
+        };
+        self.cache[slot] = (key, index as u16 + 1);
+        index
+    }
+}
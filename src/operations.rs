@@ -1,5 +1,8 @@
 use crate::errors::PuhuError;
+use crate::utils::Sample;
 use image::imageops::FilterType;
+use image::{DynamicImage, ImageBuffer, Pixel};
+use rayon::prelude::*;
 
 /// Parse a resample filter string into a FilterType
 pub fn parse_resample_filter(filter_str: Option<&str>) -> Result<FilterType, PuhuError> {
@@ -15,3 +18,258 @@ pub fn parse_resample_filter(filter_str: Option<&str>) -> Result<FilterType, Puh
         ))),
     }
 }
+
+/// Transpose method: one of the symmetries of a rectangle other than the
+/// identity, each applied in a single pass over the pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symmetry {
+    FlipLeftRight,
+    FlipTopBottom,
+    /// Quarter turn counter-clockwise
+    Rotate90,
+    Rotate180,
+    /// Quarter turn clockwise
+    Rotate270,
+    /// Mirror along the main diagonal
+    Transpose,
+    /// Mirror along the anti-diagonal
+    Transverse,
+}
+
+impl Symmetry {
+    pub fn parse(name: &str) -> Result<Self, PuhuError> {
+        Ok(match name {
+            "FLIP_LEFT_RIGHT" => Symmetry::FlipLeftRight,
+            "FLIP_TOP_BOTTOM" => Symmetry::FlipTopBottom,
+            "ROTATE_90" => Symmetry::Rotate90,
+            "ROTATE_180" => Symmetry::Rotate180,
+            "ROTATE_270" => Symmetry::Rotate270,
+            "TRANSPOSE" => Symmetry::Transpose,
+            "TRANSVERSE" => Symmetry::Transverse,
+            other => {
+                return Err(PuhuError::InvalidOperation(format!(
+                    "Unsupported transpose method: {}",
+                    other
+                )))
+            }
+        })
+    }
+
+    /// Method that undoes an EXIF orientation (tag 0x0112), so the image
+    /// displays upright
+    ///
+    /// Returns None for orientation 1 and for values outside 1-8.
+    pub fn from_orientation(orientation: u16) -> Option<Self> {
+        match orientation {
+            2 => Some(Symmetry::FlipLeftRight),
+            3 => Some(Symmetry::Rotate180),
+            4 => Some(Symmetry::FlipTopBottom),
+            5 => Some(Symmetry::Transpose),
+            6 => Some(Symmetry::Rotate270),
+            7 => Some(Symmetry::Transverse),
+            8 => Some(Symmetry::Rotate90),
+            _ => None,
+        }
+    }
+
+    fn swaps_axes(self) -> bool {
+        matches!(
+            self,
+            Symmetry::Rotate90 | Symmetry::Rotate270 | Symmetry::Transpose | Symmetry::Transverse
+        )
+    }
+
+    /// Source pixel index of the first output pixel, and the index steps
+    /// along an output row and down an output column, for a source of
+    /// `width` x `height`
+    fn source_steps(self, width: i64, height: i64) -> (i64, i64, i64) {
+        let (last_column, last_row) = (width - 1, (height - 1) * width);
+        match self {
+            Symmetry::FlipLeftRight => (last_column, -1, width),
+            Symmetry::FlipTopBottom => (last_row, 1, -width),
+            Symmetry::Rotate90 => (last_column, width, -1),
+            Symmetry::Rotate180 => (last_row + last_column, -1, -width),
+            Symmetry::Rotate270 => (last_row, -width, 1),
+            Symmetry::Transpose => (0, width, 1),
+            Symmetry::Transverse => (last_row + last_column, -width, -1),
+        }
+    }
+
+    pub fn apply(self, image: &DynamicImage) -> DynamicImage {
+        match image {
+            DynamicImage::ImageLuma8(buf) => DynamicImage::ImageLuma8(self.buffer(buf)),
+            DynamicImage::ImageLumaA8(buf) => DynamicImage::ImageLumaA8(self.buffer(buf)),
+            DynamicImage::ImageRgb8(buf) => DynamicImage::ImageRgb8(self.buffer(buf)),
+            DynamicImage::ImageRgba8(buf) => DynamicImage::ImageRgba8(self.buffer(buf)),
+            DynamicImage::ImageLuma16(buf) => DynamicImage::ImageLuma16(self.buffer(buf)),
+            DynamicImage::ImageLumaA16(buf) => DynamicImage::ImageLumaA16(self.buffer(buf)),
+            DynamicImage::ImageRgb16(buf) => DynamicImage::ImageRgb16(self.buffer(buf)),
+            DynamicImage::ImageRgba16(buf) => DynamicImage::ImageRgba16(self.buffer(buf)),
+            DynamicImage::ImageRgb32F(buf) => DynamicImage::ImageRgb32F(self.buffer(buf)),
+            DynamicImage::ImageRgba32F(buf) => DynamicImage::ImageRgba32F(self.buffer(buf)),
+            other => DynamicImage::ImageRgba8(self.buffer(&other.to_rgba8())),
+        }
+    }
+
+    /// Copy every output pixel straight from its source pixel
+    fn buffer<P>(self, src: &ImageBuffer<P, Vec<P::Subpixel>>) -> ImageBuffer<P, Vec<P::Subpixel>>
+    where
+        P: Pixel,
+        P::Subpixel: Send + Sync,
+    {
+        let channels = P::CHANNEL_COUNT as usize;
+        let (width, height) = src.dimensions();
+        let (out_width, out_height) = match self.swaps_axes() {
+            true => (height, width),
+            false => (width, height),
+        };
+        let (first, step_x, step_y) = self.source_steps(width as i64, height as i64);
+        let samples = src.as_raw();
+
+        let mut out = samples
+            .first()
+            .map_or(Vec::new(), |&v| vec![v; samples.len()]);
+        let row_len = (out_width as usize * channels).max(1);
+        out.par_chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| {
+                let mut index = first + y as i64 * step_y;
+                for pixel in row.chunks_exact_mut(channels) {
+                    let start = index as usize * channels;
+                    pixel.copy_from_slice(&samples[start..start + channels]);
+                    index += step_x;
+                }
+            });
+        ImageBuffer::from_raw(out_width, out_height, out).expect("buffer sized for the image")
+    }
+}
+
+/// Largest size within `bounds` with the aspect ratio of an image of `size`,
+/// as Pillow's `thumbnail`, or None if the image already fits
+///
+/// Each side is rounded to whichever whole number keeps the aspect ratio
+/// closest, and never to 0.
+pub fn thumbnail_size(size: (u32, u32), bounds: (u32, u32)) -> Option<(u32, u32)> {
+    let (width, height) = size;
+    let (x, y) = bounds;
+    if x >= width && y >= height {
+        return None;
+    }
+    let aspect = width as f64 / height as f64;
+    // The floor wins ties, as with Python's min()
+    let closest = |value: f64, error: &dyn Fn(f64) -> f64| {
+        let (floor, ceil) = (value.floor(), value.ceil());
+        let best = if error(ceil) < error(floor) {
+            ceil
+        } else {
+            floor
+        };
+        (best as u32).max(1)
+    };
+    let (xf, yf) = (x as f64, y as f64);
+    if xf / yf >= aspect {
+        Some((closest(yf * aspect, &|n| (aspect - n / yf).abs()), y))
+    } else {
+        let error = |n: f64| {
+            if n == 0.0 {
+                0.0
+            } else {
+                (aspect - xf / n).abs()
+            }
+        };
+        Some((x, closest(xf / aspect, &error)))
+    }
+}
+
+/// Shrink an image by whole factors, averaging every `factor.0` x `factor.1`
+/// block of pixels; blocks at the right and bottom edges may be smaller
+///
+/// With `alpha`, colors are averaged premultiplied by the last channel, so
+/// transparent pixels do not darken their neighbours.
+pub fn reduce(image: &DynamicImage, factor: (u32, u32), alpha: bool) -> DynamicImage {
+    match image {
+        DynamicImage::ImageLuma8(buf) => {
+            DynamicImage::ImageLuma8(reduce_buffer(buf, factor, alpha))
+        }
+        DynamicImage::ImageLumaA8(buf) => {
+            DynamicImage::ImageLumaA8(reduce_buffer(buf, factor, alpha))
+        }
+        DynamicImage::ImageRgb8(buf) => DynamicImage::ImageRgb8(reduce_buffer(buf, factor, alpha)),
+        DynamicImage::ImageRgba8(buf) => {
+            DynamicImage::ImageRgba8(reduce_buffer(buf, factor, alpha))
+        }
+        DynamicImage::ImageLuma16(buf) => {
+            DynamicImage::ImageLuma16(reduce_buffer(buf, factor, alpha))
+        }
+        DynamicImage::ImageLumaA16(buf) => {
+            DynamicImage::ImageLumaA16(reduce_buffer(buf, factor, alpha))
+        }
+        DynamicImage::ImageRgb16(buf) => {
+            DynamicImage::ImageRgb16(reduce_buffer(buf, factor, alpha))
+        }
+        DynamicImage::ImageRgba16(buf) => {
+            DynamicImage::ImageRgba16(reduce_buffer(buf, factor, alpha))
+        }
+        DynamicImage::ImageRgb32F(buf) => {
+            DynamicImage::ImageRgb32F(reduce_buffer(buf, factor, alpha))
+        }
+        DynamicImage::ImageRgba32F(buf) => {
+            DynamicImage::ImageRgba32F(reduce_buffer(buf, factor, alpha))
+        }
+        other => DynamicImage::ImageRgba8(reduce_buffer(&other.to_rgba8(), factor, alpha)),
+    }
+}
+
+fn reduce_buffer<P>(
+    src: &ImageBuffer<P, Vec<P::Subpixel>>,
+    (fx, fy): (u32, u32),
+    alpha: bool,
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel,
+    P::Subpixel: Sample + Send + Sync,
+{
+    let channels = P::CHANNEL_COUNT as usize;
+    let colors = if alpha { channels - 1 } else { channels };
+    let (width, height) = src.dimensions();
+    let (out_width, out_height) = (width.div_ceil(fx), height.div_ceil(fy));
+    let samples = src.as_raw();
+    let row_len = width as usize * channels;
+
+    let mut out = vec![Sample::from_f32(0.0); out_width as usize * out_height as usize * channels];
+    out.par_chunks_mut((out_width as usize * channels).max(1))
+        .enumerate()
+        .for_each(|(y, row)| {
+            let rows = y as u32 * fy..((y as u32 + 1) * fy).min(height);
+            for (x, pixel) in row.chunks_exact_mut(channels).enumerate() {
+                let columns = x as u32 * fx..((x as u32 + 1) * fx).min(width);
+                let mut sums = [0.0f32; 4];
+                let mut count = 0.0;
+                for sy in rows.clone() {
+                    let start = sy as usize * row_len + columns.start as usize * channels;
+                    let end = sy as usize * row_len + columns.end as usize * channels;
+                    for block in samples[start..end].chunks_exact(channels) {
+                        let weight = if alpha { block[colors].to_f32() } else { 1.0 };
+                        for c in 0..colors {
+                            sums[c] += block[c].to_f32() * weight;
+                        }
+                        if alpha {
+                            sums[colors] += weight;
+                        }
+                        count += 1.0;
+                    }
+                }
+                let alpha_sum = if alpha { sums[colors] } else { 0.0 };
+                for c in 0..channels {
+                    let value = match alpha {
+                        true if c == colors => alpha_sum / count,
+                        true if alpha_sum > 0.0 => sums[c] / alpha_sum,
+                        true => 0.0,
+                        false => sums[c] / count,
+                    };
+                    pixel[c] = Sample::from_f32(value);
+                }
+            }
+        });
+    ImageBuffer::from_raw(out_width, out_height, out).expect("buffer sized for the image")
+}
@@ -1,6 +1,199 @@
+use crate::dither::{self, Dither};
 use crate::errors::PuhuError;
+use crate::nearest::{Mapper, Metric};
 use color_quant::NeuQuant;
-use image::DynamicImage;
+use image::{DynamicImage, GrayImage, RgbImage, RgbaImage};
+use std::collections::HashSet;
+
+/// Colors of a palette ("P" mode) image, whose pixels are indices into it.
+///
+/// Palette images are stored as L8 buffers of indices alongside their palette.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    /// RGB triplets, one per entry
+    colors: Vec<u8>,
+    /// Alpha of the leading entries, like a PNG tRNS chunk; the rest are opaque
+    alpha: Vec<u8>,
+}
+
+impl Palette {
+    /// Palette of opaque colors, with an optional fully transparent entry
+    pub fn new(colors: Vec<u8>, transparency: Option<u8>) -> Result<Self, PuhuError> {
+        let alpha = match transparency {
+            Some(index) => {
+                let mut alpha = vec![255; index as usize];
+                alpha.push(0);
+                alpha
+            }
+            None => Vec::new(),
+        };
+        Self::with_alpha(colors, alpha)
+    }
+
+    /// Palette with the alpha of each entry; entries past the end of `alpha`
+    /// are opaque
+    pub fn with_alpha(colors: Vec<u8>, mut alpha: Vec<u8>) -> Result<Self, PuhuError> {
+        if !colors.len().is_multiple_of(3) || colors.len() > 256 * 3 {
+            return Err(PuhuError::InvalidOperation(format!(
+                "Palette must have up to 256 RGB entries, got {} values",
+                colors.len()
+            )));
+        }
+        if alpha.len() > 256 {
+            return Err(PuhuError::InvalidOperation(format!(
+                "Palette must have up to 256 alpha values, got {}",
+                alpha.len()
+            )));
+        }
+        while alpha.last() == Some(&255) {
+            alpha.pop();
+        }
+        Ok(Palette { colors, alpha })
+    }
+
+    /// Palette of RGBA entries
+    pub fn from_rgba(entries: &[[u8; 4]]) -> Result<Self, PuhuError> {
+        let colors = entries.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+        let alpha = entries.iter().map(|c| c[3]).collect();
+        Self::with_alpha(colors, alpha)
+    }
+
+    /// 256 gray levels, the palette of new "P" images
+    pub fn grayscale() -> Self {
+        Palette {
+            colors: (0..=255u8).flat_map(|v| [v, v, v]).collect(),
+            alpha: Vec::new(),
+        }
+    }
+
+    /// RGB triplets, one per entry
+    pub fn colors(&self) -> &[u8] {
+        &self.colors
+    }
+
+    /// Alpha of the leading entries; the rest are opaque
+    pub fn alphas(&self) -> &[u8] {
+        &self.alpha
+    }
+
+    /// Whether any entry is not fully opaque
+    pub fn has_alpha(&self) -> bool {
+        !self.alpha.is_empty()
+    }
+
+    /// The only entry that is not opaque, if it is fully transparent
+    pub fn transparency(&self) -> Option<u8> {
+        let (&last, rest) = self.alpha.split_last()?;
+        (last == 0 && rest.iter().all(|&a| a == 255)).then_some(rest.len() as u8)
+    }
+
+    /// Color of an entry; indices past the end of the palette are black
+    pub fn color(&self, index: u8) -> [u8; 3] {
+        let base = index as usize * 3;
+        match self.colors.get(base..base + 3) {
+            Some(rgb) => [rgb[0], rgb[1], rgb[2]],
+            None => [0, 0, 0],
+        }
+    }
+
+    /// Alpha of an entry
+    pub fn alpha(&self, index: u8) -> u8 {
+        self.alpha.get(index as usize).copied().unwrap_or(255)
+    }
+
+    /// Expand an L8 image of indices to RGB, or to RGBA if the palette has
+    /// alpha
+    pub fn expand(&self, indices: &DynamicImage) -> DynamicImage {
+        let (width, height) = (indices.width(), indices.height());
+        let indices = indices.as_bytes();
+        if self.has_alpha() {
+            let data = indices.iter().flat_map(|&i| self.rgba(i)).collect();
+            let image = RgbaImage::from_raw(width, height, data);
+            DynamicImage::ImageRgba8(image.expect("buffer sized for the image"))
+        } else {
+            let data = indices.iter().flat_map(|&i| self.color(i)).collect();
+            let image = RgbImage::from_raw(width, height, data);
+            DynamicImage::ImageRgb8(image.expect("buffer sized for the image"))
+        }
+    }
+
+    /// Color and alpha of an entry
+    pub fn rgba(&self, index: u8) -> [u8; 4] {
+        let [r, g, b] = self.color(index);
+        [r, g, b, self.alpha(index)]
+    }
+
+    /// Index of the entry nearest to an RGBA color, by RGB distance
+    pub fn nearest(&self, color: [u8; 4]) -> u8 {
+        Mapper::new(self, Metric::Rgb).nearest(color)
+    }
+
+    /// Map each pixel of an image to the nearest entry
+    pub fn remap(&self, image: &DynamicImage, metric: Metric) -> DynamicImage {
+        let rgba = image.to_rgba8();
+        let mut mapper = Mapper::new(self, metric);
+        let data = rgba.pixels().map(|p| mapper.nearest(p.0)).collect();
+        let indices = GrayImage::from_raw(rgba.width(), rgba.height(), data);
+        DynamicImage::ImageLuma8(indices.expect("buffer sized for the image"))
+    }
+
+    /// Map each pixel of an image to an entry, hiding the error with
+    /// `dither`. The error of transparent pixels is not diffused.
+    pub fn dither(&self, image: &DynamicImage, dither: &Dither, metric: Metric) -> DynamicImage {
+        if dither.method == dither::Method::None {
+            return self.remap(image, metric);
+        }
+        let rgba = image.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        let mut mapper = Mapper::new(self, metric);
+        let data = dither.apply(
+            width as usize,
+            height as usize,
+            |x, y| rgba.get_pixel(x as u32, y as u32).0,
+            self.spread(),
+            |color| {
+                let index = mapper.nearest(color);
+                if !self.has_alpha() {
+                    let [r, g, b] = self.color(index);
+                    return (index, Some([r, g, b, color[3]]));
+                }
+                if color[3] < 128 {
+                    return (index, None);
+                }
+                (index, Some(self.rgba(index)))
+            },
+        );
+        let indices = GrayImage::from_raw(width, height, data);
+        DynamicImage::ImageLuma8(indices.expect("buffer sized for the image"))
+    }
+
+    /// Typical distance between entries, the median distance from an entry
+    /// to the nearest other one, up to 255
+    fn spread(&self) -> i32 {
+        let entries: Vec<[u8; 3]> = (0..self.colors.len() / 3)
+            .map(|i| self.color(i as u8))
+            .collect();
+        let mut distances: Vec<u32> = entries
+            .iter()
+            .filter_map(|&a| {
+                let others = entries.iter().map(|&b| distance(a, b));
+                others.filter(|&d| d > 0).min()
+            })
+            .collect();
+        distances.sort_unstable();
+        match distances.get(distances.len() / 2) {
+            Some(&d) => ((d as f64).sqrt() as i32).min(255),
+            None => 255,
+        }
+    }
+}
+
+fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    a.iter()
+        .zip(b)
+        .map(|(&a, b)| (a as i32 - b as i32).pow(2) as u32)
+        .sum()
+}
 
 pub fn generate_web_palette() -> Vec<u8> {
     let mut palette = Vec::with_capacity(216 * 3);
@@ -31,18 +224,47 @@ pub fn generate_adaptive_palette(image: &DynamicImage, num_colors: u32) -> Vec<u
     nq.color_map_rgb()
 }
 
+/// The distinct colors as a palette, or `None` if there are more than `max_colors`
+pub fn exact_palette(colors: impl Iterator<Item = [u8; 3]>, max_colors: usize) -> Option<Vec<u8>> {
+    let mut seen = HashSet::new();
+    let mut palette = Vec::new();
+    for color in colors {
+        if seen.insert(color) {
+            if seen.len() > max_colors {
+                return None;
+            }
+            palette.extend(color);
+        }
+    }
+    Some(palette)
+}
+
+/// Convert an image to palette indices and the palette they refer to.
+///
+/// ADAPTIVE palettes hold the exact colors of images with few enough of
+/// them. Pixels with alpha below 128 get a transparent entry of their own.
 pub fn convert_to_palette(
     image: &DynamicImage,
     palette_type: &str,
     num_colors: u32,
-    apply_dither: bool,
-) -> Result<DynamicImage, PuhuError> {
-    let rgb_img = image.to_rgb8();
-    let (width, height) = rgb_img.dimensions();
+    dither: &Dither,
+    metric: Metric,
+) -> Result<(DynamicImage, Palette), PuhuError> {
+    let rgba_img = image.to_rgba8();
+    let has_transparency = image.color().has_alpha() && rgba_img.pixels().any(|p| p[3] < 128);
+    // Leave room for the transparent entry
+    let max_colors = num_colors.clamp(2, 256) - has_transparency as u32;
 
-    let palette = match palette_type {
+    let mut palette = match palette_type {
         "WEB" => generate_web_palette(),
-        "ADAPTIVE" => generate_adaptive_palette(image, num_colors),
+        "ADAPTIVE" => {
+            let colors = rgba_img
+                .pixels()
+                .filter(|p| p[3] >= 128)
+                .map(|p| [p[0], p[1], p[2]]);
+            exact_palette(colors, max_colors as usize)
+                .unwrap_or_else(|| generate_adaptive_palette(image, max_colors))
+        }
         _ => {
             return Err(PuhuError::InvalidOperation(format!(
                 "Unsupported palette type: '{}'. Use 'WEB' or 'ADAPTIVE'",
@@ -51,119 +273,10 @@ pub fn convert_to_palette(
         }
     };
 
-    let mut palette_indices = Vec::with_capacity((width * height) as usize);
-
-    if apply_dither {
-        palette_indices = apply_floyd_steinberg_dithering(&rgb_img, &palette, width, height);
-    } else {
-        // No dithering
-        for pixel in rgb_img.pixels() {
-            let (idx, _) = find_nearest_palette_color(&palette, pixel[0], pixel[1], pixel[2]);
-            palette_indices.push(idx);
-        }
-    }
-
-    // Convert palette indices back to RGB
-    let rgb_data: Vec<u8> = palette_indices
-        .iter()
-        .flat_map(|&idx| {
-            let base = (idx as usize) * 3;
-            [palette[base], palette[base + 1], palette[base + 2]]
-        })
-        .collect();
-
-    let result_img = image::RgbImage::from_raw(width, height, rgb_data)
-        .ok_or_else(|| PuhuError::InvalidOperation("Failed to create palette image".to_string()))?;
-
-    Ok(DynamicImage::ImageRgb8(result_img))
-}
-
-fn apply_floyd_steinberg_dithering(
-    rgb_img: &image::RgbImage,
-    palette: &[u8],
-    width: u32,
-    height: u32,
-) -> Vec<u8> {
-    let mut palette_indices = Vec::with_capacity((width * height) as usize);
-    let mut error_buffer = vec![vec![(0i16, 0i16, 0i16); width as usize]; 2];
-
-    for y in 0..height {
-        let curr_row = (y % 2) as usize;
-        let next_row = ((y + 1) % 2) as usize;
-
-        for x in 0..width as usize {
-            error_buffer[next_row][x] = (0, 0, 0);
-        }
-
-        for x in 0..width {
-            let pixel = rgb_img.get_pixel(x, y);
-            let (err_r, err_g, err_b) = error_buffer[curr_row][x as usize];
-
-            let r = (pixel[0] as i16 + err_r).clamp(0, 255) as u8;
-            let g = (pixel[1] as i16 + err_g).clamp(0, 255) as u8;
-            let b = (pixel[2] as i16 + err_b).clamp(0, 255) as u8;
-
-            let (idx, nearest) = find_nearest_palette_color(palette, r, g, b);
-            palette_indices.push(idx);
-
-            let quant_err_r = r as i16 - nearest.0 as i16;
-            let quant_err_g = g as i16 - nearest.1 as i16;
-            let quant_err_b = b as i16 - nearest.2 as i16;
-
-            // Distribute error to neighboring pixels (Floyd-Steinberg)
-            if x + 1 < width {
-                let e = &mut error_buffer[curr_row][(x + 1) as usize];
-                e.0 += quant_err_r * 7 / 16;
-                e.1 += quant_err_g * 7 / 16;
-                e.2 += quant_err_b * 7 / 16;
-            }
-            if y + 1 < height {
-                if x > 0 {
-                    let e = &mut error_buffer[next_row][(x - 1) as usize];
-                    e.0 += quant_err_r * 3 / 16;
-                    e.1 += quant_err_g * 3 / 16;
-                    e.2 += quant_err_b * 3 / 16;
-                }
-                let e = &mut error_buffer[next_row][x as usize];
-                e.0 += quant_err_r * 5 / 16;
-                e.1 += quant_err_g * 5 / 16;
-                e.2 += quant_err_b * 5 / 16;
-
-                if x + 1 < width {
-                    let e = &mut error_buffer[next_row][(x + 1) as usize];
-                    e.0 += quant_err_r * 1 / 16;
-                    e.1 += quant_err_g * 1 / 16;
-                    e.2 += quant_err_b * 1 / 16;
-                }
-            }
-        }
-    }
-
-    palette_indices
-}
-
-pub fn find_nearest_palette_color(palette: &[u8], r: u8, g: u8, b: u8) -> (u8, (u8, u8, u8)) {
-    let mut min_dist = u32::MAX;
-    let mut best_idx = 0;
-    let mut best_color = (0u8, 0u8, 0u8);
-
-    for (i, chunk) in palette.chunks(3).enumerate() {
-        let pr = chunk[0];
-        let pg = chunk[1];
-        let pb = chunk[2];
-
-        // Euclidean distance in RGB space
-        let dr = (r as i32 - pr as i32).abs() as u32;
-        let dg = (g as i32 - pg as i32).abs() as u32;
-        let db = (b as i32 - pb as i32).abs() as u32;
-        let dist = dr * dr + dg * dg + db * db;
-
-        if dist < min_dist {
-            min_dist = dist;
-            best_idx = i;
-            best_color = (pr, pg, pb);
-        }
+    let transparency = has_transparency.then_some((palette.len() / 3) as u8);
+    if transparency.is_some() {
+        palette.extend([0, 0, 0]);
     }
-
-    (best_idx as u8, best_color)
+    let palette = Palette::new(palette, transparency)?;
+    Ok((palette.dither(image, dither, metric), palette))
 }
@@ -0,0 +1,283 @@
+use crate::errors::PuhuError;
+use crate::palette::Palette;
+use color_quant::NeuQuant;
+use image::DynamicImage;
+use std::collections::HashMap;
+
+/// Algorithm used by `Image.quantize()` to choose the palette colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Split the color box with the largest error at its weighted median
+    MedianCut,
+    /// Merge the least used branches of an octree of the colors
+    FastOctree,
+    /// Train a self-organizing map on a sample of the pixels
+    NeuQuant,
+}
+
+/// Parse a quantize method, defaulting like Pillow to median cut for opaque
+/// images and the octree for images with alpha
+pub fn parse_method(method: Option<&str>, has_alpha: bool) -> Result<Method, PuhuError> {
+    match method {
+        Some("MEDIANCUT") | Some("mediancut") => Ok(Method::MedianCut),
+        Some("FASTOCTREE") | Some("fastoctree") => Ok(Method::FastOctree),
+        Some("NEUQUANT") | Some("neuquant") => Ok(Method::NeuQuant),
+        None if has_alpha => Ok(Method::FastOctree),
+        None => Ok(Method::MedianCut),
+        Some(other) => Err(PuhuError::InvalidOperation(format!(
+            "Unsupported quantize method: '{}'. Use 'MEDIANCUT', 'FASTOCTREE' or 'NEUQUANT'",
+            other
+        ))),
+    }
+}
+
+/// Build a palette of up to `max_colors` colors for an image.
+///
+/// Images with alpha are quantized in RGBA, with every fully transparent
+/// pixel sharing one entry. Images with few enough colors get a palette of
+/// their exact colors. `kmeans` is the number of k-means iterations used to
+/// refine the palette afterwards.
+pub fn quantize(
+    image: &DynamicImage,
+    max_colors: u32,
+    method: Method,
+    kmeans: u32,
+) -> Result<Palette, PuhuError> {
+    let max_colors = max_colors.clamp(1, 256) as usize;
+    let histogram = histogram(image);
+    let transparent = [0, 0, 0, 0];
+    let has_transparent = histogram.iter().any(|&(color, _)| color == transparent);
+
+    if histogram.len() <= max_colors {
+        let entries: Vec<[u8; 4]> = histogram.iter().map(|&(color, _)| color).collect();
+        return Palette::from_rgba(&entries);
+    }
+
+    // Keep fully transparent pixels apart from the colors being quantized
+    let (colors, max_colors) = if has_transparent && max_colors > 1 {
+        let colors: Vec<_> = histogram
+            .into_iter()
+            .filter(|&(color, _)| color != transparent)
+            .collect();
+        (colors, max_colors - 1)
+    } else {
+        (histogram, max_colors)
+    };
+
+    let mut entries = match method {
+        Method::MedianCut => median_cut(&colors, max_colors),
+        Method::FastOctree => octree(&colors, max_colors),
+        Method::NeuQuant => neuquant(image, max_colors),
+    };
+    refine(&mut entries, &colors, kmeans);
+    if has_transparent && entries.len() < 256 {
+        entries.push(transparent);
+    }
+    Palette::from_rgba(&entries)
+}
+
+/// Distinct RGBA colors of an image with their pixel counts, in order of
+/// first appearance. Fully transparent pixels are counted as transparent
+/// black, and images without alpha as opaque.
+fn histogram(image: &DynamicImage) -> Vec<([u8; 4], u32)> {
+    let has_alpha = image.color().has_alpha();
+    let mut positions = HashMap::new();
+    let mut colors: Vec<([u8; 4], u32)> = Vec::new();
+    for pixel in image.to_rgba8().pixels() {
+        let color = match pixel.0 {
+            [_, _, _, 0] if has_alpha => [0, 0, 0, 0],
+            [r, g, b, _] if !has_alpha => [r, g, b, 255],
+            color => color,
+        };
+        let position = *positions.entry(color).or_insert_with(|| {
+            colors.push((color, 0));
+            colors.len() - 1
+        });
+        colors[position].1 += 1;
+    }
+    colors
+}
+
+/// Mean of colors weighted by their counts
+fn mean(colors: &[([u8; 4], u32)]) -> [u8; 4] {
+    let mut sums = [0u64; 4];
+    let mut total = 0u64;
+    for &(color, count) in colors {
+        for (sum, value) in sums.iter_mut().zip(color) {
+            *sum += value as u64 * count as u64;
+        }
+        total += count as u64;
+    }
+    sums.map(|sum| ((sum + total / 2) / total.max(1)) as u8)
+}
+
+/// Sum of squared distances to the mean along each channel
+fn channel_errors(colors: &[([u8; 4], u32)]) -> [f64; 4] {
+    let mean = mean(colors);
+    let mut errors = [0.0; 4];
+    for &(color, count) in colors {
+        for channel in 0..4 {
+            let delta = color[channel] as f64 - mean[channel] as f64;
+            errors[channel] += delta * delta * count as f64;
+        }
+    }
+    errors
+}
+
+fn median_cut(colors: &[([u8; 4], u32)], max_colors: usize) -> Vec<[u8; 4]> {
+    let mut boxes = vec![colors.to_vec()];
+    while boxes.len() < max_colors {
+        // Split the box whose colors are furthest from its mean
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| {
+                let errors = channel_errors(colors);
+                let channel = (0..4).max_by(|&a, &b| errors[a].total_cmp(&errors[b]));
+                (i, channel.unwrap_or(0), errors.iter().sum::<f64>())
+            })
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(i, channel, _)| (i, channel))
+        else {
+            break;
+        };
+
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_by_key(|&(color, _)| color[channel]);
+        let total: u64 = colors.iter().map(|&(_, count)| count as u64).sum();
+        let mut below = 0;
+        let split = colors
+            .iter()
+            .position(|&(_, count)| {
+                below += count as u64;
+                below * 2 >= total
+            })
+            .map_or(1, |i| i + 1)
+            .clamp(1, colors.len() - 1);
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|colors| mean(colors)).collect()
+}
+
+/// Colors sharing the leading bits of each channel
+struct Node {
+    /// Number of leading bits shared
+    depth: u32,
+    /// The shared bits of each channel
+    key: [u8; 4],
+    colors: Vec<([u8; 4], u32)>,
+}
+
+impl Node {
+    /// Number of pixels of the node's colors
+    fn weight(&self) -> u64 {
+        self.colors.iter().map(|&(_, count)| count as u64).sum()
+    }
+}
+
+fn octree(colors: &[([u8; 4], u32)], max_colors: usize) -> Vec<[u8; 4]> {
+    let mut leaves: Vec<Node> = colors
+        .iter()
+        .map(|&(color, count)| Node {
+            depth: 8,
+            key: color,
+            colors: vec![(color, count)],
+        })
+        .collect();
+
+    // Fold the deepest leaves into their parents, least used parents first,
+    // until few enough remain
+    for depth in (1..=8).rev() {
+        if leaves.len() <= max_colors {
+            break;
+        }
+        let (deepest, mut rest): (Vec<Node>, Vec<Node>) =
+            leaves.into_iter().partition(|node| node.depth == depth);
+        let mut parents: HashMap<[u8; 4], Vec<Node>> = HashMap::new();
+        for node in deepest {
+            parents
+                .entry(node.key.map(|bits| bits >> 1))
+                .or_default()
+                .push(node);
+        }
+        let mut parents: Vec<([u8; 4], Vec<Node>)> = parents.into_iter().collect();
+        parents.sort_by_cached_key(|(key, children)| {
+            (children.iter().map(Node::weight).sum::<u64>(), *key)
+        });
+
+        let mut count = rest.len() + parents.iter().map(|(_, c)| c.len()).sum::<usize>();
+        for (key, mut children) in parents {
+            if count <= max_colors {
+                rest.extend(children);
+                continue;
+            }
+            // Merging every child of the last parent could leave fewer
+            // colors than asked for, so only the least used are merged then
+            let merged = children.len().min(count - max_colors + 1);
+            children.sort_by_key(Node::weight);
+            let kept = children.split_off(merged);
+            count -= merged - 1;
+            rest.push(Node {
+                depth: depth - 1,
+                key,
+                colors: children.into_iter().flat_map(|node| node.colors).collect(),
+            });
+            rest.extend(kept);
+        }
+        leaves = rest;
+    }
+    leaves.iter().map(|node| mean(&node.colors)).collect()
+}
+
+fn neuquant(image: &DynamicImage, max_colors: usize) -> Vec<[u8; 4]> {
+    let pixels: Vec<u8> = image
+        .to_rgba8()
+        .pixels()
+        .filter(|p| p[3] > 0)
+        .flat_map(|p| p.0)
+        .collect();
+    // Learn from every pixel of small images, and from a sample of one in
+    // up to ten of larger ones. NeuQuant needs at least two colors.
+    let sample_factor = (pixels.len() / 4 / 65536).clamp(1, 10) as i32;
+    let nq = NeuQuant::new(sample_factor, max_colors.max(2), &pixels);
+    nq.color_map_rgba()
+        .chunks_exact(4)
+        .map(|c| [c[0], c[1], c[2], c[3]])
+        .take(max_colors)
+        .collect()
+}
+
+fn distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    a.iter()
+        .zip(b)
+        .map(|(&a, b)| (a as i32 - b as i32).pow(2) as u32)
+        .sum()
+}
+
+/// Move each entry to the mean of the colors nearest to it, for up to
+/// `iterations` rounds or until no entry moves
+fn refine(entries: &mut [[u8; 4]], colors: &[([u8; 4], u32)], iterations: u32) {
+    for _ in 0..iterations {
+        let mut clusters = vec![Vec::new(); entries.len()];
+        for &(color, count) in colors {
+            let nearest = (0..entries.len()).min_by_key(|&i| distance(entries[i], color));
+            if let Some(i) = nearest {
+                clusters[i].push((color, count));
+            }
+        }
+        let mut moved = false;
+        for (entry, cluster) in entries.iter_mut().zip(&clusters) {
+            if !cluster.is_empty() {
+                let center = mean(cluster);
+                moved |= center != *entry;
+                *entry = center;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+}
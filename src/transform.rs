@@ -0,0 +1,421 @@
+use crate::errors::PuhuError;
+use crate::modes::{self, Mode};
+use crate::utils::Sample;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageBuffer, Pixel};
+use rayon::prelude::*;
+
+/// Interpolation between the pixel centres of the source of a transform
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Nearest,
+    Bilinear,
+    Bicubic,
+}
+
+impl Interpolation {
+    /// Interpolation for a resample filter; like Pillow, transforms only
+    /// accept nearest, bilinear and bicubic
+    pub fn from_filter(filter: FilterType) -> Result<Self, PuhuError> {
+        match filter {
+            FilterType::Nearest => Ok(Interpolation::Nearest),
+            FilterType::Triangle => Ok(Interpolation::Bilinear),
+            FilterType::CatmullRom => Ok(Interpolation::Bicubic),
+            _ => Err(PuhuError::InvalidOperation(
+                "Transforms only support NEAREST, BILINEAR and BICUBIC resampling".to_string(),
+            )),
+        }
+    }
+
+    /// Source indices along one axis with their weights, for a position in
+    /// pixel units where pixel `i` covers `i..i + 1`
+    fn taps(self, position: f64) -> ([(i64, f32); 4], usize) {
+        let mut taps = [(0, 0.0); 4];
+        if self == Interpolation::Nearest {
+            taps[0] = (position.floor() as i64, 1.0);
+            return (taps, 1);
+        }
+        let centred = position - 0.5;
+        let first = centred.floor();
+        let t = (centred - first) as f32;
+        let first = first as i64;
+        match self {
+            Interpolation::Bilinear => {
+                taps[0] = (first, 1.0 - t);
+                taps[1] = (first + 1, t);
+                (taps, 2)
+            }
+            _ => {
+                for (i, tap) in taps.iter_mut().enumerate() {
+                    let offset = i as i64 - 1;
+                    *tap = (first + offset, cubic(t - offset as f32));
+                }
+                (taps, 4)
+            }
+        }
+    }
+}
+
+/// Bicubic convolution kernel with a = -0.5, as Pillow uses
+fn cubic(x: f32) -> f32 {
+    const A: f32 = -0.5;
+    let x = x.abs();
+    if x < 1.0 {
+        ((A + 2.0) * x - (A + 3.0)) * x * x + 1.0
+    } else if x < 2.0 {
+        (((x - 5.0) * x + 8.0) * x - 4.0) * A
+    } else {
+        0.0
+    }
+}
+
+/// Coefficients (a, b, c, d, e, f) of an affine transform, which samples the
+/// source at (a x + b y + c, d x + e y + f) for output position (x, y)
+pub type Affine = [f64; 6];
+
+fn apply(matrix: &Affine, x: f64, y: f64) -> (f64, f64) {
+    let [a, b, c, d, e, f] = *matrix;
+    (a * x + b * y + c, d * x + e * y + f)
+}
+
+/// Output size and affine transform of Pillow's `rotate`, which turns the
+/// image `angle` degrees counter-clockwise around `center`, then moves it by
+/// `translate`
+pub fn rotation(
+    size: (u32, u32),
+    angle: f64,
+    center: Option<(f64, f64)>,
+    translate: (f64, f64),
+    expand: bool,
+) -> ((u32, u32), Affine) {
+    let (width, height) = (size.0 as f64, size.1 as f64);
+    let (cx, cy) = center.unwrap_or((width / 2.0, height / 2.0));
+    // Rounding keeps quarter turns exact
+    let round = |v: f64| (v * 1e15).round() / 1e15;
+    let radians = -angle.to_radians();
+    let (sin, cos) = (round(radians.sin()), round(radians.cos()));
+
+    let mut matrix = [cos, sin, 0.0, -sin, cos, 0.0];
+    let (c, f) = apply(&matrix, -cx - translate.0, -cy - translate.1);
+    matrix[2] = c + cx;
+    matrix[5] = f + cy;
+    if !expand {
+        return (size, matrix);
+    }
+
+    let corners = [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)];
+    let (xs, ys): (Vec<f64>, Vec<f64>) = corners.iter().map(|&(x, y)| apply(&matrix, x, y)).unzip();
+    let extent = |v: &[f64]| {
+        let max = v.iter().copied().fold(f64::MIN, f64::max);
+        let min = v.iter().copied().fold(f64::MAX, f64::min);
+        max.ceil() - min.floor()
+    };
+    let (new_width, new_height) = (extent(&xs), extent(&ys));
+    let (c, f) = apply(
+        &matrix,
+        -(new_width - width) / 2.0,
+        -(new_height - height) / 2.0,
+    );
+    matrix[2] = c;
+    matrix[5] = f;
+    ((new_width as u32, new_height as u32), matrix)
+}
+
+/// Bilinear map of an output rectangle onto a source quadrilateral
+pub struct Quad {
+    /// Output rectangle as (left, upper, right, lower)
+    rect: [i64; 4],
+    /// Source position at the upper left corner, its change along x and y,
+    /// and the change of the latter along the other axis, for x then y
+    coefficients: [f64; 8],
+}
+
+impl Quad {
+    /// Map `rect` onto the quadrilateral with upper left, lower left, lower
+    /// right and upper right corners `corners`, as in Pillow's QUAD data
+    pub fn new(rect: [i64; 4], corners: [f64; 8]) -> Self {
+        let [x0, y0, sw_x, sw_y, se_x, se_y, ne_x, ne_y] = corners;
+        let sx = 1.0 / (rect[2] - rect[0]) as f64;
+        let sy = 1.0 / (rect[3] - rect[1]) as f64;
+        let coefficients = [
+            x0,
+            (ne_x - x0) * sx,
+            (sw_x - x0) * sy,
+            (se_x - sw_x - ne_x + x0) * sx * sy,
+            y0,
+            (ne_y - y0) * sx,
+            (sw_y - y0) * sy,
+            (se_y - sw_y - ne_y + y0) * sx * sy,
+        ];
+        Quad { rect, coefficients }
+    }
+
+    fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let [x0, ax, ay, axy, y0, bx, by, bxy] = self.coefficients;
+        let (x, y) = (x - self.rect[0] as f64, y - self.rect[1] as f64);
+        (
+            x0 + ax * x + ay * y + axy * x * y,
+            y0 + bx * x + by * y + bxy * x * y,
+        )
+    }
+}
+
+/// Geometric transform of Pillow's `Image.transform`, from output to source
+/// positions
+pub enum Transform {
+    Affine(Affine),
+    /// Coefficients (a, b, c, d, e, f, g, h), sampling the source at
+    /// ((a x + b y + c) / (g x + h y + 1), (d x + e y + f) / (g x + h y + 1))
+    Perspective([f64; 8]),
+    /// Output rectangles mapped onto source quadrilaterals, with later
+    /// rectangles covering earlier ones; the rest of the output is fill
+    Mesh(Vec<Quad>),
+}
+
+impl Transform {
+    /// Map an output of `size` onto the source rectangle
+    /// (left, upper, right, lower)
+    pub fn extent(size: (u32, u32), [x0, y0, x1, y1]: [f64; 4]) -> Self {
+        let sx = (x1 - x0) / size.0 as f64;
+        let sy = (y1 - y0) / size.1 as f64;
+        Transform::Affine([sx, 0.0, x0, 0.0, sy, y0])
+    }
+
+    /// Map an output of `size` onto a source quadrilateral
+    pub fn quad(size: (u32, u32), corners: [f64; 8]) -> Self {
+        let rect = [0, 0, size.0 as i64, size.1 as i64];
+        Transform::Mesh(vec![Quad::new(rect, corners)])
+    }
+
+    /// Output rectangles with the source position of every pixel centre in
+    /// them, or None where there is no source position
+    fn regions(&self, size: (u32, u32)) -> Vec<([i64; 4], Map<'_>)> {
+        let whole = [0, 0, size.0 as i64, size.1 as i64];
+        match self {
+            Transform::Affine(matrix) => {
+                vec![(whole, Box::new(move |x, y| Some(apply(matrix, x, y))))]
+            }
+            Transform::Perspective([a, b, c, d, e, f, g, h]) => {
+                let map = move |x: f64, y: f64| {
+                    let w = g * x + h * y + 1.0;
+                    (w != 0.0).then(|| ((a * x + b * y + c) / w, (d * x + e * y + f) / w))
+                };
+                vec![(whole, Box::new(map))]
+            }
+            Transform::Mesh(quads) => quads
+                .iter()
+                .map(|quad| {
+                    (
+                        quad.rect,
+                        Box::new(|x, y| Some(quad.apply(x, y))) as Map<'_>,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+type Map<'a> = Box<dyn Fn(f64, f64) -> Option<(f64, f64)> + Sync + 'a>;
+
+/// Apply a transform, producing an image of `size` filled with `fill` where
+/// it has no source
+pub fn transform(
+    image: &DynamicImage,
+    mode: Mode,
+    size: (u32, u32),
+    transform: &Transform,
+    interpolation: Interpolation,
+    fill: [f64; 4],
+) -> DynamicImage {
+    let warp = Warp {
+        interpolation,
+        fill,
+        alpha: mode.has_alpha(),
+    };
+    // Fill is given in the values of "I" and "F" images, so they are warped
+    // as unscaled floats
+    if !matches!(mode, Mode::I | Mode::F) {
+        return warp.image(image, size, &transform.regions(size));
+    }
+    let values = modes::float_image(image, mode);
+    modes::from_float_image(&warp.image(&values, size, &transform.regions(size)), mode)
+}
+
+/// Inverse mapping from the centre of every output pixel to a source position
+///
+/// Pixels around the source are taken to be `fill`, so the edges of the
+/// source blend into it. Modes with alpha are interpolated premultiplied, so
+/// transparent fill does not darken their edges.
+struct Warp {
+    interpolation: Interpolation,
+    /// Fill color in the channels of `Mode::of`
+    fill: [f64; 4],
+    alpha: bool,
+}
+
+impl Warp {
+    fn image(
+        &self,
+        image: &DynamicImage,
+        size: (u32, u32),
+        regions: &[([i64; 4], Map<'_>)],
+    ) -> DynamicImage {
+        match image {
+            DynamicImage::ImageLuma8(buf) => {
+                DynamicImage::ImageLuma8(self.buffer(buf, size, regions))
+            }
+            DynamicImage::ImageLumaA8(buf) => {
+                DynamicImage::ImageLumaA8(self.buffer(buf, size, regions))
+            }
+            DynamicImage::ImageRgb8(buf) => {
+                DynamicImage::ImageRgb8(self.buffer(buf, size, regions))
+            }
+            DynamicImage::ImageRgba8(buf) => {
+                DynamicImage::ImageRgba8(self.buffer(buf, size, regions))
+            }
+            DynamicImage::ImageLuma16(buf) => {
+                DynamicImage::ImageLuma16(self.buffer(buf, size, regions))
+            }
+            DynamicImage::ImageLumaA16(buf) => {
+                DynamicImage::ImageLumaA16(self.buffer(buf, size, regions))
+            }
+            DynamicImage::ImageRgb16(buf) => {
+                DynamicImage::ImageRgb16(self.buffer(buf, size, regions))
+            }
+            DynamicImage::ImageRgba16(buf) => {
+                DynamicImage::ImageRgba16(self.buffer(buf, size, regions))
+            }
+            DynamicImage::ImageRgb32F(buf) => {
+                DynamicImage::ImageRgb32F(self.buffer(buf, size, regions))
+            }
+            DynamicImage::ImageRgba32F(buf) => {
+                DynamicImage::ImageRgba32F(self.buffer(buf, size, regions))
+            }
+            other => DynamicImage::ImageRgba8(self.buffer(&other.to_rgba8(), size, regions)),
+        }
+    }
+
+    fn buffer<P>(
+        &self,
+        src: &ImageBuffer<P, Vec<P::Subpixel>>,
+        size: (u32, u32),
+        regions: &[([i64; 4], Map<'_>)],
+    ) -> ImageBuffer<P, Vec<P::Subpixel>>
+    where
+        P: Pixel + Sync,
+        P::Subpixel: Sample + Send + Sync,
+    {
+        let channels = P::CHANNEL_COUNT as usize;
+        let (width, height) = size;
+        let [v0, v1, v2, v3] = self.fill.map(|v| v as f32);
+        let fill: &[f32] = match channels {
+            1 => &[v0],
+            2 => &[v0, v3],
+            3 => &[v0, v1, v2],
+            _ => &[v0, v1, v2, v3],
+        };
+        let fill_samples: Vec<P::Subpixel> = fill.iter().map(|&v| Sample::from_f32(v)).collect();
+
+        let mut samples = fill_samples.repeat(width as usize * height as usize);
+        let row_len = (width as usize * channels).max(1);
+        for (rect, map) in regions {
+            let left = rect[0].clamp(0, width as i64) as usize;
+            let right = rect[2].clamp(0, width as i64) as usize;
+            let rows =
+                rect[1].clamp(0, height as i64) as usize..rect[3].clamp(0, height as i64) as usize;
+            if left >= right {
+                continue;
+            }
+            samples
+                .par_chunks_mut(row_len)
+                .enumerate()
+                .filter(|(y, _)| rows.contains(y))
+                .for_each(|(y, row)| {
+                    let pixels = row[left * channels..right * channels].chunks_exact_mut(channels);
+                    for (x, pixel) in (left..).zip(pixels) {
+                        let Some((sx, sy)) = map(x as f64 + 0.5, y as f64 + 0.5) else {
+                            continue;
+                        };
+                        let value = self.sample(src, sx, sy, fill);
+                        for (out, v) in pixel.iter_mut().zip(value) {
+                            *out = Sample::from_f32(v);
+                        }
+                    }
+                });
+        }
+        ImageBuffer::from_raw(width, height, samples).expect("buffer sized for the image")
+    }
+
+    /// Interpolated channels at a source position
+    fn sample<P>(
+        &self,
+        src: &ImageBuffer<P, Vec<P::Subpixel>>,
+        x: f64,
+        y: f64,
+        fill: &[f32],
+    ) -> [f32; 4]
+    where
+        P: Pixel,
+        P::Subpixel: Sample,
+    {
+        let channels = fill.len();
+        let mut out = [0.0; 4];
+        if !x.is_finite() || !y.is_finite() {
+            out[..channels].copy_from_slice(fill);
+            return out;
+        }
+        let (width, height) = (src.width() as i64, src.height() as i64);
+        let samples = src.as_raw();
+        let pixel = |i: i64, j: i64, values: &mut [f32; 4]| {
+            if (0..width).contains(&i) && (0..height).contains(&j) {
+                let start = (j * width + i) as usize * channels;
+                for (v, s) in values.iter_mut().zip(&samples[start..start + channels]) {
+                    *v = s.to_f32();
+                }
+            } else {
+                values[..channels].copy_from_slice(fill);
+            }
+        };
+
+        let (xs, nx) = self.interpolation.taps(x);
+        let (ys, ny) = self.interpolation.taps(y);
+        if nx == 1 {
+            pixel(xs[0].0, ys[0].0, &mut out);
+            return out;
+        }
+
+        // Sums of premultiplied and of plain values, the latter for pixels
+        // that end up fully transparent
+        let colors = if self.alpha { channels - 1 } else { channels };
+        let mut premultiplied = [0.0f32; 4];
+        let mut plain = [0.0f32; 4];
+        let mut values = [0.0f32; 4];
+        for &(j, wy) in &ys[..ny] {
+            for &(i, wx) in &xs[..nx] {
+                let weight = wx * wy;
+                if weight == 0.0 {
+                    continue;
+                }
+                pixel(i, j, &mut values);
+                let alpha = if self.alpha { values[colors] } else { 1.0 };
+                for c in 0..channels {
+                    plain[c] += weight * values[c];
+                    premultiplied[c] += weight * values[c] * alpha;
+                }
+            }
+        }
+        if !self.alpha {
+            return plain;
+        }
+        let alpha = plain[colors];
+        out[colors] = alpha;
+        for c in 0..colors {
+            out[c] = if alpha > 0.0 {
+                premultiplied[c] / alpha
+            } else {
+                plain[c]
+            };
+        }
+        out
+    }
+}
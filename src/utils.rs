@@ -1,6 +1,12 @@
+use crate::cms::CmsTransform;
 use crate::errors::PuhuError;
-use image::{ColorType, DynamicImage, GenericImage, GenericImageView, GrayImage};
+use crate::modes::Mode;
+use image::imageops;
+use image::{
+    ColorType, DynamicImage, GenericImage, GenericImageView, GrayImage, ImageBuffer, Pixel,
+};
 use pyo3::prelude::*;
+use rayon::prelude::*;
 use std::borrow::Cow;
 
 pub fn color_type_to_mode_string(color_type: ColorType) -> String {
@@ -9,12 +15,12 @@ pub fn color_type_to_mode_string(color_type: ColorType) -> String {
         ColorType::La8 => "LA".to_string(),
         ColorType::Rgb8 => "RGB".to_string(),
         ColorType::Rgba8 => "RGBA".to_string(),
-        ColorType::L16 => "I".to_string(),
-        ColorType::La16 => "LA".to_string(),
-        ColorType::Rgb16 => "RGB".to_string(),
-        ColorType::Rgba16 => "RGBA".to_string(),
-        ColorType::Rgb32F => "RGB".to_string(),
-        ColorType::Rgba32F => "RGBA".to_string(),
+        ColorType::L16 => "I;16".to_string(),
+        ColorType::La16 => "LA;16".to_string(),
+        ColorType::Rgb16 => "RGB;16".to_string(),
+        ColorType::Rgba16 => "RGBA;16".to_string(),
+        ColorType::Rgb32F => "RGB;F".to_string(),
+        ColorType::Rgba32F => "RGBA;F".to_string(),
         _ => "RGB".to_string(), // Default fallback
     }
 }
@@ -141,40 +147,141 @@ pub fn paste_with_mask(
         return Ok(());
     }
 
-    // Generic fallback for less common mode combinations.
-    for py in 0..region.ch {
-        for px in 0..region.cw {
-            let src_x = region.sx + px;
-            let src_y = region.sy + py;
-            let dest_x = region.dx + px;
-            let dest_y = region.dy + py;
-
-            let mask_val = mask_gray.get_pixel(src_x, src_y)[0];
-            let inv_alpha = 255u16.saturating_sub(mask_val as u16);
-
-            // Get source and destination pixels
-            let src_pixel = src.get_pixel(src_x, src_y);
-            let dest_pixel = dest.get_pixel(dest_x, dest_y);
-
-            // Blend: out = (src * alpha + dest * (255 - alpha)) / 255
-            let blended = image::Rgba([
-                blend_u8(src_pixel[0], dest_pixel[0], mask_val, inv_alpha),
-                blend_u8(src_pixel[1], dest_pixel[1], mask_val, inv_alpha),
-                blend_u8(src_pixel[2], dest_pixel[2], mask_val, inv_alpha),
-                if src_pixel.0.len() > 3 && dest_pixel.0.len() > 3 {
-                    blend_u8(src_pixel[3], dest_pixel[3], mask_val, inv_alpha)
-                } else {
-                    255
-                },
-            ]);
-
-            dest.put_pixel(dest_x, dest_y, blended);
+    // Other modes are blended in their own sample type
+    match (dest, src) {
+        (DynamicImage::ImageLuma8(d), DynamicImage::ImageLuma8(s)) => {
+            blend_with_mask(d, s, &mask_gray, &region)
+        }
+        (DynamicImage::ImageLumaA8(d), DynamicImage::ImageLumaA8(s)) => {
+            blend_with_mask(d, s, &mask_gray, &region)
+        }
+        (DynamicImage::ImageLuma16(d), DynamicImage::ImageLuma16(s)) => {
+            blend_with_mask(d, s, &mask_gray, &region)
+        }
+        (DynamicImage::ImageLumaA16(d), DynamicImage::ImageLumaA16(s)) => {
+            blend_with_mask(d, s, &mask_gray, &region)
+        }
+        (DynamicImage::ImageRgb16(d), DynamicImage::ImageRgb16(s)) => {
+            blend_with_mask(d, s, &mask_gray, &region)
+        }
+        (DynamicImage::ImageRgba16(d), DynamicImage::ImageRgba16(s)) => {
+            blend_with_mask(d, s, &mask_gray, &region)
+        }
+        (DynamicImage::ImageRgb32F(d), DynamicImage::ImageRgb32F(s)) => {
+            blend_with_mask(d, s, &mask_gray, &region)
+        }
+        (DynamicImage::ImageRgba32F(d), DynamicImage::ImageRgba32F(s)) => {
+            blend_with_mask(d, s, &mask_gray, &region)
+        }
+        (dest, src) => {
+            return Err(PuhuError::InvalidOperation(format!(
+                "Cannot paste a {:?} image onto a {:?} image",
+                src.color(),
+                dest.color()
+            )))
         }
     }
 
     Ok(())
 }
 
+/// Overlay a source image of the same mode, blending by its alpha channel.
+///
+/// Images are overlaid in their own sample type, since `DynamicImage`
+/// pixels are 8-bit.
+pub fn overlay(dest: &mut DynamicImage, src: &DynamicImage, x: i32, y: i32) {
+    let (x, y) = (x as i64, y as i64);
+    match (dest, src) {
+        (DynamicImage::ImageLuma16(d), DynamicImage::ImageLuma16(s)) => {
+            imageops::overlay(d, s, x, y)
+        }
+        (DynamicImage::ImageLumaA16(d), DynamicImage::ImageLumaA16(s)) => {
+            imageops::overlay(d, s, x, y)
+        }
+        (DynamicImage::ImageRgb16(d), DynamicImage::ImageRgb16(s)) => imageops::overlay(d, s, x, y),
+        (DynamicImage::ImageRgba16(d), DynamicImage::ImageRgba16(s)) => {
+            imageops::overlay(d, s, x, y)
+        }
+        (DynamicImage::ImageRgb32F(d), DynamicImage::ImageRgb32F(s)) => {
+            imageops::overlay(d, s, x, y)
+        }
+        (DynamicImage::ImageRgba32F(d), DynamicImage::ImageRgba32F(s)) => {
+            imageops::overlay(d, s, x, y)
+        }
+        (dest, src) => imageops::overlay(dest, src, x, y),
+    }
+}
+
+/// Sample type of the buffers blended by `blend_with_mask` and resampled by
+/// geometric transforms
+pub trait Sample: Copy {
+    fn to_f32(self) -> f32;
+    /// Round and saturate a blended value
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Sample for u8 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+    fn from_f32(value: f32) -> Self {
+        value.round() as u8
+    }
+}
+
+impl Sample for u16 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+    fn from_f32(value: f32) -> Self {
+        value.round() as u16
+    }
+}
+
+impl Sample for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+/// Blend a source buffer onto a destination of the same color type through a mask
+fn blend_with_mask<P>(
+    dest: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    src: &ImageBuffer<P, Vec<P::Subpixel>>,
+    mask: &GrayImage,
+    region: &PasteRegion,
+) where
+    P: Pixel,
+    P::Subpixel: Sample,
+{
+    for py in 0..region.ch {
+        let sy = region.sy + py;
+        let dy = region.dy + py;
+        for px in 0..region.cw {
+            let sx = region.sx + px;
+            let dx = region.dx + px;
+            let alpha = mask.get_pixel(sx, sy)[0];
+            if alpha == 0 {
+                continue;
+            }
+            if alpha == 255 {
+                *dest.get_pixel_mut(dx, dy) = *src.get_pixel(sx, sy);
+                continue;
+            }
+            let weight = alpha as f32 / 255.0;
+            let s = src.get_pixel(sx, sy).channels();
+            let d = dest.get_pixel_mut(dx, dy).channels_mut();
+            for (d, &s) in d.iter_mut().zip(s) {
+                let blended = s.to_f32() * weight + d.to_f32() * (1.0 - weight);
+                *d = Sample::from_f32(blended);
+            }
+        }
+    }
+}
+
 /// Fill a region with a solid color
 /// Supports negative coordinates through clipping
 pub fn fill_region(
@@ -318,16 +425,39 @@ fn paste_with_mask_rgba8(
     }
 }
 
-/// Convert image to a different mode
-pub fn convert_mode(image: &DynamicImage, target_mode: &str) -> Result<DynamicImage, PuhuError> {
-    match target_mode {
-        "L" => Ok(DynamicImage::ImageLuma8(image.to_luma8())),
-        "LA" => Ok(DynamicImage::ImageLumaA8(image.to_luma_alpha8())),
-        "RGB" => Ok(DynamicImage::ImageRgb8(image.to_rgb8())),
-        "RGBA" => Ok(DynamicImage::ImageRgba8(image.to_rgba8())),
-        _ => Err(PuhuError::InvalidOperation(format!(
-            "Unsupported conversion mode: '{}'. Supported modes: L, LA, RGB, RGBA",
-            target_mode
-        ))),
+/// Convert pixel values between ICC profiles with a prepared transform.
+///
+/// The image must be in the transform's input mode; rows are transformed in parallel.
+pub fn apply_color_transform(
+    image: &DynamicImage,
+    mode: Mode,
+    transform: &CmsTransform,
+) -> Result<DynamicImage, PuhuError> {
+    if mode.name() != transform.input_mode {
+        return Err(PuhuError::InvalidOperation(format!(
+            "Image mode '{}' does not match the transform input mode '{}'",
+            mode.name(),
+            transform.input_mode
+        )));
     }
+
+    let (width, height) = image.dimensions();
+    let src = image.as_bytes();
+    let out_row = width as usize * transform.output_channels;
+    let mut dst = vec![0u8; out_row * height as usize];
+    if !dst.is_empty() {
+        let in_row = src.len() / height as usize;
+        src.par_chunks(in_row)
+            .zip(dst.par_chunks_mut(out_row))
+            .try_for_each(|(src_row, dst_row)| transform.transform(src_row, dst_row))?;
+    }
+
+    let converted = match transform.output_mode.as_str() {
+        "L" => ImageBuffer::from_raw(width, height, dst).map(DynamicImage::ImageLuma8),
+        "LA" => ImageBuffer::from_raw(width, height, dst).map(DynamicImage::ImageLumaA8),
+        "RGB" => ImageBuffer::from_raw(width, height, dst).map(DynamicImage::ImageRgb8),
+        // "RGBA" and the inks of "CMYK"
+        _ => ImageBuffer::from_raw(width, height, dst).map(DynamicImage::ImageRgba8),
+    };
+    Ok(converted.expect("buffer sized for the output mode"))
 }